@@ -0,0 +1,61 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use hoot::client::{Request, Response};
+use hoot::types::state::RECV_RESPONSE;
+use hoot::util::HeaderBuf;
+
+const BODY_SIZE: usize = 1024 * 1024;
+
+/// A chunked response with chunks of `chunk_size`.
+fn chunked(chunk_size: usize) -> Vec<u8> {
+    let mut v = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    let chunk = vec![b'x'; chunk_size];
+    for _ in 0..(BODY_SIZE / chunk_size) {
+        v.extend_from_slice(format!("{:x}\r\n", chunk_size).as_bytes());
+        v.extend_from_slice(&chunk);
+        v.extend_from_slice(b"\r\n");
+    }
+    v.extend_from_slice(b"0\r\n\r\n");
+    v
+}
+
+fn response() -> Response<RECV_RESPONSE> {
+    let mut buf = [0; 256];
+    Request::new(&mut buf)
+        .http_11()
+        .get("example.test", "/")
+        .unwrap()
+        .send()
+        .unwrap()
+        .flush()
+        .into_response()
+}
+
+fn dechunk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dechunk");
+    group.throughput(Throughput::Bytes(BODY_SIZE as u64));
+
+    for chunk_size in [64, 1024, 16 * 1024] {
+        let input = chunked(chunk_size);
+
+        group.bench_function(format!("chunks_{}", chunk_size), |b| {
+            let mut headers = HeaderBuf::<16>::new();
+            let mut dst = vec![0; 64 * 1024];
+            b.iter(|| {
+                let mut response = response();
+                let attempt = response.try_read_response(&input, &mut headers).unwrap();
+                let mut src = &input[attempt.input_used()..];
+
+                let mut response = response.proceed();
+                while !response.is_finished() {
+                    let part = response.read_body(src, &mut dst).unwrap();
+                    src = &src[part.input_used()..];
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, dechunk);
+criterion_main!(benches);
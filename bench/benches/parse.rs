@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use hoot::server::Request;
+use hoot::util::HeaderBuf;
+
+const REQUEST: &[u8] = b"GET /api/v1/items?page=2&sort=name HTTP/1.1\r\n\
+    Host: example.test\r\n\
+    User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0\r\n\
+    Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+    Accept-Language: en-US,en;q=0.5\r\n\
+    Accept-Encoding: gzip, deflate, br\r\n\
+    Cookie: session=0123456789abcdef0123456789abcdef; theme=dark; tracking=ab12cd34ef56\r\n\
+    Connection: keep-alive\r\n\
+    Cache-Control: max-age=0\r\n\r\n";
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(REQUEST.len() as u64));
+
+    group.bench_function("request", |b| {
+        let mut buf = HeaderBuf::<16>::new();
+        b.iter(|| {
+            let mut request = Request::new();
+            let attempt = request.try_read_request(REQUEST, &mut buf).unwrap();
+            assert!(attempt.is_success());
+        })
+    });
+
+    // The head arrives in reads of 64 bytes.
+    group.bench_function("request_partial", |b| {
+        let mut buf = HeaderBuf::<16>::new();
+        b.iter(|| {
+            let mut request = Request::new();
+            for end in (64..REQUEST.len()).step_by(64).chain([REQUEST.len()]) {
+                let attempt = request.try_read_request(&REQUEST[..end], &mut buf).unwrap();
+                if attempt.is_success() {
+                    break;
+                }
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
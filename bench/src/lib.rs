@@ -0,0 +1 @@
+//! Benchmarks for hoot, see `benches/`.
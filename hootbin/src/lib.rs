@@ -4,6 +4,7 @@
 
 use hoot::types;
 use hoot::types::state::{SEND_HEADERS, SEND_STATUS};
+use hoot::util::HeaderBuf;
 use hoot::{server::*, Header, Method, Url};
 use serde::Serialize;
 use std::collections::HashMap;
@@ -97,8 +98,10 @@ pub fn serve_single(i: impl io::Read, mut o: impl io::Write, base_url: &str) ->
         // Read more data from the inner io::Read.
         input.fill_more()?;
 
-        // Try to read all headers etc from the current input.
-        let attempt = req.try_read_request(&*input, &mut buf)?;
+        // Try to read all headers etc from the current input. The headers
+        // borrow the input, which changes each round.
+        let mut headers = HeaderBuf::<64>::new();
+        let attempt = req.try_read_request(&*input, &mut headers)?;
 
         if !attempt.is_success() {
             // Input might be ended, in which case we have a problem.
@@ -390,7 +393,7 @@ impl Body {
             if !v.is_empty() {
                 v.push_str(", ");
             }
-            v.push_str(h.value());
+            v.push_str(&String::from_utf8_lossy(h.value_raw()));
         }
     }
 
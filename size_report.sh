@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+#
+# Reports the size of the compiled library for each feature.
+#
+# Helpers are behind their own features, so code-size sensitive users only
+# pay for what they use. This builds the release rlib with no default
+# features plus each feature in turn, and with `full`, and prints the size
+# relative to the core.
+
+set -eu
+script_path=$( cd "$(dirname "${BASH_SOURCE[0]}")" ; pwd -P )
+cd "$script_path"
+
+target_dir="$script_path/target/size_report"
+
+size_of() {
+    cargo build --quiet --release --lib --no-default-features --features "$1" \
+        --target-dir "$target_dir/$2"
+    wc -c < "$target_dir/$2/release/libhoot.rlib"
+}
+
+core=$(size_of "" core)
+printf "%-14s %10s\n" "core" "$core"
+
+for feature in alloc heapless compression assets call echo keep-alive multipart proxy reply retry tls tls-rustls embedded-tls smoltcp transcript fuzz swar serde defmt log trace-hook metrics crypto suspend snapshot full; do
+    size=$(size_of "$feature" "$feature")
+    printf "%-14s %10s %+10d\n" "$feature" "$size" "$((size - core))"
+done
@@ -4,76 +4,224 @@ use core::str;
 
 use crate::chunk::Dechunker;
 use crate::error::Result;
-use crate::util::compare_lowercase_ascii;
+use crate::out::Writer;
+use crate::parser::parse_header_section;
+use crate::util::{compare_lowercase_ascii, LengthChecker};
 use crate::{CallState, Header, HootError, Method};
 
-pub(crate) fn do_read_body<'a, 'b>(
+pub(crate) fn do_read_body<'b>(
     state: &mut CallState,
-    src: &'a [u8],
+    src: &[u8],
     dst: &'b mut [u8],
 ) -> Result<BodyPart<'b>> {
     trace!("Read body");
 
+    if state.recv_too_large {
+        return Err(HootError::BodyTooLarge);
+    }
+
     // If we already read to completion, do not use any more input.
     if state.did_read_to_end {
-        return Ok(BodyPart::empty());
+        state.recv_unused = src.len();
+        return Ok(BodyPart::ended());
     }
 
-    // unwrap is ok because we can't be in state RECV_BODY without setting it.
-    let bit = match state.recv_body_mode.unwrap() {
+    // We can't be in state RECV_BODY without setting it.
+    let mode = state.recv_body_mode.ok_or(HootError::Internal)?;
+    let bit = match mode {
+        // No length checker is set up for bodies without content.
+        RecvBodyMode::LengthDelimited(0) => Ok(BodyPart::ended()),
         RecvBodyMode::LengthDelimited(_) => read_limit(state, src, dst, true),
         RecvBodyMode::Chunked => read_chunked(state, src, dst),
         RecvBodyMode::CloseDelimited => read_limit(state, src, dst, false),
     }?;
 
+    state.recv_body_len += bit.data.len() as u64;
+    if let Some(max) = state.max_recv_body {
+        if state.recv_body_len > max {
+            debug!("Body too large: {} max {}", state.recv_body_len, max);
+            state.recv_too_large = true;
+            return Err(HootError::BodyTooLarge);
+        }
+    }
+
     if bit.finished {
         state.did_read_to_end = true;
+        state.recv_unused = src.len() - bit.input_used;
+    }
+
+    #[cfg(feature = "metrics")]
+    crate::metrics::body_recv(state, bit.input_used, bit.finished);
+
+    state
+        .deadline
+        .check_body(bit.input_used > 0, bit.finished)?;
+
+    Ok(bit)
+}
+
+/// Read body through a [`BodyTransform`].
+pub(crate) fn do_read_body_transformed<'b>(
+    state: &mut CallState,
+    src: &[u8],
+    dst: &'b mut [u8],
+    transform: &mut dyn BodyTransform,
+    encoding: Option<ContentEncoding>,
+) -> Result<BodyPart<'b>> {
+    if !transform.select(encoding) {
+        return do_read_body(state, src, dst);
     }
 
+    let mut input_used = 0;
+    let capacity = transform.input_capacity().min(dst.len());
+
+    if !state.did_read_to_end && capacity > 0 {
+        // dst doubles as scratch space for the unframed body before it is
+        // handed to the transform. This keeps length/chunk accounting intact.
+        let part = do_read_body(state, src, &mut dst[..capacity])?;
+        input_used = part.input_used;
+        transform.push_input(part.data)?;
+    }
+
+    let input_ended = state.did_read_to_end;
+    let written = transform.pull_output(dst, input_ended)?;
+
+    trace!("Transformed {} from {} input", written, input_used);
+
+    let finished = input_ended && transform.is_ended();
+
     Ok(BodyPart {
-        input_used: bit.input_used,
-        data: bit.data,
-        finished: bit.finished,
+        input_used,
+        data: &dst[..written],
+        finished,
+        progress: Progress::of(finished, written, dst.len()),
     })
 }
 
-fn read_limit<'a, 'b>(
+/// Bytes left of a length delimited body.
+pub(crate) fn bytes_remaining(state: &CallState) -> Option<u64> {
+    match state.recv_body_mode? {
+        RecvBodyMode::LengthDelimited(_) => {
+            // No checker is used for 0 length.
+            Some(
+                state
+                    .recv_checker
+                    .as_ref()
+                    .map(|c| c.remaining())
+                    .unwrap_or(0),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Bytes left of the current chunk of a chunked body.
+pub(crate) fn chunk_remaining(state: &CallState) -> Option<u64> {
+    match state.recv_body_mode? {
+        RecvBodyMode::Chunked => Some(state.dechunker.map(|d| d.left() as u64).unwrap_or(0)),
+        _ => None,
+    }
+}
+
+/// Read the trailer section following a finished chunked body.
+pub(crate) fn do_read_trailers<'a, 'b>(
     state: &mut CallState,
     src: &'a [u8],
+    buf: &'b mut [Header<'a>],
+) -> Result<Option<Trailers<'a, 'b>>> {
+    if !state.did_read_to_end {
+        return Err(HootError::BodyNotFinished);
+    }
+
+    // Only chunked bodies have trailers.
+    if state.recv_body_mode != Some(RecvBodyMode::Chunked) {
+        state.recv_unused = src.len();
+        return Ok(Some(Trailers {
+            raw: &[],
+            headers: &[],
+        }));
+    }
+
+    let (len, headers) = match parse_header_section(src, buf)? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    trace!("Read trailers: {}", len);
+    state.recv_unused = src.len() - len;
+
+    Ok(Some(Trailers {
+        raw: &src[..len],
+        headers,
+    }))
+}
+
+fn read_limit<'b>(
+    state: &mut CallState,
+    src: &[u8],
     dst: &'b mut [u8],
     use_checker: bool,
 ) -> Result<BodyPart<'b>> {
-    let input_used = src.len().min(dst.len());
+    let mut input_used = src.len().min(dst.len());
+    let mut amount = input_used;
 
     let mut finished = false;
     if use_checker {
-        let checker = state.recv_checker.as_mut().unwrap();
-        checker.append(input_used, HootError::RecvMoreThanContentLength)?;
+        let checker = state.recv_checker.as_mut().ok_or(HootError::Internal)?;
+
+        let remaining = checker.remaining();
+        if amount as u64 > remaining {
+            match state.excess_body_policy {
+                ExcessBodyPolicy::Reject => {}
+                ExcessBodyPolicy::Ignore => {
+                    debug!("Ignore input beyond Content-Length");
+                    amount = remaining as usize;
+                    input_used = src.len();
+                }
+                ExcessBodyPolicy::Preserve => {
+                    amount = remaining as usize;
+                    input_used = amount;
+                }
+            }
+        }
+
+        checker.append(amount, |expected, actual| {
+            HootError::RecvMoreThanContentLength { expected, actual }
+        })?;
         finished = checker.complete();
-        trace!("Read body limited: {}", input_used);
+        trace!("Read body limited: {}", amount);
     } else {
-        trace!("Read body closed: {}", input_used);
+        trace!("Read body closed: {}", amount);
     }
 
-    let data = &mut dst[..input_used];
+    let progress = Progress::of(finished, amount, dst.len());
+
+    let data = &mut dst[..amount];
 
-    data.copy_from_slice(&src[..input_used]);
+    data.copy_from_slice(&src[..amount]);
     Ok(BodyPart {
         input_used,
         data,
         finished,
+        progress,
     })
 }
 
 fn read_chunked<'a>(state: &mut CallState, src: &[u8], dst: &'a mut [u8]) -> Result<BodyPart<'a>> {
-    if state.dechunker.is_none() {
-        state.dechunker = Some(Dechunker::new());
+    let dechunker = state.dechunker.get_or_insert_with(Dechunker::new);
+    let max_line = state.limits.max_chunk_line;
+    let (input_used, produced_output, _chunks) =
+        dechunker.parse_input_limited(src, dst, max_line)?;
+
+    #[cfg(feature = "metrics")]
+    for _ in 0.._chunks {
+        crate::metrics::record(state.metrics_id, crate::metrics::Event::ChunkRecv);
     }
-    let dechunker = state.dechunker.as_mut().unwrap();
-    let (input_used, produced_output) = dechunker.parse_input(src, dst)?;
 
-    let data = &mut dst[..produced_output];
     let finished = dechunker.is_ended();
+    let progress = Progress::of(finished, produced_output, dst.len());
+
+    let data = &mut dst[..produced_output];
 
     trace!("Read chunked: {}", input_used);
 
@@ -81,6 +229,7 @@ fn read_chunked<'a>(state: &mut CallState, src: &[u8], dst: &'a mut [u8]) -> Res
         input_used,
         data,
         finished,
+        progress,
     })
 }
 
@@ -88,6 +237,91 @@ pub struct BodyPart<'b> {
     pub(crate) input_used: usize,
     pub(crate) data: &'b [u8],
     pub(crate) finished: bool,
+    pub(crate) progress: Progress,
+}
+
+/// How to handle repeated `Content-Length` headers in incoming messages.
+///
+/// RFC 7230 §3.3.2 permits treating repeated identical values as one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentLengthPolicy {
+    /// Fail with `HootError::DuplicateContentLength`.
+    Reject,
+    /// Accept repeated values if they are identical.
+    AcceptIfIdentical,
+}
+
+impl Default for ContentLengthPolicy {
+    fn default() -> Self {
+        ContentLengthPolicy::Reject
+    }
+}
+
+/// How to handle input beyond the `Content-Length` of an incoming body.
+///
+/// The extra bytes are typically a pipelined next message, or garbage from a
+/// sloppy peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcessBodyPolicy {
+    /// Fail with `HootError::RecvMoreThanContentLength`.
+    Reject,
+    /// Use the extra input without passing it on as body.
+    Ignore,
+    /// Leave the extra input unused, for the next message. The amount is
+    /// counted by `unused_input()` of the ended response.
+    Preserve,
+}
+
+impl Default for ExcessBodyPolicy {
+    fn default() -> Self {
+        ExcessBodyPolicy::Reject
+    }
+}
+
+/// How to handle a body declared in a request with a method that has none.
+///
+/// Methods like `POST` have request bodies, see `Method::has_request_body()`.
+/// For other methods a `Content-Length` or `Transfer-Encoding` header still
+/// declares a body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestBodyPolicy {
+    /// Assume no body. Any body bytes are left as input.
+    Ignore,
+    /// Read the body as declared by the headers.
+    Read,
+    /// Fail with `HootError::UnexpectedBody`.
+    Reject,
+}
+
+impl Default for RequestBodyPolicy {
+    fn default() -> Self {
+        RequestBodyPolicy::Ignore
+    }
+}
+
+/// Why reading a [`BodyPart`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Progress {
+    /// The entire body is read.
+    Finished,
+    /// All usable input is consumed. Call again when more input is available.
+    NeedMoreInput,
+    /// The output buffer is full. Call again after handling the output,
+    /// possibly without new input.
+    OutputFull,
+}
+
+impl Progress {
+    fn of(finished: bool, output: usize, capacity: usize) -> Self {
+        if finished {
+            Progress::Finished
+        } else if output == capacity {
+            Progress::OutputFull
+        } else {
+            Progress::NeedMoreInput
+        }
+    }
 }
 
 impl BodyPart<'_> {
@@ -102,6 +336,11 @@ impl BodyPart<'_> {
     pub fn is_finished(&self) -> bool {
         self.finished
     }
+
+    /// Why the read stopped, to know what to wait for before reading again.
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
 }
 
 impl BodyPart<'_> {
@@ -110,19 +349,416 @@ impl BodyPart<'_> {
             input_used: 0,
             data: &[],
             finished: false,
+            progress: Progress::NeedMoreInput,
         }
     }
+
+    fn ended() -> Self {
+        BodyPart {
+            input_used: 0,
+            data: &[],
+            finished: true,
+            progress: Progress::Finished,
+        }
+    }
+}
+
+impl fmt::Debug for BodyPart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyPart")
+            .field("input_used", &self.input_used)
+            .field("data", &self.data.len())
+            .field("finished", &self.finished)
+            .field("progress", &self.progress)
+            .finish()
+    }
 }
 
 impl Deref for BodyPart<'_> {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &self.data
+        self.data
+    }
+}
+
+/// The trailer section after a chunked body.
+pub struct Trailers<'a, 'b> {
+    raw: &'a [u8],
+    headers: &'b [Header<'a>],
+}
+
+impl<'a, 'b> Trailers<'a, 'b> {
+    /// The trailer section exactly as received, including the ending empty line.
+    ///
+    /// Empty for bodies that are not chunked.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    pub fn headers(&self) -> &'b [Header<'a>] {
+        self.headers
+    }
+
+    /// Amount of input used by the trailer section.
+    pub fn input_used(&self) -> usize {
+        self.raw.len()
+    }
+}
+
+impl fmt::Debug for Trailers<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trailers")
+            .field("raw", &self.raw.len())
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+/// Transformation of the body, such as decompression, applied after
+/// the body framing (content-length/chunked) is removed.
+///
+/// The transform buffers input internally, which means the library can
+/// keep the framing accounting correct regardless of how much output
+/// each input byte results in.
+pub trait BodyTransform {
+    /// Whether to transform a body with the given `Content-Encoding`.
+    ///
+    /// Returning `false` passes the body through untouched.
+    fn select(&mut self, encoding: Option<ContentEncoding>) -> bool;
+
+    /// How many bytes `push_input()` can accept right now.
+    fn input_capacity(&self) -> usize;
+
+    /// Provide (unframed) body bytes. Never more than `input_capacity()`.
+    ///
+    /// More than that is an error, such as `HootError::OutputOverflow`, and
+    /// must not panic or keep part of the input.
+    fn push_input(&mut self, input: &[u8]) -> Result<()>;
+
+    /// Write transformed output to `dst`, returning the amount written.
+    ///
+    /// `input_ended` is true once the entire body has been pushed.
+    fn pull_output(&mut self, dst: &mut [u8], input_ended: bool) -> Result<usize>;
+
+    /// Whether the transform has produced all output.
+    fn is_ended(&self) -> bool;
+}
+
+/// Receives body bytes as they are sent or received, such as a checksum.
+///
+/// Used with `read_body_hashed()`, `write_bytes_hashed()` and
+/// `write_chunk_hashed()` to compute integrity checks like `Content-MD5`, or
+/// a value for a checksum trailer, without retaining the body. The bytes are
+/// the body as on the wire without framing, that is before any decoding.
+pub trait BodyHasher {
+    fn update(&mut self, bytes: &[u8]);
+}
+
+/// Feed the bytes that were written by a partial write into the hasher.
+pub(crate) fn hash_written(
+    result: Result<()>,
+    bytes: &[u8],
+    hasher: &mut dyn BodyHasher,
+) -> Result<()> {
+    match result {
+        Ok(()) => hasher.update(bytes),
+        Err(HootError::OutputFull(n)) => hasher.update(&bytes[..n]),
+        Err(_) => {}
+    }
+    result
+}
+
+/// Source of body bytes to send, such as flash or a sensor ring buffer.
+///
+/// Used with `write_body_from()`, which reads from the source straight into
+/// the output buffer, adding the chunk framing or keeping count of the
+/// `Content-Length`.
+pub trait BodySource {
+    /// Read body bytes into `buf`, returning the amount.
+    ///
+    /// 0 means nothing is available right now, or the body has ended.
+    fn read(&mut self, buf: &mut [u8]) -> usize;
+
+    /// How many bytes are left to read, if known.
+    ///
+    /// For a length delimited body, this must match what is left of the
+    /// `Content-Length`.
+    fn remaining(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl BodySource for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = self.len().min(buf.len());
+        buf[..n].copy_from_slice(&self[..n]);
+        *self = &self[n..];
+        n
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+}
+
+/// Fill `buf` from `source` until it is full or the source has no more.
+fn read_source(source: &mut dyn BodySource, buf: &mut [u8]) -> usize {
+    let mut n = 0;
+
+    while n < buf.len() {
+        let amount = source.read(&mut buf[n..]);
+        if amount == 0 {
+            break;
+        }
+        n += amount;
+    }
+
+    n
+}
+
+/// Write a length delimited body from `source`, as much as fits in `w` and is
+/// left of `checker`. Returns the amount written.
+pub(crate) fn write_length_from_source(
+    w: &mut Writer,
+    checker: &mut LengthChecker,
+    source: &mut dyn BodySource,
+) -> Result<usize> {
+    if let Some(remaining) = source.remaining() {
+        let expected = checker.expected;
+        let actual = checker.handled + remaining;
+        if actual > expected {
+            return Err(HootError::SentMoreThanContentLength { expected, actual });
+        }
+        if actual < expected {
+            return Err(HootError::SentLessThanContentLength { expected, actual });
+        }
+    }
+
+    let left = checker.remaining().min(usize::MAX as u64) as usize;
+    let n = w.write_with(|buf| {
+        let len = buf.len().min(left);
+        Ok(read_source(source, &mut buf[..len]))
+    })?;
+    checker.handled += n as u64;
+
+    Ok(n)
+}
+
+/// Write a chunk from `source`, filling what is free in `w`. Returns the amount
+/// of body written, which excludes the framing, if any.
+pub(crate) fn write_chunk_from_source(
+    w: &mut Writer,
+    source: &mut dyn BodySource,
+    chunked: bool,
+) -> Result<usize> {
+    let mut n = 0;
+    w.write_body_with(chunked, |buf| {
+        n = read_source(source, buf);
+        Ok(n)
+    })?;
+    Ok(n)
+}
+
+/// Storage that received body bytes are read into, such as a flash writer.
+///
+/// Used with `read_body_into()`, which decodes the body straight into the
+/// sink, while still checking the `Content-Length` or chunk framing.
+pub trait BodySink {
+    /// Free space to read body into. Empty when the sink is full.
+    fn unfilled(&mut self) -> &mut [u8];
+
+    /// `amount` bytes at the start of `unfilled()` now hold body.
+    fn filled(&mut self, amount: usize) -> Result<()>;
+}
+
+/// Read body from `src` into `sink` using `read`, until `src` is used, the
+/// sink is full or the body ends. Returns the amount of `src` used.
+pub(crate) fn read_into_sink(
+    src: &[u8],
+    sink: &mut dyn BodySink,
+    mut read: impl for<'b> FnMut(&[u8], &'b mut [u8]) -> Result<BodyPart<'b>>,
+) -> Result<usize> {
+    let mut used = 0;
+
+    loop {
+        let part = read(&src[used..], sink.unfilled())?;
+        let (input_used, len, finished) =
+            (part.input_used(), part.data().len(), part.is_finished());
+        used += input_used;
+        sink.filled(len)?;
+
+        if finished || input_used == 0 && len == 0 {
+            return Ok(used);
+        }
+    }
+}
+
+/// The `Content-Encoding` of a body.
+///
+/// A missing header, or `identity`, is represented by `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+    Brotli,
+    Compress,
+    /// Any other, or a list of several encodings.
+    Other,
+}
+
+impl ContentEncoding {
+    /// The header value for this encoding. `None` for `Other`.
+    pub fn as_str(&self) -> Option<&'static str> {
+        match self {
+            ContentEncoding::Gzip => Some("gzip"),
+            ContentEncoding::Deflate => Some("deflate"),
+            ContentEncoding::Brotli => Some("br"),
+            ContentEncoding::Compress => Some("compress"),
+            ContentEncoding::Other => None,
+        }
+    }
+
+    pub(crate) fn from_headers(headers: &[Header<'_>]) -> Option<Self> {
+        let head = headers
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "content-encoding"))?;
+
+        let value = match head.try_value() {
+            Some(v) => v.trim(),
+            None => return Some(ContentEncoding::Other),
+        };
+
+        Self::from_token(value)
+    }
+
+    /// A single coding. `None` for `identity` or an empty value.
+    fn from_token(value: &str) -> Option<Self> {
+        if value.is_empty() || compare_lowercase_ascii(value, "identity") {
+            return None;
+        }
+
+        let enc =
+            if compare_lowercase_ascii(value, "gzip") || compare_lowercase_ascii(value, "x-gzip") {
+                ContentEncoding::Gzip
+            } else if compare_lowercase_ascii(value, "deflate") {
+                ContentEncoding::Deflate
+            } else if compare_lowercase_ascii(value, "br") {
+                ContentEncoding::Brotli
+            } else if compare_lowercase_ascii(value, "compress") {
+                ContentEncoding::Compress
+            } else {
+                ContentEncoding::Other
+            };
+
+        Some(enc)
+    }
+}
+
+/// Max number of codings, besides `chunked`, in a [`TransferCodings`].
+pub const MAX_TRANSFER_CODINGS: usize = 4;
+
+/// The codings of the `Transfer-Encoding` header(s), RFC 7230 §3.3.1.
+///
+/// Codings are kept in the order they were applied by the sender, which is
+/// the reverse order of decoding. `chunked` is not kept among the codings,
+/// see `is_chunked()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferCodings {
+    pub(crate) codings: [ContentEncoding; MAX_TRANSFER_CODINGS],
+    pub(crate) len: usize,
+    pub(crate) present: bool,
+    pub(crate) chunked: bool,
+}
+
+impl Default for TransferCodings {
+    fn default() -> Self {
+        Self::empty()
     }
 }
 
+impl TransferCodings {
+    pub(crate) const fn empty() -> Self {
+        TransferCodings {
+            codings: [ContentEncoding::Other; MAX_TRANSFER_CODINGS],
+            len: 0,
+            present: false,
+            chunked: false,
+        }
+    }
+
+    /// Parse all `Transfer-Encoding` headers.
+    ///
+    /// Fails with `HootError::ChunkedNotLast` if `chunked` is followed by
+    /// another coding, and `HootError::TooManyTransferCodings` if there are
+    /// more than [`MAX_TRANSFER_CODINGS`].
+    pub(crate) fn from_headers(headers: &[Header<'_>]) -> Result<Self> {
+        let mut ret = TransferCodings::default();
+
+        for head in headers {
+            if !compare_lowercase_ascii(head.name(), "transfer-encoding") {
+                continue;
+            }
+
+            ret.present = true;
+
+            let s = str::from_utf8(head.value_raw())?;
+
+            for token in s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+                // chunked must be the final coding, and only applied once.
+                if ret.chunked {
+                    return Err(HootError::ChunkedNotLast);
+                }
+
+                if compare_lowercase_ascii(token, "chunked") {
+                    ret.chunked = true;
+                    continue;
+                }
+
+                // Coding parameters, like "gzip;q=1", are not used in Transfer-Encoding.
+                let name = token.split(';').next().unwrap_or("").trim();
+
+                if let Some(coding) = ContentEncoding::from_token(name) {
+                    if ret.len == MAX_TRANSFER_CODINGS {
+                        return Err(HootError::TooManyTransferCodings);
+                    }
+                    ret.codings[ret.len] = coding;
+                    ret.len += 1;
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Whether there is any `Transfer-Encoding` header.
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    /// Whether `chunked` is the final coding.
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// The codings besides `chunked` in the order they were applied.
+    ///
+    /// `identity` is left out.
+    pub fn codings(&self) -> &[ContentEncoding] {
+        &self.codings[..self.len]
+    }
+
+    /// The last applied coding besides `chunked`, which is the first to decode.
+    pub fn outermost(&self) -> Option<ContentEncoding> {
+        self.codings().last().copied()
+    }
+}
+
+/// How an incoming body is framed.
 #[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RecvBodyMode {
     /// Delimited by content-length. 0 is also a valid value when we don't expect a body,
     /// due to HEAD or status, but still want to leave the socket open.
@@ -134,30 +770,54 @@ pub enum RecvBodyMode {
 }
 
 impl RecvBodyMode {
-    pub fn for_request(http10: bool, method: Method, headers: &[Header<'_>]) -> Result<Self> {
-        let has_no_body = !method.has_request_body();
+    /// `has_body` is whether the method has a request body.
+    pub(crate) fn for_request(
+        http10: bool,
+        has_body: bool,
+        headers: &[Header<'_>],
+        policy: ContentLengthPolicy,
+        body_policy: RequestBodyPolicy,
+    ) -> Result<Self> {
+        let has_no_body = !has_body;
 
-        if has_no_body {
+        if has_no_body && body_policy == RequestBodyPolicy::Ignore {
             return Ok(Self::LengthDelimited(0));
         }
 
-        let ret = match Self::header_defined(http10, headers)? {
+        let ret = match Self::header_defined(http10, headers, policy)? {
+            // https://datatracker.ietf.org/doc/html/rfc7230#section-3.3.3
+            // If a Transfer-Encoding header field is present in a request and
+            // the chunked transfer coding is not the final encoding, the
+            // message body length cannot be determined reliably.
+            Self::CloseDelimited
+                if !http10 && TransferCodings::from_headers(headers)?.is_present() =>
+            {
+                return Err(HootError::ChunkedNotLast);
+            }
             // Request bodies cannot be close delimited (even under http10).
             Self::CloseDelimited => Self::LengthDelimited(0),
-            r @ _ => r,
+            r => r,
         };
 
+        if has_no_body
+            && body_policy == RequestBodyPolicy::Reject
+            && ret != Self::LengthDelimited(0)
+        {
+            return Err(HootError::UnexpectedBody);
+        }
+
         Ok(ret)
     }
 
-    pub fn for_response(
+    pub(crate) fn for_response(
         http10: bool,
         method: Method,
         status_code: u16,
         headers: &[Header<'_>],
+        policy: ContentLengthPolicy,
     ) -> Result<Self> {
-        let is_success = status_code >= 200 && status_code <= 299;
-        let is_informational = status_code >= 100 && status_code <= 199;
+        let is_success = (200..=299).contains(&status_code);
+        let is_informational = (100..=199).contains(&status_code);
 
         let has_no_body =
             // https://datatracker.ietf.org/doc/html/rfc2616#section-4.3
@@ -183,36 +843,69 @@ impl RecvBodyMode {
 
         // https://datatracker.ietf.org/doc/html/rfc2616#section-4.3
         // All other responses do include a message-body, although it MAY be of zero length.
-        Self::header_defined(http10, headers)
+        Self::header_defined(http10, headers, policy)
+    }
+
+    /// The mode declared by the headers alone, ignoring method and status.
+    ///
+    /// For a `HEAD` response this is the framing a `GET` would get.
+    pub(crate) fn declared(
+        http10: bool,
+        headers: &[Header<'_>],
+        policy: ContentLengthPolicy,
+    ) -> Option<Self> {
+        Self::header_defined(http10, headers, policy).ok()
     }
 
-    fn header_defined(http10: bool, headers: &[Header]) -> Result<Self> {
+    /// Whether the body this mode describes is larger than `max`.
+    ///
+    /// Bodies of unknown size exceed if `reject_unknown` is set.
+    pub(crate) fn exceeds(&self, max: u64, reject_unknown: bool) -> bool {
+        match self {
+            Self::LengthDelimited(len) => *len > max,
+            Self::Chunked | Self::CloseDelimited => reject_unknown,
+        }
+    }
+
+    fn header_defined(
+        http10: bool,
+        headers: &[Header],
+        policy: ContentLengthPolicy,
+    ) -> Result<Self> {
         let mut content_length: Option<u64> = None;
-        let mut chunked = false;
 
         for head in headers {
             if compare_lowercase_ascii(head.name(), "content-length") {
                 let v = str::from_utf8(head.value_raw())?.parse::<u64>()?;
-                if content_length.is_some() {
-                    return Err(HootError::DuplicateContentLength);
+                if let Some(prev) = content_length {
+                    let identical_ok = policy == ContentLengthPolicy::AcceptIfIdentical;
+                    if !(identical_ok && prev == v) {
+                        return Err(HootError::DuplicateContentLength);
+                    }
                 }
                 content_length = Some(v);
-            } else if !chunked && compare_lowercase_ascii(head.name(), "transfer-encoding") {
-                // Header can repeat, stop looking if we found "chunked"
-                let s = str::from_utf8(head.value_raw())?;
-                chunked = s
-                    .split(",")
-                    .map(|v| v.trim())
-                    .any(|v| compare_lowercase_ascii(v, "chunked"));
             }
         }
 
-        if chunked && !http10 {
+        let codings = TransferCodings::from_headers(headers)?;
+
+        if codings.is_present() && !http10 {
             // https://datatracker.ietf.org/doc/html/rfc2616#section-4.4
             // Messages MUST NOT include both a Content-Length header field and a
             // non-identity transfer-coding. If the message does include a non-
             // identity transfer-coding, the Content-Length MUST be ignored.
-            return Ok(Self::Chunked);
+            if codings.is_chunked() {
+                return Ok(Self::Chunked);
+            }
+
+            // https://datatracker.ietf.org/doc/html/rfc7230#section-3.3.3
+            // If a Transfer-Encoding header field is present in a response and
+            // the chunked transfer coding is not the final encoding, the message
+            // body length is determined by reading the connection until it is
+            // closed by the server.
+            if codings.outermost().is_some() {
+                return Ok(Self::CloseDelimited);
+            }
         }
 
         if let Some(len) = content_length {
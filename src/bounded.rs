@@ -0,0 +1,193 @@
+//! Copies of borrowed types into [heapless](https://docs.rs/heapless) collections.
+//!
+//! For keeping what was read beyond the lifetime of the input buffers on
+//! targets without an allocator. The capacities are fixed at compile time,
+//! and data not fitting is an error rather than truncated.
+
+use core::str;
+
+use heapless::{String, Vec};
+
+use crate::{BodySink, Header, HootError, Result};
+
+/// [`Header`] copied into a name and value of at most `N` bytes each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundedHeader<const N: usize> {
+    name: String<N>,
+    value: Vec<u8, N>,
+}
+
+impl<const N: usize> BoundedHeader<N> {
+    /// Copy `header`.
+    ///
+    /// Errors with `HootError::OutputOverflow` if the name or value is longer
+    /// than `N`.
+    pub fn new(header: &Header<'_>) -> Result<Self> {
+        let overflow = |len: usize| HootError::OutputOverflow { needed: len - N };
+
+        let mut name = String::new();
+        name.push_str(header.name())
+            .map_err(|_| overflow(header.name().len()))?;
+
+        let value =
+            Vec::from_slice(header.value_raw()).map_err(|_| overflow(header.value_raw().len()))?;
+
+        Ok(BoundedHeader { name, value })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value as `&str`, `None` if it is not UTF-8.
+    pub fn try_value(&self) -> Option<&str> {
+        str::from_utf8(&self.value).ok()
+    }
+
+    /// The value as bytes, as received.
+    pub fn value_raw(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Borrow as a [`Header`], for the APIs taking one.
+    pub fn as_header(&self) -> Header<'_> {
+        Header::new(&self.name, &self.value)
+    }
+}
+
+/// Copy up to `M` headers of at most `N` bytes each.
+///
+/// Errors with `HootError::TooManyHeaders` if there are more than `M`.
+///
+/// ```
+/// use hoot::bounded::{collect_headers, BoundedHeader};
+/// use hoot::server::Request;
+/// use hoot::util::HeaderBuf;
+///
+/// let mut buf = HeaderBuf::<16>::new();
+/// let mut request = Request::new();
+/// let attempt = request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf)?;
+///
+/// let headers: heapless::Vec<BoundedHeader<32>, 8> =
+///     collect_headers(attempt.headers().unwrap())?;
+/// assert_eq!(headers[0].name(), "Host");
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub fn collect_headers<const N: usize, const M: usize>(
+    headers: &[Header<'_>],
+) -> Result<Vec<BoundedHeader<N>, M>> {
+    let mut v = Vec::new();
+
+    for h in headers {
+        v.push(BoundedHeader::new(h)?)
+            .map_err(|_| HootError::TooManyHeaders)?;
+    }
+
+    Ok(v)
+}
+
+/// [`BodySink`] appending to a `heapless::Vec`.
+///
+/// Use with `read_body_into()`. Reading stops when the `Vec` is full, which
+/// means the body is larger than `N` if it is not finished.
+///
+/// ```
+/// use hoot::bounded::BoundedBody;
+/// use hoot::server::Request;
+/// use hoot::util::HeaderBuf;
+///
+/// const INPUT: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello";
+///
+/// let mut buf = HeaderBuf::<16>::new();
+/// let mut request = Request::new();
+/// let attempt = request.try_read_request(INPUT, &mut buf)?;
+/// let used = attempt.input_used();
+/// let mut request = request.proceed();
+///
+/// let mut body = heapless::Vec::<u8, 64>::new();
+/// request.read_body_into(&INPUT[used..], &mut BoundedBody::new(&mut body))?;
+/// assert_eq!(&body[..], b"hello");
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub struct BoundedBody<'v, const N: usize> {
+    vec: &'v mut Vec<u8, N>,
+    len: usize,
+}
+
+impl<'v, const N: usize> BoundedBody<'v, N> {
+    pub fn new(vec: &'v mut Vec<u8, N>) -> Self {
+        let len = vec.len();
+        BoundedBody { vec, len }
+    }
+}
+
+impl<const N: usize> BodySink for BoundedBody<'_, N> {
+    fn unfilled(&mut self) -> &mut [u8] {
+        // Can't fail, N is the capacity.
+        let _ = self.vec.resize(N, 0);
+        &mut self.vec[self.len..]
+    }
+
+    fn filled(&mut self, amount: usize) -> Result<()> {
+        self.len += amount;
+        Ok(())
+    }
+}
+
+impl<const N: usize> Drop for BoundedBody<'_, N> {
+    fn drop(&mut self) {
+        self.vec.truncate(self.len);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::Request;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn bounded_headers() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        let mut request = Request::new();
+        let attempt = request.try_read_request(
+            b"GET / HTTP/1.1\r\nHost: x\r\nX-Long: 0123456789\r\n\r\n",
+            &mut buf,
+        )?;
+        let headers = attempt.headers().unwrap();
+
+        let v: Vec<BoundedHeader<10>, 2> = collect_headers(headers)?;
+        assert_eq!(v[1].try_value(), Some("0123456789"));
+        assert_eq!(v[1].as_header(), headers[1]);
+
+        let err = collect_headers::<9, 2>(headers).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 1 });
+
+        let err = collect_headers::<10, 1>(headers).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::TooManyHeaders);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bounded_body() -> Result<()> {
+        const INPUT: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\n0123456789";
+
+        let mut buf = HeaderBuf::<16>::new();
+        let mut request = Request::new();
+        let attempt = request.try_read_request(INPUT, &mut buf)?;
+        let used = attempt.input_used();
+        let mut request = request.proceed();
+
+        let mut body = Vec::<u8, 8>::new();
+        body.extend_from_slice(b"ab").unwrap();
+        let n = request.read_body_into(&INPUT[used..], &mut BoundedBody::new(&mut body))?;
+
+        // Full before the end of the body.
+        assert_eq!(n, 6);
+        assert_eq!(&body[..], b"ab012345");
+        assert!(!request.is_finished());
+
+        Ok(())
+    }
+}
@@ -1,10 +1,8 @@
-use core::str;
-
 use crate::error::Result;
 use crate::parser::find_crlf;
-use crate::HootError;
+use crate::{HootError, Limit};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum Dechunker {
     Size,
     Chunk(usize),
@@ -15,6 +13,7 @@ pub(crate) enum Dechunker {
 struct Pos {
     index_in: usize,
     index_out: usize,
+    chunks: usize,
 }
 
 impl Dechunker {
@@ -22,15 +21,30 @@ impl Dechunker {
         Dechunker::Size
     }
 
+    #[cfg(test)]
     pub fn parse_input(&mut self, src: &[u8], dst: &mut [u8]) -> Result<(usize, usize)> {
+        self.parse_input_limited(src, dst, usize::MAX)
+            .map(|(i, o, _)| (i, o))
+    }
+
+    /// Parse input where chunk size lines are at most `max_line` long.
+    ///
+    /// Returns input used, output produced and the number of chunks started.
+    pub fn parse_input_limited(
+        &mut self,
+        src: &[u8],
+        dst: &mut [u8],
+        max_line: usize,
+    ) -> Result<(usize, usize, usize)> {
         let mut pos = Pos {
             index_in: 0,
             index_out: 0,
+            chunks: 0,
         };
 
         loop {
             let more = match self {
-                Dechunker::Size => self.read_size(src, &mut pos)?,
+                Dechunker::Size => self.read_size(src, &mut pos, max_line)?,
                 Dechunker::Chunk(_) => self.read_data(src, dst, &mut pos)?,
                 Dechunker::CrLf => self.read_crlf(src, &mut pos)?,
                 Dechunker::Ended => false,
@@ -41,11 +55,11 @@ impl Dechunker {
             }
         }
 
-        Ok((pos.index_in, pos.index_out))
+        Ok((pos.index_in, pos.index_out, pos.chunks))
     }
 
-    #[cfg(test)]
-    fn left(&self) -> usize {
+    /// Bytes left of the current chunk.
+    pub fn left(&self) -> usize {
         if let Self::Chunk(l) = self {
             *l
         } else {
@@ -57,22 +71,34 @@ impl Dechunker {
         matches!(self, Self::Ended)
     }
 
-    fn read_size(&mut self, src: &[u8], pos: &mut Pos) -> Result<bool> {
+    fn read_size(&mut self, src: &[u8], pos: &mut Pos, max_line: usize) -> Result<bool> {
         let src = &src[pos.index_in..];
 
         let i = match find_crlf(src) {
             Some(v) => v,
-            None => return Ok(false),
+            None => {
+                if src.len() > max_line {
+                    return Err(HootError::LimitExceeded {
+                        which: Limit::ChunkLine,
+                    });
+                }
+                return Ok(false);
+            }
         };
 
-        let len_end = src.iter().position(|c| *c == b';').unwrap_or(i);
-        let len_str = str::from_utf8(&src[..len_end])?;
-        let len = usize::from_str_radix(len_str, 16)?;
+        if i > max_line {
+            return Err(HootError::LimitExceeded {
+                which: Limit::ChunkLine,
+            });
+        }
+
+        let len = parse_size(&src[..i])?;
 
         pos.index_in += i + 2;
         *self = if len == 0 {
             Self::Ended
         } else {
+            pos.chunks += 1;
             Self::Chunk(len)
         };
 
@@ -85,13 +111,13 @@ impl Dechunker {
 
         let left = match self {
             Self::Chunk(v) => v,
-            _ => unreachable!(),
+            _ => return Err(HootError::Internal),
         };
 
         // Read the smallest amount of input/output or length left of chunk.
         let to_read = src.len().min(dst.len()).min(*left);
 
-        (&mut dst[..to_read]).copy_from_slice(&src[..to_read]);
+        dst[..to_read].copy_from_slice(&src[..to_read]);
         pos.index_in += to_read;
         pos.index_out += to_read;
         *left -= to_read;
@@ -104,15 +130,10 @@ impl Dechunker {
     }
 
     fn read_crlf(&mut self, src: &[u8], pos: &mut Pos) -> Result<bool> {
-        let src = &src[pos.index_in..];
-
-        let i = match find_crlf(src) {
-            Some(v) => v,
-            None => return Ok(false),
-        };
-
-        if i > 0 {
-            return Err(HootError::IncorrectChunk);
+        match &src[pos.index_in..] {
+            [b'\r', b'\n', ..] => {}
+            [] | [b'\r'] => return Ok(false),
+            _ => return Err(HootError::IncorrectChunk),
         }
 
         pos.index_in += 2;
@@ -122,6 +143,33 @@ impl Dechunker {
     }
 }
 
+/// Parse the hex chunk size of a size line, ignoring chunk extensions.
+fn parse_size(line: &[u8]) -> Result<usize> {
+    let mut len: usize = 0;
+    let mut digits = 0;
+
+    for b in line {
+        let d = match b {
+            b'0'..=b'9' => b - b'0',
+            b'a'..=b'f' => b - b'a' + 10,
+            b'A'..=b'F' => b - b'A' + 10,
+            _ => break,
+        };
+        len = len
+            .checked_mul(16)
+            .and_then(|l| l.checked_add(d as usize))
+            .ok_or(HootError::ParseIntError)?;
+        digits += 1;
+    }
+
+    let extension = line.get(digits).map(|b| *b == b';').unwrap_or(true);
+    if digits == 0 || !extension {
+        return Err(HootError::ParseIntError);
+    }
+
+    Ok(len)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -139,6 +187,22 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_dechunk_size_limit() -> Result<()> {
+        let mut d = Dechunker::new();
+        let mut b = [0; 1024];
+        let err = HootError::LimitExceeded {
+            which: Limit::ChunkLine,
+        };
+        assert_eq!(d.parse_input_limited(b"2;meta", &mut b, 4), Err(err));
+        assert_eq!(d.parse_input_limited(b"2;me\r\n", &mut b, 4)?, (6, 0, 1));
+        assert_eq!(
+            d.parse_input_limited(b"xx\r\n2;meta\r\n", &mut b, 4),
+            Err(err)
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_dechunk_size_meta() -> Result<()> {
         let mut d = Dechunker::new();
@@ -162,4 +226,15 @@ mod test {
         assert!(d.is_ended());
         Ok(())
     }
+
+    #[test]
+    fn test_dechunk_size_errors() {
+        let mut b = [0; 1024];
+        let mut err = |input: &[u8]| Dechunker::new().parse_input(input, &mut b).unwrap_err();
+        assert_eq!(err(b"\r\n"), HootError::ParseIntError);
+        assert_eq!(err(b"2 ;meta\r\n"), HootError::ParseIntError);
+        assert_eq!(err(b"+2\r\n"), HootError::ParseIntError);
+        assert_eq!(err(b"1ffffffffffffffff\r\n"), HootError::ParseIntError);
+        assert_eq!(err(b"2\r\nOKxx"), HootError::IncorrectChunk);
+    }
 }
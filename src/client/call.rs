@@ -0,0 +1,230 @@
+use crate::header::check_value;
+use crate::types::state::*;
+use crate::types::version::*;
+use crate::types::MethodWithoutRequestBody;
+use crate::util::compare_lowercase_ascii;
+use crate::Method;
+use crate::{HootError, Result, Url};
+
+use super::{Output, Request};
+
+/// Max number of headers a [`Call`] can hold.
+pub const MAX_CALL_HEADERS: usize = 16;
+
+/// Start a high level call.
+///
+/// This collapses the type state steps of [`Request`] for the common case of
+/// HTTP/1.1 requests without a body.
+///
+/// ```
+/// use hoot::client::call;
+///
+/// let mut buf = [0; 1024];
+///
+/// let output = call()
+///     .get("http://myhost.test:8080/some-path?q=1")
+///     .header("accept", "text/plain")
+///     .send(&mut buf)?;
+///
+/// assert_eq!(
+///     &*output,
+///     b"GET /some-path?q=1 HTTP/1.1\r\nHost: myhost.test:8080\r\n\
+///     accept: text/plain\r\n\r\n"
+/// );
+///
+/// // Once output is written to the transport, continue with the response.
+/// let response = output.into_response();
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub fn call<'a>() -> Call<'a> {
+    Call {
+        method: Method::GET,
+        url: "",
+        headers: [("", ""); MAX_CALL_HEADERS],
+        header_count: 0,
+    }
+}
+
+/// Builder for a call without request body. See [`call()`].
+pub struct Call<'a> {
+    method: Method,
+    url: &'a str,
+    headers: [(&'a str, &'a str); MAX_CALL_HEADERS],
+    header_count: usize,
+}
+
+impl<'a> Call<'a> {
+    fn with(mut self, method: Method, url: &'a str) -> Self {
+        self.method = method;
+        self.url = url;
+        self
+    }
+
+    pub fn get(self, url: &'a str) -> Self {
+        self.with(Method::GET, url)
+    }
+
+    pub fn head(self, url: &'a str) -> Self {
+        self.with(Method::HEAD, url)
+    }
+
+    pub fn delete(self, url: &'a str) -> Self {
+        self.with(Method::DELETE, url)
+    }
+
+    pub fn options(self, url: &'a str) -> Self {
+        self.with(Method::OPTIONS, url)
+    }
+
+    pub fn trace(self, url: &'a str) -> Self {
+        self.with(Method::TRACE, url)
+    }
+
+    /// Add a header.
+    ///
+    /// The header is validated when sending. The `Host` header is set from the
+    /// url, without the default port, unless added here. Adding more than
+    /// [`MAX_CALL_HEADERS`] results in `HootError::TooManyHeaders` on send.
+    pub fn header(mut self, name: &'a str, value: &'a str) -> Self {
+        if self.header_count < MAX_CALL_HEADERS {
+            self.headers[self.header_count] = (name, value);
+        }
+        // Count also overflowing headers to be able to error on send.
+        self.header_count += 1;
+        self
+    }
+
+    /// Write the request into `buf`.
+    ///
+    /// The returned [`Output`] holds the bytes to send, and continues into
+    /// the response via `into_response()`.
+    pub fn send(self, buf: &mut [u8]) -> Result<Output<'_, ENDED, (), (), ()>> {
+        if self.header_count > MAX_CALL_HEADERS {
+            return Err(HootError::TooManyHeaders);
+        }
+
+        let url = Url::parse_str(self.url).map_err(HootError::UrlError)?;
+        let path = url.path_and_query();
+        let headers = &self.headers[..self.header_count];
+
+        // A Host header overrides the one from the url.
+        let host = headers
+            .iter()
+            .find(|(name, _)| compare_lowercase_ascii(name, "host"))
+            .map(|(_, value)| *value)
+            .unwrap_or_else(|| url.host_header());
+        // The override skips header(), which checks the other values.
+        check_value(host.as_bytes())?;
+
+        trace!("Call {:?} {}", self.method, self.url);
+
+        let req = Request::new(buf).http_11();
+
+        let req = match self.method {
+            Method::GET => send_bodyless(req.get(host, path)?, headers)?,
+            Method::HEAD => send_bodyless(req.head(host, path)?, headers)?,
+            Method::DELETE => send_bodyless(req.delete(host, path)?, headers)?,
+            Method::OPTIONS => send_bodyless(req.options(host, path)?, headers)?,
+            Method::TRACE => send_bodyless(req.trace(host, path)?, headers)?,
+            // Methods with a request body are not supported by call().
+            _ => return Err(HootError::UnknownMethod),
+        };
+
+        Ok(req.flush())
+    }
+}
+
+fn send_bodyless<'b, M: MethodWithoutRequestBody>(
+    mut req: Request<'b, SEND_HEADERS, HTTP_11, M, ()>,
+    headers: &[(&str, &str)],
+) -> Result<Request<'b, ENDED, (), (), ()>> {
+    for (name, value) in headers {
+        if compare_lowercase_ascii(name, "host") {
+            continue;
+        }
+        req = req.header(name, value)?;
+    }
+    req.send()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn call_head() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = call().head("https://host.test").send(&mut buf)?;
+        assert_eq!(&*output, b"HEAD / HTTP/1.1\r\nHost: host.test\r\n\r\n");
+
+        // The fragment stays with the client.
+        let output = call().head("https://host.test/a?q#f?g").send(&mut buf)?;
+        assert_eq!(&*output, b"HEAD /a?q HTTP/1.1\r\nHost: host.test\r\n\r\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_host() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = call().get("https://host.test:443/").send(&mut buf)?;
+        assert_eq!(&*output, b"GET / HTTP/1.1\r\nHost: host.test\r\n\r\n");
+
+        let output = call()
+            .get("https://host.test:8443/")
+            .header("Host", "other.test")
+            .send(&mut buf)?;
+        assert_eq!(&*output, b"GET / HTTP/1.1\r\nHost: other.test\r\n\r\n");
+
+        let err = call()
+            .get("http://x.test/")
+            .header("Host", "a\r\nX-Evil: 1")
+            .send(&mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_bad_header() {
+        let mut buf = [0; 1024];
+
+        let err = call()
+            .get("https://host.test")
+            .header("content-length", "10")
+            .send(&mut buf)
+            .map(|_| ())
+            .unwrap_err();
+
+        assert_eq!(err, HootError::ForbiddenBodyHeader);
+    }
+
+    #[test]
+    fn call_too_many_headers() {
+        let mut buf = [0; 1024];
+
+        let mut c = call().get("https://host.test");
+        for _ in 0..=MAX_CALL_HEADERS {
+            c = c.header("x-foo", "bar");
+        }
+
+        let err = c.send(&mut buf).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::TooManyHeaders);
+    }
+
+    #[test]
+    fn call_method_with_body() {
+        let mut buf = [0; 1024];
+
+        let err = call()
+            .with(Method::POST, "https://host.test")
+            .send(&mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UnknownMethod);
+    }
+}
@@ -4,6 +4,7 @@
 //!
 //! ```
 //! use hoot::client::Request;
+//! use hoot::util::HeaderBuf;
 //! use hoot::HttpVersion;
 //!
 //! let mut buf = [0; 1024];
@@ -69,16 +70,16 @@
 //! let mut response = resume.into_response();
 //!
 //! // Try read incomplete input. The provided buffer is required to
-//! // parse response headers. The buffer can be the same as in the
-//! // request or another one.
-//! let attempt = response.try_read_response(b"HTTP/1.", &mut buf)?;
+//! // parse response headers, it holds up to 16 of them.
+//! let mut headers = HeaderBuf::<16>::new();
+//! let attempt = response.try_read_response(b"HTTP/1.", &mut headers)?;
 //! assert!(!attempt.is_success());
 //!
 //! const COMPLETE: &[u8] =
 //!     b"HTTP/1.1 200 OK\r\nHost: foo\r\nContent-Length: 10\r\n\r\n";
 //!
 //! // Try read complete input (and succeed). Borrow the buffer again.
-//! let attempt = response.try_read_response(COMPLETE, &mut buf)?;
+//! let attempt = response.try_read_response(COMPLETE, &mut headers)?;
 //! assert!(attempt.is_success());
 //!
 //! // Read status line information.
@@ -90,9 +91,9 @@
 //! // Read headers.
 //! let headers = attempt.headers().unwrap();
 //! assert_eq!(headers[0].name(), "Host");
-//! assert_eq!(headers[0].value(), "foo");
+//! assert_eq!(headers[0].value_str()?, "foo");
 //! assert_eq!(headers[1].name(), "Content-Length");
-//! assert_eq!(headers[1].value(), "10");
+//! assert_eq!(headers[1].value_str()?, "10");
 //!
 //! // Once done with status and headers we proceed to reading the body.
 //! let mut response = response.proceed();
@@ -140,3 +141,17 @@ pub use req::{Output, Request, ResumeToken};
 
 mod res;
 pub use res::{Response, Status};
+
+mod reader;
+pub use reader::BodyReader;
+
+mod upgrade;
+pub use upgrade::{Protocol, Protocols};
+
+mod sign;
+pub use sign::{CanonicalRequest, ChunkSigner, RequestSigner};
+
+#[cfg(feature = "call")]
+mod call;
+#[cfg(feature = "call")]
+pub use call::{call, Call, MAX_CALL_HEADERS};
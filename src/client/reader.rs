@@ -0,0 +1,301 @@
+use crate::types::state::*;
+use crate::{HootError, Limit, Result};
+
+use super::Response;
+
+/// Pull decoded response body from a transport.
+///
+/// Reading a body with `Response::read_body()` means keeping track of which
+/// input is used, and appending more input to what is left. `BodyReader` does
+/// that bookkeeping in `scratch`, reading more with `read` when needed.
+///
+/// `read` fills the buffer it is given from the transport, returning the
+/// amount. `0` means the connection closed, which ends a close delimited
+/// body, and is an error for others.
+///
+/// ```
+/// use hoot::client::BodyReader;
+/// use hoot::util::HeaderBuf;
+/// # use hoot::client::Request;
+/// # let mut buf = [0; 1024];
+/// # let output = Request::new(&mut buf).http_11().get("myhost.test", "/")?.send()?.flush();
+/// # let mut response = output.into_response();
+///
+/// const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+///     5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+///
+/// // Input arriving a few bytes at a time.
+/// let mut transport = INPUT.chunks(7);
+///
+/// let mut input = [0; 64];
+/// let mut len = 0;
+/// let used = loop {
+///     let more = transport.next().unwrap();
+///     input[len..len + more.len()].copy_from_slice(more);
+///     len += more.len();
+///
+///     let mut headers = HeaderBuf::<16>::new();
+///     let attempt = response.try_read_response(&input[..len], &mut headers)?;
+///     if attempt.is_success() {
+///         break attempt.input_used();
+///     }
+/// };
+///
+/// let mut scratch = [0; 16];
+/// let mut reader = BodyReader::new(response.proceed(), &mut scratch, |buf: &mut [u8]| {
+///     let more = transport.next().unwrap_or(&[]);
+///     buf[..more.len()].copy_from_slice(more);
+///     Ok(more.len())
+/// })
+/// .with_input(&input[used..len])?;
+///
+/// let mut body = [0; 64];
+/// let mut n = 0;
+/// loop {
+///     let amount = reader.read(&mut body[n..])?;
+///     if amount == 0 {
+///         break;
+///     }
+///     n += amount;
+/// }
+///
+/// assert_eq!(&body[..n], b"hello world");
+/// assert!(reader.is_finished());
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub struct BodyReader<'s, R> {
+    response: Response<RECV_BODY>,
+    scratch: &'s mut [u8],
+    read: R,
+    start: usize,
+    end: usize,
+}
+
+impl<'s, R> BodyReader<'s, R>
+where
+    R: FnMut(&mut [u8]) -> Result<usize>,
+{
+    pub fn new(response: Response<RECV_BODY>, scratch: &'s mut [u8], read: R) -> Self {
+        BodyReader {
+            response,
+            scratch,
+            read,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Start with input already read, such as what followed the response head.
+    ///
+    /// Errors with `HootError::OutputOverflow` if `input` doesn't fit in the
+    /// scratch buffer.
+    pub fn with_input(mut self, input: &[u8]) -> Result<Self> {
+        let free = self.scratch.len() - self.end;
+        if input.len() > free {
+            return Err(HootError::OutputOverflow {
+                needed: input.len() - free,
+            });
+        }
+
+        self.scratch[self.end..self.end + input.len()].copy_from_slice(input);
+        self.end += input.len();
+
+        Ok(self)
+    }
+
+    /// Read decoded body into `dst`, returning the amount.
+    ///
+    /// `0` means the body is finished.
+    pub fn read(&mut self, dst: &mut [u8]) -> Result<usize> {
+        if dst.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if self.response.is_finished() {
+                return Ok(0);
+            }
+
+            let src = &self.scratch[self.start..self.end];
+            let part = self.response.read_body(src, dst)?;
+            self.start += part.input_used();
+
+            let amount = part.data().len();
+            if amount > 0 {
+                return Ok(amount);
+            }
+
+            if part.input_used() > 0 {
+                // Framing such as a chunk size line.
+                continue;
+            }
+
+            self.read_more()?;
+        }
+    }
+
+    fn read_more(&mut self) -> Result<()> {
+        // Move what's left to the start, to make room.
+        self.scratch.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+
+        if self.end == self.scratch.len() {
+            // Only a chunk size line does not make progress on input.
+            return Err(HootError::LimitExceeded {
+                which: Limit::ChunkLine,
+            });
+        }
+
+        let amount = (self.read)(&mut self.scratch[self.end..])?;
+
+        if amount == 0 {
+            self.response.closed()
+        } else {
+            self.end += amount;
+            Ok(())
+        }
+    }
+
+    /// Whether the body is finished.
+    pub fn is_finished(&self) -> bool {
+        self.response.is_finished()
+    }
+
+    /// The response and the input that is not used, such as trailers.
+    pub fn into_inner(self) -> (Response<RECV_BODY>, &'s [u8]) {
+        let scratch: &'s [u8] = self.scratch;
+        (self.response, &scratch[self.start..self.end])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> std::io::Read for BodyReader<'_, R>
+where
+    R: FnMut(&mut [u8]) -> Result<usize>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        BodyReader::read(self, buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::util::HeaderBuf;
+    use std::io::Read;
+
+    fn response(head: &[u8]) -> (Response<RECV_BODY>, usize) {
+        let mut r = Response::new_test();
+        let mut buf = HeaderBuf::<16>::new();
+        let used = r.try_read_response(head, &mut buf).unwrap().input_used();
+        (r.proceed(), used)
+    }
+
+    #[test]
+    fn read_length_delimited() -> Result<()> {
+        const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello world";
+        let (response, used) = response(INPUT);
+
+        let mut rest = &INPUT[used..];
+        let mut scratch = [0; 4];
+        let mut reader = BodyReader::new(response, &mut scratch, |buf: &mut [u8]| {
+            let n = buf.len().min(rest.len()).min(3);
+            buf[..n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            Ok(n)
+        });
+
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hello world");
+        assert!(reader.is_finished());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_close_delimited() -> Result<()> {
+        let (response, _) = response(b"HTTP/1.1 200 OK\r\n\r\n");
+
+        let mut rest: &[u8] = b"hello";
+        let mut scratch = [0; 16];
+        let mut reader = BodyReader::new(response, &mut scratch, |buf: &mut [u8]| {
+            let n = buf.len().min(rest.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            Ok(n)
+        });
+
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"hello");
+        assert!(reader.is_finished());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_closed_early() {
+        let (response, _) = response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n");
+
+        let mut scratch = [0; 16];
+        let mut reader = BodyReader::new(response, &mut scratch, |_: &mut [u8]| Ok(0))
+            .with_input(b"hello")
+            .unwrap();
+
+        let mut dst = [0; 16];
+        assert_eq!(reader.read(&mut dst).unwrap(), 5);
+        let err = reader.read(&mut dst).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::RecvLessThanContentLength {
+                expected: 10,
+                actual: 5
+            }
+        );
+    }
+
+    #[test]
+    fn read_chunk_line_too_long() {
+        let (response, _) = response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+
+        let mut scratch = [0; 4];
+        let mut reader = BodyReader::new(response, &mut scratch, |buf: &mut [u8]| {
+            buf.fill(b'0');
+            Ok(buf.len())
+        });
+
+        let mut dst = [0; 16];
+        let err = reader.read(&mut dst).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::LimitExceeded {
+                which: Limit::ChunkLine
+            }
+        );
+    }
+
+    #[test]
+    fn leftover_trailers() -> Result<()> {
+        const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2\r\nhi\r\n0\r\nX-Sum: 1\r\n\r\n";
+        let (response, used) = response(INPUT);
+
+        let mut scratch = [0; 64];
+        let mut reader = BodyReader::new(response, &mut scratch, |_: &mut [u8]| Ok(0))
+            .with_input(&INPUT[used..])?;
+
+        let mut dst = [0; 16];
+        assert_eq!(reader.read(&mut dst)?, 2);
+        assert_eq!(reader.read(&mut dst)?, 0);
+
+        let (mut response, rest) = reader.into_inner();
+        let mut buf = HeaderBuf::<16>::new();
+        let trailers = response.read_trailers(rest, &mut buf)?.unwrap();
+        assert_eq!(trailers.headers()[0].name(), "X-Sum");
+
+        Ok(())
+    }
+}
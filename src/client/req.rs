@@ -1,10 +1,16 @@
-use core::fmt::Write;
+use core::fmt;
 use core::marker::PhantomData;
 use core::mem;
-use core::ops::Deref;
-
-use crate::error::OVERFLOW;
-use crate::header::check_and_output_header;
+use core::ops::{Deref, Range};
+
+use crate::body::{hash_written, write_chunk_from_source, write_length_from_source};
+#[cfg(feature = "serde")]
+use crate::header::is_sent;
+use crate::header::{
+    check_and_output_header, check_raw_trailers, check_value, output_header_after, HeaderStyle,
+};
+#[cfg(feature = "serde")]
+use crate::json::{to_slice, write_json_body};
 use crate::out::{Out, Writer};
 use crate::types::body::*;
 use crate::types::method::*;
@@ -13,15 +19,18 @@ use crate::types::version::*;
 use crate::types::*;
 use crate::util::LengthChecker;
 use crate::Method as M;
-use crate::{CallState, HttpVersion};
-use crate::{HootError, Result};
+use crate::{BodyHasher, BodySource, BodyTransform, CallState, ContentEncoding, HttpVersion};
+use crate::{ExtMethod, HootError, RequestTarget, Result, Url};
 
-use super::Response;
+use super::sign::{write_signature, write_signed_chunk};
+use super::{ChunkSigner, RequestSigner, Response};
 
 pub struct Request<'a, S: State, V: Version, M: Method, B: BodyType> {
     typ: Typ<S, V, M, B>,
     state: CallState,
     out: Out<'a>,
+    // End of the head in out, if it ended in this buffer.
+    head_end: Option<usize>,
 }
 
 /// Zero sized struct only to hold type state.
@@ -41,6 +50,7 @@ pub struct ResumeToken<S: State, V: Version, M: Method, B: BodyType> {
 pub struct Output<'a, S: State, V: Version, M: Method, B: BodyType> {
     token: ResumeToken<S, V, M, B>,
     output: &'a [u8],
+    head_end: Option<usize>,
 }
 
 impl<'a> Request<'a, (), (), (), ()> {
@@ -50,15 +60,91 @@ impl<'a> Request<'a, (), (), (), ()> {
             typ,
             state: CallState::default(),
             out: Out::wrap(buf),
+            head_end: None,
         }
         .transition()
     }
+
+    /// Upper bound of the bytes a request writes besides the URL and headers.
+    ///
+    /// `url_len` is the length of the host and path, after any percent
+    /// encoding. The bound covers the request line, the framing of the `Host`
+    /// header and `n_headers` headers, and the head ending written when
+    /// starting the body. It excludes the header names and values, and the
+    /// body itself.
+    ///
+    /// This is a `const fn`, to check buffer sizes at compile time.
+    ///
+    /// ```
+    /// use hoot::client::Request;
+    /// use hoot::Method;
+    ///
+    /// const HOST_AND_PATH: usize = "example.test/data".len();
+    /// const HEADERS: usize = "Accept".len() + "text/plain".len();
+    /// const NEEDED: usize = Request::max_overhead(Method::GET, HOST_AND_PATH, 1) + HEADERS;
+    ///
+    /// let mut buf = [0; NEEDED];
+    /// Request::new(&mut buf)
+    ///     .http_11()
+    ///     .get("example.test", "/data")?
+    ///     .header("Accept", "text/plain")?
+    ///     .send()?;
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub const fn max_overhead(method: M, url_len: usize, n_headers: usize) -> usize {
+        // The longest head ending, see `json::write_json_body()` and `send_encoded()`.
+        const HEAD_END: usize =
+            "Content-Type: application/json\r\nContent-Length: 18446744073709551615\r\n\r\n".len();
+
+        method.name_len()
+            + url_len
+            + "  HTTP/1.1\r\nHost: \r\n".len()
+            + n_headers * ": \r\n".len()
+            + HEAD_END
+    }
 }
 
 impl<'a, S: State, V: Version, M: Method, B: BodyType> Request<'a, S, V, M, B> {
     fn transition<S2: State, V2: Version, M2: Method, B2: BodyType>(
         self,
     ) -> Request<'a, S2, V2, M2, B2> {
+        self.transition_with_body(0)
+    }
+
+    /// Transition after writing the end of a head with a body, of which `body`
+    /// bytes are already written. The head is padded for `align_body()`.
+    fn end_head<S2: State, V2: Version, M2: Method, B2: BodyType>(
+        mut self,
+        body: usize,
+    ) -> Result<Request<'a, S2, V2, M2, B2>> {
+        let mut head_end = self.out.as_bytes().len() - body;
+
+        if let Some(align) = self.state.body_align {
+            let pad = (align - head_end % align) % align;
+
+            // Optional whitespace after the last header value, before the
+            // "\r\n\r\n" ending the head. Framing headers are always last.
+            self.out.insert_spaces(head_end - 4, pad)?;
+            head_end += pad;
+
+            trace!("Aligned body at: {}", head_end);
+        }
+
+        self.head_end = Some(head_end);
+
+        Ok(self.transition_with_body(body))
+    }
+
+    /// Transition where the output ends with `body` bytes written together
+    /// with the end of the head.
+    #[allow(unused_mut, unused_variables)]
+    fn transition_with_body<S2: State, V2: Version, M2: Method, B2: BodyType>(
+        mut self,
+        body: usize,
+    ) -> Request<'a, S2, V2, M2, B2> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::transition::<S, S2>(&mut self.state, self.out.as_bytes().len(), body);
+
         trace!(
             "Transition: {}/{}/{} -> {}/{}/{}",
             S::state_name(),
@@ -73,21 +159,65 @@ impl<'a, S: State, V: Version, M: Method, B: BodyType> Request<'a, S, V, M, B> {
         unsafe { mem::transmute(self) }
     }
 
-    fn header_raw(mut self, name: &str, bytes: &[u8], trailer: bool) -> Result<Self> {
+    fn header_raw(self, name: &str, bytes: &[u8], trailer: bool) -> Result<Self> {
+        self.header_write(name, bytes, trailer, true)
+    }
+
+    fn header_write(
+        mut self,
+        name: &str,
+        bytes: &[u8],
+        trailer: bool,
+        validate: bool,
+    ) -> Result<Self> {
         // Attempt writing the header
+        let style = HeaderStyle::of(V::version(), &self.state);
         let w = self.out.writer();
-        check_and_output_header(w, V::version(), name, bytes, trailer)?;
+        let sent = &mut self.state.sent_singular;
+        check_and_output_header(w, style, name, bytes, trailer, validate, sent)?;
         Ok(self)
     }
 
-    pub fn flush(self) -> Output<'a, S, V, M, B> {
+    /// Output in the buffer that is not yet flushed.
+    pub fn pending(&self) -> &[u8] {
+        self.out.as_bytes()
+    }
+
+    /// Tell that the first `n` bytes of `pending()` are written to the transport.
+    ///
+    /// This frees up the buffer without `flush()` and `resume()`, which keeps
+    /// the type state. Output after the `n` bytes is moved to the start of the
+    /// buffer.
+    ///
+    /// More than `pending()` is `HootError::BufferOverrun`, and nothing is
+    /// flushed.
+    pub fn flushed(&mut self, n: usize) -> Result<()> {
+        trace!("Flushed: {}", n);
+        self.out.consume(n)?;
+        if n > 0 {
+            self.head_end = None;
+        }
+        #[cfg(feature = "metrics")]
+        {
+            self.state.sent_len += n as u64;
+        }
+        Ok(())
+    }
+
+    #[allow(unused_mut)]
+    pub fn flush(mut self) -> Output<'a, S, V, M, B> {
         trace!("Flush");
+        #[cfg(feature = "metrics")]
+        {
+            self.state.sent_len += self.out.as_bytes().len() as u64;
+        }
         Output {
             token: ResumeToken {
                 typ: self.typ,
                 state: self.state,
             },
             output: self.out.into_inner(),
+            head_end: self.head_end,
         }
     }
 
@@ -102,6 +232,7 @@ impl<'a, S: State, V: Version, M: Method, B: BodyType> Request<'a, S, V, M, B> {
             typ: token.typ,
             state: token.state,
             out: Out::wrap(buf),
+            head_end: None,
         }
     }
 }
@@ -112,7 +243,16 @@ impl<'a, S: State, V: Version, M: Method, B: BodyType> Output<'a, S, V, M, B> {
     }
 
     pub fn as_bytes(&self) -> &[u8] {
-        &self.output
+        self.output
+    }
+
+    /// The span of the request head in the output, if the head ended in it
+    /// followed by a body.
+    ///
+    /// The span starts at `0`, unless output was flushed before the head
+    /// ended, and the body follows right after. See `Request::align_body()`.
+    pub fn head_span(&self) -> Option<Range<usize>> {
+        self.head_end.map(|end| 0..end)
     }
 }
 
@@ -120,7 +260,7 @@ impl<'a, S: State, V: Version, M: Method, B: BodyType> Deref for Output<'a, S, V
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &self.output
+        self.output
     }
 }
 
@@ -130,6 +270,38 @@ impl<S: State, V: Version, M: Method, B: BodyType> ResumeToken<S, V, M, B> {
     }
 }
 
+#[cfg(feature = "suspend")]
+impl<S: State, V: Version, M: Method, B: BodyType> ResumeToken<S, V, M, B> {
+    fn typestate_id() -> u32 {
+        let names = [
+            "client",
+            S::state_name(),
+            V::state_name(),
+            M::state_name(),
+            B::state_name(),
+        ];
+        crate::suspend::typestate_id(&names)
+    }
+
+    /// Write the token into `dst`, see [`suspend`](crate::suspend).
+    ///
+    /// Returns the length, which is always `RESUME_TOKEN_LEN`.
+    pub fn write_to(&self, dst: &mut [u8]) -> Result<usize> {
+        crate::suspend::write_state(&self.state, Self::typestate_id(), dst)
+    }
+
+    /// Restore a token written by `write_to()`.
+    ///
+    /// Fails with `HootError::ResumeToken` if the bytes are not a token of this
+    /// type state.
+    pub fn read_from(src: &[u8]) -> Result<Self> {
+        Ok(ResumeToken {
+            typ: Typ(PhantomData, PhantomData, PhantomData, PhantomData),
+            state: crate::suspend::read_state(src, Self::typestate_id())?,
+        })
+    }
+}
+
 impl<'a> Request<'a, INIT, (), (), ()> {
     pub fn http_10(mut self) -> Request<'a, SEND_LINE, HTTP_10, (), ()> {
         trace!("HTTP/1.0");
@@ -142,6 +314,71 @@ impl<'a> Request<'a, INIT, (), (), ()> {
         self.state.version = Some(HttpVersion::Http11);
         self.transition()
     }
+
+    /// Percent-encode spaces and control characters in the request target.
+    ///
+    /// By default such targets are rejected with `HootError::InvalidTarget`. With
+    /// encoding, a `#fragment` is dropped since it must never be sent.
+    pub fn percent_encode_target(mut self) -> Self {
+        trace!("Percent-encode target");
+        self.state.percent_encode_target = true;
+        self
+    }
+
+    /// Write header names in canonical case, such as `Content-Type` for
+    /// `content-type`.
+    ///
+    /// For peers and signature schemes sensitive to case. See the
+    /// [crate docs](crate#header-order) for the order headers are written in.
+    pub fn canonical_header_names(mut self) -> Self {
+        trace!("Canonical header names");
+        self.state.canonical_header_names = true;
+        self
+    }
+
+    /// Start the body at an offset in the buffer that is a multiple of
+    /// `align`, such as for a DMA descriptor pointing straight into it.
+    ///
+    /// The last header line of the head is padded with spaces, which is
+    /// allowed whitespace before the line end. The head is contiguous from the
+    /// start of the buffer as long as it is not flushed before the body, and
+    /// `Output::head_span()` tells where it ends. For bodies sent in several
+    /// outputs, only the first part is aligned.
+    ///
+    /// An `align` of `0` or `1` means no alignment.
+    ///
+    /// ```
+    /// use hoot::client::Request;
+    ///
+    /// let mut buf = [0; 1024];
+    /// let mut request = Request::new(&mut buf)
+    ///     .align_body(64)
+    ///     .http_11()
+    ///     .post("x.test", "/upload")?
+    ///     .with_body(5)?;
+    /// request.write_bytes(b"hello")?;
+    /// let output = request.flush();
+    ///
+    /// let head = output.head_span().unwrap();
+    /// assert_eq!(head, 0..64);
+    /// assert!(output[..64].ends_with(b"Content-Length: 5      \r\n\r\n"));
+    /// assert_eq!(&output[64..], b"hello");
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn align_body(mut self, align: usize) -> Self {
+        trace!("Align body: {}", align);
+        self.state.body_align = Some(align).filter(|a| *a > 1);
+        self
+    }
+
+    /// Set the id of this exchange in [metrics](crate::metrics) events.
+    ///
+    /// The id carries over to the response.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics_id(mut self, id: u32) -> Self {
+        self.state.metrics_id = id;
+        self
+    }
 }
 
 macro_rules! write_line_10 {
@@ -150,16 +387,20 @@ macro_rules! write_line_10 {
             mut self,
             path: &str,
         ) -> Result<Request<'a, SEND_HEADERS, HTTP_10, $meth_up, ()>> {
-            write_line_10(self.out.writer(), stringify!($meth_up), path)?;
+            let encode = self.state.percent_encode_target;
+            write_line_10(self.out.writer(), M::$meth_up, path, encode)?;
             self.state.method = Some(M::$meth_up);
             Ok(self.transition())
         }
     };
 }
 
-fn write_line_10(mut w: Writer<'_, '_>, method: &str, path: &str) -> Result<()> {
-    trace!("{} {} HTTP/1.0", method, path);
-    write!(w, "{} {} HTTP/1.0\r\n", method, path).or(OVERFLOW)?;
+fn write_line_10(mut w: Writer<'_, '_>, method: M, path: &str, encode: bool) -> Result<()> {
+    trace!("{:?} {} HTTP/1.0", method, path);
+    RequestTarget::parse(method, path)?;
+    write!(w, "{:?} ", method)?;
+    write_target(&mut w, path, encode)?;
+    write!(w, " HTTP/1.0\r\n")?;
     w.commit();
     Ok(())
 }
@@ -171,24 +412,90 @@ macro_rules! write_line_11 {
             host: &str,
             path: &str,
         ) -> Result<Request<'a, SEND_HEADERS, HTTP_11, $meth_up, ()>> {
-            write_line_11(self.out.writer(), stringify!($meth_up), host, path)?;
+            let encode = self.state.percent_encode_target;
+            write_line_11(self.out.writer(), M::$meth_up, host, path, encode)?;
             self.state.method = Some(M::$meth_up);
             Ok(self.transition())
         }
     };
 }
 
-fn write_line_11(mut w: Writer<'_, '_>, method: &str, host: &str, path: &str) -> Result<()> {
-    trace!("{} {} HTTP/1.1", method, path);
-    write!(w, "{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, host).or(OVERFLOW)?;
+fn write_line_11(
+    mut w: Writer<'_, '_>,
+    method: M,
+    host: &str,
+    path: &str,
+    encode: bool,
+) -> Result<()> {
+    trace!("{:?} {} HTTP/1.1", method, path);
+    RequestTarget::parse(method, path)?;
+    // Same as for header(), Host must not inject more headers.
+    check_value(host.as_bytes())?;
+    write!(w, "{:?} ", method)?;
+    write_target(&mut w, path, encode)?;
+    write!(w, " HTTP/1.1\r\nHost: {}\r\n", host)?;
     w.commit();
     Ok(())
 }
 
+/// Write the request target, rejecting or percent-encoding bytes that must not
+/// be sent. A `#fragment` is dropped when encoding.
+fn write_target(w: &mut Writer<'_, '_>, path: &str, encode: bool) -> Result<()> {
+    let path = if encode {
+        path.split('#').next().unwrap_or("")
+    } else {
+        path
+    };
+
+    let bytes = path.as_bytes();
+    let mut start = 0;
+
+    for (i, b) in bytes.iter().enumerate() {
+        if !needs_encoding(*b) {
+            continue;
+        }
+
+        if !encode {
+            return Err(HootError::InvalidTarget { offset: i });
+        }
+
+        w.write_bytes(&bytes[start..i])?;
+        write!(w, "%{:02X}", b)?;
+        start = i + 1;
+    }
+
+    w.write_bytes(&bytes[start..])?;
+
+    Ok(())
+}
+
+#[inline(always)]
+fn needs_encoding(b: u8) -> bool {
+    b == b' ' || b == b'#' || b < 0x20 || b == 0x7f
+}
+
 impl<'a> Request<'a, SEND_LINE, HTTP_10, (), ()> {
     write_line_10!(get, GET);
     write_line_10!(head, HEAD);
     write_line_10!(post, POST);
+    write_line_10!(put, PUT);
+    write_line_10!(delete, DELETE);
+    write_line_10!(options, OPTIONS);
+    write_line_10!(trace, TRACE);
+    write_line_10!(patch, PATCH);
+
+    /// Request with a method outside the standard set, with or without a body.
+    pub fn extension(
+        mut self,
+        method: ExtMethod,
+        path: &str,
+    ) -> Result<Request<'a, SEND_HEADERS, HTTP_10, EXTENSION, ()>> {
+        let encode = self.state.percent_encode_target;
+        let method = M::Extension(method);
+        write_line_10(self.out.writer(), method, path, encode)?;
+        self.state.method = Some(method);
+        Ok(self.transition())
+    }
 }
 
 impl<'a> Request<'a, SEND_LINE, HTTP_11, (), ()> {
@@ -201,6 +508,82 @@ impl<'a> Request<'a, SEND_LINE, HTTP_11, (), ()> {
     write_line_11!(options, OPTIONS);
     write_line_11!(trace, TRACE);
     write_line_11!(patch, PATCH);
+    write_line_11!(query, QUERY);
+    write_line_11!(propfind, PROPFIND);
+    write_line_11!(proppatch, PROPPATCH);
+    write_line_11!(mkcol, MKCOL);
+    write_line_11!(copy, COPY);
+    write_line_11!(r#move, MOVE);
+    write_line_11!(lock, LOCK);
+    write_line_11!(unlock, UNLOCK);
+
+    /// Request with a method outside the standard set, with or without a body.
+    pub fn extension(
+        mut self,
+        method: ExtMethod,
+        host: &str,
+        path: &str,
+    ) -> Result<Request<'a, SEND_HEADERS, HTTP_11, EXTENSION, ()>> {
+        let encode = self.state.percent_encode_target;
+        let method = M::Extension(method);
+        write_line_11(self.out.writer(), method, host, path, encode)?;
+        self.state.method = Some(method);
+        Ok(self.transition())
+    }
+}
+
+macro_rules! url_request {
+    ($name:ident, $meth:ident, $meth_up:tt) => {
+        #[doc = concat!("Start an HTTP/1.1 `", stringify!($meth_up), "` request to `url`.")]
+        pub fn $name(
+            buf: &'a mut [u8],
+            url: &str,
+        ) -> Result<Request<'a, SEND_HEADERS, HTTP_11, $meth_up, ()>> {
+            let url = Url::parse_str(url).map_err(HootError::UrlError)?;
+            Request::new(buf)
+                .http_11()
+                .$meth(url.host_header(), url.path_and_query())
+        }
+    };
+}
+
+/// Requests from a full URL, such as `http://example.test/path?q=1`.
+///
+/// The method is in the type state, so whether a body is sent is checked when
+/// compiling. A `GET` has no body to write:
+///
+/// ```compile_fail
+/// # use hoot::client::Request;
+/// let mut buf = [0; 1024];
+/// Request::get_url(&mut buf, "http://example.test/")?.with_body(5)?;
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+///
+/// While a `POST` has:
+///
+/// ```
+/// # use hoot::client::Request;
+/// let mut buf = [0; 1024];
+/// let output = Request::post_url(&mut buf, "http://example.test/items")?
+///     .send_body_full(b"hello")?
+///     .flush();
+///
+/// assert_eq!(
+///     &*output,
+///     b"POST /items HTTP/1.1\r\nHost: example.test\r\nContent-Length: 5\r\n\r\nhello"
+/// );
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+impl<'a> Request<'a, (), (), (), ()> {
+    url_request!(get_url, get, GET);
+    url_request!(head_url, head, HEAD);
+    url_request!(post_url, post, POST);
+    url_request!(put_url, put, PUT);
+    url_request!(delete_url, delete, DELETE);
+    url_request!(options_url, options, OPTIONS);
+    url_request!(trace_url, trace, TRACE);
+    url_request!(patch_url, patch, PATCH);
+    url_request!(query_url, query, QUERY);
 }
 
 impl<'a, M: Method, V: Version> Request<'a, SEND_HEADERS, V, M, ()> {
@@ -213,6 +596,118 @@ impl<'a, M: Method, V: Version> Request<'a, SEND_HEADERS, V, M, ()> {
         trace!("Set header bytes {}: {:?}", name, bytes);
         self.header_raw(name, bytes, false)
     }
+
+    /// Set a header without validating the name and value.
+    ///
+    /// For trusted constants only. Values from user input must use `header()`,
+    /// which rejects control characters that could inject headers. Forbidden
+    /// and repeated headers are still rejected.
+    pub fn header_unchecked(self, name: &str, value: &str) -> Result<Self> {
+        trace!("Set header unchecked {}: {}", name, value);
+        self.header_write(name, value.as_bytes(), false, false)
+    }
+
+    /// Sign the request, setting the header `name` to the value from `signer`.
+    ///
+    /// The signer gets a [`CanonicalRequest`](super::CanonicalRequest) of the
+    /// head written so far, with the values of the `signed` headers and
+    /// `body_hash`. That could be the hex SHA-256 of the body, or
+    /// `UNSIGNED-PAYLOAD` for AWS SigV4. Headers set after this are not signed,
+    /// so set those the signature covers first, such as `x-amz-date`.
+    ///
+    /// The entire head must still be in the buffer, that is, not flushed since
+    /// the request line. Fails with `HootError::Sign` otherwise, or if a header
+    /// in `signed` is not set.
+    ///
+    /// ```
+    /// use core::fmt;
+    /// use hoot::client::{CanonicalRequest, Request, RequestSigner};
+    ///
+    /// struct Hmac;
+    ///
+    /// impl RequestSigner for Hmac {
+    ///     fn sign(&mut self, req: &CanonicalRequest<'_>, out: &mut dyn fmt::Write) -> fmt::Result {
+    ///         // A real signer hashes these with its key.
+    ///         write!(out, "HMAC {} {}", req.method(), req.path())?;
+    ///         for name in req.signed_headers() {
+    ///             write!(out, " {}={}", name, req.header(name).unwrap())?;
+    ///         }
+    ///         write!(out, " {}", req.body_hash())
+    ///     }
+    /// }
+    ///
+    /// let mut buf = [0; 1024];
+    /// let output = Request::new(&mut buf)
+    ///     .http_11()
+    ///     .get("x.test", "/a?b=1")?
+    ///     .header("x-date", "20150830T123600Z")?
+    ///     .sign("Signature", &["host", "x-date"], "UNSIGNED-PAYLOAD", &mut Hmac)?
+    ///     .send()?
+    ///     .flush();
+    ///
+    /// assert!(output.ends_with(
+    ///     b"Signature: HMAC GET /a host=x.test x-date=20150830T123600Z UNSIGNED-PAYLOAD\r\n\r\n"
+    /// ));
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn sign(
+        mut self,
+        name: &str,
+        signed: &[&str],
+        body_hash: &str,
+        signer: &mut dyn RequestSigner,
+    ) -> Result<Self> {
+        trace!("Sign request: {}", name);
+
+        let style = HeaderStyle::of(V::version(), &self.state);
+        let w = self.out.writer();
+        let sent = &mut self.state.sent_singular;
+        output_header_after(w, style, name, sent, |head, w| {
+            write_signature(head, signed, body_hash, signer, w)
+        })?;
+
+        Ok(self)
+    }
+}
+
+impl<'a, M: Method> Request<'a, SEND_HEADERS, HTTP_11, M, ()> {
+    /// Offer to upgrade the connection to `protocols`, such as `websocket` or
+    /// `TLS/1.2`.
+    ///
+    /// Sends `Upgrade` along with `Connection: Upgrade`. This is how to retry
+    /// a request refused with `426 Upgrade Required`, see
+    /// `ResponseAttempt::upgrade_required()`. A server accepting answers with
+    /// `101 Switching Protocols`, see `ResponseAttempt::switched_to()`.
+    /// Protocol specific headers, such as `Sec-WebSocket-Key`, are set with
+    /// `header()`.
+    pub fn upgrade(self, protocols: &str) -> Result<Self> {
+        trace!("Offer upgrade: {}", protocols);
+        self.header("Connection", "Upgrade")?
+            .header("Upgrade", protocols)
+    }
+
+    /// Offer to upgrade the connection to h2c.
+    ///
+    /// `settings` is the base64url encoded payload of the SETTINGS frame that
+    /// starts HTTP/2. A server accepting answers with a `101`, see
+    /// `Response::switch_to_h2c()`. Otherwise the response is HTTP/1.1.
+    pub fn h2c_upgrade(self, settings: &str) -> Result<Self> {
+        trace!("Offer h2c upgrade");
+        self.header("Connection", "Upgrade, HTTP2-Settings")?
+            .header("Upgrade", "h2c")?
+            .header("HTTP2-Settings", settings)
+    }
+}
+
+impl<'a, M: Method> Request<'a, SEND_HEADERS, HTTP_10, M, ()> {
+    /// Ask the server to keep the connection open with `Connection: keep-alive`.
+    ///
+    /// HTTP/1.0 connections close after the response otherwise. Whether the
+    /// server agreed is in `ResponseAttempt::must_close()`.
+    pub fn keep_alive(self) -> Result<Self> {
+        trace!("Keep-alive");
+        self.header_raw("Connection", b"keep-alive", false)
+    }
 }
 
 impl<'a, M: MethodWithRequestBody> Request<'a, SEND_HEADERS, HTTP_10, M, ()> {
@@ -225,19 +720,19 @@ impl<'a, M: MethodWithRequestBody> Request<'a, SEND_HEADERS, HTTP_10, M, ()> {
         trace!("Length delimited body: {}", length);
 
         let mut w = self.out.writer();
-        write!(w, "Content-Length: {}\r\n\r\n", length).or(OVERFLOW)?;
+        write!(w, "Content-Length: {}\r\n\r\n", length)?;
         w.commit();
 
         self.state.send_checker = Some(LengthChecker::new(length));
 
-        Ok(self.transition())
+        self.end_head(0)
     }
 
-    pub fn without_body(mut self) -> Result<Request<'a, RECV_RESPONSE, HTTP_11, M, ()>> {
+    pub fn without_body(mut self) -> Result<Request<'a, RECV_RESPONSE, HTTP_10, M, ()>> {
         trace!("Without body");
 
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
         w.commit();
 
         Ok(self.transition())
@@ -254,29 +749,209 @@ impl<'a, M: MethodWithRequestBody> Request<'a, SEND_HEADERS, HTTP_11, M, ()> {
         trace!("Length delimited body: {}", length);
 
         let mut w = self.out.writer();
-        write!(w, "Content-Length: {}\r\n\r\n", length).or(OVERFLOW)?;
+        write!(w, "Content-Length: {}\r\n\r\n", length)?;
         w.commit();
 
         self.state.send_checker = Some(LengthChecker::new(length));
 
-        Ok(self.transition())
+        self.end_head(0)
     }
 
     pub fn with_chunked(mut self) -> Result<Request<'a, SEND_BODY, HTTP_11, M, BODY_CHUNKED>> {
         trace!("Chunked body");
 
         let mut w = self.out.writer();
-        write!(w, "Transfer-Encoding: chunked\r\n\r\n").or(OVERFLOW)?;
+        write!(w, "Transfer-Encoding: chunked\r\n\r\n")?;
         w.commit();
 
-        Ok(self.transition())
+        self.end_head(0)
+    }
+
+    /// Chunked body encoded (compressed) by a [`BodyTransform`].
+    ///
+    /// Since the encoded length is unknown up front, this always uses chunked
+    /// transfer. Write the body using `write_chunk_with()` and `end_chunks_with()`.
+    pub fn with_encoded_chunked(
+        mut self,
+        encoding: ContentEncoding,
+        transform: &mut dyn BodyTransform,
+    ) -> Result<Request<'a, SEND_BODY, HTTP_11, M, BODY_CHUNKED>> {
+        let name = select_encoding(encoding, transform)?;
+
+        trace!("Chunked body encoded: {}", name);
+
+        let mut w = self.out.writer();
+        write!(
+            w,
+            "Content-Encoding: {}\r\nTransfer-Encoding: chunked\r\n\r\n",
+            name
+        )?;
+        w.commit();
+
+        self.end_head(0)
     }
 
     pub fn without_body(mut self) -> Result<Request<'a, RECV_RESPONSE, HTTP_11, M, ()>> {
         trace!("Without body");
 
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
+        w.commit();
+
+        Ok(self.transition())
+    }
+}
+
+impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_HEADERS, V, M, ()> {
+    /// Send an entire body, with `Content-Length` set to its length.
+    ///
+    /// Same as `with_body()`, `write_bytes()` and `finish()`, except the entire
+    /// body must fit in the buffer.
+    pub fn send_body_full(mut self, body: &[u8]) -> Result<Request<'a, ENDED, (), (), ()>> {
+        trace!("Send full body: {}", body.len());
+
+        let mut w = self.out.writer();
+        write!(w, "Content-Length: {}\r\n\r\n", body.len())?;
+        w.write_bytes(body)?;
+        w.commit();
+
+        self.end_head(body.len())
+    }
+
+    /// Send an entire body encoded (compressed) by a [`BodyTransform`].
+    ///
+    /// The body is encoded straight into the output buffer, followed by a
+    /// `Content-Length` of the encoded size. The entire encoded body must
+    /// fit in the buffer, use `with_encoded_chunked()` for larger bodies.
+    pub fn send_encoded(
+        mut self,
+        body: &[u8],
+        encoding: ContentEncoding,
+        transform: &mut dyn BodyTransform,
+    ) -> Result<Request<'a, ENDED, (), (), ()>> {
+        let name = select_encoding(encoding, transform)?;
+
+        // Space reserved for the headers, which are written when we know the length.
+        const RESERVE: usize =
+            "Content-Encoding: compress\r\nContent-Length: 18446744073709551615\r\n\r\n".len();
+
+        let mut body_len = 0;
+        let mut w = self.out.writer();
+
+        w.write_with(|buf| {
+            if buf.len() < RESERVE {
+                return Err(HootError::OutputOverflow {
+                    needed: RESERVE - buf.len(),
+                });
+            }
+
+            let (head, rest) = buf.split_at_mut(RESERVE);
+            let n = encode_all(body, rest, transform)?;
+            body_len = n;
+
+            trace!("Encoded body {}: {} -> {}", name, body.len(), n);
+
+            let mut hout = Out::wrap(head);
+            let mut hw = hout.writer();
+            write!(
+                hw,
+                "Content-Encoding: {}\r\nContent-Length: {}\r\n\r\n",
+                name, n
+            )?;
+            hw.commit();
+            let head_len = hout.into_inner().len();
+
+            buf.copy_within(RESERVE..(RESERVE + n), head_len);
+
+            Ok(head_len + n)
+        })?;
+
+        w.commit();
+
+        self.end_head(body_len)
+    }
+}
+
+impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_HEADERS, V, M, ()> {
+    /// Send `value` serialized as JSON, with `Content-Length`.
+    ///
+    /// `Content-Type: application/json` is added unless set as a header. The
+    /// entire value must fit in the buffer, use `with_chunked()` and
+    /// `write_json()` to send larger bodies in parts.
+    #[cfg(feature = "serde")]
+    pub fn send_json<T: serde::Serialize + ?Sized>(
+        mut self,
+        value: &T,
+    ) -> Result<Request<'a, ENDED, (), (), ()>> {
+        let content_type = !is_sent(self.state.sent_singular, "content-type");
+
+        let mut w = self.out.writer();
+        let len = write_json_body(&mut w, value, content_type)?;
+        w.commit();
+
+        self.end_head(len)
+    }
+}
+
+fn select_encoding(
+    encoding: ContentEncoding,
+    transform: &mut dyn BodyTransform,
+) -> Result<&'static str> {
+    let name = encoding.as_str().ok_or(HootError::Compress)?;
+
+    if !transform.select(Some(encoding)) {
+        return Err(HootError::Compress);
+    }
+
+    Ok(name)
+}
+
+fn encode_all(mut body: &[u8], out: &mut [u8], transform: &mut dyn BodyTransform) -> Result<usize> {
+    let mut n = 0;
+
+    loop {
+        let amount = transform.input_capacity().min(body.len());
+        transform.push_input(&body[..amount])?;
+        body = &body[amount..];
+
+        let ended = body.is_empty();
+        let produced = transform.pull_output(&mut out[n..], ended)?;
+        n += produced;
+
+        if ended && transform.is_ended() {
+            return Ok(n);
+        }
+
+        if n == out.len() {
+            // How much more the encoding needs is unknown.
+            return Err(HootError::OutputOverflow { needed: 1 });
+        }
+
+        if amount == 0 && produced == 0 {
+            // The transform is stuck.
+            return Err(HootError::Compress);
+        }
+    }
+}
+
+impl<'a, V: Version, M: Method> Request<'a, SEND_HEADERS, V, M, ()> {
+    /// Send the request without a body.
+    ///
+    /// For methods that have a body, such as `POST`, this sends
+    /// `Content-Length: 0`. For others it is the same as `send()`.
+    pub fn send_empty(mut self) -> Result<Request<'a, ENDED, (), (), ()>> {
+        // The request line sets the method.
+        let method = self.state.method.ok_or(HootError::Internal)?;
+        let has_body = method.has_request_body();
+
+        trace!("Send empty, Content-Length: {}", has_body);
+
+        let mut w = self.out.writer();
+        if has_body {
+            write!(w, "Content-Length: 0\r\n\r\n")?;
+        } else {
+            write!(w, "\r\n")?;
+        }
         w.commit();
 
         Ok(self.transition())
@@ -287,43 +962,95 @@ impl<'a, V: Version, M: MethodWithoutRequestBody> Request<'a, SEND_HEADERS, V, M
     // TODO: Can we find a trait bound that allows us to call this without_body()?
     pub fn send(mut self) -> Result<Request<'a, ENDED, (), (), ()>> {
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
         w.commit();
 
         Ok(self.transition())
     }
+
+    /// Send a body even though the method has none, like `DELETE` with a body.
+    ///
+    /// Continue with `with_body()` or `with_chunked()`, which declare the body
+    /// as for methods with one. Servers may ignore or reject such bodies.
+    pub fn allow_body(self) -> Request<'a, SEND_HEADERS, V, ALLOW_BODY, ()> {
+        trace!("Allow body");
+        self.transition()
+    }
 }
 
 impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_BODY, V, M, BODY_LENGTH> {
     #[inline(always)]
-    fn checker(&mut self) -> &mut LengthChecker {
-        self.state
-            .send_checker
-            .as_mut()
-            // If we don't have the checker when in type state SEND_BODY, we got a bug.
-            .expect("SendByteCheck when SEND_BODY")
+    fn checker(&mut self) -> Result<&mut LengthChecker> {
+        // If we don't have the checker when in type state SEND_BODY, we got a bug.
+        self.state.send_checker.as_mut().ok_or(HootError::Internal)
     }
 
+    /// Write body bytes.
+    ///
+    /// If the output buffer can't hold all bytes, as much as fits is written and
+    /// this returns `HootError::OutputFull` with the amount. Call `.flush()` and
+    /// continue with the rest of the bytes after resuming.
     pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
         trace!("Write bytes len: {}", bytes.len());
 
         // This returns Err if we try to write more bytes than content-length.
-        self.checker()
-            .append(bytes.len(), HootError::SentMoreThanContentLength)?;
+        self.checker()?.check(bytes.len(), |expected, actual| {
+            HootError::SentMoreThanContentLength { expected, actual }
+        })?;
+
+        let n = bytes.len().min(self.out.available());
 
         let mut w = self.out.writer();
-        w.write_bytes(bytes)?;
+        w.write_bytes(&bytes[..n])?;
         w.commit();
 
+        self.checker()?
+            .append(n, |expected, actual| HootError::SentMoreThanContentLength {
+                expected,
+                actual,
+            })?;
+
+        if n < bytes.len() {
+            return Err(HootError::OutputFull(n));
+        }
+
         Ok(())
     }
 
+    /// Write body bytes like `write_bytes()`, feeding what is written to `hasher`.
+    pub fn write_bytes_hashed(&mut self, bytes: &[u8], hasher: &mut dyn BodyHasher) -> Result<()> {
+        let result = self.write_bytes(bytes);
+        hash_written(result, bytes, hasher)
+    }
+
+    /// Write body bytes read straight into the buffer from a [`BodySource`].
+    ///
+    /// Returns the amount written, which is 0 when the buffer is full or the
+    /// source has nothing right now. Call `finish()` once `Content-Length`
+    /// bytes are written.
+    pub fn write_body_from(&mut self, source: &mut dyn BodySource) -> Result<usize> {
+        let checker = self
+            .state
+            .send_checker
+            .as_mut()
+            .ok_or(HootError::Internal)?;
+
+        let mut w = self.out.writer();
+        let n = write_length_from_source(&mut w, checker, source)?;
+        w.commit();
+
+        trace!("Write body from source: {}", n);
+
+        Ok(n)
+    }
+
     pub fn finish(mut self) -> Result<Request<'a, ENDED, (), (), ()>> {
         trace!("Body finished");
 
         // This returns Err if we have written less than content-length.
-        self.checker()
-            .assert_expected(HootError::SentLessThanContentLength)?;
+        self.checker()?.assert_expected(|expected, actual| {
+            HootError::SentLessThanContentLength { expected, actual }
+        })?;
 
         Ok(self.transition())
     }
@@ -341,16 +1068,137 @@ impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_BODY, V, M, BODY
         let mut w = self.out.writer();
 
         // chunk length
-        write!(w, "{:0x?}\r\n", bytes.len()).or(OVERFLOW)?;
+        write!(w, "{:0x?}\r\n", bytes.len())?;
 
         // chunk
         w.write_bytes(bytes)?;
 
         // chunk end
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
+
+        w.commit();
+
+        Ok(self)
+    }
+
+    /// Encode bytes using a [`BodyTransform`] and write the output as a chunk.
+    ///
+    /// Used after `with_encoded_chunked()`. Returns how many of the `bytes` were
+    /// consumed, which can be less than all, or 0, if the transform or output
+    /// buffer is full. In that case `flush()` and write the rest after resuming.
+    pub fn write_chunk_with(
+        &mut self,
+        bytes: &[u8],
+        transform: &mut dyn BodyTransform,
+    ) -> Result<usize> {
+        let amount = transform.input_capacity().min(bytes.len());
+        transform.push_input(&bytes[..amount])?;
+
+        let mut w = self.out.writer();
+        let n = w.write_chunk_with(|buf| transform.pull_output(buf, false))?;
+        w.commit();
+
+        trace!("Write chunk with transform: {} -> {}", amount, n);
+
+        Ok(amount)
+    }
+
+    /// Write the remaining output of the transform after the last `write_chunk_with()`.
+    ///
+    /// Returns true when the transform is completely written. If false, `flush()`
+    /// and call again after resuming. Once true, call `finish()` or `with_trailer()`.
+    pub fn end_chunks_with(&mut self, transform: &mut dyn BodyTransform) -> Result<bool> {
+        let mut w = self.out.writer();
+        w.write_chunk_with(|buf| transform.pull_output(buf, true))?;
+        w.commit();
+
+        Ok(transform.is_ended())
+    }
+
+    /// Write bytes as a chunk, without consuming `self`.
+    ///
+    /// If the output buffer can't hold all bytes, as much as fits is written as
+    /// a chunk and this returns `HootError::OutputFull` with the amount. Call
+    /// `.flush()` and continue with the rest of the bytes after resuming.
+    pub fn write_chunk_partial(&mut self, bytes: &[u8]) -> Result<()> {
+        trace!("Write chunk partial len: {}", bytes.len());
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut n = 0;
+
+        let mut w = self.out.writer();
+        w.write_chunk_with(|buf| {
+            n = bytes.len().min(buf.len());
+            buf[..n].copy_from_slice(&bytes[..n]);
+            Ok(n)
+        })?;
+        w.commit();
+
+        if n < bytes.len() {
+            return Err(HootError::OutputFull(n));
+        }
+
+        Ok(())
+    }
+
+    /// Write a chunk like `write_chunk_partial()`, feeding what is written to `hasher`.
+    pub fn write_chunk_hashed(&mut self, bytes: &[u8], hasher: &mut dyn BodyHasher) -> Result<()> {
+        let result = self.write_chunk_partial(bytes);
+        hash_written(result, bytes, hasher)
+    }
+
+    /// Write bytes as a chunk with an extension from `signer`, such as the
+    /// chunk signatures of AWS SigV4 streaming uploads.
+    ///
+    /// The entire chunk must fit in the buffer, otherwise nothing is written.
+    /// End the body with `finish_signed()`, since the final chunk is signed
+    /// too.
+    pub fn write_chunk_signed(&mut self, bytes: &[u8], signer: &mut dyn ChunkSigner) -> Result<()> {
+        trace!("Write signed chunk len: {}", bytes.len());
+
+        // An empty chunk would end the body.
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut w = self.out.writer();
+        write_signed_chunk(&mut w, bytes, signer)?;
+        w.commit();
+        signer.written();
+
+        Ok(())
+    }
+
+    /// Write a chunk read straight into the buffer from a [`BodySource`].
+    ///
+    /// Returns the amount of body written, which is 0 when the buffer is full
+    /// or the source has nothing right now. Call `finish()` once the source
+    /// has ended.
+    pub fn write_body_from(&mut self, source: &mut dyn BodySource) -> Result<usize> {
+        let mut w = self.out.writer();
+        let n = write_chunk_from_source(&mut w, source, true)?;
+        w.commit();
+
+        trace!("Write chunk from source: {}", n);
+
+        Ok(n)
+    }
 
+    /// Write `value` serialized as JSON as one chunk.
+    ///
+    /// Fails with `HootError::OutputOverflow` if the value does not fit in the
+    /// buffer, in which case nothing is written.
+    #[cfg(feature = "serde")]
+    pub fn write_json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Result<Self> {
+        let mut w = self.out.writer();
+        let n = w.write_chunk_with(|buf| to_slice(value, buf))?;
         w.commit();
 
+        trace!("Write JSON chunk len: {}", n);
+
         Ok(self)
     }
 
@@ -358,7 +1206,7 @@ impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_BODY, V, M, BODY
         trace!("With trailer");
 
         let mut w = self.out.writer();
-        write!(w, "0\r\n").or(OVERFLOW)?;
+        write!(w, "0\r\n")?;
         w.commit();
 
         Ok(self.transition())
@@ -368,8 +1216,24 @@ impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_BODY, V, M, BODY
         trace!("Body chunks finished");
 
         let mut w = self.out.writer();
-        write!(w, "0\r\n\r\n").or(OVERFLOW)?;
+        write!(w, "0\r\n\r\n")?;
+        w.commit();
+
+        Ok(self.transition())
+    }
+
+    /// End the body with a final chunk signed by `signer`, after
+    /// `write_chunk_signed()`.
+    pub fn finish_signed(
+        mut self,
+        signer: &mut dyn ChunkSigner,
+    ) -> Result<Request<'a, ENDED, (), (), ()>> {
+        trace!("Signed body chunks finished");
+
+        let mut w = self.out.writer();
+        write_signed_chunk(&mut w, &[], signer)?;
         w.commit();
+        signer.written();
 
         Ok(self.transition())
     }
@@ -393,7 +1257,26 @@ impl<'a, V: Version, M: MethodWithRequestBody> Request<'a, SEND_TRAILER, V, M, B
         trace!("Trailer finish");
 
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
+        w.commit();
+
+        Ok(self.transition())
+    }
+
+    /// Write a raw trailer section, such as `Trailers::raw()` from a received body.
+    ///
+    /// The bytes are passed on exactly, after checking they are a complete trailer
+    /// section, including the ending empty line, without forbidden trailers. The
+    /// check needs some free space in the buffer to parse into.
+    pub fn finish_raw(mut self, raw: &[u8]) -> Result<Request<'a, ENDED, (), (), ()>> {
+        trace!("Trailer finish raw: {}", raw.len());
+
+        let mut w = self.out.writer();
+        w.write_bytes(raw)?;
+
+        let (written, _) = w.split_and_borrow();
+        check_raw_trailers(written)?;
+
         w.commit();
 
         Ok(self.transition())
@@ -412,34 +1295,55 @@ impl ResumeToken<ENDED, (), (), ()> {
     }
 }
 
-#[cfg(feature = "std")]
-mod std_impls {
-    use super::*;
-    use std::fmt;
-
-    impl<'a, S: State, V: Version, M: Method, B: BodyType> fmt::Debug for Request<'a, S, V, M, B> {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.debug_struct("Request").finish()
-        }
+impl<'a, S: State, V: Version, M: Method, B: BodyType> fmt::Debug for Request<'a, S, V, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("typ", &self.typ)
+            .field("state", &self.state)
+            .field("available", &self.out.available())
+            .finish()
     }
+}
 
-    impl fmt::Debug for CallState {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.debug_struct("CallState")
-                .field("method", &self.method)
-                .field("send_checker", &self.send_checker)
-                .field("recv_body_mode", &self.recv_body_mode)
-                .field("recv_checker", &self.recv_checker)
-                .field("did_read_to_end", &self.did_read_to_end)
-                .finish()
-        }
+impl<S: State, V: Version, M: Method, B: BodyType> fmt::Debug for ResumeToken<S, V, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResumeToken")
+            .field("typ", &self.typ)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<S: State, V: Version, M: Method, B: BodyType> fmt::Debug for Typ<S, V, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}/{}",
+            S::state_name(),
+            V::state_name(),
+            M::state_name(),
+            B::state_name()
+        )
+    }
+}
+
+/// Forks the request, see the `snapshot` feature.
+#[cfg(feature = "snapshot")]
+impl<S: State, V: Version, M: Method, B: BodyType> Clone for ResumeToken<S, V, M, B> {
+    fn clone(&self) -> Self {
+        ResumeToken {
+            typ: Typ(PhantomData, PhantomData, PhantomData, PhantomData),
+            state: self.state.clone(),
+        }
     }
 }
 
 #[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
-    use crate::HootError;
+    use crate::client::CanonicalRequest;
+    use crate::util::HeaderBuf;
+    use crate::{HootError, UrlError};
 
     #[test]
     pub fn test_illegal_header_name() -> Result<()> {
@@ -451,7 +1355,108 @@ mod test {
             .header(":bad:", "fine value");
 
         let e = x.unwrap_err();
-        assert_eq!(e, HootError::HeaderName);
+        assert_eq!(e, HootError::HeaderName { offset: 0 });
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_extension_method() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let mut x = Request::new(&mut buf)
+            .http_11()
+            .extension(ExtMethod::new("SEARCH")?, "myhost.test", "/path")?
+            .with_body(4)?;
+        x.write_bytes(b"<x/>")?;
+        let output = x.finish()?.flush();
+
+        assert_eq!(
+            &output[..],
+            b"SEARCH /path HTTP/1.1\r\nHost: myhost.test\r\nContent-Length: 4\r\n\r\n<x/>"
+        );
+
+        let mut buf = [0; 1024];
+        let output = Request::new(&mut buf)
+            .http_11()
+            .r#move("myhost.test", "/a")?
+            .header("Destination", "/b")?
+            .send()?
+            .flush();
+
+        assert_eq!(
+            &output[..],
+            b"MOVE /a HTTP/1.1\r\nHost: myhost.test\r\nDestination: /b\r\n\r\n"
+        );
+
+        let long = "X".repeat(crate::MAX_EXT_METHOD_LEN + 1);
+        for name in ["", "SEA RCH", &long] {
+            let e = ExtMethod::new(name).unwrap_err();
+            assert_eq!(e, HootError::UnknownMethod);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_allow_body() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .delete("myhost.test", "/items")?
+            .allow_body()
+            .with_chunked()?
+            .write_chunk(b"[1,2]")?
+            .finish()?
+            .flush();
+
+        assert_eq!(
+            &output[..],
+            b"DELETE /items HTTP/1.1\r\nHost: myhost.test\r\n\
+            Transfer-Encoding: chunked\r\n\r\n5\r\n[1,2]\r\n0\r\n\r\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_http10() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut x = Request::new(&mut buf)
+            .http_10()
+            .put("/path")?
+            .keep_alive()?
+            .with_body(2)?;
+        x.write_bytes(b"hi")?;
+        let output = x.finish()?.flush();
+
+        assert_eq!(
+            &output[..],
+            b"PUT /path HTTP/1.0\r\nConnection: keep-alive\r\nContent-Length: 2\r\n\r\nhi"
+        );
+
+        // A HTTP/1.1 response persists only if the server agrees to keep-alive.
+        let mut response = output.into_response();
+        let attempt = response.try_read_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(attempt.must_close());
+
+        let output = Request::new(&mut buf)
+            .http_10()
+            .get("/path")?
+            .send()?
+            .flush();
+        let mut response = output.into_response();
+        let attempt = response.try_read_response(
+            b"HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 0\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(!attempt.must_close());
 
         Ok(())
     }
@@ -466,7 +1471,7 @@ mod test {
             .header_bytes("x-broken", b"value\0xx");
 
         let e = x.unwrap_err();
-        assert_eq!(e, HootError::HeaderValue);
+        assert_eq!(e, HootError::HeaderValue { offset: 5 });
 
         Ok(())
     }
@@ -485,6 +1490,579 @@ mod test {
         Ok(())
     }
 
+    /// Test transform that uppercases via a tiny internal buffer.
+    struct Upper([u8; 4], usize, bool);
+
+    impl BodyTransform for Upper {
+        fn select(&mut self, encoding: Option<ContentEncoding>) -> bool {
+            encoding == Some(ContentEncoding::Gzip)
+        }
+
+        fn input_capacity(&self) -> usize {
+            self.0.len() - self.1
+        }
+
+        fn push_input(&mut self, input: &[u8]) -> Result<()> {
+            self.0[self.1..(self.1 + input.len())].copy_from_slice(input);
+            self.1 += input.len();
+            Ok(())
+        }
+
+        fn pull_output(&mut self, dst: &mut [u8], input_ended: bool) -> Result<usize> {
+            let n = self.1.min(dst.len());
+            for (d, s) in dst.iter_mut().zip(&self.0[..n]) {
+                *d = s.to_ascii_uppercase();
+            }
+            self.0.copy_within(n..self.1, 0);
+            self.1 -= n;
+            self.2 = input_ended && self.1 == 0;
+            Ok(n)
+        }
+
+        fn is_ended(&self) -> bool {
+            self.2
+        }
+    }
+
+    #[test]
+    pub fn test_send_encoded() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .send_encoded(
+                b"hello",
+                ContentEncoding::Gzip,
+                &mut Upper([0; 4], 0, false),
+            )?
+            .flush();
+
+        assert_eq!(
+            &*output,
+            b"POST /path HTTP/1.1\r\nHost: myhost.test\r\n\
+            Content-Encoding: gzip\r\nContent-Length: 5\r\n\r\nHELLO"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_encoded_chunked() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut t = Upper([0; 4], 0, false);
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_encoded_chunked(ContentEncoding::Gzip, &mut t)?;
+
+        assert_eq!(req.write_chunk_with(b"hello", &mut t)?, 4);
+        assert_eq!(req.write_chunk_with(b"o", &mut t)?, 1);
+        assert!(req.end_chunks_with(&mut t)?);
+
+        let output = req.finish()?.flush();
+
+        assert_eq!(
+            &*output,
+            b"POST /path HTTP/1.1\r\nHost: myhost.test\r\n\
+            Content-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\nHELL\r\n1\r\nO\r\n0\r\n\r\n"
+        );
+
+        let err = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_encoded_chunked(ContentEncoding::Deflate, &mut t)
+            .unwrap_err();
+        assert_eq!(err, HootError::Compress);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_write_bytes_resume() -> Result<()> {
+        let mut buf = [0; 80];
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_body(20)?;
+
+        let body = b"0123456789abcdefghij";
+        let err = req.write_bytes(body).unwrap_err();
+
+        // 62 bytes of request line and headers leaves 18 for the body.
+        assert_eq!(err, HootError::OutputFull(18));
+
+        let token = req.flush().ready();
+        let mut req = Request::resume(token, &mut buf);
+        req.write_bytes(&body[18..])?;
+
+        let output = req.finish()?.flush();
+        assert_eq!(&*output, b"ij");
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_content_length_mismatch() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_body(4)?;
+
+        req.write_bytes(b"abc")?;
+        let err = req.write_bytes(b"de").unwrap_err();
+        assert_eq!(
+            err,
+            HootError::SentMoreThanContentLength {
+                expected: 4,
+                actual: 5
+            }
+        );
+
+        let err = req.finish().map(|_| ()).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::SentLessThanContentLength {
+                expected: 4,
+                actual: 3
+            }
+        );
+
+        Ok(())
+    }
+
+    struct Sum(u64, usize);
+
+    impl BodyHasher for Sum {
+        fn update(&mut self, bytes: &[u8]) {
+            self.0 += bytes.iter().map(|b| *b as u64).sum::<u64>();
+            self.1 += bytes.len();
+        }
+    }
+
+    #[test]
+    pub fn test_write_bytes_hashed() -> Result<()> {
+        let mut buf = [0; 80];
+        let mut sum = Sum(0, 0);
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_body(20)?;
+
+        let body = b"0123456789abcdefghij";
+        let err = req.write_bytes_hashed(body, &mut sum).unwrap_err();
+        assert_eq!(err, HootError::OutputFull(18));
+        assert_eq!(sum.1, 18);
+
+        let token = req.flush().ready();
+        let mut req = Request::resume(token, &mut buf);
+        req.write_bytes_hashed(&body[18..], &mut sum)?;
+
+        assert_eq!(sum.1, 20);
+        assert_eq!(sum.0, body.iter().map(|b| *b as u64).sum::<u64>());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_write_chunk_partial() -> Result<()> {
+        let mut buf = [0; 96];
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_chunked()?;
+
+        let body = b"0123456789abcdefghij";
+        let err = req.write_chunk_partial(body).unwrap_err();
+
+        // 70 bytes of request line and headers and the chunk framing
+        // overhead leaves 6 for the chunk.
+        assert_eq!(err, HootError::OutputFull(6));
+        let n = 6;
+
+        let token = req.flush().ready();
+        let mut req = Request::resume(token, &mut buf);
+        req.write_chunk_partial(&body[n..])?;
+
+        let output = req.finish()?.flush();
+        assert!(output.ends_with(b"ij\r\n0\r\n\r\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_finish_raw_trailers() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_chunked()?;
+
+        let err = req
+            .with_trailer()?
+            .finish_raw(b"Content-Length: 1\r\n\r\n")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::ForbiddenTrailer);
+
+        let req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_chunked()?;
+
+        let err = req
+            .with_trailer()?
+            .finish_raw(b"X-Sum: 1\r\n")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::IncorrectTrailers);
+
+        let req = Request::new(&mut buf)
+            .http_11()
+            .post("myhost.test", "/path")?
+            .with_chunked()?;
+
+        let output = req.with_trailer()?.finish_raw(b"X-Sum: 1\r\n\r\n")?.flush();
+        assert!(output.ends_with(b"0\r\nX-Sum: 1\r\n\r\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_asterisk_and_authority() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .options("myhost.test", "*")?
+            .send()?
+            .flush();
+        assert!(output.starts_with(b"OPTIONS * HTTP/1.1\r\n"));
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .connect("myhost.test:443", "myhost.test:443")?
+            .send()?
+            .flush();
+        assert!(output.starts_with(b"CONNECT myhost.test:443 HTTP/1.1\r\n"));
+
+        let err = Request::new(&mut buf)
+            .http_11()
+            .get("myhost.test", "*")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::InvalidTarget { offset: 0 });
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_invalid_target() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        for path in ["/a b", "/a#frag", "/a\x01"] {
+            let err = Request::new(&mut buf)
+                .http_11()
+                .get("myhost.test", path)
+                .map(|_| ())
+                .unwrap_err();
+            assert_eq!(err, HootError::InvalidTarget { offset: 2 });
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_percent_encode_target() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .percent_encode_target()
+            .http_10()
+            .get("/a b\x7f?q=1#frag")?
+            .flush();
+
+        assert_eq!(&*output, b"GET /a%20b%7F?q=1 HTTP/1.0\r\n");
+
+        Ok(())
+    }
+
+    struct Echo;
+
+    impl RequestSigner for Echo {
+        fn sign(&mut self, req: &CanonicalRequest<'_>, out: &mut dyn fmt::Write) -> fmt::Result {
+            write!(out, "{} {} {}", req.method(), req.path(), req.query())?;
+            for name in req.signed_headers() {
+                for value in req.headers(name) {
+                    write!(out, " {}={}", name, value)?;
+                }
+            }
+            write!(out, " {}", req.body_hash())
+        }
+    }
+
+    #[test]
+    pub fn test_sign() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .put("x.test", "/a%20b?x=1&y")?
+            .header("X-Amz-Date", " 20150830T123600Z")?
+            .header("x-multi", "1")?
+            .header("X-Multi", "2")?
+            .sign("authorization", &["x-multi", "host"], "abc", &mut Echo)?
+            .send_body_full(b"hi")?
+            .flush();
+
+        assert_eq!(
+            &*output,
+            b"PUT /a%20b?x=1&y HTTP/1.1\r\nHost: x.test\r\nX-Amz-Date:  20150830T123600Z\r\n\
+            x-multi: 1\r\nX-Multi: 2\r\n\
+            authorization: PUT /a%20b x=1&y x-multi=1 x-multi=2 host=x.test abc\r\n\
+            Content-Length: 2\r\n\r\nhi"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_sign_errors() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let err = Request::new(&mut buf)
+            .http_11()
+            .get("x.test", "/")?
+            .sign("Authorization", &["x-missing"], "", &mut Echo)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::Sign);
+
+        let output = Request::new(&mut buf).http_11().get("x.test", "/")?.flush();
+        let err = Request::resume(output.ready(), &mut buf)
+            .sign("Authorization", &[], "", &mut Echo)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::Sign);
+
+        let mut buf = [0; 48];
+        let err = Request::new(&mut buf)
+            .http_11()
+            .get("x.test", "/")?
+            .sign("Authorization", &["host"], "abc", &mut Echo)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 19 });
+
+        struct Inject;
+        impl RequestSigner for Inject {
+            fn sign(&mut self, _: &CanonicalRequest<'_>, out: &mut dyn fmt::Write) -> fmt::Result {
+                write!(out, "x\r\nX-Injected: 1")
+            }
+        }
+
+        let mut buf = [0; 1024];
+        let err = Request::new(&mut buf)
+            .http_11()
+            .get("x.test", "/")?
+            .sign("Authorization", &[], "", &mut Inject)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_canonical_header_names() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .canonical_header_names()
+            .http_11()
+            .post("x.test", "/")?
+            .header("x-amz-DATE", "20150830T123600Z")?
+            .header("content-type", "text/plain")?
+            .send_body_full(b"hi")?
+            .flush();
+
+        assert_eq!(
+            &*output,
+            b"POST / HTTP/1.1\r\nHost: x.test\r\nX-Amz-Date: 20150830T123600Z\r\n\
+            Content-Type: text/plain\r\nContent-Length: 2\r\n\r\nhi"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_align_body() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .align_body(16)
+            .http_11()
+            .post("x.test", "/")?
+            .send_body_full(b"hi")?
+            .flush();
+
+        assert_eq!(output.head_span(), Some(0..64));
+        assert_eq!(
+            &*output,
+            b"POST / HTTP/1.1\r\nHost: x.test\r\nContent-Length: 2            \r\n\r\nhi"
+        );
+
+        // Already aligned, no padding.
+        let mut buf = [0; 1024];
+        let output = Request::new(&mut buf)
+            .align_body(4)
+            .http_11()
+            .post("x.test", "/")?
+            .send_body_full(b"hi")?
+            .flush();
+        assert_eq!(output.head_span(), Some(0..52));
+
+        // Chunked, the first chunk size starts aligned.
+        let mut buf = [0; 1024];
+        let output = Request::new(&mut buf)
+            .align_body(32)
+            .http_11()
+            .put("x.test", "/")?
+            .with_chunked()?
+            .write_chunk(b"hi")?
+            .flush();
+        assert_eq!(output.head_span(), Some(0..64));
+        assert_eq!(&output[64..], b"2\r\nhi\r\n");
+
+        // Not enough room to pad.
+        let mut buf = [0; 60];
+        let err = Request::new(&mut buf)
+            .align_body(64)
+            .http_11()
+            .post("x.test", "/")?
+            .with_body(2)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 4 });
+
+        // The head is gone once flushed.
+        let mut buf = [0; 1024];
+        let mut request = Request::new(&mut buf)
+            .align_body(16)
+            .http_11()
+            .post("x.test", "/")?
+            .with_body(2)?;
+        request.flushed(64)?;
+        request.write_bytes(b"hi")?;
+        assert_eq!(request.flush().head_span(), None);
+
+        // Without alignment, the span is still there.
+        let mut buf = [0; 1024];
+        let output = Request::new(&mut buf)
+            .http_11()
+            .post("x.test", "/")?
+            .send_body_full(b"hi")?
+            .flush();
+        assert_eq!(output.head_span(), Some(0..52));
+
+        // No body, no span.
+        let mut buf = [0; 1024];
+        let output = Request::new(&mut buf)
+            .align_body(16)
+            .http_11()
+            .get("x.test", "/a")?
+            .send()?
+            .flush();
+        assert_eq!(output.head_span(), None);
+        assert_eq!(output.len(), 33);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_host_injection() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let err = Request::new(&mut buf)
+            .http_11()
+            .get("a\r\nX-Evil: 1", "/")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 1 });
+
+        let method = ExtMethod::new("PURGE")?;
+        let err = Request::new(&mut buf)
+            .http_11()
+            .extension(method, "a\0", "/")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_header_injection() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        for (name, value, expected) in [
+            (
+                "X-Foo",
+                "a\r\nX-Evil: 1",
+                HootError::HeaderValue { offset: 1 },
+            ),
+            ("X-Foo", "a\nb", HootError::HeaderValue { offset: 1 }),
+            ("X-Foo", "a\0b", HootError::HeaderValue { offset: 1 }),
+            ("X Foo", "a", HootError::HeaderName { offset: 1 }),
+            ("X-Foo:", "a", HootError::HeaderName { offset: 5 }),
+            ("", "a", HootError::HeaderName { offset: 0 }),
+        ] {
+            let err = Request::new(&mut buf)
+                .http_11()
+                .get("myhost.test", "/")?
+                .header(name, value)
+                .map(|_| ())
+                .unwrap_err();
+            assert_eq!(err, expected);
+        }
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .get("myhost.test", "/")?
+            .header("X-Foo", "a\tb \u{e5}")?
+            .header_unchecked("X-Const", "yes")?
+            .send()?
+            .flush();
+        assert!(output.ends_with("X-Foo: a\tb \u{e5}\r\nX-Const: yes\r\n\r\n".as_bytes()));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_duplicate_header() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let req = Request::new(&mut buf)
+            .http_10()
+            .get("/path")?
+            .header("Host", "myhost.test")?
+            .header("Accept", "text/plain")?
+            .header("Accept", "text/html")?;
+
+        let err = req.header("host", "other.test").map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::DuplicateHeader);
+
+        Ok(())
+    }
+
     #[test]
     pub fn test_illegal_http11_header() -> Result<()> {
         let mut buf = [0; 1024];
@@ -499,4 +2077,165 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    pub fn test_flushed() -> Result<()> {
+        const HEAD: &[u8] = b"PUT /upload HTTP/1.1\r\nHost: x.test\r\nContent-Length: 16\r\n\r\n";
+
+        // Too small for head and body at once.
+        let mut buf = [0; 64];
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .put("x.test", "/upload")?
+            .with_body(16)?;
+        assert_eq!(req.pending(), HEAD);
+
+        // A partial write to the transport keeps the rest.
+        req.flushed(10)?;
+        assert_eq!(req.pending(), &HEAD[10..]);
+
+        // More than pending is an error, and flushes nothing.
+        let err = req.flushed(HEAD.len()).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::BufferOverrun {
+                amount: HEAD.len(),
+                available: HEAD.len() - 10
+            }
+        );
+        assert_eq!(req.pending(), &HEAD[10..]);
+
+        req.flushed(HEAD.len() - 10)?;
+        assert!(req.pending().is_empty());
+
+        req.write_bytes(b"0123456789abcdef")?;
+        assert_eq!(req.pending(), b"0123456789abcdef");
+
+        let output = req.finish()?.flush();
+        assert_eq!(&*output, b"0123456789abcdef");
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_write_body_from() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("x.test", "/")?
+            .with_body(10)?;
+
+        let mut source: &[u8] = b"0123456789";
+        let n = req.write_body_from(&mut source)?;
+        assert_eq!(n, 10);
+        assert_eq!(req.write_body_from(&mut source)?, 0);
+        let output = req.finish()?.flush();
+        assert!(output.ends_with(b"Content-Length: 10\r\n\r\n0123456789"));
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("x.test", "/")?
+            .with_body(10)?;
+        let mut source: &[u8] = b"012345678";
+        let err = req.write_body_from(&mut source).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::SentLessThanContentLength {
+                expected: 10,
+                actual: 9
+            }
+        );
+
+        // A source of unknown length, two bytes at a time.
+        struct Counter(u8);
+
+        impl BodySource for Counter {
+            fn read(&mut self, buf: &mut [u8]) -> usize {
+                let n = buf.len().min(2).min(5 - self.0 as usize);
+                for b in &mut buf[..n] {
+                    *b = b'a' + self.0;
+                    self.0 += 1;
+                }
+                n
+            }
+        }
+
+        let mut req = Request::new(&mut buf)
+            .http_11()
+            .post("x.test", "/")?
+            .with_chunked()?;
+        assert_eq!(req.write_body_from(&mut Counter(0))?, 5);
+        let output = req.finish()?.flush();
+        assert!(output.ends_with(b"\r\n\r\n5\r\nabcde\r\n0\r\n\r\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_send_body_full() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .http_10()
+            .put("/")?
+            .send_body_full(b"hello")?
+            .flush();
+        assert!(output.ends_with(b"\r\nContent-Length: 5\r\n\r\nhello"));
+
+        let err = Request::new(&mut buf[..55])
+            .http_11()
+            .post("x.test", "/")?
+            .send_body_full(b"hello")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 2 });
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_send_empty() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .post("x.test", "/")?
+            .send_empty()?
+            .flush();
+        assert!(output.ends_with(b"Host: x.test\r\nContent-Length: 0\r\n\r\n"));
+
+        let output = Request::new(&mut buf)
+            .http_11()
+            .get("x.test", "/")?
+            .send_empty()?
+            .flush();
+        assert!(output.ends_with(b"Host: x.test\r\n\r\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_url_requests() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let output = Request::get_url(&mut buf, "https://x.test:443/a?b=1#c")?
+            .send()?
+            .flush();
+        assert_eq!(&*output, b"GET /a?b=1 HTTP/1.1\r\nHost: x.test\r\n\r\n");
+
+        let output = Request::put_url(&mut buf, "http://x.test:8080")?
+            .send_empty()?
+            .flush();
+        assert_eq!(
+            &*output,
+            b"PUT / HTTP/1.1\r\nHost: x.test:8080\r\nContent-Length: 0\r\n\r\n"
+        );
+
+        let err = Request::get_url(&mut buf, "/no/host")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UrlError(UrlError::MissingScheme));
+
+        Ok(())
+    }
 }
@@ -3,16 +3,27 @@ use core::marker::PhantomData;
 use core::mem;
 use core::str;
 
-use crate::body::{do_read_body, RecvBodyMode};
-use crate::header::transmute_headers;
+use crate::body::{
+    bytes_remaining, chunk_remaining, do_read_body, do_read_body_transformed, do_read_trailers,
+    read_into_sink, RecvBodyMode,
+};
+use crate::parser::{check_head_limits, limit_header_count, ParseMode};
+use crate::strict::check_framing;
 use crate::types::state::*;
 use crate::types::*;
-use crate::util::{cast_buf_for_headers, LengthChecker};
-use crate::BodyPart;
-use crate::{CallState, Result};
-use crate::{Header, HootError, HttpVersion};
+use crate::util::{
+    compare_lowercase_ascii, limit_header_buf, LengthChecker, EMPTY_HEADER, MAX_HEADERS,
+};
+use crate::{h2c, H2cHandoff, Header, HootError, HttpVersion};
+use crate::{
+    BodyHasher, BodySink, CallState, ConnectionOptions, Deadline, Result, TransferCodings,
+};
+use crate::{
+    BodyPart, BodyTransform, ContentEncoding, ContentLengthPolicy, ExcessBodyPolicy, Limit, Limits,
+    Trailers,
+};
 
-use super::ResumeToken;
+use super::{Protocols, ResumeToken};
 
 pub struct Response<S: State> {
     _typ: PhantomData<S>,
@@ -27,8 +38,22 @@ impl Response<()> {
         }
     }
 
+    /// Response to a request sent outside of `client::Request`, such as by
+    /// `proxy::Relay`.
+    #[cfg(any(feature = "proxy", feature = "fuzz"))]
+    pub(crate) fn forwarded(method: crate::Method) -> Response<RECV_RESPONSE> {
+        Response {
+            _typ: PhantomData,
+            state: CallState {
+                version: Some(HttpVersion::Http11),
+                method: Some(method),
+                ..Default::default()
+            },
+        }
+    }
+
     #[cfg(all(test, feature = "std"))]
-    fn new_test() -> Response<RECV_RESPONSE> {
+    pub(crate) fn new_test() -> Response<RECV_RESPONSE> {
         use crate::Method as M;
         Response {
             _typ: PhantomData,
@@ -43,6 +68,8 @@ impl Response<()> {
 
 impl<S: State> Response<S> {
     fn transition<S2: State>(self) -> Response<S2> {
+        trace!("Transition: {} -> {}", S::state_name(), S2::state_name());
+
         // SAFETY: this only changes the type state of the PhantomData
         unsafe { mem::transmute(self) }
     }
@@ -50,8 +77,10 @@ impl<S: State> Response<S> {
     fn do_try_read_response<'a, 'b>(
         &mut self,
         input: &'a [u8],
-        buf: &'b mut [u8],
+        buf: &'b mut [Header<'a>],
     ) -> Result<ResponseAttempt<'a, 'b>> {
+        trace!("Try read response: {} bytes", input.len());
+
         let already_read_response = self.state.recv_body_mode.is_some();
 
         // Status/header reads only work once.
@@ -59,28 +88,97 @@ impl<S: State> Response<S> {
             return Ok(ResponseAttempt::empty());
         }
 
-        let headers = cast_buf_for_headers(buf);
-        let mut r = httparse::Response::new(headers);
+        if self.state.accept_http09 {
+            let n = input.len().min(5);
+            if input[..n] != b"HTTP/"[..n] {
+                return Ok(self.http09_response());
+            }
+        }
 
-        let n = match r.parse(input)? {
-            httparse::Status::Complete(v) => v,
-            httparse::Status::Partial => return Ok(ResponseAttempt::empty()),
-        };
+        let limits = self.state.limits;
+        let (headers, count_limited) = limit_header_count(limit_header_buf(buf), &limits);
+
+        let mode = self.state.parse_mode;
+
+        // Headers are taken from the scan once the head is complete, see HeadScan.
+        let head = self
+            .state
+            .head_scan
+            .scan(input, mode, true, headers.len())
+            .and_then(|complete| {
+                if complete {
+                    self.state.head_scan.response(input, headers).map(Some)
+                } else {
+                    Ok(None)
+                }
+            });
 
-        let ver = match r.version.unwrap() {
-            0 => HttpVersion::Http10,
-            1 => HttpVersion::Http11,
-            _ => return Err(HootError::Version),
+        let head = match head {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                trace!(
+                    "Read partial response, scanned: {}",
+                    self.state.head_scan.scanned()
+                );
+                check_head_limits(input, None, &limits)?;
+                self.state.deadline.check_head(false)?;
+                #[cfg(feature = "metrics")]
+                crate::metrics::record(self.state.metrics_id, crate::metrics::Event::ParseRetry);
+                return Ok(ResponseAttempt::empty());
+            }
+            Err(HootError::TooManyHeaders) if count_limited => {
+                return Err(HootError::LimitExceeded {
+                    which: Limit::HeaderCount,
+                })
+            }
+            Err(e) => {
+                debug!("Read response failed: {}", e);
+                return Err(e);
+            }
         };
 
-        let status = Status(ver, r.code.unwrap(), r.reason.unwrap_or(""));
+        let n = head.len;
+
+        check_head_limits(input, Some(n), &limits)?;
+        self.state.deadline.check_head(true)?;
+
+        let ver = head.line.version;
+
+        let status = Status(ver, head.line.code, head.line.reason);
+        trace!("Read complete response: {:?}", status);
 
         // Derive body mode from knowledge this far.
         let http10 = ver == HttpVersion::Http10;
-        let method = self.state.method.unwrap(); // Ok for same reason as above.
-        let headers = transmute_headers(r.headers);
-        let mode = RecvBodyMode::for_response(http10, method, status.1, headers)?;
+        let method = self.state.method.ok_or(HootError::Internal)?;
+        let headers = head.headers;
+        trace!("Headers: {:?}", headers);
+
+        if self.state.parse_mode.is_strict() {
+            check_framing(headers)?;
+        }
+
+        let mode = RecvBodyMode::for_response(
+            http10,
+            method,
+            status.1,
+            headers,
+            self.state.content_length_policy,
+        )?;
         self.state.recv_body_mode = Some(mode);
+        trace!("Body mode: {:?}", mode);
+
+        let encoding = ContentEncoding::from_headers(headers);
+        self.state.content_encoding = encoding;
+
+        let transfer_codings = TransferCodings::from_headers(headers)?;
+        self.state.transfer_codings = transfer_codings;
+
+        if let Some(max) = self.state.max_recv_body {
+            if mode.exceeds(max, self.state.reject_unknown_recv_length) {
+                debug!("Response body too large: {:?} max {}", mode, max);
+                self.state.recv_too_large = true;
+            }
+        }
 
         // If we are awaiting a length, put a length checker in place
         if let RecvBodyMode::LengthDelimited(len) = mode {
@@ -89,18 +187,63 @@ impl<S: State> Response<S> {
             }
         }
 
+        #[cfg(feature = "metrics")]
+        crate::metrics::head_recv(&self.state, n);
+
         Ok(ResponseAttempt {
             input_used: n,
             status: Some(status),
             headers: Some(headers),
+            content_encoding: encoding,
+            transfer_codings,
+            body_mode: Some(mode),
+            declared_mode: RecvBodyMode::declared(
+                http10,
+                headers,
+                self.state.content_length_policy,
+            ),
+            too_large: self.state.recv_too_large,
+            http10_request: self.state.version == Some(HttpVersion::Http10),
+            http09: false,
         })
     }
+
+    /// An HTTP/0.9 response, which is all body until close.
+    fn http09_response<'a, 'b>(&mut self) -> ResponseAttempt<'a, 'b> {
+        debug!("Response without status line, reading as HTTP/0.9");
+
+        let mode = RecvBodyMode::CloseDelimited;
+        self.state.recv_body_mode = Some(mode);
+
+        if let Some(max) = self.state.max_recv_body {
+            if mode.exceeds(max, self.state.reject_unknown_recv_length) {
+                self.state.recv_too_large = true;
+            }
+        }
+
+        ResponseAttempt {
+            status: Some(Status(HttpVersion::Http10, 200, "")),
+            headers: Some(&[]),
+            body_mode: Some(mode),
+            declared_mode: Some(mode),
+            too_large: self.state.recv_too_large,
+            http09: true,
+            ..ResponseAttempt::empty()
+        }
+    }
 }
 
 pub struct ResponseAttempt<'a, 'b> {
     input_used: usize,
     status: Option<Status<'a>>,
     headers: Option<&'b [Header<'a>]>,
+    content_encoding: Option<ContentEncoding>,
+    transfer_codings: TransferCodings,
+    body_mode: Option<RecvBodyMode>,
+    declared_mode: Option<RecvBodyMode>,
+    http10_request: bool,
+    http09: bool,
+    too_large: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -126,11 +269,23 @@ impl<'a, 'b> ResponseAttempt<'a, 'b> {
             input_used: 0,
             status: None,
             headers: None,
+            content_encoding: None,
+            transfer_codings: TransferCodings::empty(),
+            body_mode: None,
+            declared_mode: None,
+            too_large: false,
+            http10_request: false,
+            http09: false,
         }
     }
 
     pub fn is_success(&self) -> bool {
-        self.input_used > 0
+        self.status.is_some()
+    }
+
+    /// Whether this is an HTTP/0.9 response, see `Response::accept_http09()`.
+    pub fn is_http09(&self) -> bool {
+        self.http09
     }
 
     pub fn input_used(&self) -> usize {
@@ -144,34 +299,299 @@ impl<'a, 'b> ResponseAttempt<'a, 'b> {
     pub fn headers(&self) -> Option<&'b [Header<'a>]> {
         self.headers
     }
+
+    /// The `Content-Encoding` of the response body, if any.
+    pub fn content_encoding(&self) -> Option<ContentEncoding> {
+        self.content_encoding
+    }
+
+    /// The `Transfer-Encoding` codings of the response body.
+    pub fn transfer_codings(&self) -> TransferCodings {
+        self.transfer_codings
+    }
+
+    /// Whether this is a `101` switching to h2c, see
+    /// `Request::h2c_upgrade()`.
+    pub fn is_h2c_upgrade(&self) -> bool {
+        match (self.status, self.headers) {
+            (Some(s), Some(h)) => s.code() == 101 && h2c::is_upgrade(h),
+            _ => false,
+        }
+    }
+
+    /// Protocols required by a `426 Upgrade Required` response.
+    ///
+    /// This is `None` for other statuses, or if the `Upgrade` header is missing.
+    /// A 426 is a final response. Its body must be read as usual, after which
+    /// the connection can be reused (unless told otherwise) to retry the request
+    /// with one of the protocols, see `Request::upgrade()`.
+    pub fn upgrade_required(&self) -> Option<Protocols<'b>> {
+        if self.status?.code() != 426 {
+            return None;
+        }
+
+        self.upgrade_header()
+    }
+
+    /// How long a `429 Too Many Requests` or `503 Service Unavailable` asks
+    /// to wait before retrying, from `Retry-After`.
+    ///
+    /// See [`retry_delay()`](crate::retry::retry_delay).
+    #[cfg(feature = "retry")]
+    pub fn retry_after(&self, clock: &dyn crate::Clock) -> Option<core::time::Duration> {
+        crate::retry::retry_delay(self.status?.code(), self.headers?, clock)
+    }
+
+    /// Protocols a `101 Switching Protocols` response switches to.
+    ///
+    /// This is `None` for other statuses, or if the `Upgrade` header is missing.
+    /// The connection speaks the new protocol right after the response head.
+    /// Input past `input_used()` belongs to it, and the response is left with
+    /// `Response::abort()`.
+    pub fn switched_to(&self) -> Option<Protocols<'b>> {
+        if self.status?.code() != 101 {
+            return None;
+        }
+
+        self.upgrade_header()
+    }
+
+    fn upgrade_header(&self) -> Option<Protocols<'b>> {
+        self.headers?
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "upgrade"))
+            .and_then(|h| h.try_value())
+            .map(Protocols::new)
+    }
+
+    /// How the response body is framed.
+    pub fn body_mode(&self) -> Option<RecvBodyMode> {
+        self.body_mode
+    }
+
+    /// The expected length of the response body, if it is length delimited.
+    ///
+    /// This is `Some(0)` for responses without a body, such as to a `HEAD`.
+    pub fn content_length(&self) -> Option<u64> {
+        match self.body_mode? {
+            RecvBodyMode::LengthDelimited(len) => Some(len),
+            _ => None,
+        }
+    }
+
+    /// How the headers declare the body framing, ignoring method and status.
+    ///
+    /// Differs from `body_mode()` when no body follows regardless of headers,
+    /// such as for `HEAD` or `304 Not Modified`. For a `HEAD` response this is
+    /// the framing the `GET` would have. `CloseDelimited` means the headers
+    /// give no length. `None` if the headers are invalid.
+    pub fn declared_body_mode(&self) -> Option<RecvBodyMode> {
+        self.declared_mode
+    }
+
+    /// The `Content-Length` declared by the headers, ignoring method and status.
+    ///
+    /// See `declared_body_mode()`.
+    pub fn declared_content_length(&self) -> Option<u64> {
+        match self.declared_mode? {
+            RecvBodyMode::LengthDelimited(len) => Some(len),
+            _ => None,
+        }
+    }
+
+    /// Whether the response body uses chunked transfer encoding.
+    pub fn is_chunked(&self) -> bool {
+        self.body_mode == Some(RecvBodyMode::Chunked)
+    }
+
+    /// Whether the connection must be closed after the response.
+    ///
+    /// This is the case for bodies delimited by closing, `Connection: close`, and
+    /// HTTP/1.0 without `Connection: keep-alive`. Responses to HTTP/1.0 requests
+    /// are treated as HTTP/1.0, so they need `keep-alive` whatever their version.
+    pub fn must_close(&self) -> bool {
+        let (status, headers) = match (self.status, self.headers) {
+            (Some(s), Some(h)) => (s, h),
+            _ => return false,
+        };
+
+        if self.body_mode == Some(RecvBodyMode::CloseDelimited) {
+            return true;
+        }
+
+        let version = if self.http10_request {
+            HttpVersion::Http10
+        } else {
+            status.version()
+        };
+
+        !ConnectionOptions::new(headers).is_persistent(version)
+    }
+
+    /// The options of the `Connection` header, such as hop-by-hop headers.
+    pub fn connection(&self) -> Option<ConnectionOptions<'a, 'b>> {
+        self.headers.map(ConnectionOptions::new)
+    }
+
+    /// Whether the response body is above the max set by `set_max_body_size()`.
+    ///
+    /// If this is true, the response should be dropped with `Response::abort()`.
+    pub fn is_too_large(&self) -> bool {
+        self.too_large
+    }
 }
 
 impl Response<RECV_RESPONSE> {
+    /// Try to read the response head from `input`, using `buf` for the headers.
+    ///
+    /// If the head is not complete, the attempt is empty. Call again with the
+    /// same input, from the start of the response, and more bytes appended.
+    /// Complete lines are not scanned again by later attempts.
     pub fn try_read_response<'a, 'b>(
         &mut self,
         input: &'a [u8],
-        buf: &'b mut [u8],
+        buf: &'b mut [Header<'a>],
     ) -> Result<ResponseAttempt<'a, 'b>> {
         self.do_try_read_response(input, buf)
     }
 
+    /// Set a max size for the response body.
+    ///
+    /// This is checked as soon as the status and headers are read, against the
+    /// declared `Content-Length`. Bodies without declared size (chunked or
+    /// close delimited) are only considered too large when `allow_unknown_length`
+    /// is false. See `ResponseAttempt::is_too_large()`. If allowed, reading them
+    /// fails with `HootError::BodyTooLarge` once more than `max` is read.
+    pub fn set_max_body_size(&mut self, max: u64, allow_unknown_length: bool) {
+        self.state.max_recv_body = Some(max);
+        self.state.reject_unknown_recv_length = !allow_unknown_length;
+    }
+
+    /// Set limits for reading the response.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.state.limits = limits;
+    }
+
+    /// Set time budgets for reading the response.
+    pub fn set_deadline(&mut self, deadline: Deadline) {
+        self.state.deadline.budgets = deadline;
+    }
+
+    /// Supply the current time for [`Deadline`](crate::Deadline) accounting.
+    ///
+    /// Milliseconds from any monotonic source. Call before each read.
+    pub fn set_now(&mut self, now_ms: u64) {
+        self.state.deadline.set_now(now_ms);
+    }
+
+    /// Reject responses that could be framed differently by other parsers.
+    ///
+    /// See [`Violation`](crate::Violation) for what is checked. Intended for proxies, where a
+    /// disagreement on where a message ends enables request smuggling. Replaces
+    /// `set_lenient()`.
+    pub fn set_strict(&mut self) {
+        self.state.parse_mode = ParseMode::Strict;
+    }
+
+    /// Tolerate responses from broken peers.
+    ///
+    /// Accepts extra spaces in the status line, whitespace before the header
+    /// colon, obs-fold continuation lines, and duplicate `Content-Length`
+    /// headers with identical values. Replaces `set_strict()`.
+    ///
+    /// A folded header value keeps the line breaks of the fold, which must be
+    /// replaced with a space before the value is used or forwarded. Use
+    /// `Header::value_unfolded()` for the parts of the value.
+    pub fn set_lenient(&mut self) {
+        self.state.parse_mode = ParseMode::Lenient;
+        self.state.content_length_policy = ContentLengthPolicy::AcceptIfIdentical;
+    }
+
+    /// Set how repeated `Content-Length` headers are handled.
+    ///
+    /// Defaults to [`ContentLengthPolicy::Reject`].
+    pub fn set_content_length_policy(&mut self, policy: ContentLengthPolicy) {
+        self.state.content_length_policy = policy;
+    }
+
+    /// Set how input beyond the `Content-Length` of the body is handled.
+    ///
+    /// Defaults to [`ExcessBodyPolicy::Reject`].
+    pub fn set_excess_body_policy(&mut self, policy: ExcessBodyPolicy) {
+        self.state.excess_body_policy = policy;
+    }
+
+    /// Read responses without a status line as HTTP/0.9.
+    ///
+    /// Some ancient servers answer with the body only, delimited by closing the
+    /// connection. With this, input that doesn't start with `HTTP/` is such a
+    /// body. The attempt is successful without using any input, and reports a
+    /// `200` with HTTP/1.0 and no headers, see `ResponseAttempt::is_http09()`.
+    /// By default the input fails to parse.
+    pub fn accept_http09(&mut self) {
+        self.state.accept_http09 = true;
+    }
+
     pub fn proceed(self) -> Response<RECV_BODY> {
         self.transition()
     }
+
+    /// Give up on the response without reading the body.
+    ///
+    /// The unread body is still on the connection, which means the caller
+    /// must close it.
+    pub fn abort(self) -> Response<ENDED> {
+        debug!("Abort response");
+        self.transition()
+    }
+
+    /// Take a `101` accepting an upgrade to h2c, ending HTTP/1.1 on the
+    /// connection.
+    ///
+    /// `attempt` is the successful attempt of this response, and `input` the
+    /// input it read. Fails with `HootError::NotH2cUpgrade` unless
+    /// [`ResponseAttempt::is_h2c_upgrade()`]. See [`H2cHandoff`].
+    pub fn switch_to_h2c<'a>(
+        self,
+        attempt: ResponseAttempt<'a, '_>,
+        input: &'a [u8],
+    ) -> Result<H2cHandoff<'a>> {
+        if !attempt.is_h2c_upgrade() {
+            return Err(HootError::NotH2cUpgrade);
+        }
+
+        trace!("Switch to h2c");
+
+        Ok(H2cHandoff::new(None, &input[attempt.input_used..]))
+    }
 }
 
 impl Response<RECV_BODY> {
-    pub fn read_body<'a, 'b>(&mut self, src: &'a [u8], dst: &'b mut [u8]) -> Result<BodyPart<'b>> {
+    /// Supply the current time for [`Deadline`](crate::Deadline) accounting.
+    ///
+    /// Milliseconds from any monotonic source. Call before each read.
+    pub fn set_now(&mut self, now_ms: u64) {
+        self.state.deadline.set_now(now_ms);
+    }
+
+    /// Read the head when going straight to the body. The headers are parsed
+    /// into a buffer on the stack, since they are not kept.
+    ///
+    /// Returns whether the head is complete.
+    fn read_head_only(&mut self, src: &[u8]) -> Result<bool> {
+        let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+        Ok(self.do_try_read_response(src, &mut headers)?.is_success())
+    }
+
+    pub fn read_body<'b>(&mut self, src: &[u8], dst: &'b mut [u8]) -> Result<BodyPart<'b>> {
         let already_read_response = self.state.recv_body_mode.is_some();
 
         // It's valid to skip try_read_response() and progress straight to reading
         // the body. This ensures we skip the corresponding input.
         if !already_read_response {
-            let r = self.do_try_read_response(src, dst)?;
-
             // Still not enough input for the entire status and headers. Need
             // to try again later.
-            if !r.is_success() {
+            if !self.read_head_only(src)? {
                 return Ok(BodyPart::empty());
             }
         }
@@ -179,6 +599,121 @@ impl Response<RECV_BODY> {
         do_read_body(&mut self.state, src, dst)
     }
 
+    /// Read body like `read_body()`, feeding the read data to `hasher`.
+    pub fn read_body_hashed<'b>(
+        &mut self,
+        src: &[u8],
+        dst: &'b mut [u8],
+        hasher: &mut dyn BodyHasher,
+    ) -> Result<BodyPart<'b>> {
+        let part = self.read_body(src, dst)?;
+        hasher.update(part.data());
+        Ok(part)
+    }
+
+    /// Read body through a [`BodyTransform`], such as a decompressor.
+    ///
+    /// Works like `read_body()`, but the unframed body is passed through the
+    /// transform before ending up in `dst`. The transform decides, via
+    /// `BodyTransform::select()`, whether it handles the `Content-Encoding`.
+    ///
+    /// Transforms buffer input, which means the returned part is the only
+    /// reliable indicator of finished. `is_finished()` only tells whether the
+    /// entire (encoded) body has been read.
+    pub fn read_body_with<'b>(
+        &mut self,
+        src: &[u8],
+        dst: &'b mut [u8],
+        transform: &mut dyn BodyTransform,
+    ) -> Result<BodyPart<'b>> {
+        let already_read_response = self.state.recv_body_mode.is_some();
+
+        if !already_read_response && !self.read_head_only(src)? {
+            return Ok(BodyPart::empty());
+        }
+
+        let encoding = self.state.content_encoding;
+        do_read_body_transformed(&mut self.state, src, dst, transform, encoding)
+    }
+
+    /// Read body through a [`BodyTransform`] for the transfer coding.
+    ///
+    /// Like `read_body_with()`, but the transform selects on the outermost
+    /// transfer coding besides `chunked`, see [`TransferCodings::outermost()`].
+    /// Bodies with several such codings need further decoding of the output.
+    pub fn read_body_transfer_with<'b>(
+        &mut self,
+        src: &[u8],
+        dst: &'b mut [u8],
+        transform: &mut dyn BodyTransform,
+    ) -> Result<BodyPart<'b>> {
+        let already_read = self.state.recv_body_mode.is_some();
+
+        if !already_read && !self.read_head_only(src)? {
+            return Ok(BodyPart::empty());
+        }
+
+        let encoding = self.state.transfer_codings.outermost();
+        do_read_body_transformed(&mut self.state, src, dst, transform, encoding)
+    }
+
+    /// Read the body straight into a [`BodySink`].
+    ///
+    /// Returns how much of `src` was used. Reading stops when `src` is used, the
+    /// sink is full or the body is finished.
+    pub fn read_body_into(&mut self, src: &[u8], sink: &mut dyn BodySink) -> Result<usize> {
+        read_into_sink(src, sink, |src, dst| self.read_body(src, dst))
+    }
+
+    /// Read the body into a `Vec`, growing it as needed.
+    ///
+    /// Returns how much of `src` was used. Bound the size with
+    /// `set_max_body_size()`, as the `Vec` otherwise grows with whatever the
+    /// server sends.
+    #[cfg(feature = "alloc")]
+    pub fn read_body_to_vec(
+        &mut self,
+        src: &[u8],
+        body: &mut alloc::vec::Vec<u8>,
+    ) -> Result<usize> {
+        self.read_body_into(src, &mut crate::owned::VecSink::new(body))
+    }
+
+    /// Read the body into `json`, to deserialize it once finished.
+    ///
+    /// Returns how much of `src` was used. See [`JsonBody`](crate::json::JsonBody).
+    #[cfg(feature = "serde")]
+    pub fn read_json(&mut self, src: &[u8], json: &mut crate::json::JsonBody<'_>) -> Result<usize> {
+        json.read(src, |src, dst| self.read_body(src, dst))
+    }
+
+    /// Read the trailer section after the body is finished.
+    ///
+    /// Returns `None` if more input is needed. For bodies that are not chunked
+    /// the trailers are empty. The raw bytes can be passed on as is by a proxy
+    /// using `finish_raw()`.
+    pub fn read_trailers<'a, 'b>(
+        &mut self,
+        src: &'a [u8],
+        buf: &'b mut [Header<'a>],
+    ) -> Result<Option<Trailers<'a, 'b>>> {
+        do_read_trailers(&mut self.state, src, buf)
+    }
+
+    /// Bytes left to read of a length delimited body.
+    ///
+    /// This is `None` for chunked and close delimited bodies.
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        bytes_remaining(&self.state)
+    }
+
+    /// Bytes left to read of the current chunk of a chunked body.
+    ///
+    /// This is `Some(0)` between chunks, and `None` if the body is not chunked.
+    pub fn chunk_remaining(&self) -> Option<u64> {
+        chunk_remaining(&self.state)
+    }
+
     pub fn is_finished(&self) -> bool {
         use RecvBodyMode::*;
 
@@ -189,14 +724,43 @@ impl Response<RECV_BODY> {
 
         match mode {
             LengthDelimited(n) => n == 0 || self.state.did_read_to_end,
-            Chunked => self.state.did_read_to_end,
-            CloseDelimited => unreachable!("CloseDelimited is not possible for server::Request"),
+            // Close delimited bodies end when the connection closes, which
+            // the caller knows about, not us.
+            Chunked | CloseDelimited => self.state.did_read_to_end,
+        }
+    }
+
+    /// The connection closed before `is_finished()`.
+    ///
+    /// This ends a close delimited body, and is an error for others.
+    pub(crate) fn closed(&mut self) -> Result<()> {
+        match self.state.recv_body_mode {
+            Some(RecvBodyMode::CloseDelimited) => {
+                self.state.did_read_to_end = true;
+                Ok(())
+            }
+            _ if self.is_finished() => Ok(()),
+            _ => {
+                if let Some(checker) = &self.state.recv_checker {
+                    checker.assert_expected(|expected, actual| {
+                        HootError::RecvLessThanContentLength { expected, actual }
+                    })?;
+                }
+                Err(HootError::BodyNotFinished)
+            }
         }
     }
 
+    /// Consider the body read.
+    ///
+    /// Input following the body is not used, and is counted by
+    /// `unused_input()` of the ended response.
     pub fn finish(self) -> Result<Response<ENDED>> {
         if let Some(checker) = &self.state.recv_checker {
-            checker.assert_expected(HootError::RecvLessThanContentLength)?;
+            checker.assert_expected(|expected, actual| HootError::RecvLessThanContentLength {
+                expected,
+                actual,
+            })?;
         }
 
         if !self.is_finished() {
@@ -207,6 +771,43 @@ impl Response<RECV_BODY> {
     }
 }
 
+impl Response<ENDED> {
+    /// Amount of bytes at the end of the last input that followed the
+    /// response.
+    ///
+    /// The last input is what was last given to `read_body()`, or to
+    /// `read_trailers()` if called. The unused bytes are the start of a
+    /// pipelined response, or garbage. For reusing the connection, they are
+    /// input to the next exchange.
+    ///
+    /// ```
+    /// use hoot::util::HeaderBuf;
+    /// # use hoot::client::Request;
+    /// # let mut buf = [0; 1024];
+    /// # let output = Request::new(&mut buf).http_11().get("myhost.test", "/")?.send()?.flush();
+    /// # let mut response = output.into_response();
+    /// const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+    ///     2\r\nhi\r\n0\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n";
+    ///
+    /// let mut headers = HeaderBuf::<16>::new();
+    /// let used = response.try_read_response(INPUT, &mut headers)?.input_used();
+    /// let mut response = response.proceed();
+    ///
+    /// let part = response.read_body(&INPUT[used..], &mut buf)?;
+    /// let used = used + part.input_used();
+    /// let trailers = response.read_trailers(&INPUT[used..], &mut headers)?.unwrap();
+    /// assert!(trailers.headers().is_empty());
+    ///
+    /// let response = response.finish()?;
+    /// let next = &INPUT[INPUT.len() - response.unused_input()..];
+    /// assert_eq!(next, b"HTTP/1.1 204 No Content\r\n\r\n");
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn unused_input(&self) -> usize {
+        self.state.recv_unused
+    }
+}
+
 impl fmt::Debug for Status<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("Status")
@@ -217,13 +818,42 @@ impl fmt::Debug for Status<'_> {
     }
 }
 
+impl<S: State> fmt::Debug for Response<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("typ", &S::state_name())
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// Forks the response, see the `snapshot` feature.
+#[cfg(feature = "snapshot")]
+impl<S: State> Clone for Response<S> {
+    fn clone(&self) -> Self {
+        Response {
+            _typ: PhantomData,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Status<'_> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Status({}, {=u16}, {=str})", self.0, self.1, self.2);
+    }
+}
+
 #[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
+    use crate::util::HeaderBuf;
+    use crate::{DeadlinePhase, Limit, Progress, Violation};
 
     #[test]
     fn test_recv_no_headers() -> Result<()> {
-        let mut buf = [0; 1024];
+        let mut buf = HeaderBuf::<16>::new();
         let mut r: Response<RECV_RESPONSE> = Response::new_test();
 
         let a = r.try_read_response(b"HTTP/1.1 404\r\n\r\n", &mut buf)?;
@@ -235,4 +865,597 @@ mod test {
         assert!(a.headers().unwrap().is_empty());
         Ok(())
     }
+
+    #[test]
+    fn test_max_body_size() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_max_body_size(10, true);
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(a.is_too_large());
+
+        let mut r = r.proceed();
+        let err = r.read_body(b"hello world", &mut buf).unwrap_err();
+        assert_eq!(err, HootError::BodyTooLarge);
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_max_body_size(10, true);
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(!a.is_too_large());
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_max_body_size(10, false);
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(a.is_too_large());
+        r.abort();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_body_progress() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n",
+            &mut headers,
+        )?;
+        let mut r = r.proceed();
+
+        let part = r.read_body(b"hello", &mut buf[..3])?;
+        assert_eq!(part.progress(), Progress::OutputFull);
+
+        let part = r.read_body(b"lo", &mut buf)?;
+        assert_eq!(part.progress(), Progress::NeedMoreInput);
+
+        let part = r.read_body(b"world", &mut buf)?;
+        assert_eq!(part.progress(), Progress::Finished);
+
+        let part = r.read_body(b"", &mut buf)?;
+        assert_eq!(part.progress(), Progress::Finished);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_trailers() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut headers,
+        )?;
+        let mut r = r.proceed();
+
+        let err = r.read_trailers(b"", &mut headers).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::BodyNotFinished);
+
+        let part = r.read_body(b"2\r\nhi\r\n0\r\nX-Sum: 1\r\n\r\n", &mut buf)?;
+        assert!(part.is_finished());
+        let used = part.input_used();
+        let rest = &b"2\r\nhi\r\n0\r\nX-Sum: 1\r\n\r\n"[used..];
+
+        assert!(r.read_trailers(&rest[..5], &mut headers)?.is_none());
+
+        let trailers = r.read_trailers(rest, &mut headers)?.unwrap();
+        assert_eq!(trailers.raw(), b"X-Sum: 1\r\n\r\n");
+        assert_eq!(trailers.headers()[0].name(), "X-Sum");
+        assert_eq!(trailers.input_used(), 12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        const INPUT: &[u8] =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Length: 2\r\n\r\n";
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        assert!(r.try_read_response(INPUT, &mut buf)?.is_success());
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_strict();
+        let err = r
+            .try_read_response(INPUT, &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            HootError::StrictViolation {
+                which: Violation::TransferEncodingAndContentLength
+            }
+        );
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_strict();
+        let err = r
+            .try_read_response(b"HTTP/1.1 200 OK\r\nX: 1\r\n 2\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            HootError::StrictViolation {
+                which: Violation::ObsFold
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_body_metadata() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", &mut buf)?;
+        assert_eq!(a.body_mode(), Some(RecvBodyMode::LengthDelimited(5)));
+        assert_eq!(a.content_length(), Some(5));
+        assert!(!a.is_chunked());
+        assert!(!a.must_close());
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: Close\r\n\r\n",
+            &mut buf,
+        )?;
+        assert_eq!(a.content_length(), None);
+        assert!(a.is_chunked());
+        assert!(a.must_close());
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n", &mut buf)?;
+        assert!(a.must_close());
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(b"HTTP/1.1 200 OK\r\n\r\n", &mut buf)?;
+        assert_eq!(a.body_mode(), Some(RecvBodyMode::CloseDelimited));
+        assert!(a.must_close());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_http09() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let err = r
+            .try_read_response(b"<html>hi</html>", &mut headers)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::Version { offset: 0 });
+
+        // Too short to tell.
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.accept_http09();
+        assert!(!r.try_read_response(b"HTT", &mut headers)?.is_success());
+
+        let a = r.try_read_response(b"HTTP/1.1 204 No Content\r\n\r\n", &mut headers)?;
+        assert!(a.is_success() && !a.is_http09());
+        assert_eq!(a.status().unwrap().code(), 204);
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.accept_http09();
+        let a = r.try_read_response(b"<html>", &mut headers)?;
+        assert!(a.is_success() && a.is_http09());
+        assert_eq!(a.input_used(), 0);
+        assert_eq!(a.body_mode(), Some(RecvBodyMode::CloseDelimited));
+        assert!(a.must_close());
+
+        let mut r = r.proceed();
+        let part = r.read_body(b"<html>hi</html>", &mut buf)?;
+        assert_eq!(part.data(), b"<html>hi</html>");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_declared() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.state.method = Some(crate::Method::HEAD);
+        let a = r.try_read_response(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", &mut buf)?;
+        assert_eq!(a.body_mode(), Some(RecvBodyMode::LengthDelimited(0)));
+        assert_eq!(
+            a.declared_body_mode(),
+            Some(RecvBodyMode::LengthDelimited(5))
+        );
+        assert_eq!(a.declared_content_length(), Some(5));
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.state.method = Some(crate::Method::HEAD);
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut buf,
+        )?;
+        assert!(!a.is_chunked());
+        assert_eq!(a.declared_body_mode(), Some(RecvBodyMode::Chunked));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_body_hashed() -> Result<()> {
+        struct Count(usize);
+
+        impl BodyHasher for Count {
+            fn update(&mut self, bytes: &[u8]) {
+                self.0 += bytes.len();
+            }
+        }
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut count = Count(0);
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut headers,
+        )?;
+        let mut r = r.proceed();
+        r.read_body_hashed(b"3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n", &mut buf, &mut count)?;
+        assert_eq!(count.0, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadline() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_deadline(Deadline {
+            headers: Some(100),
+            idle: Some(10),
+            ..Default::default()
+        });
+
+        r.set_now(0);
+        assert!(!r
+            .try_read_response(b"HTTP/1.1 200 OK\r\n", &mut headers)?
+            .is_success());
+        r.set_now(50);
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(a.is_success());
+
+        let mut r = r.proceed();
+        r.set_now(55);
+        r.read_body(b"he", &mut buf)?;
+        r.set_now(70);
+        let err = r.read_body(b"", &mut buf).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::Timeout {
+                which: DeadlinePhase::Idle
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes_remaining() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            &mut headers,
+        )?;
+        let mut r = r.proceed();
+        assert_eq!(r.bytes_remaining(), Some(5));
+        assert_eq!(r.chunk_remaining(), None);
+        r.read_body(b"hel", &mut buf)?;
+        assert_eq!(r.bytes_remaining(), Some(2));
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut headers,
+        )?;
+        let mut r = r.proceed();
+        assert_eq!(r.bytes_remaining(), None);
+        assert_eq!(r.chunk_remaining(), Some(0));
+        r.read_body(b"a\r\nhel", &mut buf)?;
+        assert_eq!(r.chunk_remaining(), Some(7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_length_policy() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\n";
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let err = r
+            .try_read_response(INPUT, &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::DuplicateContentLength);
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_content_length_policy(ContentLengthPolicy::AcceptIfIdentical);
+        assert!(r.try_read_response(INPUT, &mut buf)?.is_success());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        const INPUT: &[u8] =
+            b"HTTP/1.1  200  OK\nContent-Length : 2\nContent-Length: 2\nX: a\n b\n\nhi";
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        assert!(r.try_read_response(INPUT, &mut headers).is_err());
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_lenient();
+        let a = r.try_read_response(INPUT, &mut headers)?;
+        assert_eq!(a.status().unwrap().1, 200);
+        assert_eq!(a.input_used(), INPUT.len() - 2);
+        let folded = &a.headers().unwrap()[2];
+        assert_eq!(folded.value_raw(), b"a\n b");
+        let parts: Vec<_> = folded.value_unfolded().collect();
+        assert_eq!(parts, [&b"a"[..], &b"b"[..]]);
+
+        let mut r = r.proceed();
+        let part = r.read_body(b"hi", &mut buf)?;
+        assert!(part.is_finished());
+
+        // Differing lengths are never ok.
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_lenient();
+        let err = r
+            .try_read_response(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\n",
+                &mut headers,
+            )
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::DuplicateContentLength);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let limits = Limits {
+            max_headers: 1,
+            max_header_bytes: 32,
+            max_line: 20,
+            max_target: 20,
+            max_chunk_line: 3,
+        };
+        let exceeded = |which| Err(HootError::LimitExceeded { which });
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_limits(limits);
+        let a = r.try_read_response(b"HTTP/1.1 200 OK, but way too long", &mut headers);
+        assert_eq!(a.map(|_| ()), exceeded(Limit::Line));
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_limits(limits);
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nX-Long: 12345678901234567890123456789",
+            &mut headers,
+        );
+        assert_eq!(a.map(|_| ()), exceeded(Limit::HeaderBytes));
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_limits(limits);
+        let a = r.try_read_response(b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\n\r\n", &mut headers);
+        assert_eq!(a.map(|_| ()), exceeded(Limit::HeaderCount));
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        r.set_limits(limits);
+        r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut headers,
+        )?;
+        let mut r = r.proceed();
+        let a = r.read_body(b"0000", &mut buf);
+        assert_eq!(a.map(|_| ()), exceeded(Limit::ChunkLine));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_required() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(
+            b"HTTP/1.1 426 Upgrade Required\r\nUpgrade: HTTP/2.0, foo\r\n\
+            Connection: Upgrade\r\nContent-Length: 2\r\n\r\n",
+            &mut headers,
+        )?;
+
+        let mut protocols = a.upgrade_required().unwrap();
+        let p = protocols.next().unwrap();
+        assert_eq!((p.name(), p.version()), ("HTTP", Some("2.0")));
+        let p = protocols.next().unwrap();
+        assert_eq!((p.name(), p.version()), ("foo", None));
+        assert!(protocols.next().is_none());
+
+        // The body is read like any other response.
+        let mut r = r.proceed();
+        let part = r.read_body(b"no", &mut buf)?;
+        assert_eq!(&*part, b"no");
+        r.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_retry() -> Result<()> {
+        use crate::client::Request;
+
+        let mut buf = [0; 1024];
+        let output = Request::new(&mut buf)
+            .http_11()
+            .get("x", "/")?
+            .upgrade("TLS/1.2")?
+            .send()?
+            .flush();
+        assert_eq!(
+            &*output,
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade\r\nUpgrade: TLS/1.2\r\n\r\n"
+        );
+
+        const INPUT: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: TLS/1.2\r\n\
+            Connection: Upgrade\r\n\r\n\x16\x03\x03";
+
+        let mut r = output.into_response();
+        let mut headers = HeaderBuf::<16>::new();
+        let a = r.try_read_response(INPUT, &mut headers)?;
+        assert!(a.upgrade_required().is_none());
+        let p = a.switched_to().unwrap().next().unwrap();
+        assert_eq!((p.name(), p.version()), ("TLS", Some("1.2")));
+        assert_eq!(&INPUT[a.input_used()..], b"\x16\x03\x03");
+        r.abort();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a =
+            r.try_read_response(b"HTTP/1.1 200 OK\r\nUpgrade: TLS/1.2\r\n\r\n", &mut headers)?;
+        assert!(a.switched_to().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_codings() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n",
+            &mut buf,
+        )?;
+        let te = a.transfer_codings();
+        assert!(te.is_chunked());
+        assert_eq!(te.codings(), &[ContentEncoding::Gzip]);
+        assert_eq!(a.body_mode(), Some(RecvBodyMode::Chunked));
+
+        // Without final chunked, the body is read until close.
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let a = r.try_read_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nContent-Length: 5\r\n\r\n",
+            &mut buf,
+        )?;
+        assert_eq!(
+            a.transfer_codings().outermost(),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(a.body_mode(), Some(RecvBodyMode::CloseDelimited));
+
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let err = r
+            .try_read_response(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+                &mut buf,
+            )
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::ChunkedNotLast);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unused_input() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        const CHUNKED: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2\r\nhi\r\n0\r\nX-Sum: 1\r\n\r\nHTTP/1.1";
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let used = r.try_read_response(CHUNKED, &mut headers)?.input_used();
+        let mut r = r.proceed();
+        let part = r.read_body(&CHUNKED[used..], &mut buf)?;
+        let used = used + part.input_used();
+
+        r.read_trailers(&CHUNKED[used..], &mut headers)?.unwrap();
+        assert_eq!(r.finish()?.unused_input(), b"HTTP/1.1".len());
+
+        const LENGTH: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let used = r.try_read_response(LENGTH, &mut headers)?.input_used();
+        let mut r = r.proceed();
+        r.read_body(&LENGTH[used..], &mut buf)?;
+        assert_eq!(r.finish()?.unused_input(), 0);
+
+        // Input given after the end is all unused.
+        let mut r: Response<RECV_RESPONSE> = Response::new_test();
+        let used = r.try_read_response(LENGTH, &mut headers)?.input_used();
+        let mut r = r.proceed();
+        r.read_body(&LENGTH[used..], &mut buf)?;
+        r.read_body(b"HTTP/1.1", &mut buf)?;
+        assert_eq!(r.finish()?.unused_input(), 8);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excess_body_policy() -> Result<()> {
+        const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhiHTTP/1.1";
+        let mut buf = [0; 1024];
+
+        let read = |policy: Option<ExcessBodyPolicy>, buf: &mut [u8]| {
+            let mut r: Response<RECV_RESPONSE> = Response::new_test();
+            if let Some(p) = policy {
+                r.set_excess_body_policy(p);
+            }
+            let mut headers = HeaderBuf::<16>::new();
+            let used = r.try_read_response(INPUT, &mut headers)?.input_used();
+            let mut r = r.proceed();
+            let part = r.read_body(&INPUT[used..], buf)?;
+            assert_eq!(&*part, b"hi");
+            let used = part.input_used();
+            Ok::<_, HootError>((used, r.finish()?.unused_input()))
+        };
+
+        let err = read(None, &mut buf).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::RecvMoreThanContentLength {
+                expected: 2,
+                actual: 10
+            }
+        );
+
+        assert_eq!(read(Some(ExcessBodyPolicy::Ignore), &mut buf)?, (10, 0));
+        assert_eq!(read(Some(ExcessBodyPolicy::Preserve), &mut buf)?, (2, 8));
+
+        Ok(())
+    }
 }
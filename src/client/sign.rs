@@ -0,0 +1,185 @@
+use core::fmt;
+use core::str;
+
+use crate::out::{Count, Writer};
+use crate::{HootError, Result};
+
+/// The parts of a request that a signature covers, such as for AWS SigV4 or
+/// an HMAC of the request.
+///
+/// Made by `Request::sign()` from the head in the output buffer, so the
+/// request is not kept twice to be signed.
+#[derive(Debug, Clone, Copy)]
+pub struct CanonicalRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    query: &'a str,
+    // Header lines after the request line.
+    headers: &'a str,
+    signed: &'a [&'a str],
+    body_hash: &'a str,
+}
+
+impl<'a> CanonicalRequest<'a> {
+    /// Parse the head written so far. Every header in `signed` must be set.
+    fn parse(head: &'a [u8], signed: &'a [&'a str], body_hash: &'a str) -> Result<Self> {
+        // The head was checked when written, except with header_bytes().
+        let head = str::from_utf8(head).map_err(|_| HootError::Sign)?;
+        let (line, headers) = head.split_once("\r\n").ok_or(HootError::Sign)?;
+
+        // Anything else than a request line means it was already flushed.
+        let mut parts = line.splitn(3, ' ');
+        let method = parts.next().unwrap_or("");
+        let target = parts.next().unwrap_or("");
+        let version = parts.next().unwrap_or("");
+        if method.is_empty() || target.is_empty() || !version.starts_with("HTTP/1.") {
+            return Err(HootError::Sign);
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let request = CanonicalRequest {
+            method,
+            path,
+            query,
+            headers,
+            signed,
+            body_hash,
+        };
+
+        if signed.iter().any(|n| request.header(n).is_none()) {
+            return Err(HootError::Sign);
+        }
+
+        Ok(request)
+    }
+
+    /// The method, such as `GET`.
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// The path of the target as sent, that is percent-encoded.
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+
+    /// The query of the target as sent, without the `?`. Empty if none.
+    pub fn query(&self) -> &'a str {
+        self.query
+    }
+
+    /// The names of the headers to sign, as given to `Request::sign()`.
+    pub fn signed_headers(&self) -> &'a [&'a str] {
+        self.signed
+    }
+
+    /// The first value of the header `name`, ignoring case, trimmed.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers(name).next()
+    }
+
+    /// All values of the header `name`, ignoring case, trimmed and in order.
+    pub fn headers<'n>(&self, name: &'n str) -> impl Iterator<Item = &'a str> + 'n
+    where
+        'a: 'n,
+    {
+        self.headers
+            .split("\r\n")
+            .filter_map(|l| l.split_once(':'))
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.trim())
+    }
+
+    /// The hash of the body, as given to `Request::sign()`.
+    pub fn body_hash(&self) -> &'a str {
+        self.body_hash
+    }
+}
+
+/// Produces the signature header of a request, see `Request::sign()`.
+///
+/// hoot has no cryptography. The signer does the hashing and HMAC, and
+/// holds the keys.
+pub trait RequestSigner {
+    /// Write the value of the signature header for `request` to `out`.
+    ///
+    /// An error other than from `out` fails with `HootError::Sign`.
+    fn sign(&mut self, request: &CanonicalRequest<'_>, out: &mut dyn fmt::Write) -> fmt::Result;
+}
+
+/// Signs each chunk of a chunked body, see `Request::write_chunk_signed()`.
+///
+/// Schemes like AWS SigV4 streaming chain each chunk signature to the one
+/// before, see `crypto::AwsChunkSigner` with the `crypto` feature.
+pub trait ChunkSigner {
+    /// Write the chunk extension for `data`, such as `chunk-signature=…`.
+    ///
+    /// The final empty chunk is signed too. This is called again for the same
+    /// chunk if it did not fit in the buffer, so the state to chain must not
+    /// change until `written()`.
+    fn sign_chunk(&mut self, data: &[u8], out: &mut dyn fmt::Write) -> fmt::Result;
+
+    /// The chunk last signed was written.
+    fn written(&mut self) {}
+}
+
+/// Write the signature of the request in `head` as a header value.
+pub(crate) fn write_signature(
+    head: &[u8],
+    signed: &[&str],
+    body_hash: &str,
+    signer: &mut dyn RequestSigner,
+    w: &mut Writer,
+) -> Result<()> {
+    let request = CanonicalRequest::parse(head, signed, body_hash)?;
+    write_signed(w, |out| signer.sign(&request, out))
+}
+
+/// Write a chunk with the extension from `signer`. Empty `data` is the final
+/// chunk, including the end of the body.
+pub(crate) fn write_signed_chunk(
+    w: &mut Writer,
+    data: &[u8],
+    signer: &mut dyn ChunkSigner,
+) -> Result<()> {
+    write!(w, "{:x};", data.len())?;
+
+    w.write_after(|_, w| {
+        write_signed(w, |out| signer.sign_chunk(data, out))?;
+
+        let (ext, _) = w.split_and_borrow();
+        if ext.iter().any(|b| *b < 0x20 || *b == 0x7f) {
+            return Err(HootError::Sign);
+        }
+
+        Ok(())
+    })?;
+
+    write!(w, "\r\n")?;
+    w.write_bytes(data)?;
+    write!(w, "\r\n")?;
+
+    Ok(())
+}
+
+/// Write the output of a signer, which is called twice on overflow.
+fn write_signed(
+    w: &mut Writer,
+    mut sign: impl FnMut(&mut dyn fmt::Write) -> fmt::Result,
+) -> Result<()> {
+    let available = w.available();
+    if sign(w).is_ok() {
+        return Ok(());
+    }
+
+    // Sign again to know the length, like Writer::write_fmt().
+    let mut count = Count(0);
+    if sign(&mut count).is_err() {
+        return Err(HootError::Sign);
+    }
+
+    Err(HootError::OutputOverflow {
+        needed: count.0.saturating_sub(available).max(1),
+    })
+}
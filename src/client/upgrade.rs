@@ -0,0 +1,75 @@
+use core::fmt;
+use core::str::Split;
+
+/// A protocol from an `Upgrade` header, such as `HTTP/2.0` or `TLS/1.2`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Protocol<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+}
+
+impl<'a> Protocol<'a> {
+    pub(crate) fn parse(s: &'a str) -> Self {
+        match s.split_once('/') {
+            Some((name, version)) => Protocol {
+                name: name.trim(),
+                version: Some(version.trim()),
+            },
+            None => Protocol {
+                name: s.trim(),
+                version: None,
+            },
+        }
+    }
+
+    /// Protocol name, such as `HTTP`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Protocol version, such as `2.0`.
+    pub fn version(&self) -> Option<&'a str> {
+        self.version
+    }
+}
+
+/// Protocols of an `Upgrade` header, such as required by a `426 Upgrade
+/// Required` response, or offered by a request.
+///
+/// Iterates the `Upgrade` header in order of preference.
+#[derive(Clone)]
+pub struct Protocols<'a>(Split<'a, char>);
+
+impl<'a> Protocols<'a> {
+    pub(crate) fn new(value: &'a str) -> Self {
+        Protocols(value.split(','))
+    }
+}
+
+impl<'a> Iterator for Protocols<'a> {
+    type Item = Protocol<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let s = self.0.next()?;
+            if !s.trim().is_empty() {
+                return Some(Protocol::parse(s));
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Protocol<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(v) => write!(f, "{}/{}", self.name, v),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Protocols<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
@@ -0,0 +1,637 @@
+//! Decoding of `Content-Encoding: gzip` and `deflate` bodies.
+//!
+//! Enabled with the `compression` feature. The [`Decompressor`] holds all state
+//! needed to inflate a body, including the 32KiB DEFLATE window. It is big, and
+//! kept outside of the request/response state machines, so the user decides
+//! where it lives (stack, static or heap).
+//!
+//! The [`Compressor`], for sending encoded bodies, additionally requires the
+//! `std` feature since the underlying DEFLATE implementation allocates.
+
+use miniz_oxide::inflate::core::inflate_flags::{
+    TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_PARSE_ZLIB_HEADER,
+};
+use miniz_oxide::inflate::core::{decompress, DecompressorOxide, TINFL_LZ_DICT_SIZE};
+use miniz_oxide::inflate::TINFLStatus;
+
+use crate::{BodyTransform, ContentEncoding, HootError, Result};
+
+/// Size of the buffer holding body bytes that are not yet inflated.
+const STAGING_SIZE: usize = 1024;
+
+/// Value for an `Accept-Encoding` header matching what [`Decompressor`] decodes.
+pub const ACCEPT_ENCODING: &str = "gzip, deflate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Expecting the gzip header, or the start of a deflate stream.
+    Start,
+    /// Inflating. The bool is whether the stream has a zlib wrapper.
+    Inflate(bool),
+    /// Skipping the gzip CRC32/ISIZE trailer.
+    Trailer(usize),
+    Ended,
+}
+
+/// Inflates gzip/deflate encoded bodies.
+///
+/// This is a [`BodyTransform`] used with `read_body_with()`. Bodies without a
+/// gzip/deflate `Content-Encoding` pass through untouched.
+pub struct Decompressor {
+    inflater: DecompressorOxide,
+    window: [u8; TINFL_LZ_DICT_SIZE],
+    /// Position in window for the next inflated byte.
+    window_pos: usize,
+    /// Inflated bytes in window (ending at window_pos) not yet handed out.
+    pending: usize,
+    staging: [u8; STAGING_SIZE],
+    staging_len: usize,
+    stage: Stage,
+    encoding: ContentEncoding,
+    keep_raw: bool,
+}
+
+impl Decompressor {
+    pub fn new() -> Self {
+        Decompressor {
+            inflater: DecompressorOxide::new(),
+            window: [0; TINFL_LZ_DICT_SIZE],
+            window_pos: 0,
+            pending: 0,
+            staging: [0; STAGING_SIZE],
+            staging_len: 0,
+            stage: Stage::Start,
+            encoding: ContentEncoding::Gzip,
+            keep_raw: false,
+        }
+    }
+
+    /// Do not decode, but output the raw (encoded) body bytes.
+    pub fn set_keep_raw(&mut self, keep_raw: bool) {
+        self.keep_raw = keep_raw;
+    }
+
+    /// Reset to decode another body.
+    pub fn reset(&mut self) {
+        self.inflater.init();
+        self.window_pos = 0;
+        self.pending = 0;
+        self.staging_len = 0;
+        self.stage = Stage::Start;
+    }
+
+    fn consume_staging(&mut self, amount: usize) {
+        self.staging.copy_within(amount..self.staging_len, 0);
+        self.staging_len -= amount;
+    }
+
+    /// Copy pending inflated bytes out of the window.
+    fn drain(&mut self, dst: &mut [u8]) -> usize {
+        let amount = self.pending.min(dst.len());
+        let start = self.window_pos - self.pending;
+
+        dst[..amount].copy_from_slice(&self.window[start..(start + amount)]);
+        self.pending -= amount;
+
+        amount
+    }
+
+    fn inflate_into(&mut self, dst: &mut [u8], input_ended: bool) -> Result<usize> {
+        let mut written = 0;
+
+        loop {
+            written += self.drain(&mut dst[written..]);
+
+            if self.pending > 0 || written == dst.len() {
+                break;
+            }
+
+            let staged = &self.staging[..self.staging_len];
+
+            match self.stage {
+                Stage::Start => {
+                    let (used, zlib) = match self.encoding {
+                        ContentEncoding::Gzip => match gzip_header_len(staged)? {
+                            Some(n) => (n, false),
+                            None if input_ended => return Err(HootError::Decompress),
+                            None => break,
+                        },
+                        _ => {
+                            if staged.len() < 2 {
+                                if input_ended {
+                                    return Err(HootError::Decompress);
+                                }
+                                break;
+                            }
+                            (0, is_zlib_header(staged[0], staged[1]))
+                        }
+                    };
+
+                    trace!("Start inflate (zlib: {})", zlib);
+                    self.consume_staging(used);
+                    self.stage = Stage::Inflate(zlib);
+                }
+
+                Stage::Inflate(zlib) => {
+                    // The window is only written from window_pos to its end. Once at
+                    // the end, we wrap around to write from the beginning again.
+                    if self.window_pos == self.window.len() {
+                        self.window_pos = 0;
+                    }
+
+                    let mut flags = 0;
+                    if zlib {
+                        flags |= TINFL_FLAG_PARSE_ZLIB_HEADER;
+                    }
+                    if !input_ended {
+                        flags |= TINFL_FLAG_HAS_MORE_INPUT;
+                    }
+
+                    let (status, used, produced) = decompress(
+                        &mut self.inflater,
+                        staged,
+                        &mut self.window,
+                        self.window_pos,
+                        flags,
+                    );
+
+                    self.consume_staging(used);
+                    self.window_pos += produced;
+                    self.pending = produced;
+
+                    match status {
+                        TINFLStatus::Done => {
+                            self.stage = match self.encoding {
+                                ContentEncoding::Gzip => Stage::Trailer(8),
+                                _ => Stage::Ended,
+                            };
+                        }
+                        TINFLStatus::HasMoreOutput => {}
+                        TINFLStatus::NeedsMoreInput => {
+                            if used == 0 && produced == 0 {
+                                break;
+                            }
+                        }
+                        _ => return Err(HootError::Decompress),
+                    }
+                }
+
+                Stage::Trailer(left) => {
+                    let amount = left.min(self.staging_len);
+                    self.consume_staging(amount);
+
+                    let left = left - amount;
+                    // Tolerate a missing trailer when the body ended.
+                    self.stage = if left == 0 || input_ended {
+                        Stage::Ended
+                    } else {
+                        Stage::Trailer(left)
+                    };
+
+                    if self.stage != Stage::Ended {
+                        break;
+                    }
+                }
+
+                Stage::Ended => break,
+            }
+        }
+
+        Ok(written)
+    }
+}
+
+impl Default for Decompressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BodyTransform for Decompressor {
+    fn select(&mut self, encoding: Option<ContentEncoding>) -> bool {
+        match encoding {
+            Some(e @ (ContentEncoding::Gzip | ContentEncoding::Deflate)) if !self.keep_raw => {
+                self.encoding = e;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn input_capacity(&self) -> usize {
+        STAGING_SIZE - self.staging_len
+    }
+
+    fn push_input(&mut self, input: &[u8]) -> Result<()> {
+        push_staging(&mut self.staging, &mut self.staging_len, input)
+    }
+
+    fn pull_output(&mut self, dst: &mut [u8], input_ended: bool) -> Result<usize> {
+        self.inflate_into(dst, input_ended)
+    }
+
+    fn is_ended(&self) -> bool {
+        self.stage == Stage::Ended && self.pending == 0
+    }
+}
+
+/// Append `input` to the staging buffer, failing if it's more than
+/// `input_capacity()`.
+fn push_staging(staging: &mut [u8], len: &mut usize, input: &[u8]) -> Result<()> {
+    no_panic!({
+        let free = match staging.get_mut(*len..) {
+            Some(v) => v,
+            None => return Err(HootError::Internal),
+        };
+        if input.len() > free.len() {
+            return Err(HootError::OutputOverflow {
+                needed: input.len() - free.len(),
+            });
+        }
+
+        free[..input.len()].copy_from_slice(input);
+        *len += input.len();
+        Ok(())
+    })
+}
+
+#[cfg(feature = "std")]
+pub use self::compress::Compressor;
+
+#[cfg(feature = "std")]
+mod compress {
+    use miniz_oxide::deflate::core::{compress, create_comp_flags_from_zip_params};
+    use miniz_oxide::deflate::core::{CompressorOxide, TDEFLFlush, TDEFLStatus};
+
+    use super::{push_staging, STAGING_SIZE};
+    use crate::{BodyTransform, ContentEncoding, HootError, Result};
+
+    // Minimal gzip header. No mtime, unknown OS.
+    const GZIP_HEADER: &[u8] = &[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Stage {
+        Header(usize),
+        Deflate,
+        Trailer(usize),
+        Ended,
+    }
+
+    /// Deflates bodies to send as `Content-Encoding: gzip` or `deflate`.
+    ///
+    /// This is a [`BodyTransform`] used with `send_encoded()` or
+    /// `with_encoded_chunked()`.
+    pub struct Compressor {
+        inner: CompressorOxide,
+        level: u8,
+        staging: [u8; STAGING_SIZE],
+        staging_len: usize,
+        stage: Stage,
+        encoding: ContentEncoding,
+        crc: u32,
+        size: u32,
+    }
+
+    impl Compressor {
+        /// Create a compressor with a compression level 0-10.
+        pub fn new(level: u8) -> Self {
+            Compressor {
+                inner: CompressorOxide::new(0),
+                level: level.min(10),
+                staging: [0; STAGING_SIZE],
+                staging_len: 0,
+                stage: Stage::Ended,
+                encoding: ContentEncoding::Gzip,
+                crc: 0,
+                size: 0,
+            }
+        }
+
+        fn consume_staging(&mut self, amount: usize) {
+            self.staging.copy_within(amount..self.staging_len, 0);
+            self.staging_len -= amount;
+        }
+
+        fn trailer(&self) -> [u8; 8] {
+            let mut t = [0; 8];
+            t[..4].copy_from_slice(&self.crc.to_le_bytes());
+            t[4..].copy_from_slice(&self.size.to_le_bytes());
+            t
+        }
+    }
+
+    impl BodyTransform for Compressor {
+        fn select(&mut self, encoding: Option<ContentEncoding>) -> bool {
+            let (encoding, stage, window_bits) = match encoding {
+                // gzip wraps a raw deflate stream.
+                Some(e @ ContentEncoding::Gzip) => (e, Stage::Header(0), -15),
+                // deflate is really the zlib format.
+                Some(e @ ContentEncoding::Deflate) => (e, Stage::Deflate, 15),
+                _ => return false,
+            };
+
+            let flags = create_comp_flags_from_zip_params(self.level as i32, window_bits, 0);
+            self.inner = CompressorOxide::new(flags);
+            self.encoding = encoding;
+            self.stage = stage;
+            self.staging_len = 0;
+            self.crc = 0;
+            self.size = 0;
+
+            true
+        }
+
+        fn input_capacity(&self) -> usize {
+            STAGING_SIZE - self.staging_len
+        }
+
+        fn push_input(&mut self, input: &[u8]) -> Result<()> {
+            push_staging(&mut self.staging, &mut self.staging_len, input)
+        }
+
+        fn pull_output(&mut self, dst: &mut [u8], input_ended: bool) -> Result<usize> {
+            let mut written = 0;
+
+            while written < dst.len() {
+                match self.stage {
+                    Stage::Header(pos) => {
+                        let amount = (GZIP_HEADER.len() - pos).min(dst.len() - written);
+                        dst[written..(written + amount)]
+                            .copy_from_slice(&GZIP_HEADER[pos..(pos + amount)]);
+                        written += amount;
+
+                        let pos = pos + amount;
+                        self.stage = if pos == GZIP_HEADER.len() {
+                            Stage::Deflate
+                        } else {
+                            Stage::Header(pos)
+                        };
+                    }
+
+                    Stage::Deflate => {
+                        if !input_ended && self.staging_len == 0 {
+                            break;
+                        }
+
+                        let flush = if input_ended {
+                            TDEFLFlush::Finish
+                        } else {
+                            TDEFLFlush::None
+                        };
+
+                        let input = &self.staging[..self.staging_len];
+                        let (status, used, produced) =
+                            compress(&mut self.inner, input, &mut dst[written..], flush);
+
+                        self.crc = crc32(self.crc, &input[..used]);
+                        self.size = self.size.wrapping_add(used as u32);
+                        self.consume_staging(used);
+                        written += produced;
+
+                        match status {
+                            TDEFLStatus::Done => {
+                                self.stage = match self.encoding {
+                                    ContentEncoding::Gzip => Stage::Trailer(0),
+                                    _ => Stage::Ended,
+                                };
+                            }
+                            TDEFLStatus::Okay => {
+                                if used == 0 && produced == 0 {
+                                    break;
+                                }
+                            }
+                            _ => return Err(HootError::Compress),
+                        }
+                    }
+
+                    Stage::Trailer(pos) => {
+                        let trailer = self.trailer();
+                        let amount = (trailer.len() - pos).min(dst.len() - written);
+                        dst[written..(written + amount)]
+                            .copy_from_slice(&trailer[pos..(pos + amount)]);
+                        written += amount;
+
+                        let pos = pos + amount;
+                        self.stage = if pos == trailer.len() {
+                            Stage::Ended
+                        } else {
+                            Stage::Trailer(pos)
+                        };
+                    }
+
+                    Stage::Ended => break,
+                }
+            }
+
+            Ok(written)
+        }
+
+        fn is_ended(&self) -> bool {
+            self.stage == Stage::Ended
+        }
+    }
+
+    fn crc32(crc: u32, data: &[u8]) -> u32 {
+        let mut crc = !crc;
+        for b in data {
+            crc ^= *b as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+        !crc
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_crc32() {
+            assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        }
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc1950#section-2.2
+fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    let is_deflate = cmf & 0x0f == 8;
+    let check = (cmf as u16) << 8 | flg as u16;
+    is_deflate && check % 31 == 0
+}
+
+// https://www.rfc-editor.org/rfc/rfc1952#section-2.3
+fn gzip_header_len(buf: &[u8]) -> Result<Option<usize>> {
+    const FHCRC: u8 = 2;
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+
+    if buf.len() < 10 {
+        return Ok(None);
+    }
+
+    if buf[0] != 0x1f || buf[1] != 0x8b || buf[2] != 8 {
+        return Err(HootError::Decompress);
+    }
+
+    let flags = buf[3];
+    let mut n = 10;
+
+    if flags & FEXTRA > 0 {
+        if buf.len() < n + 2 {
+            return Ok(None);
+        }
+        n += 2 + u16::from_le_bytes([buf[n], buf[n + 1]]) as usize;
+    }
+
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag > 0 {
+            let rest = buf.get(n..).unwrap_or(&[]);
+            match rest.iter().position(|c| *c == 0) {
+                Some(i) => n += i + 1,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    if flags & FHCRC > 0 {
+        n += 2;
+    }
+
+    if buf.len() < n {
+        return Ok(None);
+    }
+
+    Ok(Some(n))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::body::{do_read_body_transformed, RecvBodyMode};
+    use crate::util::HeaderBuf;
+    use crate::util::LengthChecker;
+    use crate::CallState;
+
+    const PLAIN: &[u8] = b"Hello hoot! Hello hoot! Hello hoot! Hello hoot! ";
+
+    const GZIP: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xf3\x48\xcd\xc9\xc9\x57\
+        \xc8\xc8\xcf\x2f\x51\x54\xf0\x20\x82\x0d\x00\x9d\xe7\x70\xb7\x30\x00\x00\x00";
+
+    const ZLIB: &[u8] = b"\x78\x9c\xf3\x48\xcd\xc9\xc9\x57\xc8\xc8\xcf\x2f\x51\x54\xf0\x20\
+        \x82\x0d\x00\x95\x17\x10\x3d";
+
+    const RAW: &[u8] = b"\xf3\x48\xcd\xc9\xc9\x57\xc8\xc8\xcf\x2f\x51\x54\xf0\x20\x82\x0d\x00";
+
+    fn state(encoding: ContentEncoding, len: usize) -> CallState {
+        CallState {
+            recv_body_mode: Some(RecvBodyMode::LengthDelimited(len as u64)),
+            recv_checker: Some(LengthChecker::new(len as u64)),
+            content_encoding: Some(encoding),
+            ..Default::default()
+        }
+    }
+
+    fn decode_all(encoding: ContentEncoding, input: &[u8], step: usize) -> Vec<u8> {
+        let mut state = state(encoding, input.len());
+        let enc = Some(encoding);
+        let mut dec = Box::new(Decompressor::new());
+        let mut dst = [0; 7];
+        let mut output = vec![];
+        let mut pos = 0;
+
+        loop {
+            let end = (pos + step).min(input.len());
+            let part =
+                do_read_body_transformed(&mut state, &input[pos..end], &mut dst, &mut *dec, enc)
+                    .unwrap();
+            pos += part.input_used();
+            output.extend_from_slice(&part);
+            if part.is_finished() {
+                break;
+            }
+        }
+
+        assert_eq!(pos, input.len());
+        output
+    }
+
+    #[test]
+    fn gzip() {
+        assert_eq!(decode_all(ContentEncoding::Gzip, GZIP, 1000), PLAIN);
+        assert_eq!(decode_all(ContentEncoding::Gzip, GZIP, 3), PLAIN);
+    }
+
+    #[test]
+    fn deflate_zlib_and_raw() {
+        assert_eq!(decode_all(ContentEncoding::Deflate, ZLIB, 1000), PLAIN);
+        assert_eq!(decode_all(ContentEncoding::Deflate, RAW, 1), PLAIN);
+    }
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut body = vec![];
+        for i in 0..5000 {
+            body.extend_from_slice(format!("line {}\n", i).as_bytes());
+        }
+
+        for encoding in [ContentEncoding::Gzip, ContentEncoding::Deflate] {
+            let mut comp = Box::new(Compressor::new(6));
+            assert!(comp.select(Some(encoding)));
+
+            let mut encoded = vec![];
+            let mut input = &body[..];
+            let mut buf = [0; 333];
+            loop {
+                let amount = comp.input_capacity().min(input.len());
+                comp.push_input(&input[..amount]).unwrap();
+                input = &input[amount..];
+                let n = comp.pull_output(&mut buf, input.is_empty()).unwrap();
+                encoded.extend_from_slice(&buf[..n]);
+                if comp.is_ended() {
+                    break;
+                }
+            }
+            assert!(encoded.len() < body.len());
+
+            assert_eq!(decode_all(encoding, &encoded, 100), body);
+        }
+    }
+
+    #[test]
+    fn push_over_capacity() {
+        let mut dec = Box::new(Decompressor::new());
+        dec.push_input(&[0; 1000]).unwrap();
+        assert_eq!(dec.input_capacity(), 24);
+
+        let err = dec.push_input(&[0; 30]).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 6 });
+        assert_eq!(dec.input_capacity(), 24);
+    }
+
+    #[test]
+    fn keep_raw() {
+        let mut state = state(ContentEncoding::Gzip, GZIP.len());
+        let mut dec = Box::new(Decompressor::new());
+        dec.set_keep_raw(true);
+        let mut dst = [0; 1024];
+
+        let enc = Some(ContentEncoding::Gzip);
+        let part = do_read_body_transformed(&mut state, GZIP, &mut dst, &mut *dec, enc).unwrap();
+        assert_eq!(&*part, GZIP);
+        assert!(part.is_finished());
+    }
+
+    #[test]
+    fn detect_encoding() {
+        let mut buf = HeaderBuf::<16>::new();
+        let headers =
+            crate::parser::parse_headers(b"Content-Encoding: GZIP\r\n\r\n", &mut buf).unwrap();
+        assert_eq!(
+            ContentEncoding::from_headers(headers),
+            Some(ContentEncoding::Gzip)
+        );
+    }
+}
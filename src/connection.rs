@@ -0,0 +1,158 @@
+use crate::util::compare_lowercase_ascii;
+use crate::{Header, HttpVersion};
+
+/// The options of the `Connection` header(s), RFC 7230 §6.1.
+///
+/// Besides `close`, `keep-alive` and `upgrade`, the header lists the names of
+/// headers that only apply to the current connection. Proxies must remove
+/// those, and the standard hop-by-hop headers, before forwarding a message.
+///
+/// ```
+/// use hoot::ConnectionOptions;
+/// # use hoot::Header;
+/// # fn forward(headers: &[Header]) {
+/// let options = ConnectionOptions::new(headers);
+///
+/// for header in options.end_to_end() {
+///     // write header to the next hop
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionOptions<'a, 'b> {
+    headers: &'b [Header<'a>],
+    close: bool,
+    keep_alive: bool,
+    upgrade: bool,
+}
+
+// Headers that are always hop-by-hop, RFC 7230 §6.1 and RFC 2616 §13.5.1.
+const HEADERS_HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+impl<'a, 'b> ConnectionOptions<'a, 'b> {
+    /// Interpret the `Connection` headers among `headers`.
+    pub fn new(headers: &'b [Header<'a>]) -> Self {
+        let mut ret = ConnectionOptions {
+            headers,
+            close: false,
+            keep_alive: false,
+            upgrade: false,
+        };
+
+        for token in ret.tokens() {
+            if compare_lowercase_ascii(token, "close") {
+                ret.close = true;
+            } else if compare_lowercase_ascii(token, "keep-alive") {
+                ret.keep_alive = true;
+            } else if compare_lowercase_ascii(token, "upgrade") {
+                ret.upgrade = true;
+            }
+        }
+
+        ret
+    }
+
+    /// All tokens of all `Connection` headers, in order.
+    ///
+    /// Values that are not UTF-8 are skipped.
+    pub fn tokens(&self) -> impl Iterator<Item = &'b str> {
+        self.headers
+            .iter()
+            .filter(|h| compare_lowercase_ascii(h.name(), "connection"))
+            .filter_map(|h| h.try_value())
+            .flat_map(|v| v.split(','))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+
+    /// Whether `close` is listed.
+    pub fn is_close(&self) -> bool {
+        self.close
+    }
+
+    /// Whether `keep-alive` is listed.
+    pub fn is_keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// Whether `upgrade` is listed, meaning the `Upgrade` header applies.
+    pub fn is_upgrade(&self) -> bool {
+        self.upgrade
+    }
+
+    /// Whether the connection persists after a message of `version`.
+    ///
+    /// HTTP/1.1 persists unless `close` is listed, HTTP/1.0 only if
+    /// `keep-alive` is listed.
+    pub fn is_persistent(&self, version: HttpVersion) -> bool {
+        if self.close {
+            return false;
+        }
+        version == HttpVersion::Http11 || self.keep_alive
+    }
+
+    /// Whether the header `name` only applies to the current connection.
+    ///
+    /// This is the case for the standard hop-by-hop headers, such as
+    /// `Transfer-Encoding`, and headers listed in `Connection`.
+    pub fn is_hop_by_hop(&self, name: &str) -> bool {
+        HEADERS_HOP_BY_HOP
+            .iter()
+            .any(|n| compare_lowercase_ascii(name, n))
+            || self.tokens().any(|t| t.eq_ignore_ascii_case(name))
+    }
+
+    /// The headers that only apply to the current connection.
+    pub fn hop_by_hop(&self) -> impl Iterator<Item = &'b Header<'a>> + '_ {
+        self.headers.iter().filter(|h| self.is_hop_by_hop(h.name()))
+    }
+
+    /// The headers to forward to the next hop.
+    pub fn end_to_end(&self) -> impl Iterator<Item = &'b Header<'a>> + '_ {
+        self.headers
+            .iter()
+            .filter(|h| !self.is_hop_by_hop(h.name()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_headers;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn connection_options() {
+        let mut buf = HeaderBuf::<16>::new();
+        let headers = parse_headers(
+            b"Connection: Keep-Alive, X-Secret\r\nconnection: upgrade\r\n\
+            X-Secret: 1\r\nUpgrade: websocket\r\nAccept: */*\r\n\r\n",
+            &mut buf,
+        )
+        .unwrap();
+
+        let c = ConnectionOptions::new(headers);
+        assert!(c.is_keep_alive());
+        assert!(c.is_upgrade());
+        assert!(!c.is_close());
+        assert!(c.is_persistent(HttpVersion::Http10));
+
+        assert!(c.is_hop_by_hop("x-secret"));
+        assert!(c.is_hop_by_hop("Transfer-Encoding"));
+        assert!(!c.is_hop_by_hop("accept"));
+
+        let names: Vec<_> = c.end_to_end().map(|h| h.name()).collect();
+        assert_eq!(names, ["Accept"]);
+        assert_eq!(c.hop_by_hop().count(), 4);
+    }
+}
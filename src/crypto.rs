@@ -0,0 +1,273 @@
+//! Hashing and signing of bodies, with the RustCrypto traits.
+//!
+//! hoot brings no hash functions of its own. Plug in any [`Digest`] and
+//! [`Mac`], such as `Sha256` of the `sha2` crate and `Hmac<Sha256>` of `hmac`.
+//!
+//! A header like `x-amz-content-sha256` needs the hash of the body before
+//! the body is sent. For a body that fits in memory, hash it with
+//! [`hex_digest()`] first. A streamed body can instead be:
+//!
+//! * Hashed as it is written with [`DigestHasher`], and the hash sent as a
+//!   trailer, for `STREAMING-UNSIGNED-PAYLOAD-TRAILER`.
+//! * Signed chunk by chunk with [`AwsChunkSigner`], for
+//!   `STREAMING-AWS4-HMAC-SHA256-PAYLOAD`.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::str;
+
+use digest::{Digest, Mac};
+
+use crate::client::ChunkSigner;
+use crate::{BodyHasher, HootError, Result};
+
+/// Longest hex digest, for 512 bit hashes.
+const MAX_HEX: usize = 128;
+
+/// Streaming hash of a body, for `write_bytes_hashed()` and
+/// `write_chunk_hashed()`.
+///
+/// ```
+/// use hoot::client::Request;
+/// use hoot::crypto::DigestHasher;
+/// use sha2::Sha256;
+///
+/// let mut buf = [0; 1024];
+/// let mut request = Request::new(&mut buf)
+///     .http_11()
+///     .put("x.test", "/a")?
+///     .header("x-amz-content-sha256", "STREAMING-UNSIGNED-PAYLOAD-TRAILER")?
+///     .header("x-amz-trailer", "x-amz-checksum-sha256")?
+///     .with_chunked()?;
+///
+/// let mut hasher = DigestHasher::<Sha256>::new();
+/// request.write_chunk_hashed(b"hello", &mut hasher)?;
+///
+/// let mut hex = [0; 64];
+/// let output = request
+///     .with_trailer()?
+///     .trailer("x-amz-checksum-sha256", hasher.finalize_hex(&mut hex)?)?
+///     .finish()?
+///     .flush();
+///
+/// assert!(output.ends_with(b"x-amz-checksum-sha256: \
+///     2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824\r\n\r\n"));
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DigestHasher<D>(D);
+
+impl<D: Digest> DigestHasher<D> {
+    pub fn new() -> Self {
+        DigestHasher(D::new())
+    }
+
+    /// The hash of the body so far as lower case hex, written to `buf`.
+    pub fn finalize_hex(self, buf: &mut [u8]) -> Result<&str> {
+        hex(&self.0.finalize(), buf)
+    }
+}
+
+impl<D: Digest> BodyHasher for DigestHasher<D> {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+/// The hash of `bytes` as lower case hex, written to `buf`.
+///
+/// Such as for `x-amz-content-sha256` of a body that is sent whole.
+pub fn hex_digest<'b, D: Digest>(bytes: &[u8], buf: &'b mut [u8]) -> Result<&'b str> {
+    hex(&D::digest(bytes), buf)
+}
+
+/// Chunk signatures of AWS SigV4 streaming uploads,
+/// `STREAMING-AWS4-HMAC-SHA256-PAYLOAD`.
+///
+/// The signature of each chunk covers its hash and the signature before it,
+/// starting from the signature of the request head. `mac` is keyed with the
+/// SigV4 signing key, and `D` hashes the chunks. Those are `Hmac<Sha256>` and
+/// `Sha256` for AWS.
+///
+/// The request head is signed as usual with `Request::sign()`, with
+/// `Content-Encoding: aws-chunked` and `x-amz-decoded-content-length` set.
+pub struct AwsChunkSigner<'a, M, D> {
+    mac: M,
+    timestamp: &'a str,
+    scope: &'a str,
+    prev: [u8; MAX_HEX],
+    prev_len: usize,
+    next: [u8; MAX_HEX],
+    _digest: PhantomData<D>,
+}
+
+impl<'a, M: Mac + Clone, D: Digest> AwsChunkSigner<'a, M, D> {
+    /// `timestamp` is the request's `x-amz-date`, such as `20130524T000000Z`,
+    /// and `scope` the credential scope, such as
+    /// `20130524/us-east-1/s3/aws4_request`. `seed` is the hex signature of
+    /// the request head.
+    ///
+    /// Fails with `HootError::Sign` if the seed is longer than a hex 512 bit
+    /// signature.
+    pub fn new(mac: M, timestamp: &'a str, scope: &'a str, seed: &str) -> Result<Self> {
+        let mut prev = [0; MAX_HEX];
+        prev.get_mut(..seed.len())
+            .ok_or(HootError::Sign)?
+            .copy_from_slice(seed.as_bytes());
+
+        Ok(AwsChunkSigner {
+            mac,
+            timestamp,
+            scope,
+            prev,
+            prev_len: seed.len(),
+            next: [0; MAX_HEX],
+            _digest: PhantomData,
+        })
+    }
+
+    /// The signature of the chunk before the next one, or the seed.
+    pub fn signature(&self) -> &str {
+        // Can't fail, prev is either a &str or hex.
+        str::from_utf8(&self.prev[..self.prev_len]).unwrap_or_default()
+    }
+}
+
+impl<'a, M: Mac + Clone, D: Digest> ChunkSigner for AwsChunkSigner<'a, M, D> {
+    fn sign_chunk(&mut self, data: &[u8], out: &mut dyn fmt::Write) -> fmt::Result {
+        let mut hash = [0; MAX_HEX];
+        let mut mac = self.mac.clone();
+
+        mac.update(b"AWS4-HMAC-SHA256-PAYLOAD\n");
+        mac.update(self.timestamp.as_bytes());
+        mac.update(b"\n");
+        mac.update(self.scope.as_bytes());
+        mac.update(b"\n");
+        mac.update(&self.prev[..self.prev_len]);
+        mac.update(b"\n");
+        let empty = hex_digest::<D>(b"", &mut hash).map_err(|_| fmt::Error)?;
+        mac.update(empty.as_bytes());
+        mac.update(b"\n");
+        let chunk = hex_digest::<D>(data, &mut hash).map_err(|_| fmt::Error)?;
+        mac.update(chunk.as_bytes());
+
+        let signature =
+            hex(&mac.finalize().into_bytes(), &mut self.next).map_err(|_| fmt::Error)?;
+
+        write!(out, "chunk-signature={}", signature)
+    }
+
+    fn written(&mut self) {
+        // Can't be longer than MAX_HEX, or sign_chunk() would fail.
+        self.prev_len = 2 * M::output_size();
+        self.prev = self.next;
+    }
+}
+
+fn hex<'b>(bytes: &[u8], buf: &'b mut [u8]) -> Result<&'b str> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let len = bytes.len() * 2;
+    if buf.len() < len {
+        return Err(HootError::OutputOverflow {
+            needed: len - buf.len(),
+        });
+    }
+
+    for (i, b) in bytes.iter().enumerate() {
+        buf[i * 2] = DIGITS[(b >> 4) as usize];
+        buf[i * 2 + 1] = DIGITS[(b & 0xf) as usize];
+    }
+
+    // Can't fail, it's all hex digits.
+    Ok(str::from_utf8(&buf[..len])?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::Request;
+    use hmac::Hmac;
+    use sha2::Sha256;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    fn hmac(key: &[u8], data: &str) -> [u8; 32] {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    #[test]
+    fn hex_digests() -> Result<()> {
+        let mut buf = [0; 64];
+        assert_eq!(
+            hex_digest::<Sha256>(b"", &mut buf)?,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let mut small = [0; 10];
+        let err = hex_digest::<Sha256>(b"", &mut small).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 54 });
+
+        let mut hasher = DigestHasher::<Sha256>::new();
+        hasher.update(b"hel");
+        hasher.update(b"lo");
+        assert_eq!(
+            hasher.finalize_hex(&mut buf)?,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+
+        Ok(())
+    }
+
+    // Example of the AWS docs, "Signature calculations for the Authorization
+    // header: Transferring payload in multiple chunks".
+    #[test]
+    fn aws_chunk_signatures() -> Result<()> {
+        let k_date = hmac(b"AWS4wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY", "20130524");
+        let k_region = hmac(&k_date, "us-east-1");
+        let k_service = hmac(&k_region, "s3");
+        let k_signing = hmac(&k_service, "aws4_request");
+        let mac = <HmacSha256 as Mac>::new_from_slice(&k_signing).unwrap();
+
+        let mut signer = AwsChunkSigner::<_, Sha256>::new(
+            mac,
+            "20130524T000000Z",
+            "20130524/us-east-1/s3/aws4_request",
+            "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9",
+        )?;
+
+        let mut buf = vec![0; 70_000];
+        let mut request = Request::new(&mut buf)
+            .http_11()
+            .put("s3.amazonaws.com", "/examplebucket/chunkObject.txt")?
+            .with_chunked()?;
+
+        let data = [b'a'; 65536];
+        request.write_chunk_signed(&data, &mut signer)?;
+        assert_eq!(
+            signer.signature(),
+            "ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648"
+        );
+
+        let output = request.flush();
+        assert!(output.ends_with(b"\r\n"));
+        let mut buf = vec![0; 2048];
+        let mut request = Request::resume(output.ready(), &mut buf);
+
+        request.write_chunk_signed(&data[..1024], &mut signer)?;
+        assert_eq!(
+            signer.signature(),
+            "0055627c9e194cb4542bae2aa5492e3c1575bbb81b612b7d234b86a503ef5497"
+        );
+
+        let output = request.finish_signed(&mut signer)?.flush();
+        assert!(output.ends_with(
+            b"0;chunk-signature=\
+            b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9\r\n\r\n"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+/// Source of the current time for the `Date` header.
+///
+/// Set with `server::Response::with_clock()`, which makes `send_status()`
+/// follow the status line with a `Date` header, RFC 7231 §7.1.1.2.
+pub trait Clock {
+    /// Seconds since the Unix epoch.
+    fn unix_time(&self) -> u64;
+}
+
+/// [`Clock`] reading the system time.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn unix_time(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// Length of an IMF-fixdate, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) const HTTP_DATE_LEN: usize = 29;
+
+// 9999-12-31 23:59:59, the last time with a four digit year.
+const MAX_SECS: u64 = 253_402_300_799;
+
+const DAYS: [&[u8; 3]; 7] = [b"Thu", b"Fri", b"Sat", b"Sun", b"Mon", b"Tue", b"Wed"];
+
+const MONTHS: [&[u8; 3]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+/// Format seconds since the Unix epoch as an IMF-fixdate.
+pub(crate) fn format_http_date(secs: u64) -> [u8; HTTP_DATE_LEN] {
+    let secs = secs.min(MAX_SECS);
+    let days = secs / 86_400;
+    let rem = secs % 86_400;
+
+    let (year, month, day) = civil_from_days(days);
+
+    let mut b = *b"Thu, 01 Jan 1970 00:00:00 GMT";
+    b[0..3].copy_from_slice(DAYS[(days % 7) as usize]);
+    put2(&mut b[5..7], day);
+    b[8..11].copy_from_slice(MONTHS[month as usize - 1]);
+    put2(&mut b[12..14], year / 100);
+    put2(&mut b[14..16], year % 100);
+    put2(&mut b[17..19], rem / 3600);
+    put2(&mut b[20..22], rem / 60 % 60);
+    put2(&mut b[23..25], rem % 60);
+    b
+}
+
+fn put2(dst: &mut [u8], v: u64) {
+    dst[0] = b'0' + (v / 10) as u8;
+    dst[1] = b'0' + (v % 10) as u8;
+}
+
+/// Parse an HTTP-date to seconds since the Unix epoch, RFC 7231 §7.1.1.1.
+///
+/// Takes the IMF-fixdate, and the obsolete RFC 850 and asctime formats.
+/// The day name is not checked. `None` for malformed dates and dates before
+/// the epoch.
+#[cfg(feature = "retry")]
+pub(crate) fn parse_http_date(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let comma = s.find(',');
+    let mut t = s[comma.or_else(|| s.find(' '))? + 1..].split_ascii_whitespace();
+
+    let (day, month, year, time) = if comma.is_some() {
+        let first = t.next()?;
+        let ret = match first.split_once('-') {
+            // Sunday, 06-Nov-94 08:49:37 GMT
+            Some((day, rest)) => {
+                let (month, year) = rest.split_once('-')?;
+                let year: u64 = parse_digits(year)?;
+                // Two digit years are at most 50 years ahead, RFC 7231 §7.1.1.1.
+                let year = match year {
+                    0..=69 => 2000 + year,
+                    70..=99 => 1900 + year,
+                    _ => year,
+                };
+                (day, month, year, t.next()?)
+            }
+            // Sun, 06 Nov 1994 08:49:37 GMT
+            None => (first, t.next()?, parse_digits(t.next()?)?, t.next()?),
+        };
+        if t.next()? != "GMT" {
+            return None;
+        }
+        ret
+    } else {
+        // Sun Nov  6 08:49:37 1994
+        let month = t.next()?;
+        let day = t.next()?;
+        let time = t.next()?;
+        (day, month, parse_digits(t.next()?)?, time)
+    };
+
+    if t.next().is_some() {
+        return None;
+    }
+
+    let day = parse_digits(day)?;
+    let month = MONTHS.iter().position(|m| &m[..] == month.as_bytes())? as u64 + 1;
+
+    let mut hms = time.split(':');
+    let hour = parse_digits(hms.next()?)?;
+    let min = parse_digits(hms.next()?)?;
+    let sec = parse_digits(hms.next()?)?;
+
+    if hms.next().is_some()
+        || !(1..=31).contains(&day)
+        || !(1970..=9999).contains(&year)
+        || hour > 23
+        || min > 59
+        // Allowing a leap second.
+        || sec > 60
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    Some(days * 86_400 + hour * 3600 + min * 60 + sec)
+}
+
+#[cfg(feature = "retry")]
+fn parse_digits(s: &str) -> Option<u64> {
+    if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// (year, month, day) to days since epoch, the inverse of civil_from_days().
+#[cfg(feature = "retry")]
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// Days since epoch to (year, month, day), from Howard Hinnant's date algorithms.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn http_date() {
+        assert_eq!(&format_http_date(0), b"Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            &format_http_date(784_111_777),
+            b"Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            &format_http_date(951_782_400),
+            b"Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+        assert_eq!(
+            &format_http_date(u64::MAX),
+            b"Fri, 31 Dec 9999 23:59:59 GMT"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "retry")]
+    fn parse_date() {
+        const SECS: Option<u64> = Some(784_111_777);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), SECS);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), SECS);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), SECS);
+
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"),
+            Some(951_782_400)
+        );
+        assert_eq!(
+            parse_http_date("Monday, 01-Jan-24 00:00:00 GMT"),
+            Some(1_704_067_200)
+        );
+
+        for secs in [0, 951_782_400, 1_719_791_999, 253_402_300_799] {
+            let date = format_http_date(secs);
+            let date = core::str::from_utf8(&date).unwrap();
+            assert_eq!(parse_http_date(date), Some(secs));
+        }
+
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT x"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Wed, 31 Dec 1969 23:59:59 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49 GMT"), None);
+        assert_eq!(parse_http_date("120"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+}
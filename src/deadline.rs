@@ -0,0 +1,144 @@
+use core::fmt;
+
+use crate::{HootError, Result};
+
+/// Time budgets for reading an incoming message, in milliseconds.
+///
+/// Set with `client::Response::set_deadline()` or `server::Request::set_deadline()`,
+/// and supply the time with `set_now()` before each read. A read that makes no
+/// progress after a budget is used up fails with `HootError::Timeout`, which
+/// protects against peers sending slowly on purpose (slowloris).
+///
+/// Time is provided by the caller as milliseconds from any monotonic source.
+/// Without `set_now()`, no budgets are enforced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deadline {
+    /// Max time from the first read attempt until the head is complete.
+    pub headers: Option<u64>,
+    /// Max time from the head being complete until the body is complete.
+    pub body: Option<u64>,
+    /// Max time between reads that make progress on the body.
+    pub idle: Option<u64>,
+}
+
+/// Which budget of a [`Deadline`] was used up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeadlinePhase {
+    Headers,
+    Body,
+    Idle,
+}
+
+impl fmt::Display for DeadlinePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DeadlinePhase::Headers => "headers",
+            DeadlinePhase::Body => "body",
+            DeadlinePhase::Idle => "idle",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Deadline accounting kept in the call state.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DeadlineState {
+    pub budgets: Deadline,
+    pub now: Option<u64>,
+    pub head_start: Option<u64>,
+    pub body_start: Option<u64>,
+    pub last_progress: Option<u64>,
+}
+
+impl DeadlineState {
+    pub fn set_now(&mut self, now_ms: u64) {
+        self.now = Some(now_ms);
+    }
+
+    /// Check the header budget after a read attempt of the head.
+    pub fn check_head(&mut self, complete: bool) -> Result<()> {
+        let now = match self.now {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let start = *self.head_start.get_or_insert(now);
+
+        if complete {
+            self.body_start = Some(now);
+            self.last_progress = Some(now);
+            return Ok(());
+        }
+
+        check(self.budgets.headers, start, now, DeadlinePhase::Headers)
+    }
+
+    /// Check the body and idle budgets after a read of the body.
+    pub fn check_body(&mut self, progress: bool, finished: bool) -> Result<()> {
+        let now = match self.now {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        if finished {
+            return Ok(());
+        }
+
+        let start = *self.body_start.get_or_insert(now);
+        check(self.budgets.body, start, now, DeadlinePhase::Body)?;
+
+        let last = self.last_progress.get_or_insert(now);
+        if progress {
+            *last = now;
+            return Ok(());
+        }
+
+        check(self.budgets.idle, *last, now, DeadlinePhase::Idle)
+    }
+}
+
+fn check(budget: Option<u64>, start: u64, now: u64, which: DeadlinePhase) -> Result<()> {
+    match budget {
+        Some(b) if now.saturating_sub(start) > b => {
+            debug!("Deadline {} exceeded", which);
+            Err(HootError::Timeout { which })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deadline_phases() {
+        let mut d = DeadlineState {
+            budgets: Deadline {
+                headers: Some(100),
+                body: Some(1000),
+                idle: Some(50),
+            },
+            ..Default::default()
+        };
+        let timeout = |which| Err(HootError::Timeout { which });
+
+        // No time, no checks.
+        assert_eq!(d.check_head(false), Ok(()));
+
+        d.set_now(10);
+        assert_eq!(d.check_head(false), Ok(()));
+        d.set_now(111);
+        assert_eq!(d.check_head(false), timeout(DeadlinePhase::Headers));
+        assert_eq!(d.check_head(true), Ok(()));
+
+        d.set_now(150);
+        assert_eq!(d.check_body(true, false), Ok(()));
+        d.set_now(201);
+        assert_eq!(d.check_body(false, false), timeout(DeadlinePhase::Idle));
+        d.set_now(1112);
+        assert_eq!(d.check_body(true, false), timeout(DeadlinePhase::Body));
+        assert_eq!(d.check_body(true, true), Ok(()));
+    }
+}
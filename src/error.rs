@@ -3,27 +3,43 @@ use core::num::ParseIntError;
 use core::str::Utf8Error;
 
 use crate::url::UrlError;
+use crate::{DeadlinePhase, Limit, Violation};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[non_exhaustive]
 pub enum HootError {
     /// The borrowed buffer did not have enough space to hold the
     /// data we attempted to write.
     ///
-    /// Call `.flush()`, write the output to the transport followed by `Call::resume()`.
-    OutputOverflow,
+    /// At least `needed` more bytes of space were needed. Call `.flush()`, write
+    /// the output to the transport followed by `Call::resume()`, or retry with a
+    /// buffer that is larger by `needed`.
+    OutputOverflow { needed: usize },
+
+    /// The borrowed buffer filled up after writing the contained number of bytes.
+    ///
+    /// Call `.flush()`, write the output to the transport, resume and continue
+    /// writing the remaining bytes.
+    OutputFull(usize),
 
     /// Invalid byte in header name.
-    HeaderName,
+    ///
+    /// The `offset` of the byte is in the input for incoming headers, and in
+    /// the name for outgoing.
+    HeaderName { offset: usize },
 
     /// Invalid byte in header value.
-    HeaderValue,
+    ///
+    /// The `offset` of the byte is in the input for incoming headers, and in
+    /// the value for outgoing.
+    HeaderValue { offset: usize },
 
-    /// Invalid Response status.
-    Status,
+    /// Invalid Response status, at `offset` in the input.
+    Status { offset: usize },
 
-    /// Invalid byte in new line.
-    NewLine,
+    /// Invalid byte in new line, at `offset` in the input.
+    NewLine { offset: usize },
 
     /// Parsed more headers than provided buffer can contain.
     TooManyHeaders,
@@ -41,16 +57,20 @@ pub enum HootError {
     ForbiddenTrailer,
 
     /// Attempt to send more content than declared in the `Content-Length` header.
-    SentMoreThanContentLength,
+    ///
+    /// `actual` is the length including the rejected bytes.
+    SentMoreThanContentLength { expected: u64, actual: u64 },
 
     /// Attempt to send less content than declared in the `Content-Length` header.
-    SentLessThanContentLength,
+    SentLessThanContentLength { expected: u64, actual: u64 },
 
-    /// Attempt to send more content than declared in the `Content-Length` header.
-    RecvMoreThanContentLength,
+    /// Received more content than declared in the `Content-Length` header.
+    ///
+    /// `actual` is the length including the rejected bytes.
+    RecvMoreThanContentLength { expected: u64, actual: u64 },
 
-    /// Attempt to send less content than declared in the `Content-Length` header.
-    RecvLessThanContentLength,
+    /// Received less content than declared in the `Content-Length` header.
+    RecvLessThanContentLength { expected: u64, actual: u64 },
 
     /// Failed to read bytes as &str
     ConvertBytesToStr,
@@ -67,14 +87,21 @@ pub enum HootError {
     /// More than one Content-Length header in response.
     DuplicateContentLength,
 
+    /// Request declared a body for a method without one, see
+    /// [`RequestBodyPolicy`](crate::RequestBodyPolicy).
+    UnexpectedBody,
+
     /// Incoming chunked encoding is incorrect.
     IncorrectChunk,
 
-    /// Invalid byte where token is required.
-    Token,
+    /// Invalid byte where token is required, at `offset` in the input.
+    Token { offset: usize },
+
+    /// Invalid byte in HTTP version, at `offset` in the input.
+    Version { offset: usize },
 
-    /// Invalid byte in HTTP version.
-    Version,
+    /// HTTP version other than 1.x, such as the `PRI * HTTP/2.0` preface.
+    UnsupportedVersion { major: u8 },
 
     /// Did not read body to finish.
     BodyNotFinished,
@@ -87,9 +114,89 @@ pub enum HootError {
 
     /// Failed to get a `TryInto<u64>`.
     NotU64,
-}
 
-pub(crate) static OVERFLOW: Result<()> = Err(HootError::OutputOverflow);
+    /// Failed to decompress a gzip/deflate encoded body.
+    Decompress,
+
+    /// The body is larger than the configured maximum.
+    BodyTooLarge,
+
+    /// Failed to encode (compress) a body to send.
+    Compress,
+
+    /// The request target contains spaces, control characters or a `#fragment`,
+    /// or an incoming request line contains control characters.
+    ///
+    /// The `offset` of the byte is in the input for incoming requests, and in
+    /// the target for outgoing.
+    InvalidTarget { offset: usize },
+
+    /// Bytes are not a valid serialized connection ticket.
+    ConnectionTicket,
+
+    /// Incoming data exceeded one of the configured [`Limits`](crate::Limits).
+    LimitExceeded { which: Limit },
+
+    /// Repeated values of the header must not be combined, like `Set-Cookie`.
+    NotCombinable,
+
+    /// A raw trailer section is not exactly one complete set of headers.
+    IncorrectTrailers,
+
+    /// Incoming data violated strict parsing, see [`Violation`](crate::Violation).
+    StrictViolation { which: Violation },
+
+    /// HTTP/1.1 request without a `Host` header.
+    MissingHost,
+
+    /// Request with more than one `Host` header.
+    DuplicateHost,
+
+    /// Attempt to send a header twice that must appear at most once, like `Content-Type`.
+    DuplicateHeader,
+
+    /// Reading took longer than the [`Deadline`](crate::Deadline) allows.
+    Timeout { which: DeadlinePhase },
+
+    /// Attempt to answer `Expect: 100-continue` for a request without it.
+    NotExpectingContinue,
+
+    /// Attempt to send an interim `1xx` response to an HTTP/1.0 request.
+    InterimHttp10,
+
+    /// Incoming `Transfer-Encoding` where `chunked` is not the final coding.
+    ChunkedNotLast,
+
+    /// Incoming `Transfer-Encoding` with more than
+    /// [`MAX_TRANSFER_CODINGS`](crate::MAX_TRANSFER_CODINGS) codings.
+    TooManyTransferCodings,
+
+    /// Failed to serialize or deserialize a JSON body.
+    Json,
+
+    /// Attempt to switch to h2c for a message that is not an h2c upgrade.
+    NotH2cUpgrade,
+
+    /// Bytes are not a `ResumeToken` of the expected type state.
+    ResumeToken,
+
+    /// Failed to sign a request with `Request::sign()`.
+    ///
+    /// The head was flushed before signing, a header to sign is not set, or
+    /// the signer failed. Also for a [`ChunkSigner`](crate::client::ChunkSigner)
+    /// that fails or writes control characters.
+    Sign,
+
+    /// More bytes were reported used or filled than a buffer has.
+    ///
+    /// `amount` is what was reported, `available` what the buffer had.
+    BufferOverrun { amount: usize, available: usize },
+
+    /// A state machine got into a state its type state rules out.
+    ///
+    /// This is a bug in hoot, returned instead of panicking.
+    Internal,
+}
 
 pub type Result<T> = core::result::Result<T, HootError>;
 
@@ -105,17 +212,47 @@ impl From<ParseIntError> for HootError {
     }
 }
 
-impl From<httparse::Error> for HootError {
-    fn from(value: httparse::Error) -> Self {
-        match value {
-            httparse::Error::HeaderName => HootError::HeaderName,
-            httparse::Error::HeaderValue => HootError::HeaderValue,
-            httparse::Error::NewLine => HootError::NewLine,
-            httparse::Error::Status => HootError::Status,
-            httparse::Error::Token => HootError::Token,
-            httparse::Error::TooManyHeaders => HootError::TooManyHeaders,
-            httparse::Error::Version => HootError::Version,
-        }
+impl HootError {
+    /// The status a server should respond with when reading a request fails
+    /// with this error.
+    ///
+    /// This is `None` for errors that are not caused by the incoming request.
+    /// See [`error_response()`](crate::server::error_response) to send it.
+    pub fn suggested_status(&self) -> Option<u16> {
+        use HootError::*;
+        let code = match self {
+            HeaderName { .. }
+            | HeaderValue { .. }
+            | NewLine { .. }
+            | Token { .. }
+            | ParseIntError
+            | ConvertBytesToStr
+            | DuplicateContentLength
+            | UnexpectedBody
+            | IncorrectChunk
+            | RecvMoreThanContentLength { .. }
+            | InvalidTarget { .. }
+            | StrictViolation { .. }
+            | MissingHost
+            | DuplicateHost
+            | ChunkedNotLast
+            | TooManyTransferCodings
+            | Version { .. }
+            | Json => 400,
+            LimitExceeded {
+                which: Limit::ChunkLine,
+            } => 400,
+            LimitExceeded {
+                which: Limit::Line | Limit::Target,
+            } => 414,
+            TooManyHeaders | LimitExceeded { .. } => 431,
+            BodyTooLarge => 413,
+            Timeout { .. } => 408,
+            UnknownMethod => 501,
+            UnsupportedVersion { .. } => 505,
+            _ => return None,
+        };
+        Some(code)
     }
 }
 
@@ -123,34 +260,114 @@ impl fmt::Display for HootError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use HootError::*;
         let s = match self {
-            OutputOverflow => "output buffer overflow",
-            HeaderName => "invalid header name",
-            HeaderValue => "invalid header value",
-            NewLine => "invalid new line",
-            Status => "invalid response status",
-            Token => "invalid token",
+            OutputOverflow { needed } => {
+                write!(f, "output buffer overflow, {} more bytes needed", needed)?;
+                return Ok(());
+            }
+            BufferOverrun { amount, available } => {
+                write!(f, "buffer overrun, {} bytes of {}", amount, available)?;
+                return Ok(());
+            }
+            HeaderName { offset } => {
+                write!(f, "invalid header name at {}", offset)?;
+                return Ok(());
+            }
+            HeaderValue { offset } => {
+                write!(f, "invalid header value at {}", offset)?;
+                return Ok(());
+            }
+            NewLine { offset } => {
+                write!(f, "invalid new line at {}", offset)?;
+                return Ok(());
+            }
+            Status { offset } => {
+                write!(f, "invalid response status at {}", offset)?;
+                return Ok(());
+            }
+            Token { offset } => {
+                write!(f, "invalid token at {}", offset)?;
+                return Ok(());
+            }
             TooManyHeaders => "too many headers",
-            Version => "invalid HTTP version",
+            Version { offset } => {
+                write!(f, "invalid HTTP version at {}", offset)?;
+                return Ok(());
+            }
+            UnsupportedVersion { major } => {
+                write!(f, "unsupported HTTP version {}", major)?;
+                return Ok(());
+            }
             ForbiddenBodyHeader => "forbidden header name",
             ForbiddenHttp11Header => "forbidden header for http1.1",
             ForbiddenTrailer => "forbidden trailer",
-            SentMoreThanContentLength => "sent more than content-length",
-            SentLessThanContentLength => "sent less than content-length",
-            RecvMoreThanContentLength => "received more than content-length",
-            RecvLessThanContentLength => "received less than content-length",
+            SentMoreThanContentLength { expected, actual } => {
+                write!(f, "sent {} of content-length {}", actual, expected)?;
+                return Ok(());
+            }
+            SentLessThanContentLength { expected, actual } => {
+                write!(f, "sent {} of content-length {}", actual, expected)?;
+                return Ok(());
+            }
+            RecvMoreThanContentLength { expected, actual } => {
+                write!(f, "received {} of content-length {}", actual, expected)?;
+                return Ok(());
+            }
+            RecvLessThanContentLength { expected, actual } => {
+                write!(f, "received {} of content-length {}", actual, expected)?;
+                return Ok(());
+            }
             ConvertBytesToStr => "failed to convert &[u8] to &str",
             HttpVersionMismatch => "http version mismatch",
             StatusIsNotComplete => "called complete() before entire status read",
             ParseIntError => "failed to parse integer",
             DuplicateContentLength => "multiple content-length headers",
+            UnexpectedBody => "body for request method without body",
             IncorrectChunk => "incorrect incoming body chunk",
             BodyNotFinished => "called finish() before body was finished",
             UnknownMethod => "unknown incoming method",
+            LimitExceeded { which } => {
+                write!(f, "limit exceeded: {}", which)?;
+                return Ok(());
+            }
+            StrictViolation { which } => {
+                write!(f, "strict parsing: {}", which)?;
+                return Ok(());
+            }
+            Timeout { which } => {
+                write!(f, "timeout: {}", which)?;
+                return Ok(());
+            }
+            OutputFull(n) => {
+                write!(f, "output buffer full after {} bytes", n)?;
+                return Ok(());
+            }
             UrlError(v) => {
                 write!(f, "url: {}", v)?;
                 return Ok(());
             }
             NotU64 => "not possible to convert to u64",
+            Decompress => "failed to decompress body",
+            BodyTooLarge => "body larger than allowed",
+            Compress => "failed to compress body",
+            InvalidTarget { offset } => {
+                write!(f, "invalid request target at {}", offset)?;
+                return Ok(());
+            }
+            ConnectionTicket => "invalid connection ticket",
+            NotCombinable => "header values can not be combined",
+            IncorrectTrailers => "incorrect raw trailers",
+            MissingHost => "missing host header",
+            DuplicateHost => "multiple host headers",
+            DuplicateHeader => "header must not be repeated",
+            NotExpectingContinue => "request does not expect 100-continue",
+            InterimHttp10 => "interim response to http/1.0 request",
+            ChunkedNotLast => "chunked is not the final transfer-coding",
+            TooManyTransferCodings => "too many transfer-codings",
+            Json => "failed to serialize or deserialize json",
+            NotH2cUpgrade => "not an h2c upgrade",
+            ResumeToken => "invalid resume token",
+            Sign => "failed to sign request",
+            Internal => "internal error in hoot",
         };
 
         write!(f, "{}", s)
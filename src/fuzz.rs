@@ -0,0 +1,233 @@
+//! Entry points for fuzzing.
+//!
+//! Each function runs arbitrary bytes through the parsing and body decoding of
+//! the state machines, using fixed buffers, and panics if an internal invariant
+//! breaks. Errors from invalid input are expected and ignored. With cargo-fuzz:
+//!
+//! ```ignore
+//! #![no_main]
+//! use libfuzzer_sys::fuzz_target;
+//!
+//! fuzz_target!(|data: &[u8]| hoot::fuzz::fuzz(data));
+//! ```
+//!
+//! The first byte of the input picks options, such as strict parsing and how
+//! the input is split into reads. The rest is the message.
+
+use crate::client;
+use crate::server;
+use crate::types::state::RECV_BODY;
+use crate::util::HeaderBuf;
+use crate::{BodyPart, Method, RequestBodyPolicy, Result};
+
+const MAX_HEADERS: usize = 64;
+const BODY_BUF: usize = 256;
+
+const METHODS: [Method; 17] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::CONNECT,
+    Method::OPTIONS,
+    Method::TRACE,
+    Method::PATCH,
+    Method::QUERY,
+    Method::PROPFIND,
+    Method::PROPPATCH,
+    Method::MKCOL,
+    Method::COPY,
+    Method::MOVE,
+    Method::LOCK,
+    Method::UNLOCK,
+];
+
+/// Options from the first byte of the fuzz input.
+#[derive(Clone, Copy)]
+struct Config {
+    strict: bool,
+    lenient: bool,
+    step: usize,
+    method: Method,
+}
+
+fn split_config(data: &[u8]) -> Option<(Config, &[u8])> {
+    let (first, rest) = data.split_first()?;
+    let b = *first as usize;
+    let config = Config {
+        strict: b & 1 > 0,
+        lenient: b & 2 > 0,
+        // 1, 2, 8 or everything at once.
+        step: [1, 2, 8, usize::MAX][(b >> 2) & 3],
+        method: METHODS[(b >> 4) % METHODS.len()],
+    };
+    Some((config, rest))
+}
+
+/// Fuzz both the request and the response parsing with `data`.
+pub fn fuzz(data: &[u8]) {
+    fuzz_request(data);
+    fuzz_response(data);
+}
+
+/// Fuzz `server::Request` with `data` as incoming request.
+pub fn fuzz_request(data: &[u8]) {
+    let (config, input) = match split_config(data) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let mut buf = HeaderBuf::<MAX_HEADERS>::new();
+    let mut request = server::Request::new();
+    request.set_request_body_policy(RequestBodyPolicy::Read);
+    if config.strict {
+        request.set_strict();
+    } else if config.lenient {
+        request.set_lenient();
+    }
+
+    let mut end: usize = 0;
+    let used = loop {
+        end = end.saturating_add(config.step).min(input.len());
+        let attempt = match request.try_read_request(&input[..end], &mut buf) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        if attempt.is_success() {
+            assert!(attempt.input_used() <= end);
+            assert!(attempt.line().is_some() && attempt.headers().is_some());
+            break attempt.input_used();
+        }
+        if end == input.len() {
+            return;
+        }
+    };
+
+    let mut request: server::Request<RECV_BODY> = request.proceed();
+    let rest = &input[used..];
+
+    let pos = read_all(rest, config.step, |src, dst| request.read_body(src, dst));
+
+    if request.is_finished() {
+        if let Ok(Some(t)) = request.read_trailers(&rest[pos..], &mut buf) {
+            assert!(t.raw().len() <= rest.len() - pos);
+        }
+        let _ = request.into_response();
+    }
+}
+
+/// Fuzz `client::Response` with `data` as incoming response.
+pub fn fuzz_response(data: &[u8]) {
+    let (config, input) = match split_config(data) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let mut buf = HeaderBuf::<MAX_HEADERS>::new();
+    let mut response = client::Response::forwarded(config.method);
+    if config.strict {
+        response.set_strict();
+    } else if config.lenient {
+        response.set_lenient();
+    }
+
+    let mut end: usize = 0;
+    let used = loop {
+        end = end.saturating_add(config.step).min(input.len());
+        let attempt = match response.try_read_response(&input[..end], &mut buf) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        if attempt.is_success() {
+            assert!(attempt.input_used() <= end);
+            assert!(attempt.status().is_some() && attempt.body_mode().is_some());
+            break attempt.input_used();
+        }
+        if end == input.len() {
+            return;
+        }
+    };
+
+    let mut response = response.proceed();
+    let rest = &input[used..];
+
+    let pos = read_all(rest, config.step, |src, dst| response.read_body(src, dst));
+
+    if response.is_finished() {
+        if let Ok(Some(t)) = response.read_trailers(&rest[pos..], &mut buf) {
+            assert!(t.raw().len() <= rest.len() - pos);
+        }
+        let _ = response.finish();
+    }
+}
+
+/// Read the body in steps, checking the parts. Returns the input used.
+fn read_all(
+    input: &[u8],
+    step: usize,
+    mut read: impl for<'b> FnMut(&[u8], &'b mut [u8]) -> Result<BodyPart<'b>>,
+) -> usize {
+    let mut dst = [0; BODY_BUF];
+    let mut pos = 0;
+    let mut end: usize = 0;
+
+    loop {
+        end = end.saturating_add(step).min(input.len());
+        let src = &input[pos..end];
+
+        let part = match read(src, &mut dst) {
+            Ok(v) => v,
+            Err(_) => return pos,
+        };
+
+        assert!(part.input_used() <= src.len());
+        assert!(part.data().len() <= BODY_BUF);
+        pos += part.input_used();
+
+        if part.is_finished() {
+            return pos;
+        }
+
+        let stalled = part.input_used() == 0 && part.data().is_empty();
+        if stalled && end == input.len() {
+            return pos;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuzz_seeds() {
+        let seeds: &[&[u8]] = &[
+            b"",
+            b"\x00GET / HTTP/1.1\r\nHost: x\r\n\r\n",
+            b"\x2cPOST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3\r\nabc\r\n0\r\nX-T: 1\r\n\r\n",
+            b"\x05HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc",
+            b"\x0dHTTP/1.0 200 OK\r\n\r\nuntil close",
+            b"\x04HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffffff\r\n",
+        ];
+        for seed in seeds {
+            fuzz(seed);
+        }
+
+        // Pseudo random mutations of the seeds.
+        let mut x: u32 = 1;
+        let mut input = [0; 128];
+        for _ in 0..2000 {
+            for seed in seeds.iter().filter(|s| !s.is_empty()) {
+                let len = seed.len().min(input.len());
+                input[..len].copy_from_slice(&seed[..len]);
+                for _ in 0..4 {
+                    x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    input[(x >> 8) as usize % len] = (x >> 16) as u8;
+                }
+                fuzz(&input[..len]);
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Upgrade from HTTP/1.1 to HTTP/2 over cleartext ("h2c"), RFC 7540 §3.2.
+
+use crate::util::compare_lowercase_ascii;
+use crate::{ConnectionOptions, Header};
+
+/// A connection handed off to HTTP/2 over cleartext ("h2c") after an
+/// upgrade, RFC 7540 §3.2.
+///
+/// hoot only handles the HTTP/1.1 part, and hands the connection off to a
+/// separate HTTP/2 implementation. A server checks for an upgrade with
+/// `RequestAttempt::h2c_settings()` and accepts it with
+/// `server::Request::switch_to_h2c()`. A client offers one with
+/// `client::Request::h2c_upgrade()`, and takes a `101` answering it with
+/// `client::Response::switch_to_h2c()`.
+///
+/// ```
+/// use hoot::server::Request;
+/// use hoot::util::HeaderBuf;
+///
+/// const INPUT: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+///     Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\nPRI * HTTP/2.0";
+///
+/// let mut buf = HeaderBuf::<16>::new();
+/// let mut request = Request::new();
+/// let attempt = request.try_read_request(INPUT, &mut buf)?;
+/// assert!(attempt.h2c_settings().is_some());
+///
+/// let mut out = [0; 128];
+/// let (handoff, output) = request.switch_to_h2c(attempt, INPUT, &mut out)?;
+///
+/// assert_eq!(
+///     output,
+///     b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n"
+/// );
+/// assert_eq!(handoff.settings(), Some("AAMAAABkAAQAAP__"));
+/// assert_eq!(handoff.rest(), b"PRI * HTTP/2.0");
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct H2cHandoff<'a> {
+    settings: Option<&'a str>,
+    rest: &'a [u8],
+}
+
+impl<'a> H2cHandoff<'a> {
+    pub(crate) fn new(settings: Option<&'a str>, rest: &'a [u8]) -> Self {
+        H2cHandoff { settings, rest }
+    }
+
+    /// The `HTTP2-Settings` of the upgraded request, a base64url encoded
+    /// SETTINGS frame payload.
+    ///
+    /// This is `None` for clients, which sent the settings themselves.
+    pub fn settings(&self) -> Option<&'a str> {
+        self.settings
+    }
+
+    /// Input after the HTTP/1.1 message, which is the start of HTTP/2.
+    pub fn rest(&self) -> &'a [u8] {
+        self.rest
+    }
+}
+
+/// Whether the `Upgrade` header, listed in `Connection`, has `h2c`.
+pub(crate) fn is_upgrade(headers: &[Header<'_>]) -> bool {
+    ConnectionOptions::new(headers).is_upgrade()
+        && headers
+            .iter()
+            .filter(|h| compare_lowercase_ascii(h.name(), "upgrade"))
+            .filter_map(|h| h.try_value())
+            .flat_map(|v| v.split(','))
+            .any(|p| compare_lowercase_ascii(p.trim(), "h2c"))
+}
+
+/// The `HTTP2-Settings` of a request upgrading to h2c.
+///
+/// The request must have exactly one such header, listed in `Connection`.
+pub(crate) fn settings<'a>(headers: &[Header<'a>]) -> Option<&'a str> {
+    if !is_upgrade(headers) {
+        return None;
+    }
+
+    let listed = ConnectionOptions::new(headers)
+        .tokens()
+        .any(|t| compare_lowercase_ascii(t, "http2-settings"));
+
+    let mut all = headers
+        .iter()
+        .filter(|h| compare_lowercase_ascii(h.name(), "http2-settings"));
+
+    match (listed, all.next(), all.next()) {
+        (true, Some(h), None) => h.try_value(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::HeaderBuf;
+    use crate::{client, server, HootError, Result};
+
+    #[test]
+    fn h2c_upgrades() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut out = [0; 128];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let not_upgrades: &[&[u8]] = &[
+            b"GET / HTTP/1.1\r\nHost: x\r\nUpgrade: h2c\r\nHTTP2-Settings: A\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade\r\n\
+            Upgrade: h2c\r\nHTTP2-Settings: A\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: h2c\r\nHTTP2-Settings: A\r\nHTTP2-Settings: B\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: websocket\r\nHTTP2-Settings: A\r\n\r\n",
+        ];
+        for input in not_upgrades {
+            let mut request = server::Request::new();
+            let attempt = request.try_read_request(input, &mut headers)?;
+            assert_eq!(attempt.h2c_settings(), None);
+            let err = request
+                .switch_to_h2c(attempt, input, &mut out)
+                .map(|_| ())
+                .unwrap_err();
+            assert_eq!(err, HootError::NotH2cUpgrade);
+        }
+
+        const BODY: &[u8] =
+            b"POST / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: foo, H2C\r\nHTTP2-Settings: A\r\nContent-Length: 1\r\n\r\nx";
+        let mut request = server::Request::new();
+        let attempt = request.try_read_request(BODY, &mut headers)?;
+        assert_eq!(attempt.h2c_settings(), Some("A"));
+        let err = request
+            .switch_to_h2c(attempt, BODY, &mut out)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UnexpectedBody);
+
+        let output = client::Request::new(&mut buf)
+            .http_11()
+            .get("x", "/")?
+            .h2c_upgrade("A")?
+            .send()?
+            .flush();
+        assert_eq!(
+            &output[..],
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+            Upgrade: h2c\r\nHTTP2-Settings: A\r\n\r\n"
+        );
+
+        const SWITCH: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+            Upgrade: h2c\r\n\r\n\x00\x00\x00\x04";
+        let mut response = output.into_response();
+        let attempt = response.try_read_response(SWITCH, &mut headers)?;
+        assert!(attempt.is_h2c_upgrade());
+        let handoff = response.switch_to_h2c(attempt, SWITCH)?;
+        assert_eq!(handoff.settings(), None);
+        assert_eq!(handoff.rest(), b"\x00\x00\x00\x04");
+
+        let output = client::Request::new(&mut buf)
+            .http_11()
+            .get("x", "/")?
+            .h2c_upgrade("A")?
+            .send()?
+            .flush();
+        const DECLINE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let mut response = output.into_response();
+        let attempt = response.try_read_response(DECLINE, &mut headers)?;
+        assert!(!attempt.is_h2c_upgrade());
+        let err = response
+            .switch_to_h2c(attempt, DECLINE)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::NotH2cUpgrade);
+
+        Ok(())
+    }
+}
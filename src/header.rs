@@ -1,13 +1,10 @@
-use core::fmt;
-use core::fmt::Write;
-use core::mem;
+use core::fmt::{self, Write as _};
 use core::str;
-use httparse::Header as InnerHeader;
 
-use crate::error::{Result, OVERFLOW};
+use crate::error::Result;
 use crate::out::Writer;
-use crate::parser::parse_headers;
-use crate::util::compare_lowercase_ascii;
+use crate::parser::{parse_headers, scan_header_section};
+use crate::util::{compare_lowercase_ascii, EMPTY_HEADER};
 use crate::{HootError, HttpVersion};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -17,25 +14,106 @@ pub struct Header<'a> {
 }
 
 impl<'a> Header<'a> {
+    pub(crate) const fn new(name: &'a str, value: &'a [u8]) -> Self {
+        Header { name, value }
+    }
+
     #[inline(always)]
     pub fn name(&self) -> &str {
         self.name
     }
 
     #[inline(always)]
-    pub fn try_value(&self) -> Option<&str> {
+    pub fn try_value(&self) -> Option<&'a str> {
         str::from_utf8(self.value).ok()
     }
 
+    /// The value as `&str`.
+    ///
+    /// Header values are not required to be UTF-8. Legacy peers might send
+    /// latin-1, in which case this fails with `HootError::ConvertBytesToStr`.
     #[inline(always)]
-    pub fn value(&self) -> &str {
-        self.try_value().expect("header value to be valid utf-8")
+    pub fn value_str(&self) -> Result<&str> {
+        Ok(str::from_utf8(self.value)?)
     }
 
+    /// The value as bytes, as received.
     #[inline(always)]
     pub fn value_raw(&self) -> &[u8] {
         self.value
     }
+
+    /// The value split at obs-fold line breaks, RFC 7230 §3.2.4.
+    ///
+    /// A folded value, only accepted with `set_lenient()` on a client
+    /// response, keeps the line breaks of the fold in `value_raw()`. Join the
+    /// parts with a single space to interpret or forward the value. A value
+    /// without folds is a single part.
+    ///
+    /// ```
+    /// use hoot::client::Request;
+    /// use hoot::util::HeaderBuf;
+    ///
+    /// let mut buf = [0; 1024];
+    /// let output = Request::new(&mut buf).http_11().get("x.test", "/")?.send()?.flush();
+    /// let mut r = output.ready().into_response();
+    /// r.set_lenient();
+    ///
+    /// let input = b"HTTP/1.1 200 OK\r\nX-A: one\r\n  two\r\n\r\n";
+    /// let mut buf = HeaderBuf::<16>::new();
+    /// let a = r.try_read_response(input, &mut buf)?;
+    ///
+    /// let header = &a.headers().unwrap()[0];
+    /// assert_eq!(header.value_raw(), b"one\r\n  two");
+    /// let parts: Vec<_> = header.value_unfolded().collect();
+    /// assert_eq!(parts, [&b"one"[..], &b"two"[..]]);
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn value_unfolded(&self) -> Unfolded<'a> {
+        Unfolded(self.value)
+    }
+}
+
+/// Parts of a header value between obs-fold line breaks, see
+/// [`Header::value_unfolded()`].
+#[derive(Debug, Clone)]
+pub struct Unfolded<'a>(&'a [u8]);
+
+impl<'a> Iterator for Unfolded<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.0.is_empty() {
+                return None;
+            }
+
+            // A value has no line breaks other than folds.
+            let end = self.0.iter().position(|b| *b == b'\n');
+            let (part, rest) = match end {
+                Some(i) => (&self.0[..i], &self.0[i + 1..]),
+                None => (self.0, &[][..]),
+            };
+            self.0 = rest;
+
+            let part = trim(part);
+            if !part.is_empty() {
+                return Some(part);
+            }
+        }
+    }
+}
+
+/// Trim whitespace and a CR of a line break.
+fn trim(v: &[u8]) -> &[u8] {
+    let ws = |b: &u8| matches!(*b, b' ' | b'\t' | b'\r');
+    let start = v.iter().position(|b| !ws(b)).unwrap_or(v.len());
+    let end = v
+        .iter()
+        .rposition(|b| !ws(b))
+        .map(|i| i + 1)
+        .unwrap_or(start);
+    &v[start..end]
 }
 
 impl<'a> fmt::Debug for Header<'a> {
@@ -51,23 +129,194 @@ impl<'a> fmt::Debug for Header<'a> {
     }
 }
 
-pub(crate) fn transmute_headers<'a, 'b>(headers: &'b [InnerHeader<'a>]) -> &'b [Header<'a>] {
-    // SAFETY: Our goal is to have hoot::Header be structurally the same
-    // as httparse::Header. This is asserted by the test below.
-    unsafe { mem::transmute(headers) }
+#[cfg(feature = "defmt")]
+impl<'a> defmt::Format for Header<'a> {
+    fn format(&self, f: defmt::Formatter) {
+        if let Some(value) = self.try_value() {
+            defmt::write!(
+                f,
+                "Header {{ name: {=str}, value: {=str} }}",
+                self.name,
+                value
+            );
+        } else {
+            defmt::write!(
+                f,
+                "Header {{ name: {=str}, value: {=[u8]} }}",
+                self.name,
+                self.value
+            );
+        }
+    }
+}
+
+/// Lookups with multi-value semantics over parsed headers.
+///
+/// Headers may repeat. Most repeated headers can be combined into one comma
+/// separated value, but `Set-Cookie` can't, since cookie values themselves
+/// contain commas. [`HeaderMap::get_combined`] refuses to combine those.
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderMap<'a, 'b> {
+    headers: &'b [Header<'a>],
+}
+
+impl<'a, 'b> HeaderMap<'a, 'b> {
+    pub fn new(headers: &'b [Header<'a>]) -> Self {
+        HeaderMap { headers }
+    }
+
+    /// The first value of the header `name`, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&'b Header<'a>> {
+        self.get_all(name).next()
+    }
+
+    /// All values of the header `name`, ignoring case, in order.
+    pub fn get_all<'n>(&self, name: &'n str) -> impl Iterator<Item = &'b Header<'a>> + 'n
+    where
+        'b: 'n,
+    {
+        self.headers
+            .iter()
+            .filter(move |h| h.name().eq_ignore_ascii_case(name))
+    }
+
+    /// All values of the header `name` joined with `, ` into `buf`.
+    ///
+    /// Errors with `HootError::NotCombinable` for headers that must not be joined,
+    /// such as `Set-Cookie`. Use [`HeaderMap::get_all`] for those.
+    pub fn get_combined<'c>(&self, name: &str, buf: &'c mut [u8]) -> Result<Option<&'c str>> {
+        if !is_combinable(name) {
+            return Err(HootError::NotCombinable);
+        }
+
+        let mut len = 0;
+        let mut found = false;
+
+        for (i, h) in self.get_all(name).enumerate() {
+            found = true;
+
+            let value = h.try_value().ok_or(HootError::ConvertBytesToStr)?.trim();
+            let sep: &[u8] = if i > 0 { b", " } else { b"" };
+
+            let end = len + sep.len() + value.len();
+            if end > buf.len() {
+                return Err(HootError::OutputOverflow {
+                    needed: end - buf.len(),
+                });
+            }
+
+            buf[len..(len + sep.len())].copy_from_slice(sep);
+            buf[(len + sep.len())..end].copy_from_slice(value.as_bytes());
+            len = end;
+        }
+
+        if !found {
+            return Ok(None);
+        }
+
+        // Can't fail since we only copied &str and ASCII separators.
+        Ok(Some(str::from_utf8(&buf[..len])?))
+    }
+}
+
+/// Whether repeated values of the header can be joined with commas.
+fn is_combinable(name: &str) -> bool {
+    !HEADERS_NOT_COMBINABLE
+        .iter()
+        .any(|n| compare_lowercase_ascii(name, n))
+}
+
+// Headers that can't be joined since their values may contain commas.
+const HEADERS_NOT_COMBINABLE: &[&str] = &["set-cookie"];
+
+/// How a message writes its headers.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HeaderStyle {
+    pub version: HttpVersion,
+    /// Write names in canonical case, such as `Content-Type`.
+    pub canonical: bool,
+}
+
+impl HeaderStyle {
+    pub fn of(version: HttpVersion, state: &crate::CallState) -> Self {
+        HeaderStyle {
+            version,
+            canonical: state.canonical_header_names,
+        }
+    }
 }
 
 pub(crate) fn check_and_output_header(
-    mut w: Writer,
-    version: HttpVersion,
+    w: Writer,
+    style: HeaderStyle,
     name: &str,
     bytes: &[u8],
     trailer: bool,
+    validate: bool,
+    sent_singular: &mut u32,
+) -> Result<()> {
+    if validate {
+        check_name_value(name, bytes)?;
+    }
+
+    output_header_with(w, style, name, trailer, validate, sent_singular, |w| {
+        w.write_bytes(bytes)?;
+        Ok(())
+    })
+}
+
+/// Like `check_and_output_header()`, with a value formatted straight into
+/// the output. The caller makes sure `value` can't inject headers.
+pub(crate) fn output_header_fmt(
+    w: Writer,
+    style: HeaderStyle,
+    name: &str,
+    value: fmt::Arguments<'_>,
+    sent_singular: &mut u32,
+) -> Result<()> {
+    output_header_with(w, style, name, false, true, sent_singular, |w| {
+        w.write_fmt(value)
+    })
+}
+
+/// Like `output_header_fmt()`, with the value written by `value` given the
+/// output before the header, such as a signature of the head.
+pub(crate) fn output_header_after(
+    w: Writer,
+    style: HeaderStyle,
+    name: &str,
+    sent_singular: &mut u32,
+    value: impl FnOnce(&[u8], &mut Writer) -> Result<()>,
+) -> Result<()> {
+    check_name_value(name, b"")?;
+
+    output_header_with(w, style, name, false, true, sent_singular, |w| {
+        w.write_after(|before, w| {
+            value(before, w)?;
+
+            let (written, _) = w.split_and_borrow();
+            check_name_value(name, written)
+        })
+    })
+}
+
+fn output_header_with(
+    mut w: Writer,
+    style: HeaderStyle,
+    name: &str,
+    trailer: bool,
+    validate: bool,
+    sent_singular: &mut u32,
+    value: impl FnOnce(&mut Writer) -> Result<()>,
 ) -> Result<()> {
     // Writer header
-    write!(w, "{}: ", name).or(OVERFLOW)?;
-    w.write_bytes(bytes)?;
-    write!(w, "\r\n").or(OVERFLOW)?;
+    if style.canonical {
+        write!(w, "{}: ", Canonical(name))?;
+    } else {
+        write!(w, "{}: ", name)?;
+    }
+    value(&mut w)?;
+    write!(w, "\r\n")?;
 
     if trailer {
         check_headers(name, HEADERS_FORBID_TRAILER, HootError::ForbiddenTrailer)?;
@@ -75,7 +324,7 @@ pub(crate) fn check_and_output_header(
         // These headers are forbidden because we write them with
         check_headers(name, HEADERS_FORBID_BODY, HootError::ForbiddenBodyHeader)?;
 
-        match version {
+        match style.version {
             HttpVersion::Http10 => {
                 // TODO: forbid specific headers for 1.0
             }
@@ -85,24 +334,131 @@ pub(crate) fn check_and_output_header(
         }
     }
 
-    // TODO: forbid headers that are not allowed to be repeated
+    // Trailers are a separate section, and mostly forbidden from repeating
+    // anything in the header.
+    let singular = if trailer { 0 } else { singular_bit(name) };
 
-    // Parse the written result to see if httparse can validate it.
-    let (written, buf) = w.split_and_borrow();
+    if *sent_singular & singular > 0 {
+        return Err(HootError::DuplicateHeader);
+    }
 
-    let result = parse_headers(written, buf)?;
+    if validate {
+        // Parse the written result to see if the parser can validate it.
+        let (written, _) = w.split_and_borrow();
 
-    if result.len() != 1 {
-        // If we don't manage to parse back the hedaer we just wrote, it's a bug in hoot.
-        panic!("Failed to parse one written header");
+        // One more than expected, to tell a bug from a header too many.
+        let mut buf = [EMPTY_HEADER; 2];
+        let result = parse_headers(written, &mut buf)?;
+
+        if result.len() != 1 {
+            // If we don't manage to parse back the header we just wrote, it's a bug in hoot.
+            return Err(HootError::Internal);
+        }
     }
 
     // If nothing error before this, commit the result to Out.
     w.commit();
 
+    *sent_singular |= singular;
+
+    Ok(())
+}
+
+/// Header name with the first letter of each `-` separated word in upper
+/// case, and the rest in lower case.
+struct Canonical<'a>(&'a str);
+
+impl fmt::Display for Canonical<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut upper = true;
+        for c in self.0.chars() {
+            let c = if upper {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            };
+            f.write_char(c)?;
+            upper = c == '-';
+        }
+        Ok(())
+    }
+}
+
+/// Check that the name is a token, and that the value has no control characters
+/// (except tab) that could inject further headers.
+fn check_name_value(name: &str, bytes: &[u8]) -> Result<()> {
+    if name.is_empty() {
+        return Err(HootError::HeaderName { offset: 0 });
+    }
+
+    if let Some(offset) = name.bytes().position(|b| !is_tchar(b)) {
+        return Err(HootError::HeaderName { offset });
+    }
+
+    check_value(bytes)
+}
+
+/// Check a header value has no control characters, such as CR and LF.
+pub(crate) fn check_value(bytes: &[u8]) -> Result<()> {
+    if let Some(offset) = bytes
+        .iter()
+        .position(|b| (*b < 0x20 && *b != b'\t') || *b == 0x7f)
+    {
+        return Err(HootError::HeaderValue { offset });
+    }
+
     Ok(())
 }
 
+// tchar = "!" / "#" / "$" / "%" / "&" / "'" / "*" / "+" / "-" / "." /
+//         "^" / "_" / "`" / "|" / "~" / DIGIT / ALPHA
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Whether the singular header `name` is in the `sent_singular` set.
+#[cfg(feature = "serde")]
+pub(crate) fn is_sent(sent_singular: u32, name: &str) -> bool {
+    sent_singular & singular_bit(name) > 0
+}
+
+/// Bit in the `sent_singular` set for headers that must not be repeated.
+fn singular_bit(name: &str) -> u32 {
+    HEADERS_SINGULAR
+        .iter()
+        .position(|n| compare_lowercase_ascii(name, n))
+        .map(|i| 1 << i)
+        .unwrap_or(0)
+}
+
+// Headers that must appear at most once, RFC 7230 §3.2.2. At most 32.
+const HEADERS_SINGULAR: &[&str] = &[
+    "host",
+    "content-length",
+    "transfer-encoding",
+    "content-type",
+    "content-location",
+    "content-range",
+    "authorization",
+    "proxy-authorization",
+    "location",
+    "date",
+    "etag",
+    "last-modified",
+    "expires",
+    "age",
+    "retry-after",
+    "max-forwards",
+    "if-modified-since",
+    "if-unmodified-since",
+    "if-range",
+    "referer",
+    "user-agent",
+    "from",
+    "deprecation",
+    "sunset",
+];
+
 // Headers that are not allowed because we set them as part of making a call.
 const HEADERS_FORBID_BODY: &[&str] = &[
     // header set by with_body()
@@ -136,6 +492,23 @@ const HEADERS_FORBID_TRAILER: &[&str] = &[
 // authentication headers (e.g., Authorization or Set-Cookie),
 // or Content-Encoding, Content-Type, Content-Range, and Trailer itself.
 
+/// Check a raw trailer section for passing it on byte-exact.
+pub(crate) fn check_raw_trailers(raw: &[u8]) -> Result<()> {
+    let check =
+        |name: &str| check_headers(name, HEADERS_FORBID_TRAILER, HootError::ForbiddenTrailer);
+
+    let len = match scan_header_section(raw, check)? {
+        Some(v) => v,
+        None => return Err(HootError::IncorrectTrailers),
+    };
+
+    if len != raw.len() {
+        return Err(HootError::IncorrectTrailers);
+    }
+
+    Ok(())
+}
+
 fn check_headers(name: &str, forbidden: &[&str], err: HootError) -> Result<()> {
     for c in forbidden {
         if !compare_lowercase_ascii(name, c) {
@@ -151,13 +524,42 @@ fn check_headers(name: &str, forbidden: &[&str], err: HootError) -> Result<()> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use memoffset::offset_of;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn header_map_combined() -> Result<()> {
+        let mut hbuf = HeaderBuf::<16>::new();
+        let headers = parse_headers(
+            b"Accept: a\r\nSet-Cookie: x=1; Expires=Wed, 21 Oct\r\n\
+            accept: b\r\nSet-Cookie: y=2\r\n\r\n",
+            &mut hbuf,
+        )?;
+        let map = HeaderMap::new(headers);
+
+        let mut buf = [0; 64];
+        assert_eq!(map.get_combined("ACCEPT", &mut buf)?, Some("a, b"));
+        assert_eq!(map.get_combined("x-missing", &mut buf)?, None);
+
+        assert_eq!(
+            map.get_combined("set-cookie", &mut buf),
+            Err(HootError::NotCombinable)
+        );
+        let cookies: Vec<_> = map
+            .get_all("Set-Cookie")
+            .filter_map(|h| h.try_value())
+            .collect();
+        assert_eq!(cookies, ["x=1; Expires=Wed, 21 Oct", "y=2"]);
+
+        Ok(())
+    }
 
     #[test]
-    fn assert_httparse_header_transmutability() {
-        assert_eq!(mem::size_of::<Header>(), mem::size_of::<InnerHeader>());
-        assert_eq!(mem::align_of::<Header>(), mem::align_of::<InnerHeader>());
-        assert_eq!(offset_of!(Header, name), offset_of!(InnerHeader, name));
-        assert_eq!(offset_of!(Header, value), offset_of!(InnerHeader, value));
+    fn canonical_name() {
+        let c = |name| Canonical(name).to_string();
+        assert_eq!(c("content-length"), "Content-Length");
+        assert_eq!(c("X-REQUEST-ID"), "X-Request-Id");
+        assert_eq!(c("www-authenticate"), "Www-Authenticate");
+        assert_eq!(c("x--a"), "X--A");
+        assert_eq!(c("-x"), "-X");
     }
 }
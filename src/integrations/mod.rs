@@ -0,0 +1,6 @@
+//! Glue for running hoot over network stacks.
+//!
+//! Each integration is behind a feature of the same name.
+
+#[cfg(feature = "smoltcp")]
+pub mod smoltcp;
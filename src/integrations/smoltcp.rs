@@ -0,0 +1,280 @@
+//! Running hoot over a [smoltcp](https://docs.rs/smoltcp) TCP socket.
+//!
+//! A smoltcp socket has fixed size buffers. Output from hoot can be larger
+//! than the free part of the transmit buffer, and is sent with
+//! [`SendOutput`] over as many polls as it takes. Input arrives in whatever
+//! pieces the peer sent, and is collected with [`RecvInput`] until hoot can
+//! parse it. What hoot reports as used is then consumed, and the rest kept for
+//! the next step.
+//!
+//! ```ignore
+//! let mut input = RecvInput::<1024>::new();
+//!
+//! // On each poll of the interface.
+//! let socket = sockets.get_mut::<tcp::Socket>(handle);
+//! input.recv(socket)?;
+//!
+//! let attempt = request.try_read_request(input.input(), &mut buf)?;
+//! if attempt.is_success() {
+//!     let used = attempt.input_used();
+//!     input.consume(used)?;
+//!     // ...
+//! }
+//! ```
+
+use ::smoltcp::socket::tcp::{RecvError, SendError, Socket, State};
+
+use crate::util::InputBuffer;
+use crate::HootError;
+
+/// Output being sent over a socket.
+#[derive(Debug)]
+pub struct SendOutput<'a> {
+    output: &'a [u8],
+    sent: usize,
+}
+
+impl<'a> SendOutput<'a> {
+    pub fn new(output: &'a [u8]) -> Self {
+        SendOutput { output, sent: 0 }
+    }
+
+    /// Enqueue as much of the output as fits in the transmit buffer.
+    ///
+    /// Returns whether all output is enqueued. Call again on later polls
+    /// until it is.
+    pub fn send(&mut self, socket: &mut Socket) -> Result<bool, SendError> {
+        if !self.is_done() {
+            let rest = &self.output[self.sent..];
+
+            let n = socket.send(|window| {
+                let n = window.len().min(rest.len());
+                window[..n].copy_from_slice(&rest[..n]);
+                (n, n)
+            })?;
+
+            self.sent += n;
+        }
+
+        Ok(self.is_done())
+    }
+
+    /// Whether all output is enqueued.
+    pub fn is_done(&self) -> bool {
+        self.sent == self.output.len()
+    }
+}
+
+/// Input collected from a socket until hoot has used it.
+#[derive(Debug, Default)]
+pub struct RecvInput<const N: usize> {
+    buf: InputBuffer<N>,
+}
+
+impl<const N: usize> RecvInput<N> {
+    pub fn new() -> Self {
+        RecvInput {
+            buf: InputBuffer::new(),
+        }
+    }
+
+    /// Dequeue received bytes into the free part of the buffer.
+    ///
+    /// Returns the number of bytes received, which is `0` also while the
+    /// connection is not yet established. The receive buffer of the socket
+    /// can wrap around, in which case the rest is received by the next call.
+    /// `RecvError::Finished` means the peer closed the connection, which ends
+    /// a response body without declared length.
+    pub fn recv(&mut self, socket: &mut Socket) -> Result<usize, RecvError> {
+        if !socket.can_recv() {
+            return match socket.state() {
+                // The peer sent FIN.
+                State::CloseWait | State::LastAck | State::Closing | State::TimeWait => {
+                    Err(RecvError::Finished)
+                }
+                _ => Ok(0),
+            };
+        }
+
+        let buf = &mut self.buf;
+        socket.recv(|window| {
+            let n = buf.append(window);
+            (n, n)
+        })
+    }
+
+    /// The input not yet used.
+    pub fn input(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consume `used` bytes of input, as reported by `input_used()`.
+    ///
+    /// More than [`input()`](Self::input) is `HootError::BufferOverrun`.
+    pub fn consume(&mut self, used: usize) -> Result<(), HootError> {
+        self.buf.consume(used)
+    }
+
+    /// Whether the buffer is full.
+    ///
+    /// If hoot can't make progress on a full buffer, the message head is too
+    /// large for it.
+    pub fn is_full(&self) -> bool {
+        self.buf.is_full()
+    }
+}
+
+#[cfg(all(test, feature = "std", feature = "call", feature = "reply"))]
+mod test {
+    use super::*;
+    use crate::client::call;
+    use crate::server::{reply, Request};
+    use crate::util::HeaderBuf;
+    use ::smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+    use ::smoltcp::phy::{Loopback, Medium};
+    use ::smoltcp::socket::tcp::SocketBuffer;
+    use ::smoltcp::time::{Duration, Instant};
+    use ::smoltcp::wire::{HardwareAddress, IpAddress, IpCidr};
+
+    struct Net {
+        device: Loopback,
+        iface: Interface,
+        sockets: SocketSet<'static>,
+        now: Instant,
+    }
+
+    impl Net {
+        fn poll(&mut self, handle: SocketHandle) -> &mut Socket<'static> {
+            self.iface
+                .poll(self.now, &mut self.device, &mut self.sockets);
+            self.now += Duration::from_millis(1);
+            self.sockets.get_mut::<Socket>(handle)
+        }
+    }
+
+    #[test]
+    fn call_over_loopback() -> Result<(), Box<dyn std::error::Error>> {
+        let mut device = Loopback::new(Medium::Ip);
+        let now = Instant::from_millis(0);
+        let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut device, now);
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+                .unwrap();
+        });
+
+        // Buffers smaller than the messages, to send and receive in pieces.
+        let socket = || {
+            Socket::new(
+                SocketBuffer::new(vec![0; 16]),
+                SocketBuffer::new(vec![0; 16]),
+            )
+        };
+
+        let mut sockets = SocketSet::new(vec![]);
+        let server = sockets.add(socket());
+        let client = sockets.add(socket());
+
+        sockets.get_mut::<Socket>(server).listen(80)?;
+        let addr = (IpAddress::v4(127, 0, 0, 1), 80);
+        sockets
+            .get_mut::<Socket>(client)
+            .connect(iface.context(), addr, 49152)?;
+
+        let mut net = Net {
+            device,
+            iface,
+            sockets,
+            now,
+        };
+
+        // Client sends the request, and server reads it.
+        let mut client_out = [0; 256];
+        let output = call().get("http://127.0.0.1/hello").send(&mut client_out)?;
+        let len = output.len();
+        let response = output.into_response();
+        let mut send = SendOutput::new(&client_out[..len]);
+
+        let mut request = Request::new();
+        let mut input = RecvInput::<64>::new();
+
+        let mut polls = 0;
+        let variant = loop {
+            polls += 1;
+            assert!(polls < 1000);
+
+            if net.poll(client).may_send() {
+                send.send(net.poll(client))?;
+            }
+
+            input.recv(net.poll(server))?;
+            let mut headers = HeaderBuf::<16>::new();
+            let attempt = request.try_read_request(input.input(), &mut headers)?;
+            if attempt.is_success() {
+                assert_eq!(attempt.line().unwrap().path(), "/hello");
+                let used = attempt.input_used();
+                input.consume(used)?;
+                break request.proceed().into_response()?;
+            }
+        };
+
+        assert!(send.is_done());
+        assert!(polls > 1, "request not sent in pieces");
+
+        // Server sends the response, and client reads it.
+        let mut server_out = [0; 256];
+        let output = reply(200)
+            .body(b"hello world, in several windows")
+            .send(variant, &mut server_out)?;
+        let mut send = SendOutput::new(output.as_bytes());
+
+        let mut response = response;
+        let mut input = RecvInput::<64>::new();
+
+        let mut polls = 0;
+        let mut response = loop {
+            polls += 1;
+            assert!(polls < 1000);
+
+            send.send(net.poll(server))?;
+
+            input.recv(net.poll(client))?;
+            let mut headers = HeaderBuf::<16>::new();
+            let attempt = response.try_read_response(input.input(), &mut headers)?;
+            if attempt.is_success() {
+                assert_eq!(attempt.status().unwrap().code(), 200);
+                let used = attempt.input_used();
+                input.consume(used)?;
+                break response.proceed();
+            }
+        };
+
+        let mut body = vec![];
+        let mut dst = [0; 64];
+        while !response.is_finished() {
+            polls += 1;
+            assert!(polls < 1000);
+
+            send.send(net.poll(server))?;
+
+            input.recv(net.poll(client))?;
+            let part = response.read_body(input.input(), &mut dst)?;
+            body.extend_from_slice(part.data());
+            let used = part.input_used();
+            input.consume(used)?;
+        }
+
+        assert_eq!(body, b"hello world, in several windows");
+
+        let err = input.consume(1).unwrap_err();
+        assert_eq!(
+            err,
+            HootError::BufferOverrun {
+                amount: 1,
+                available: 0
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! JSON bodies with serde, without allocation.
+//!
+//! Values are serialized straight into the output buffer with `send_json()`,
+//! or as a chunk with `write_json()`. Incoming bodies are read into a
+//! [`JsonBody`] with `read_json()`, and deserialized from there.
+//!
+//! ```
+//! use hoot::json::JsonBody;
+//! use hoot::server::{Request, ResponseVariant};
+//! use hoot::types::state::RECV_BODY;
+//! use hoot::util::HeaderBuf;
+//!
+//! const INPUT: &[u8] = b"POST /sum HTTP/1.1\r\nHost: example.test\r\n\
+//!     Content-Length: 7\r\n\r\n[1,2,3]";
+//!
+//! let mut buf = HeaderBuf::<16>::new();
+//! let mut request = Request::new();
+//! let attempt = request.try_read_request(INPUT, &mut buf)?;
+//! let used = attempt.input_used();
+//!
+//! let mut request: Request<RECV_BODY> = request.proceed();
+//! let mut body_buf = [0; 256];
+//! let mut json = JsonBody::new(&mut body_buf);
+//! request.read_json(&INPUT[used..], &mut json)?;
+//! assert!(request.is_finished());
+//!
+//! let numbers: [u32; 3] = json.parse()?;
+//!
+//! let token = match request.into_response()? {
+//!     ResponseVariant::Post(v) => v,
+//!     _ => unreachable!(),
+//! };
+//!
+//! let mut out = [0; 1024];
+//! let output = hoot::server::Response::resume(token, &mut out)
+//!     .send_status(200, "OK")?
+//!     .send_json(&numbers.iter().sum::<u32>())?
+//!     .flush();
+//!
+//! assert_eq!(
+//!     &output[..],
+//!     b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 1\r\n\r\n6"
+//! );
+//! # Ok::<(), hoot::HootError>(())
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::out::{Out, Writer};
+use crate::{BodyPart, HootError, Result};
+
+/// Buffer for reading an incoming JSON body.
+///
+/// The body is read into the buffer with `read_json()` of `server::Request` or
+/// `client::Response`, and once it is finished, deserialized with [`parse()`].
+///
+/// [`parse()`]: JsonBody::parse
+pub struct JsonBody<'d> {
+    buf: &'d mut [u8],
+    len: usize,
+}
+
+impl<'d> JsonBody<'d> {
+    /// Read the body into `buf`. Bodies larger than `buf` fail with
+    /// `HootError::BodyTooLarge`.
+    pub fn new(buf: &'d mut [u8]) -> Self {
+        JsonBody { buf, len: 0 }
+    }
+
+    /// The body read so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Deserialize the body. Values may borrow from the buffer.
+    ///
+    /// Fails with `HootError::Json` if the body is not a `T`.
+    pub fn parse<'x, T: Deserialize<'x>>(&'x self) -> Result<T> {
+        let (value, _) =
+            serde_json_core::from_slice(self.as_bytes()).map_err(|_| HootError::Json)?;
+        Ok(value)
+    }
+
+    /// Read body from `src` using `read`, until `src` is used or the body ends.
+    ///
+    /// Returns the amount of `src` used.
+    pub(crate) fn read(
+        &mut self,
+        src: &[u8],
+        mut read: impl for<'b> FnMut(&[u8], &'b mut [u8]) -> Result<BodyPart<'b>>,
+    ) -> Result<usize> {
+        let mut used = 0;
+
+        loop {
+            let part = read(&src[used..], &mut self.buf[self.len..])?;
+            used += part.input_used();
+            self.len += part.data().len();
+
+            if part.is_finished() {
+                return Ok(used);
+            }
+
+            if part.input_used() == 0 && part.data().is_empty() {
+                // Stalled with input left means the buffer is full.
+                if used < src.len() && self.len == self.buf.len() {
+                    return Err(HootError::BodyTooLarge);
+                }
+                return Ok(used);
+            }
+        }
+    }
+}
+
+/// Serialize `value` into `buf`, returning the length.
+pub(crate) fn to_slice<T: Serialize + ?Sized>(value: &T, buf: &mut [u8]) -> Result<usize> {
+    serde_json_core::to_slice(value, buf).map_err(|e| match e {
+        // serde-json-core does not tell how much more it needs.
+        serde_json_core::ser::Error::BufferFull => HootError::OutputOverflow { needed: 1 },
+        _ => HootError::Json,
+    })
+}
+
+/// Write the end of a head and `value` as a length delimited body.
+///
+/// `Content-Type` is written unless `content_type` is false.
+pub(crate) fn write_json_body<T: Serialize + ?Sized>(
+    w: &mut Writer,
+    value: &T,
+    content_type: bool,
+) -> Result<usize> {
+    // Space reserved for the headers, which are written when we know the length.
+    const RESERVE: usize =
+        "Content-Type: application/json\r\nContent-Length: 18446744073709551615\r\n\r\n".len();
+
+    let mut body_len = 0;
+    w.write_with(|buf| {
+        if buf.len() < RESERVE {
+            return Err(HootError::OutputOverflow {
+                needed: RESERVE - buf.len(),
+            });
+        }
+
+        let (head, rest) = buf.split_at_mut(RESERVE);
+        let n = to_slice(value, rest)?;
+        body_len = n;
+
+        trace!("Serialized JSON body: {}", n);
+
+        let mut hout = Out::wrap(head);
+        let mut hw = hout.writer();
+        if content_type {
+            write!(hw, "Content-Type: application/json\r\n")?;
+        }
+        write!(hw, "Content-Length: {}\r\n\r\n", n)?;
+        hw.commit();
+        let head_len = hout.into_inner().len();
+
+        buf.copy_within(RESERVE..(RESERVE + n), head_len);
+
+        Ok(head_len + n)
+    })?;
+
+    Ok(body_len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{Request, Response};
+    use crate::types::state::RECV_RESPONSE;
+    use crate::util::HeaderBuf;
+
+    fn get(buf: &mut [u8]) -> Result<Response<RECV_RESPONSE>> {
+        let output = Request::new(buf)
+            .http_11()
+            .get("example.test", "/")?
+            .send()?
+            .flush();
+        Ok(output.into_response())
+    }
+
+    #[test]
+    fn json_chunks_and_partial_reads() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let output = Request::new(&mut buf)
+            .http_11()
+            .post("example.test", "/")?
+            .header("Content-Type", "application/json")?
+            .with_chunked()?
+            .write_json(&(1, "a"))?
+            .finish()?
+            .flush();
+
+        assert_eq!(
+            &output[..],
+            b"POST / HTTP/1.1\r\nHost: example.test\r\nContent-Type: application/json\r\n\
+            Transfer-Encoding: chunked\r\n\r\n7\r\n[1,\"a\"]\r\n0\r\n\r\n"
+        );
+
+        let mut buf = [0; 1024];
+        let err = Request::new(&mut buf[..70])
+            .http_11()
+            .post("example.test", "/")?
+            .send_json("not quite fitting")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 39 });
+
+        const INPUT: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n[true,2]\n";
+
+        let mut response = get(&mut buf)?;
+        let attempt = response.try_read_response(INPUT, &mut headers)?;
+        let used = attempt.input_used();
+        let mut response = response.proceed();
+
+        let mut body_buf = [0; 16];
+        let mut json = JsonBody::new(&mut body_buf);
+        assert_eq!(response.read_json(&INPUT[used..(used + 4)], &mut json)?, 4);
+        assert!(!response.is_finished());
+        assert_eq!(response.read_json(&INPUT[(used + 4)..], &mut json)?, 5);
+        assert!(response.is_finished());
+
+        let value: (bool, u8) = json.parse()?;
+        assert_eq!(value, (true, 2));
+        let err = json.parse::<u8>().map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::Json);
+
+        let mut response = get(&mut buf)?;
+        let attempt = response.try_read_response(INPUT, &mut headers)?;
+        let used = attempt.input_used();
+        let mut response = response.proceed();
+
+        let mut body_buf = [0; 4];
+        let mut json = JsonBody::new(&mut body_buf);
+        let err = response
+            .read_json(&INPUT[used..], &mut json)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::BodyTooLarge);
+
+        Ok(())
+    }
+}
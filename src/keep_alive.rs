@@ -0,0 +1,361 @@
+//! Keep-alive idle timeout tracking.
+//!
+//! hoot does not own connections, but the bookkeeping to decide whether an
+//! idle connection is safe to reuse is fiddly to get right. Servers advertise
+//! how long they keep idle connections via `Keep-Alive: timeout=5, max=100`.
+//! Writing a request onto a connection the server is just closing is a classic
+//! race. [`IdleExpiry`] expires connections slightly before the advertised
+//! timeout to avoid it.
+//!
+//! Time is provided by the caller as milliseconds from any monotonic source.
+
+use core::str;
+
+use crate::connection::ConnectionOptions;
+use crate::util::compare_lowercase_ascii;
+use crate::{Header, HootError, Result};
+
+/// Parsed `Keep-Alive` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// Idle timeout in seconds.
+    pub timeout: Option<u64>,
+    /// Number of requests the server still allows on the connection. Servers
+    /// count this down with each response.
+    pub max: Option<u32>,
+}
+
+impl KeepAlive {
+    /// Parse a `Keep-Alive` header value. Unknown parameters are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut ka = KeepAlive::default();
+
+        for param in value.split(',') {
+            let (name, v) = match param.split_once('=') {
+                Some((n, v)) => (n.trim(), v.trim()),
+                None => continue,
+            };
+
+            if compare_lowercase_ascii(name, "timeout") {
+                ka.timeout = v.parse().ok();
+            } else if compare_lowercase_ascii(name, "max") {
+                ka.max = v.parse().ok();
+            }
+        }
+
+        ka
+    }
+
+    /// Find and parse the `Keep-Alive` header.
+    pub fn from_headers(headers: &[Header<'_>]) -> Option<Self> {
+        headers
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "keep-alive"))
+            .and_then(|h| h.try_value())
+            .map(Self::parse)
+    }
+}
+
+/// Decides when an idle connection should no longer be reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleExpiry {
+    last_activity: u64,
+    timeout_ms: u64,
+    margin_ms: u64,
+    requests_left: Option<u32>,
+}
+
+impl IdleExpiry {
+    /// Create with a timeout to use when the server does not advertise one.
+    ///
+    /// `margin_ms` is how much before the timeout the connection is considered
+    /// expired.
+    pub fn new(now_ms: u64, default_timeout_ms: u64, margin_ms: u64) -> Self {
+        IdleExpiry {
+            last_activity: now_ms,
+            timeout_ms: default_timeout_ms,
+            margin_ms,
+            requests_left: None,
+        }
+    }
+
+    /// Update with the `Keep-Alive` of a response.
+    ///
+    /// Call this once per completed response. It both records the activity and
+    /// the requests left on the connection. The `max` of the latest response is
+    /// what is left, without one the last `max` is counted down.
+    pub fn response_done(&mut self, now_ms: u64, keep_alive: Option<KeepAlive>) {
+        self.last_activity = now_ms;
+
+        let ka = keep_alive.unwrap_or_default();
+        if let Some(t) = ka.timeout {
+            self.timeout_ms = t.saturating_mul(1000);
+        }
+        self.requests_left = ka
+            .max
+            .or_else(|| self.requests_left.map(|n| n.saturating_sub(1)));
+    }
+
+    /// Update with the headers of a response.
+    ///
+    /// Same as [`response_done()`](Self::response_done) with the `Keep-Alive`
+    /// header, and a `Connection: close` expires the connection.
+    pub fn response_headers(&mut self, now_ms: u64, headers: &[Header<'_>]) {
+        self.response_done(now_ms, KeepAlive::from_headers(headers));
+
+        if ConnectionOptions::new(headers).is_close() {
+            self.requests_left = Some(0);
+        }
+    }
+
+    /// Record other activity on the connection.
+    pub fn touch(&mut self, now_ms: u64) {
+        self.last_activity = now_ms;
+    }
+
+    /// Time in milliseconds the connection can still be reused.
+    pub fn remaining(&self, now_ms: u64) -> u64 {
+        let usable = self.timeout_ms.saturating_sub(self.margin_ms);
+        let idle = now_ms.saturating_sub(self.last_activity);
+        usable.saturating_sub(idle)
+    }
+
+    /// Whether the connection should be closed rather than reused.
+    ///
+    /// A `max=1` counts as used up, since servers differ in whether it
+    /// includes the response it is sent with.
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        let exhausted = self.requests_left.map(|n| n <= 1).unwrap_or(false);
+        exhausted || self.remaining(now_ms) == 0
+    }
+
+    /// Record the expectation of the connection to `peer` for persisting.
+    pub fn ticket<'a>(&self, peer: &'a str) -> ConnectionTicket<'a> {
+        ConnectionTicket {
+            peer,
+            expiry: *self,
+        }
+    }
+}
+
+/// How to use a connection recorded in a [`ConnectionTicket`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeStrategy {
+    /// The connection is expected to still be usable. Try sending a request
+    /// on it, and fall back to reconnecting if that fails (see [`retry_on_stale`]).
+    FastResume,
+    /// The connection is expected to be gone. Open a new one.
+    Reconnect,
+}
+
+/// Serializable expectation about a connection.
+///
+/// Devices that reboot often, but keep their NAT bindings, can persist this and
+/// decide on boot whether to try the old connection or reconnect. Since the
+/// ticket outlives the process, times must come from a clock that survives a
+/// reboot, such as an RTC, rather than a monotonic timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionTicket<'a> {
+    peer: &'a str,
+    expiry: IdleExpiry,
+}
+
+const TICKET_VERSION: u8 = 2;
+const TICKET_HEADER: usize = 1 + 8 * 3 + 4 + 2;
+
+impl<'a> ConnectionTicket<'a> {
+    /// The peer, typically `host:port`, the connection was to.
+    pub fn peer(&self) -> &'a str {
+        self.peer
+    }
+
+    /// Decide how to use the connection at `now_ms`.
+    ///
+    /// A clock that went backwards means the time can't be trusted, which
+    /// results in [`ResumeStrategy::Reconnect`].
+    pub fn strategy(&self, now_ms: u64) -> ResumeStrategy {
+        if now_ms < self.expiry.last_activity || self.expiry.is_expired(now_ms) {
+            ResumeStrategy::Reconnect
+        } else {
+            ResumeStrategy::FastResume
+        }
+    }
+
+    /// Continue the idle bookkeeping of a resumed connection.
+    pub fn into_expiry(self) -> IdleExpiry {
+        self.expiry
+    }
+
+    /// Serialize into `dst`. Returns the length.
+    pub fn write_to(&self, dst: &mut [u8]) -> Result<usize> {
+        let peer = self.peer.as_bytes();
+        let len = TICKET_HEADER + peer.len();
+
+        if peer.len() > u16::MAX as usize {
+            return Err(HootError::ConnectionTicket);
+        }
+        if dst.len() < len {
+            return Err(HootError::OutputOverflow {
+                needed: len - dst.len(),
+            });
+        }
+
+        let e = &self.expiry;
+        dst[0] = TICKET_VERSION;
+        dst[1..9].copy_from_slice(&e.last_activity.to_le_bytes());
+        dst[9..17].copy_from_slice(&e.timeout_ms.to_le_bytes());
+        dst[17..25].copy_from_slice(&e.margin_ms.to_le_bytes());
+        dst[25..29].copy_from_slice(&e.requests_left.unwrap_or(u32::MAX).to_le_bytes());
+        dst[29..31].copy_from_slice(&(peer.len() as u16).to_le_bytes());
+        dst[TICKET_HEADER..len].copy_from_slice(peer);
+
+        Ok(len)
+    }
+
+    /// Deserialize from bytes written by [`ConnectionTicket::write_to`].
+    pub fn read_from(src: &'a [u8]) -> Result<Self> {
+        if src.len() < TICKET_HEADER || src[0] != TICKET_VERSION {
+            return Err(HootError::ConnectionTicket);
+        }
+
+        let u64_at = |i: usize| {
+            let mut b = [0; 8];
+            b.copy_from_slice(&src[i..(i + 8)]);
+            u64::from_le_bytes(b)
+        };
+        let u32_at = |i: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&src[i..(i + 4)]);
+            u32::from_le_bytes(b)
+        };
+
+        let peer_len = u16::from_le_bytes([src[29], src[30]]) as usize;
+        let peer = src
+            .get(TICKET_HEADER..(TICKET_HEADER + peer_len))
+            .ok_or(HootError::ConnectionTicket)?;
+        let peer = str::from_utf8(peer).map_err(|_| HootError::ConnectionTicket)?;
+
+        let requests_left = u32_at(25);
+
+        Ok(ConnectionTicket {
+            peer,
+            expiry: IdleExpiry {
+                last_activity: u64_at(1),
+                timeout_ms: u64_at(9),
+                margin_ms: u64_at(17),
+                requests_left: if requests_left == u32::MAX {
+                    None
+                } else {
+                    Some(requests_left)
+                },
+            },
+        })
+    }
+}
+
+/// Whether to retry a request that failed on a reused connection.
+///
+/// A request is retried once when the connection was reused (it may have gone
+/// stale), nothing of the response was received, and the method is idempotent.
+pub fn retry_on_stale(
+    attempt: u32,
+    reused: bool,
+    got_response_bytes: bool,
+    idempotent: bool,
+) -> bool {
+    attempt == 0 && reused && !got_response_bytes && idempotent
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_keep_alive() {
+        let ka = KeepAlive::parse("timeout=5, max=100");
+        assert_eq!(ka.timeout, Some(5));
+        assert_eq!(ka.max, Some(100));
+
+        let ka = KeepAlive::parse("Timeout = 2, foo");
+        assert_eq!(ka.timeout, Some(2));
+        assert_eq!(ka.max, None);
+    }
+
+    #[test]
+    fn idle_expiry() {
+        let mut e = IdleExpiry::new(0, 60_000, 500);
+        e.response_done(1000, Some(KeepAlive::parse("timeout=5, max=2")));
+
+        assert_eq!(e.remaining(1000), 4500);
+        assert!(!e.is_expired(5499));
+        assert!(e.is_expired(5500));
+
+        // Without a new max, the last one counts down.
+        e.response_done(2000, None);
+        assert!(e.is_expired(2000));
+    }
+
+    #[test]
+    fn idle_expiry_max() {
+        let mut e = IdleExpiry::new(0, 60_000, 500);
+
+        // The max counts down, it is what is left rather than a total.
+        for (i, max) in (2..=100).rev().enumerate() {
+            let now = i as u64 * 10;
+            e.response_done(
+                now,
+                Some(KeepAlive {
+                    timeout: None,
+                    max: Some(max),
+                }),
+            );
+            assert!(!e.is_expired(now));
+        }
+
+        e.response_done(1000, Some(KeepAlive::parse("max=1")));
+        assert!(e.is_expired(1000));
+    }
+
+    #[test]
+    fn idle_expiry_close() {
+        let mut e = IdleExpiry::new(0, 60_000, 500);
+        e.response_headers(1000, &[Header::new("Keep-Alive", b"timeout=5, max=100")]);
+        assert!(!e.is_expired(1000));
+
+        e.response_headers(2000, &[Header::new("Connection", b"close")]);
+        assert!(e.is_expired(2000));
+    }
+
+    #[test]
+    fn ticket_roundtrip() -> Result<()> {
+        let mut e = IdleExpiry::new(0, 60_000, 500);
+        e.response_done(1000, Some(KeepAlive::parse("timeout=5")));
+
+        let mut buf = [0; 64];
+        let n = e.ticket("myhost.test:80").write_to(&mut buf)?;
+
+        let t = ConnectionTicket::read_from(&buf[..n])?;
+        assert_eq!(t.peer(), "myhost.test:80");
+        assert_eq!(t.strategy(2000), ResumeStrategy::FastResume);
+        assert_eq!(t.strategy(5500), ResumeStrategy::Reconnect);
+        // Clock went backwards.
+        assert_eq!(t.strategy(500), ResumeStrategy::Reconnect);
+        assert_eq!(t.into_expiry(), e);
+
+        assert_eq!(
+            ConnectionTicket::read_from(&buf[..10]),
+            Err(HootError::ConnectionTicket)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn retry_once() {
+        assert!(retry_on_stale(0, true, false, true));
+        assert!(!retry_on_stale(1, true, false, true));
+        assert!(!retry_on_stale(0, false, false, true));
+        assert!(!retry_on_stale(0, true, true, true));
+        assert!(!retry_on_stale(0, true, false, false));
+    }
+}
@@ -10,24 +10,83 @@
 //! * Correct - Encourage (or force) correct HTTP 1.1 usage.
 //!
 //! The library has both a client and a server implementation.
+//!
+//! # Header order
+//!
+//! Headers are written as they are set, never reordered or buffered. A
+//! request or response head is always, in order:
+//!
+//! 1. The request or status line.
+//! 2. `Host` for HTTP/1.1 requests, and `Date` for responses with a clock.
+//! 3. Headers in the order of `header()` calls, and helpers such as
+//!    `upgrade()` that set headers.
+//! 4. Headers of the body, `Content-Type` for JSON, `Content-Encoding`,
+//!    then `Content-Length` or `Transfer-Encoding`.
+//!
+//! Names are written as given, or in canonical case such as `Content-Length`
+//! with `canonical_header_names()` on a client request or
+//! `set_canonical_header_names()` on a server request. The headers written by
+//! hoot are always in canonical case.
+//!
+//! # Panics
+//!
+//! hoot is written not to panic on input, from the network or the caller. A
+//! state machine that finds itself in a state its type state rules out, which
+//! is a bug in hoot, fails with `HootError::Internal` instead. The library is
+//! built with clippy denying `panic!`, `unreachable!`, `unwrap()` and
+//! `expect()` outside of tests.
+//!
+//! Misuse is an error too, such as `flushed()` with more than the pending
+//! output, which is `HootError::BufferOverrun`.
+//!
+//! With the `no-panic` feature, release builds fail to link if the functions
+//! that move buffer positions can panic: `flushed()`, `InputBuffer::filled()`
+//! and `consume()`, `RecvInput::consume()` and `push_input()` of the
+//! compression transforms. Parsing and writing are not checked, since their
+//! slice indexing is not yet proven free of bounds check panics. Run the
+//! check with `cargo test --release --features no-panic,full`.
 
 // For tests we use std.
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+// Some tests predate the clippy cleanups.
+#![cfg_attr(test, allow(clippy::write_literal))]
+// Errors instead of panics, see "Panics" above.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::unreachable,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::manual_assert,
+        clippy::debug_assert_with_mut_call
+    )
+)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
 #[macro_use]
-extern crate log;
+mod no_panic;
+
+#[macro_use]
+pub mod trace;
 
 mod chunk;
 use chunk::Dechunker;
 
 mod out;
+pub use out::OutBufs;
 
-mod util;
+pub mod util;
 use util::LengthChecker;
 
 pub mod types;
 
 mod parser;
+use parser::{HeadScan, ParseMode};
+
+mod scan;
 
 mod error;
 pub use error::HootError;
@@ -38,14 +97,92 @@ pub mod client;
 pub mod server;
 
 mod header;
-pub use header::Header;
+use header::is_tchar;
+pub use header::{Header, HeaderMap, Unfolded};
 
 mod body;
-pub use body::BodyPart;
-use body::RecvBodyMode;
+pub use body::{
+    BodyHasher, BodyPart, BodySink, BodySource, BodyTransform, ContentEncoding,
+    ContentLengthPolicy, ExcessBodyPolicy, Progress, RecvBodyMode, RequestBodyPolicy, Trailers,
+    TransferCodings, MAX_TRANSFER_CODINGS,
+};
+
+mod limits;
+pub use limits::{Limit, Limits};
+
+mod strict;
+pub use strict::Violation;
+
+mod deadline;
+use deadline::DeadlineState;
+pub use deadline::{Deadline, DeadlinePhase};
+
+mod connection;
+pub use connection::ConnectionOptions;
+
+mod h2c;
+pub use h2c::H2cHandoff;
+
+mod date;
+pub use date::Clock;
+#[cfg(feature = "std")]
+pub use date::SystemClock;
 
 mod url;
-pub use url::{Url, UrlError};
+pub use url::{DnsName, HostKind, HostValidator, Url, UrlError};
+
+mod target;
+pub use target::RequestTarget;
+
+mod link;
+pub use link::{Link, Links};
+
+#[cfg(feature = "compression")]
+pub mod compression;
+
+#[cfg(feature = "keep-alive")]
+pub mod keep_alive;
+
+#[cfg(feature = "retry")]
+pub mod retry;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "crypto")]
+pub mod crypto;
+
+#[cfg(feature = "multipart")]
+pub mod multipart;
+
+#[cfg(feature = "proxy")]
+pub mod proxy;
+
+#[cfg(feature = "alloc")]
+pub mod owned;
+
+#[cfg(feature = "heapless")]
+pub mod bounded;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+#[cfg(feature = "smoltcp")]
+pub mod integrations;
+
+// Test tooling, which panics on mismatches like assert_eq!().
+#[cfg(feature = "transcript")]
+#[allow(clippy::panic, clippy::unwrap_used)]
+pub mod transcript;
+
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+
+#[cfg(feature = "serde")]
+pub mod json;
+
+#[cfg(feature = "suspend")]
+pub mod suspend;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum HttpVersion {
@@ -53,12 +190,12 @@ pub enum HttpVersion {
     Http11,
 }
 
+/// From the minor version of HTTP/1.x. Minors above 1 are treated as 1.1.
 impl From<u8> for HttpVersion {
     fn from(value: u8) -> Self {
         match value {
             0 => Self::Http10,
-            1 => Self::Http11,
-            _ => panic!("Unknown HTTP version"),
+            _ => Self::Http11,
         }
     }
 }
@@ -74,12 +211,104 @@ pub enum Method {
     TRACE,
     CONNECT,
     PATCH,
+    /// Safe and idempotent like `GET`, but with a body, see
+    /// draft-ietf-httpbis-safe-method-w-body.
+    QUERY,
+    /// WebDAV, RFC 4918.
+    PROPFIND,
+    PROPPATCH,
+    MKCOL,
+    COPY,
+    MOVE,
+    LOCK,
+    UNLOCK,
+    /// A method outside the standard set, like `SEARCH`.
+    Extension(ExtMethod),
 }
 
 impl Method {
+    /// Whether requests with the method have a body.
+    ///
+    /// This is true for `POST`, `PUT`, `PATCH`, `QUERY` and the WebDAV methods that
+    /// define a body (`PROPFIND`, `PROPPATCH`, `MKCOL` and `LOCK`), though it
+    /// may be empty. It is also true for extension methods, which can have a
+    /// body as declared by the headers. See
+    /// `server::Request::accept_extension_methods()`.
+    ///
+    /// Servers can decide differently with
+    /// `server::Request::set_has_request_body()`.
     pub fn has_request_body(&self) -> bool {
         use Method::*;
-        matches!(self, POST | PUT | PATCH)
+        matches!(
+            self,
+            POST | PUT | PATCH | QUERY | PROPFIND | PROPPATCH | MKCOL | LOCK | Extension(_)
+        )
+    }
+
+    /// Length of the method name, as sent in the request line.
+    pub(crate) const fn name_len(&self) -> usize {
+        use Method::*;
+        match self {
+            OPTIONS => "OPTIONS".len(),
+            GET => "GET".len(),
+            POST => "POST".len(),
+            PUT => "PUT".len(),
+            DELETE => "DELETE".len(),
+            HEAD => "HEAD".len(),
+            TRACE => "TRACE".len(),
+            CONNECT => "CONNECT".len(),
+            PATCH => "PATCH".len(),
+            QUERY => "QUERY".len(),
+            PROPFIND => "PROPFIND".len(),
+            PROPPATCH => "PROPPATCH".len(),
+            MKCOL => "MKCOL".len(),
+            COPY => "COPY".len(),
+            MOVE => "MOVE".len(),
+            LOCK => "LOCK".len(),
+            UNLOCK => "UNLOCK".len(),
+            Extension(m) => m.len as usize,
+        }
+    }
+}
+
+/// Max length of an [`ExtMethod`] name.
+pub const MAX_EXT_METHOD_LEN: usize = 24;
+
+/// Name of an extension method, stored inline to keep [`Method`] `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ExtMethod {
+    name: [u8; MAX_EXT_METHOD_LEN],
+    len: u8,
+}
+
+impl ExtMethod {
+    /// Extension method with `name`, which is case sensitive.
+    ///
+    /// Fails with `HootError::UnknownMethod` if the name is empty, not a token,
+    /// or longer than [`MAX_EXT_METHOD_LEN`].
+    pub fn new(name: &str) -> Result<Self> {
+        let bytes = name.as_bytes();
+
+        if bytes.is_empty()
+            || bytes.len() > MAX_EXT_METHOD_LEN
+            || !bytes.iter().all(|b| is_tchar(*b))
+        {
+            return Err(HootError::UnknownMethod);
+        }
+
+        let mut ext = ExtMethod {
+            name: [0; MAX_EXT_METHOD_LEN],
+            len: bytes.len() as u8,
+        };
+        ext.name[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(ext)
+    }
+
+    /// The method name.
+    pub fn as_str(&self) -> &str {
+        // Tokens are ASCII.
+        str::from_utf8(&self.name[..self.len as usize]).unwrap_or_default()
     }
 }
 
@@ -97,12 +326,20 @@ impl TryFrom<&str> for Method {
             "TRACE" => Ok(Method::TRACE),
             "CONNECT" => Ok(Method::CONNECT),
             "PATCH" => Ok(Method::PATCH),
+            "QUERY" => Ok(Method::QUERY),
+            "PROPFIND" => Ok(Method::PROPFIND),
+            "PROPPATCH" => Ok(Method::PROPPATCH),
+            "MKCOL" => Ok(Method::MKCOL),
+            "COPY" => Ok(Method::COPY),
+            "MOVE" => Ok(Method::MOVE),
+            "LOCK" => Ok(Method::LOCK),
+            "UNLOCK" => Ok(Method::UNLOCK),
             _ => Err(HootError::UnknownMethod),
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub(crate) struct CallState {
     pub version: Option<HttpVersion>,
     pub method: Option<Method>,
@@ -111,9 +348,61 @@ pub(crate) struct CallState {
     pub recv_checker: Option<LengthChecker>,
     pub dechunker: Option<Dechunker>,
     pub did_read_to_end: bool,
+    /// Input after the end of the body, in the last input read.
+    pub recv_unused: usize,
+    pub content_encoding: Option<ContentEncoding>,
+    pub transfer_codings: TransferCodings,
+    pub max_recv_body: Option<u64>,
+    pub reject_unknown_recv_length: bool,
+    pub recv_too_large: bool,
+    /// Body bytes read, to enforce `max_recv_body` on bodies without length.
+    pub recv_body_len: u64,
+    pub percent_encode_target: bool,
+    pub canonical_header_names: bool,
+    /// Alignment of the start of the request body in the buffer.
+    pub body_align: Option<usize>,
+    pub limits: Limits,
+    pub parse_mode: ParseMode,
+    pub head_scan: HeadScan,
+    pub content_length_policy: ContentLengthPolicy,
+    pub excess_body_policy: ExcessBodyPolicy,
+    pub request_body_policy: RequestBodyPolicy,
+    pub extension_methods: Option<bool>,
+    pub has_request_body: Option<fn(Method) -> bool>,
+    pub accept_http09: bool,
+    pub sent_singular: u32,
+    pub deadline: DeadlineState,
+    pub expect_continue: bool,
+    pub date: Option<u64>,
+    pub sent_status: Option<u16>,
+    /// Identifies the exchange in metrics events.
+    #[cfg(feature = "metrics")]
+    pub metrics_id: u32,
+    /// Output flushed so far.
+    #[cfg(feature = "metrics")]
+    pub sent_len: u64,
+    /// Length of the sent head, once it is complete.
+    #[cfg(feature = "metrics")]
+    pub sent_head: u64,
 }
 
 use core::fmt;
+use core::str;
+
+impl fmt::Debug for CallState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallState")
+            .field("version", &self.version)
+            .field("method", &self.method)
+            .field("send_checker", &self.send_checker)
+            .field("recv_body_mode", &self.recv_body_mode)
+            .field("recv_checker", &self.recv_checker)
+            .field("dechunker", &self.dechunker)
+            .field("did_read_to_end", &self.did_read_to_end)
+            .field("content_encoding", &self.content_encoding)
+            .finish_non_exhaustive()
+    }
+}
 
 impl fmt::Debug for HttpVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -136,6 +425,57 @@ impl fmt::Debug for Method {
             Self::TRACE => write!(f, "TRACE"),
             Self::CONNECT => write!(f, "CONNECT"),
             Self::PATCH => write!(f, "PATCH"),
+            Self::QUERY => write!(f, "QUERY"),
+            Self::PROPFIND => write!(f, "PROPFIND"),
+            Self::PROPPATCH => write!(f, "PROPPATCH"),
+            Self::MKCOL => write!(f, "MKCOL"),
+            Self::COPY => write!(f, "COPY"),
+            Self::MOVE => write!(f, "MOVE"),
+            Self::LOCK => write!(f, "LOCK"),
+            Self::UNLOCK => write!(f, "UNLOCK"),
+            Self::Extension(m) => write!(f, "{}", m.as_str()),
+        }
+    }
+}
+
+impl fmt::Debug for ExtMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ExtMethod").field(&self.as_str()).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for HttpVersion {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Http10 => defmt::write!(f, "HTTP/1.0"),
+            Self::Http11 => defmt::write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Method {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::OPTIONS => defmt::write!(f, "OPTIONS"),
+            Self::GET => defmt::write!(f, "GET"),
+            Self::POST => defmt::write!(f, "POST"),
+            Self::PUT => defmt::write!(f, "PUT"),
+            Self::DELETE => defmt::write!(f, "DELETE"),
+            Self::HEAD => defmt::write!(f, "HEAD"),
+            Self::TRACE => defmt::write!(f, "TRACE"),
+            Self::CONNECT => defmt::write!(f, "CONNECT"),
+            Self::PATCH => defmt::write!(f, "PATCH"),
+            Self::QUERY => defmt::write!(f, "QUERY"),
+            Self::PROPFIND => defmt::write!(f, "PROPFIND"),
+            Self::PROPPATCH => defmt::write!(f, "PROPPATCH"),
+            Self::MKCOL => defmt::write!(f, "MKCOL"),
+            Self::COPY => defmt::write!(f, "COPY"),
+            Self::MOVE => defmt::write!(f, "MOVE"),
+            Self::LOCK => defmt::write!(f, "LOCK"),
+            Self::UNLOCK => defmt::write!(f, "UNLOCK"),
+            Self::Extension(m) => defmt::write!(f, "{=str}", m.as_str()),
         }
     }
 }
@@ -0,0 +1,57 @@
+use core::fmt;
+
+/// Limits on incoming data, to defend against hostile peers.
+///
+/// Set with `client::Response::set_limits()` or `server::Request::set_limits()`.
+/// Exceeding a limit results in `HootError::LimitExceeded`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Max number of headers. Also limited by the size of the header buffer,
+    /// and never more than 100.
+    pub max_headers: usize,
+    /// Max size of all headers in bytes, excluding the status/request line.
+    pub max_header_bytes: usize,
+    /// Max length of the status/request line.
+    pub max_line: usize,
+    /// Max length of the request target, such as `/path?query`. Only for
+    /// requests, where exceeding it suggests `414 URI Too Long`.
+    pub max_target: usize,
+    /// Max length of a chunk size line in a chunked body.
+    pub max_chunk_line: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_headers: 100,
+            max_header_bytes: 64 * 1024,
+            max_line: 8 * 1024,
+            max_target: 8 * 1024,
+            max_chunk_line: 1024,
+        }
+    }
+}
+
+/// Which of the [`Limits`] was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Limit {
+    HeaderCount,
+    HeaderBytes,
+    Line,
+    Target,
+    ChunkLine,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Limit::HeaderCount => "header count",
+            Limit::HeaderBytes => "header bytes",
+            Limit::Line => "line length",
+            Limit::Target => "request target length",
+            Limit::ChunkLine => "chunk line length",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,336 @@
+use core::fmt;
+
+use crate::{HootError, Result};
+
+/// A link of a `Link` header, RFC 8288.
+///
+/// Parsed with [`Links`], or made with `Link::new()` for sending with
+/// `server::Response::link()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Link<'a> {
+    target: &'a str,
+    rel: Option<&'a str>,
+    media_type: Option<&'a str>,
+    // Raw params of a parsed link, such as `rel="next"; title="Next"`.
+    params: &'a str,
+}
+
+impl<'a> Link<'a> {
+    /// Link to `target`, such as `/items?page=2`.
+    pub fn new(target: &'a str) -> Self {
+        Link {
+            target,
+            rel: None,
+            media_type: None,
+            params: "",
+        }
+    }
+
+    /// Set the relation type, such as `next`. Several are separated by space.
+    pub fn with_rel(mut self, rel: &'a str) -> Self {
+        self.rel = Some(rel);
+        self
+    }
+
+    /// Set the media type hint, such as `application/json`.
+    pub fn with_media_type(mut self, media_type: &'a str) -> Self {
+        self.media_type = Some(media_type);
+        self
+    }
+
+    /// The target URI reference, without the `<>`.
+    pub fn target(&self) -> &'a str {
+        self.target
+    }
+
+    /// The `rel` param, such as `next` or `prev last`.
+    pub fn rel(&self) -> Option<&'a str> {
+        self.rel
+    }
+
+    /// Whether `rel` has the relation type `rel`, ignoring ASCII case.
+    pub fn has_rel(&self, rel: &str) -> bool {
+        self.rel
+            .map(|r| r.split(' ').any(|v| v.eq_ignore_ascii_case(rel)))
+            .unwrap_or(false)
+    }
+
+    /// The `type` param, such as `application/json`.
+    pub fn media_type(&self) -> Option<&'a str> {
+        self.media_type
+    }
+
+    /// Any param of a parsed link, such as `title`. `Some("")` if the param
+    /// has no value.
+    ///
+    /// The first occurrence is used, RFC 8288 §3. Quotes are removed, but
+    /// escapes are left as is.
+    pub fn param(&self, name: &str) -> Option<&'a str> {
+        Params(self.params)
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| unquote(v))
+    }
+
+    /// Check the link can be sent without injecting anything into the header.
+    ///
+    /// The error offset is in the value as written by `Display`.
+    pub(crate) fn check(&self) -> Result<()> {
+        let bad_target = |b: u8| b <= b' ' || b == 0x7f || b == b'>';
+        if let Some(i) = self.target.bytes().position(bad_target) {
+            return Err(HootError::HeaderValue { offset: 1 + i });
+        }
+
+        let bad_param = |b: u8| (b < b' ' && b != b'\t') || b == 0x7f || b == b'"' || b == b'\\';
+        let mut offset = self.target.len() + 2;
+        for (prefix, value) in [("; rel=\"", self.rel), ("; type=\"", self.media_type)] {
+            let value = match value {
+                Some(v) => v,
+                None => continue,
+            };
+            offset += prefix.len();
+            if let Some(i) = value.bytes().position(bad_param) {
+                return Err(HootError::HeaderValue { offset: offset + i });
+            }
+            offset += value.len() + 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Links of a `Link` header value, such as
+/// `</items?page=3>; rel="next", </items?page=1>; rel="prev"`.
+///
+/// Iteration stops at the first malformed link. A response can have several
+/// `Link` headers, each is parsed on its own.
+///
+/// ```
+/// use hoot::Links;
+///
+/// let value = r#"</items?page=3>; rel="next", </items?page=1>; rel=prev; title="First""#;
+/// let mut links = Links::new(value);
+///
+/// let next = links.next().unwrap();
+/// assert_eq!(next.target(), "/items?page=3");
+/// assert!(next.has_rel("next"));
+///
+/// let prev = links.next().unwrap();
+/// assert_eq!(prev.rel(), Some("prev"));
+/// assert_eq!(prev.param("title"), Some("First"));
+///
+/// assert!(links.next().is_none());
+/// ```
+#[derive(Clone)]
+pub struct Links<'a>(&'a str);
+
+impl<'a> Links<'a> {
+    pub fn new(value: &'a str) -> Self {
+        Links(value)
+    }
+}
+
+impl<'a> Iterator for Links<'a> {
+    type Item = Link<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let s = self.0.trim_start_matches([',', ' ', '\t']);
+
+        let rest = match s.strip_prefix('<') {
+            Some(v) => v,
+            None => {
+                // Malformed or the end, either way we're done.
+                self.0 = "";
+                return None;
+            }
+        };
+
+        let (target, rest) = match rest.split_once('>') {
+            Some(v) => v,
+            None => {
+                self.0 = "";
+                return None;
+            }
+        };
+
+        // The params end at a comma, which can't be inside a quoted value.
+        let end = find_unquoted(rest, b',').unwrap_or(rest.len());
+        let params = rest[..end].trim().trim_start_matches(';').trim_start();
+        self.0 = &rest[end..];
+
+        let param = |name: &str| {
+            Params(params)
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| unquote(v))
+        };
+
+        Some(Link {
+            target,
+            rel: param("rel"),
+            media_type: param("type"),
+            params,
+        })
+    }
+}
+
+/// Params separated by `;`, as `(name, raw value)`.
+struct Params<'a>(&'a str);
+
+impl<'a> Iterator for Params<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.0.is_empty() {
+                return None;
+            }
+
+            let end = find_unquoted(self.0, b';').unwrap_or(self.0.len());
+            let param = &self.0[..end];
+            self.0 = self.0.get(end + 1..).unwrap_or("");
+
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            let name = name.trim();
+            if !name.is_empty() {
+                return Some((name, value.trim()));
+            }
+        }
+    }
+}
+
+/// Position of `needle` outside of quoted strings.
+fn find_unquoted(s: &str, needle: u8) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, b) in s.bytes().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if quoted && b == b'\\' {
+            escaped = true;
+        } else if b == b'"' {
+            quoted = !quoted;
+        } else if !quoted && b == needle {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+fn unquote(v: &str) -> &str {
+    v.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(v)
+}
+
+impl<'a> fmt::Display for Link<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.target)?;
+        if let Some(rel) = self.rel {
+            write!(f, "; rel=\"{}\"", rel)?;
+        }
+        if let Some(media_type) = self.media_type {
+            write!(f, "; type=\"{}\"", media_type)?;
+        }
+        for (name, value) in Params(self.params) {
+            if name.eq_ignore_ascii_case("rel") || name.eq_ignore_ascii_case("type") {
+                continue;
+            }
+            if value.is_empty() {
+                write!(f, "; {}", name)?;
+            } else {
+                write!(f, "; {}={}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for Link<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl<'a> fmt::Debug for Links<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_links() {
+        let mut links = Links::new(
+            r#" <https://x.test/a,b>;rel="next last" ; title="a, \"b\"; c",<x>;
+            rel=prev;anchor ,, <y>"#,
+        );
+
+        let l = links.next().unwrap();
+        assert_eq!(l.target(), "https://x.test/a,b");
+        assert_eq!(l.rel(), Some("next last"));
+        assert!(l.has_rel("LAST"));
+        assert!(!l.has_rel("prev"));
+        assert_eq!(l.param("title"), Some(r#"a, \"b\"; c"#));
+
+        let l = links.next().unwrap();
+        assert_eq!(l.target(), "x");
+        assert_eq!(l.rel(), Some("prev"));
+        assert_eq!(l.param("anchor"), Some(""));
+        assert_eq!(l.media_type(), None);
+
+        let l = links.next().unwrap();
+        assert_eq!(l.target(), "y");
+        assert_eq!(l.rel(), None);
+
+        assert!(links.next().is_none());
+    }
+
+    #[test]
+    fn parse_malformed() {
+        let mut links = Links::new("<a>; rel=next, b; rel=prev, <c>");
+        assert_eq!(links.next().unwrap().target(), "a");
+        assert!(links.next().is_none());
+        assert!(links.next().is_none());
+
+        assert!(Links::new("<unterminated; rel=next").next().is_none());
+    }
+
+    #[test]
+    fn first_rel_wins() {
+        let l = Links::new("<a>; rel=first; rel=second").next().unwrap();
+        assert_eq!(l.rel(), Some("first"));
+    }
+
+    #[test]
+    fn display_parsed() {
+        let l = Links::new(r#"<a>;type=text/html; title="x;y" ;rel=next; anchor"#)
+            .next()
+            .unwrap();
+        assert_eq!(
+            l.to_string(),
+            r#"<a>; rel="next"; type="text/html"; title="x;y"; anchor"#
+        );
+    }
+
+    #[test]
+    fn check_link() {
+        let l = Link::new("/a?b")
+            .with_rel("next")
+            .with_media_type("text/html");
+        assert!(l.check().is_ok());
+
+        let err = Link::new("/a>").check().unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 3 });
+
+        let err = Link::new("/a").with_rel("x\"").check().unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 12 });
+
+        let l = Link::new("/a").with_rel("x").with_media_type("\r");
+        let err = l.check().unwrap_err();
+        assert_eq!(err, HootError::HeaderValue { offset: 21 });
+    }
+}
@@ -0,0 +1,230 @@
+//! Transfer accounting of the state machines.
+//!
+//! With the `metrics` feature, the state machines report what they send and
+//! receive to a [`MetricsHook`]. Each event carries the id set with
+//! `set_metrics_id()` on the request, which lets a gateway attribute events to
+//! connections without wrapping every call.
+//!
+//! Durations are only reported when the time is supplied with `set_now()`, as
+//! for [`Deadline`](crate::Deadline).
+//!
+//! ```
+//! use core::sync::atomic::{AtomicU64, Ordering};
+//! use hoot::metrics::{set_metrics_hook, Event, MetricsHook};
+//! use hoot::server::Request;
+//! use hoot::util::HeaderBuf;
+//!
+//! static RECEIVED: AtomicU64 = AtomicU64::new(0);
+//!
+//! struct Count;
+//!
+//! impl MetricsHook for Count {
+//!     fn record(&self, id: u32, event: Event) {
+//!         if let Event::HeadRecv { bytes } = event {
+//!             RECEIVED.fetch_add(bytes as u64, Ordering::Relaxed);
+//!         }
+//!     }
+//! }
+//!
+//! static HOOK: &dyn MetricsHook = &Count;
+//! set_metrics_hook(&HOOK);
+//!
+//! let mut buf = HeaderBuf::<16>::new();
+//! let mut request = Request::new();
+//! request.set_metrics_id(7);
+//! request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf)?;
+//!
+//! assert!(RECEIVED.load(Ordering::Relaxed) >= 27);
+//! # Ok::<(), hoot::HootError>(())
+//! ```
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::types::State;
+use crate::CallState;
+
+/// Something sent or received by a state machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Event {
+    /// The head was written, with the length including the final empty line.
+    HeadSent { bytes: u64 },
+    /// The message was written, with the length of the body and its framing.
+    ///
+    /// This is `0` for messages without body.
+    BodySent { bytes: u64 },
+    /// A head was parsed from `bytes` of input.
+    HeadRecv { bytes: usize },
+    /// A head was not complete in the input, and must be parsed again with more.
+    ParseRetry,
+    /// Input used reading the body, including chunk framing.
+    BodyRecv { bytes: usize },
+    /// A chunk was started reading.
+    ChunkRecv,
+    /// Milliseconds from the first attempt to parse a head to it being complete.
+    HeadDuration { ms: u64 },
+    /// Milliseconds from the start of the body to its end.
+    BodyDuration { ms: u64 },
+}
+
+/// Receiver of metrics events, see [`set_metrics_hook()`].
+pub trait MetricsHook: Sync {
+    /// Handle one event of the exchange `id`.
+    fn record(&self, id: u32, event: Event);
+}
+
+static HOOK: AtomicPtr<&'static dyn MetricsHook> = AtomicPtr::new(ptr::null_mut());
+
+/// Send all metrics events to `hook`, replacing any previous hook.
+///
+/// The double reference keeps the pointer thin, like `set_trace_hook()`.
+pub fn set_metrics_hook(hook: &'static &'static dyn MetricsHook) {
+    let hook = hook as *const &'static dyn MetricsHook as *mut &'static dyn MetricsHook;
+    HOOK.store(hook, Ordering::Release);
+}
+
+pub(crate) fn record(id: u32, event: Event) {
+    let hook = HOOK.load(Ordering::Acquire);
+    // SAFETY: The pointer is either null or from a &'static in set_metrics_hook().
+    if let Some(hook) = unsafe { hook.as_ref() } {
+        hook.record(id, event);
+    }
+}
+
+/// A head was parsed from `bytes` of input.
+pub(crate) fn head_recv(state: &CallState, bytes: usize) {
+    record(state.metrics_id, Event::HeadRecv { bytes });
+
+    let d = &state.deadline;
+    if let (Some(start), Some(now)) = (d.head_start, d.now) {
+        let ms = now.saturating_sub(start);
+        record(state.metrics_id, Event::HeadDuration { ms });
+    }
+}
+
+/// Body input was used, `finished` if that ended the body.
+pub(crate) fn body_recv(state: &CallState, bytes: usize, finished: bool) {
+    if bytes > 0 {
+        record(state.metrics_id, Event::BodyRecv { bytes });
+    }
+
+    let d = &state.deadline;
+    if let (true, Some(start), Some(now)) = (finished, d.body_start, d.now) {
+        let ms = now.saturating_sub(start);
+        record(state.metrics_id, Event::BodyDuration { ms });
+    }
+}
+
+/// Account sent output on a transition from `S` to `S2`.
+///
+/// `pending` is the output not yet flushed, of which `body` is body written
+/// together with the end of the head.
+pub(crate) fn transition<S: State, S2: State>(state: &mut CallState, pending: usize, body: usize) {
+    let produced = state.sent_len + pending as u64;
+
+    if is_head::<S>() && !is_head::<S2>() {
+        let bytes = produced - body as u64;
+        state.sent_head = bytes;
+        record(state.metrics_id, Event::HeadSent { bytes });
+    }
+
+    if is_sending::<S>() && !is_sending::<S2>() {
+        let bytes = produced - state.sent_head;
+        record(state.metrics_id, Event::BodySent { bytes });
+    }
+}
+
+fn is_head<S: State>() -> bool {
+    matches!(
+        S::state_name(),
+        "INIT" | "SEND_LINE" | "SEND_STATUS" | "SEND_HINTS" | "SEND_HEADERS"
+    )
+}
+
+fn is_sending<S: State>() -> bool {
+    is_head::<S>() || matches!(S::state_name(), "SEND_BODY" | "SEND_TRAILER")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::HeaderBuf;
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::server::{Request, Response, ResponseVariant};
+    use crate::Result;
+
+    static EVENTS: Mutex<Vec<(u32, Event)>> = Mutex::new(Vec::new());
+
+    struct Collect;
+
+    impl MetricsHook for Collect {
+        fn record(&self, id: u32, event: Event) {
+            // Other tests run on other ids.
+            if id == 42 {
+                EVENTS.lock().unwrap().push((id, event));
+            }
+        }
+    }
+
+    #[test]
+    fn server_exchange() -> Result<()> {
+        static HOOK: &dyn MetricsHook = &Collect;
+        set_metrics_hook(&HOOK);
+
+        const INPUT: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2\r\nhi\r\n3\r\nyou\r\n0\r\n\r\n";
+        let head = INPUT.len() - 20;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        r.set_metrics_id(42);
+        r.set_now(10);
+        r.try_read_request(&INPUT[..10], &mut headers)?;
+        r.set_now(15);
+        r.try_read_request(INPUT, &mut headers)?;
+
+        let mut r = r.proceed();
+        r.set_now(20);
+        let used = r.read_body(&INPUT[head..head + 7], &mut buf)?.input_used();
+        r.set_now(30);
+        r.read_body(&INPUT[head + used..], &mut buf)?;
+
+        let token = match r.into_response()? {
+            ResponseVariant::Post(v) => v,
+            _ => unreachable!(),
+        };
+
+        let mut buf = [0; 1024];
+        let mut res = Response::resume(token, &mut buf)
+            .send_status(200, "OK")?
+            .with_body(5)?;
+        res.flushed(19)?;
+        res.write_bytes(b"hello")?;
+        res.finish()?;
+
+        let events = EVENTS.lock().unwrap();
+        let events: Vec<_> = events.iter().map(|(_, e)| *e).collect();
+        assert_eq!(
+            events,
+            [
+                Event::ParseRetry,
+                Event::HeadRecv { bytes: head },
+                Event::HeadDuration { ms: 5 },
+                Event::ChunkRecv,
+                Event::BodyRecv { bytes: 7 },
+                Event::ChunkRecv,
+                Event::BodyRecv { bytes: 11 },
+                Event::BodyDuration { ms: 15 },
+                Event::HeadSent { bytes: 38 },
+                Event::BodySent { bytes: 5 },
+            ]
+        );
+
+        Ok(())
+    }
+}
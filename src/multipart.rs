@@ -0,0 +1,304 @@
+//! `multipart/x-mixed-replace` streaming.
+//!
+//! This is the format of MJPEG-style streams, where a server keeps a response
+//! open and sends a sequence of parts, each replacing the previous one.
+//!
+//! ```text
+//! --myboundary\r\n
+//! Content-Type: image/jpeg\r\n
+//! Content-Length: 4\r\n
+//! \r\n
+//! ....\r\n
+//! --myboundary\r\n
+//! ...
+//! ```
+//!
+//! Servers write parts using [`MixedReplace`] together with
+//! [`Response::write_part`](crate::server::Response::write_part), one chunk
+//! per part, so each part can be flushed to the transport as it is produced.
+//! Clients split the (dechunked) response body into parts with [`PartSplitter`].
+
+use core::str;
+
+use crate::out::Out;
+use crate::parser::parse_header_section;
+use crate::util::compare_lowercase_ascii;
+use crate::{Header, HootError, Result};
+
+/// Writer of `multipart/x-mixed-replace` parts.
+#[derive(Debug, Clone, Copy)]
+pub struct MixedReplace<'a> {
+    boundary: &'a str,
+}
+
+impl<'a> MixedReplace<'a> {
+    /// Create a writer using the boundary.
+    pub fn new(boundary: &'a str) -> Self {
+        MixedReplace { boundary }
+    }
+
+    /// Write the `Content-Type` header value into `buf`.
+    pub fn content_type<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str> {
+        let mut out = Out::wrap(buf);
+        let mut w = out.writer();
+        write!(w, "multipart/x-mixed-replace; boundary={}", self.boundary)?;
+        w.commit();
+
+        // Can't fail since we only wrote a &str.
+        Ok(str::from_utf8(out.into_inner())?)
+    }
+
+    /// Write an entire part (delimiter, headers and data) into `dst`.
+    ///
+    /// A `Content-Length` header is added for the data. Returns the length.
+    pub fn write_part(
+        &self,
+        headers: &[(&str, &str)],
+        data: &[u8],
+        dst: &mut [u8],
+    ) -> Result<usize> {
+        let mut out = Out::wrap(dst);
+        let mut w = out.writer();
+
+        write!(w, "--{}\r\n", self.boundary)?;
+        for (name, value) in headers {
+            write!(w, "{}: {}\r\n", name, value)?;
+        }
+        write!(w, "Content-Length: {}\r\n\r\n", data.len())?;
+        w.write_bytes(data)?;
+        write!(w, "\r\n")?;
+        w.commit();
+
+        Ok(out.into_inner().len())
+    }
+
+    /// Write the closing delimiter into `dst`. Returns the length.
+    pub fn write_close(&self, dst: &mut [u8]) -> Result<usize> {
+        let mut out = Out::wrap(dst);
+        let mut w = out.writer();
+        write!(w, "--{}--\r\n", self.boundary)?;
+        w.commit();
+
+        Ok(out.into_inner().len())
+    }
+}
+
+/// Extract the boundary from a `multipart/*` `Content-Type` header value.
+pub fn boundary_from_content_type(value: &str) -> Option<&str> {
+    value.split(';').skip(1).find_map(|param| {
+        let (name, v) = param.split_once('=')?;
+        if compare_lowercase_ascii(name.trim(), "boundary") {
+            Some(v.trim().trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// A part split out of a multipart body.
+pub struct Part<'a, 'b> {
+    headers: &'b [Header<'a>],
+    data: &'a [u8],
+    input_used: usize,
+}
+
+impl<'a, 'b> Part<'a, 'b> {
+    /// Headers of the part.
+    pub fn headers(&self) -> &'b [Header<'a>] {
+        self.headers
+    }
+
+    /// Data of the part.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Amount of input consumed by this part.
+    pub fn input_used(&self) -> usize {
+        self.input_used
+    }
+}
+
+/// Splits a multipart body into parts.
+#[derive(Debug)]
+pub struct PartSplitter<'a> {
+    boundary: &'a str,
+    ended: bool,
+}
+
+impl<'a> PartSplitter<'a> {
+    /// Create a splitter for the boundary.
+    ///
+    /// See [`boundary_from_content_type`].
+    pub fn new(boundary: &'a str) -> Self {
+        PartSplitter {
+            boundary,
+            ended: false,
+        }
+    }
+
+    /// Whether the closing delimiter has been seen.
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Try to split the next part from `input`.
+    ///
+    /// Returns `None` if the input does not yet hold an entire part, in which case
+    /// the call should be retried with more input. The `buf` is used to parse the
+    /// part headers into. Parts without `Content-Length` end at the next delimiter.
+    pub fn next_part<'i, 'b>(
+        &mut self,
+        input: &'i [u8],
+        buf: &'b mut [Header<'i>],
+    ) -> Result<Option<Part<'i, 'b>>> {
+        if self.ended {
+            return Ok(None);
+        }
+
+        let delim = self.boundary.as_bytes();
+
+        // Anything before the delimiter is preamble or the CRLF ending the
+        // previous part.
+        let start = match find(input, b"--", delim) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let after = start + 2 + delim.len();
+
+        if input.len() < after + 2 {
+            return Ok(None);
+        }
+
+        if &input[after..(after + 2)] == b"--" {
+            trace!("Multipart closing delimiter");
+            self.ended = true;
+            return Ok(None);
+        }
+
+        let line_end = match input[after..].windows(2).position(|w| w == b"\r\n") {
+            Some(v) => after + v + 2,
+            None => return Ok(None),
+        };
+
+        let (header_len, headers) = match parse_header_section(&input[line_end..], buf)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let data_start = line_end + header_len;
+        let rest = &input[data_start..];
+
+        let content_length = headers
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "content-length"));
+
+        let (data, input_used) = if let Some(h) = content_length {
+            let len: usize = h
+                .try_value()
+                .ok_or(HootError::ConvertBytesToStr)?
+                .trim()
+                .parse()?;
+
+            if rest.len() < len {
+                return Ok(None);
+            }
+
+            (&rest[..len], data_start + len)
+        } else {
+            match find(rest, b"\r\n--", delim) {
+                Some(end) => (&rest[..end], data_start + end),
+                None => return Ok(None),
+            }
+        };
+
+        trace!("Multipart part len: {}", data.len());
+
+        Ok(Some(Part {
+            headers,
+            data,
+            input_used,
+        }))
+    }
+}
+
+/// Find the position of `prefix` immediately followed by `needle`.
+fn find(haystack: &[u8], prefix: &[u8], needle: &[u8]) -> Option<usize> {
+    let len = prefix.len() + needle.len();
+    haystack
+        .windows(len)
+        .position(|w| &w[..prefix.len()] == prefix && &w[prefix.len()..] == needle)
+}
+
+impl<'a, 'b> core::fmt::Debug for Part<'a, 'b> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Part")
+            .field("headers", &self.headers)
+            .field("data_len", &self.data.len())
+            .field("input_used", &self.input_used)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn boundary() {
+        let v = "multipart/x-mixed-replace; Boundary=\"frame\"";
+        assert_eq!(boundary_from_content_type(v), Some("frame"));
+        assert_eq!(boundary_from_content_type("image/jpeg"), None);
+    }
+
+    #[test]
+    fn write_and_split() -> Result<()> {
+        let mp = MixedReplace::new("frame");
+        let mut input = [0; 256];
+
+        let mut n = mp.write_part(&[("Content-Type", "image/jpeg")], b"abc", &mut input)?;
+        n += mp.write_part(&[], b"defg", &mut input[n..])?;
+        n += mp.write_close(&mut input[n..])?;
+        let input = &input[..n];
+
+        let mut splitter = PartSplitter::new("frame");
+        let mut buf = HeaderBuf::<16>::new();
+
+        // Incomplete input.
+        assert!(splitter.next_part(&input[..30], &mut buf)?.is_none());
+
+        let part = splitter.next_part(input, &mut buf)?.unwrap();
+        assert_eq!(part.headers()[0].value_str()?, "image/jpeg");
+        assert_eq!(part.data(), b"abc");
+        let used = part.input_used();
+
+        let part = splitter.next_part(&input[used..], &mut buf)?.unwrap();
+        assert_eq!(part.data(), b"defg");
+        let used = used + part.input_used();
+
+        assert!(splitter.next_part(&input[used..], &mut buf)?.is_none());
+        assert!(splitter.is_ended());
+
+        Ok(())
+    }
+
+    #[test]
+    fn split_without_length() -> Result<()> {
+        const INPUT: &[u8] = b"preamble\r\n--b\r\nX: 1\r\n\r\nhello\r\n--b\r\n\r\n";
+
+        let mut splitter = PartSplitter::new("b");
+        let mut buf = HeaderBuf::<16>::new();
+
+        let part = splitter.next_part(INPUT, &mut buf)?.unwrap();
+        assert_eq!(part.headers()[0].name(), "X");
+        assert_eq!(part.data(), b"hello");
+
+        // Second part has no end yet.
+        let used = part.input_used();
+        assert!(splitter.next_part(&INPUT[used..], &mut buf)?.is_none());
+        assert!(!splitter.is_ended());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! Link time check that functions can't panic.
+//!
+//! With the `no-panic` feature in a release build, [`no_panic!`] wraps a
+//! function body with a guard that is dropped only when the body unwinds.
+//! The drop calls a symbol that doesn't exist, so if the optimizer can't
+//! remove every panic path, linking fails with `hoot_function_may_panic`
+//! in the error. Debug builds keep the panic paths, so there it does nothing.
+//!
+//! The check needs `panic = "unwind"`, which is what tests build with:
+//!
+//! ```text
+//! cargo test --release --features no-panic,full
+//! ```
+
+#[cfg(all(feature = "no-panic", not(debug_assertions)))]
+pub(crate) struct Guard;
+
+#[cfg(all(feature = "no-panic", not(debug_assertions)))]
+impl Drop for Guard {
+    fn drop(&mut self) {
+        extern "C" {
+            #[link_name = "hoot_function_may_panic"]
+            fn may_panic() -> !;
+        }
+
+        // SAFETY: Never called, since the function doesn't link when it is.
+        unsafe { may_panic() }
+    }
+}
+
+/// Fail linking if `$body` can panic, see module docs.
+#[cfg(all(feature = "no-panic", not(debug_assertions)))]
+macro_rules! no_panic {
+    ($body:block) => {{
+        let guard = $crate::no_panic::Guard;
+        #[allow(clippy::redundant_closure_call)]
+        let ret = (|| $body)();
+        core::mem::forget(guard);
+        ret
+    }};
+}
+
+#[cfg(not(all(feature = "no-panic", not(debug_assertions))))]
+macro_rules! no_panic {
+    ($body:block) => {
+        $body
+    };
+}
@@ -1,4 +1,5 @@
 use core::fmt;
+use core::mem;
 
 use crate::{HootError, Result};
 
@@ -12,13 +13,15 @@ impl<'a> Out<'a> {
         Out { buf, pos: 0 }
     }
 
-    fn output<'b>(&mut self, bytes: &'b [u8], from: usize) -> Result<usize> {
+    fn output(&mut self, bytes: &[u8], from: usize) -> Result<usize> {
         let start = self.pos + from;
         let remaining = self.buf.len() - start;
         let len = bytes.len();
 
         if len > remaining {
-            return Err(HootError::OutputOverflow);
+            return Err(HootError::OutputOverflow {
+                needed: len - remaining,
+            });
         }
 
         let into = &mut self.buf[start..(start + len)];
@@ -34,9 +37,53 @@ impl<'a> Out<'a> {
         }
     }
 
+    /// Free space left in the buffer.
+    pub fn available(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
     pub fn into_inner(self) -> &'a [u8] {
         &self.buf[..self.pos]
     }
+
+    /// Output written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.pos]
+    }
+
+    /// Insert `n` spaces at `at` in the output, moving what follows.
+    pub fn insert_spaces(&mut self, at: usize, n: usize) -> Result<()> {
+        if n > self.available() {
+            return Err(HootError::OutputOverflow {
+                needed: n - self.available(),
+            });
+        }
+
+        self.buf.copy_within(at..self.pos, at + n);
+        self.buf[at..(at + n)].fill(b' ');
+        self.pos += n;
+
+        Ok(())
+    }
+
+    /// Drop the first `amount` bytes of output, moving the rest to the start.
+    pub fn consume(&mut self, amount: usize) -> Result<()> {
+        no_panic!({
+            let pending = match self.buf.get_mut(..self.pos) {
+                Some(v) => v,
+                None => return Err(HootError::Internal),
+            };
+            if amount > pending.len() {
+                return Err(HootError::BufferOverrun {
+                    amount,
+                    available: pending.len(),
+                });
+            }
+            pending.copy_within(amount.., 0);
+            self.pos -= amount;
+            Ok(())
+        })
+    }
 }
 
 pub(crate) struct Writer<'b, 'a> {
@@ -49,7 +96,7 @@ impl<'b, 'a> Writer<'b, 'a> {
     pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize> {
         let ret = self.out.output(bytes, self.inc.unwrap_or(0));
 
-        if let Err(_) = &ret {
+        if ret.is_err() {
             // Do not increase position if we encountered an error while writing.
             self.inc = None;
         } else {
@@ -64,6 +111,30 @@ impl<'b, 'a> Writer<'b, 'a> {
         ret
     }
 
+    /// Write formatted output, which makes `write!()` return a `Result`.
+    ///
+    /// On overflow, the error has how much more the entire write needed.
+    pub fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<()> {
+        let available = self.available();
+
+        if fmt::Write::write_fmt(self, args).is_ok() {
+            return Ok(());
+        }
+
+        let mut count = Count(0);
+        // Count never fails, unless a Display impl does.
+        let _ = fmt::Write::write_fmt(&mut count, args);
+
+        Err(HootError::OutputOverflow {
+            needed: count.0.saturating_sub(available).max(1),
+        })
+    }
+
+    /// Free space left after this writer.
+    pub fn available(&self) -> usize {
+        self.out.available() - self.inc.unwrap_or(0)
+    }
+
     // Splits the buffer in Out at the position: Out.pos + Writer.inc.
     // The first half is chopped off to where Writer started.
     //
@@ -89,6 +160,94 @@ impl<'b, 'a> Writer<'b, 'a> {
         (&used[s..e], rest)
     }
 
+    /// Let `f` write directly into the free space of the buffer.
+    ///
+    /// `f` returns how much it wrote.
+    pub fn write_with(&mut self, f: impl FnOnce(&mut [u8]) -> Result<usize>) -> Result<usize> {
+        let (_, rest) = self.split_and_borrow();
+        let n = f(rest)?;
+
+        if let Some(inc) = &mut self.inc {
+            *inc += n;
+        }
+
+        Ok(n)
+    }
+
+    /// Let `f` write after this writer, given the output before it.
+    pub fn write_after(
+        &mut self,
+        f: impl FnOnce(&[u8], &mut Writer<'_, '_>) -> Result<()>,
+    ) -> Result<()> {
+        let start = self.out.pos;
+        let inc = self.inc.unwrap_or(0);
+        let (before, rest) = self.out.buf.split_at_mut(start + inc);
+
+        let mut out = Out::wrap(rest);
+        let mut w = out.writer();
+        f(&before[..start], &mut w)?;
+        w.commit();
+
+        self.inc = Some(inc + out.pos);
+
+        Ok(())
+    }
+
+    /// Write a chunk (for transfer-encoding: chunked) where the chunk data is
+    /// produced by `f` directly into the buffer.
+    ///
+    /// `f` returns how much it wrote, 0 means no chunk is written. If the buffer
+    /// does not have enough space for the chunk framing, `f` is not called.
+    pub fn write_chunk_with(
+        &mut self,
+        f: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> Result<usize> {
+        // Max hex digits for a usize + \r\n
+        const OVERHEAD: usize = mem::size_of::<usize>() * 2 + 2;
+
+        self.write_with(|buf| {
+            // Need room for overhead + at least one byte + trailing \r\n
+            if buf.len() < OVERHEAD + 3 {
+                return Ok(0);
+            }
+
+            let end = buf.len() - 2;
+            let n = f(&mut buf[OVERHEAD..end])?;
+
+            if n == 0 {
+                return Ok(0);
+            }
+
+            let mut head = [0; OVERHEAD];
+            let mut hout = Out::wrap(&mut head);
+            let mut hw = hout.writer();
+            write!(hw, "{:0x?}\r\n", n)?;
+            hw.commit();
+            let head = hout.into_inner();
+
+            // Move chunk data to directly follow the chunk size line.
+            buf.copy_within(OVERHEAD..(OVERHEAD + n), head.len());
+            buf[..head.len()].copy_from_slice(head);
+            let end = head.len() + n;
+            buf[end..(end + 2)].copy_from_slice(b"\r\n");
+
+            Ok(end + 2)
+        })
+    }
+
+    /// Write body produced by `f`, as a chunk if `chunked`, otherwise as is.
+    pub fn write_body_with(
+        &mut self,
+        chunked: bool,
+        f: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> Result<usize> {
+        if chunked {
+            self.write_chunk_with(f)
+        } else {
+            self.write_with(f)
+        }
+    }
+
     pub fn commit(mut self) {
         if let Some(inc) = self.inc.take() {
             // Commit increase to borrowed Out.
@@ -104,10 +263,115 @@ impl<'b, 'a> fmt::Write for Writer<'b, 'a> {
     }
 }
 
+/// Counts the length of formatted output.
+pub(crate) struct Count(pub usize);
+
+impl fmt::Write for Count {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Scatter-gather output over several buffers.
+///
+/// Requests and responses write into one buffer at a time, but the buffer can
+/// be swapped at every `flush()`/`resume()`. `OutBufs` hands out buffers in
+/// order and keeps track of how much each holds, so the request line, headers
+/// and body can end up in separate buffers (such as DMA descriptors) that are
+/// written to the transport in one vectored write.
+///
+/// ```
+/// use hoot::client::Request;
+/// use hoot::OutBufs;
+///
+/// let mut line = [0; 64];
+/// let mut headers = [0; 64];
+/// let mut bufs = [&mut line[..], &mut headers[..]];
+/// let mut out = OutBufs::new(&mut bufs);
+///
+/// let output = Request::new(out.next_buf()?)
+///     .http_11()
+///     .get("myhost.test", "/path")?
+///     .flush();
+/// let len = output.len();
+/// let token = output.ready();
+/// out.filled(len);
+///
+/// let output = Request::resume(token, out.next_buf()?)
+///     .header("accept", "*/*")?
+///     .send()?
+///     .flush();
+/// let len = output.len();
+/// let _response = output.into_response();
+/// out.filled(len);
+///
+/// let mut slices = out.as_slices();
+/// assert_eq!(slices.next(), Some(&b"GET /path HTTP/1.1\r\nHost: myhost.test\r\n"[..]));
+/// assert_eq!(slices.next(), Some(&b"accept: */*\r\n\r\n"[..]));
+/// assert_eq!(slices.next(), None);
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub struct OutBufs<'a, 'b> {
+    bufs: &'a mut [&'b mut [u8]],
+    used: usize,
+}
+
+impl<'a, 'b> OutBufs<'a, 'b> {
+    pub fn new(bufs: &'a mut [&'b mut [u8]]) -> Self {
+        OutBufs { bufs, used: 0 }
+    }
+
+    /// Borrow the next unused buffer.
+    ///
+    /// Errors with `HootError::OutputOverflow` when all buffers are used.
+    pub fn next_buf(&mut self) -> Result<&mut [u8]> {
+        match self.bufs.get_mut(self.used) {
+            Some(b) => Ok(b),
+            None => Err(HootError::OutputOverflow { needed: 1 }),
+        }
+    }
+
+    /// Record how much output the buffer from the last `next_buf()` holds.
+    ///
+    /// Until this is called, the same buffer is returned by `next_buf()`.
+    pub fn filled(&mut self, len: usize) {
+        if let Some(b) = self.bufs.get_mut(self.used) {
+            // Shrink the buffer to the output.
+            let taken = mem::take(b);
+            *b = &mut taken[..len];
+            self.used += 1;
+        }
+    }
+
+    /// The buffers holding output, in order.
+    pub fn as_slices(&self) -> impl Iterator<Item = &[u8]> {
+        self.bufs[..self.used].iter().map(|b| &b[..])
+    }
+
+    /// Total amount of output.
+    pub fn len(&self) -> usize {
+        self.as_slices().map(|b| b.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, 'b> fmt::Debug for OutBufs<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutBufs")
+            .field("bufs", &self.bufs.len())
+            .field("used", &self.used)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use core::fmt::Write;
 
     #[test]
     pub fn write_and_commit() {
@@ -124,4 +388,37 @@ mod test {
 
         assert_eq!(std::str::from_utf8(&buf[0..14]).unwrap(), "testing 123456");
     }
+
+    #[test]
+    pub fn write_overflow_needed() {
+        let mut buf = [0; 10];
+        let mut out = Out::wrap(&mut buf);
+
+        let mut w = out.writer();
+        write!(w, "abc").unwrap();
+        let err = write!(w, "testing {}", 123456).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 7 });
+
+        let mut w = out.writer();
+        let err = w.write_bytes(b"0123456789ab").unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 2 });
+    }
+
+    #[test]
+    pub fn write_chunk_with() {
+        let mut buf = [0; 1024];
+        let mut out = Out::wrap(&mut buf);
+
+        let mut w = out.writer();
+        let n = w
+            .write_chunk_with(|b| {
+                b[..11].copy_from_slice(b"hello world");
+                Ok(11)
+            })
+            .unwrap();
+        assert_eq!(n, 16);
+        w.commit();
+
+        assert_eq!(out.into_inner(), b"b\r\nhello world\r\n");
+    }
 }
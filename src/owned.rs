@@ -0,0 +1,166 @@
+//! Owned variants of borrowed types, for when an allocator is available.
+//!
+//! The state machines stay allocation free. These are conveniences for
+//! keeping what was read beyond the lifetime of the input buffers.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str;
+
+use crate::client::Status;
+use crate::{BodySink, Header, HttpVersion, Result};
+
+/// Owned [`Header`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedHeader {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl OwnedHeader {
+    /// Copy all of `headers`.
+    pub fn from_headers(headers: &[Header<'_>]) -> Vec<OwnedHeader> {
+        headers.iter().map(OwnedHeader::from).collect()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value as `&str`, `None` if it is not UTF-8.
+    pub fn try_value(&self) -> Option<&str> {
+        str::from_utf8(&self.value).ok()
+    }
+
+    /// The value as bytes, as received.
+    pub fn value_raw(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Borrow as a [`Header`], for the APIs taking one.
+    pub fn as_header(&self) -> Header<'_> {
+        Header::new(&self.name, &self.value)
+    }
+}
+
+impl From<&Header<'_>> for OwnedHeader {
+    fn from(h: &Header<'_>) -> Self {
+        OwnedHeader {
+            name: h.name().to_string(),
+            value: h.value_raw().to_vec(),
+        }
+    }
+}
+
+/// Owned client response [`Status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedStatus {
+    pub version: HttpVersion,
+    pub code: u16,
+    /// The reason phrase, as sent by the server.
+    pub reason: String,
+}
+
+impl From<&Status<'_>> for OwnedStatus {
+    fn from(s: &Status<'_>) -> Self {
+        OwnedStatus {
+            version: s.version(),
+            code: s.code(),
+            reason: s.text().to_string(),
+        }
+    }
+}
+
+/// How much a `Vec` grows at a time when reading body into it.
+const GROW: usize = 1024;
+
+/// Sink growing a `Vec`. The space not filled is dropped with the sink.
+pub(crate) struct VecSink<'v> {
+    vec: &'v mut Vec<u8>,
+    len: usize,
+}
+
+impl<'v> VecSink<'v> {
+    pub(crate) fn new(vec: &'v mut Vec<u8>) -> Self {
+        let len = vec.len();
+        VecSink { vec, len }
+    }
+}
+
+impl BodySink for VecSink<'_> {
+    fn unfilled(&mut self) -> &mut [u8] {
+        self.vec.resize(self.len + GROW, 0);
+        &mut self.vec[self.len..]
+    }
+
+    fn filled(&mut self, amount: usize) -> Result<()> {
+        self.len += amount;
+        Ok(())
+    }
+}
+
+impl Drop for VecSink<'_> {
+    fn drop(&mut self) {
+        self.vec.truncate(self.len);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::client::Response;
+    use crate::server::Request;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn owned_response() -> Result<()> {
+        const INPUT: &[u8] = b"HTTP/1.1 201 Made It\r\nX-Foo: bar\r\n\
+            Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+
+        let mut buf = HeaderBuf::<16>::new();
+        let mut response = Response::new_test();
+        let attempt = response.try_read_response(INPUT, &mut buf)?;
+
+        let status = OwnedStatus::from(attempt.status().unwrap());
+        assert_eq!(status.code, 201);
+        assert_eq!(status.reason, "Made It");
+
+        let headers = OwnedHeader::from_headers(attempt.headers().unwrap());
+        assert_eq!(headers[0].name(), "X-Foo");
+        assert_eq!(headers[0].try_value(), Some("bar"));
+        assert_eq!(headers[0].as_header(), attempt.headers().unwrap()[0]);
+
+        let used = attempt.input_used();
+        let mut response = response.proceed();
+        let mut body = b"prefix ".to_vec();
+        let n = response.read_body_to_vec(&INPUT[used..], &mut body)?;
+
+        // The empty trailer section is left for read_trailers().
+        assert_eq!(n, INPUT.len() - used - 2);
+        assert_eq!(body, b"prefix hello world");
+        assert!(response.is_finished());
+
+        Ok(())
+    }
+
+    #[test]
+    fn owned_request_body() -> Result<()> {
+        let body = [b'a'; 3000];
+        let mut input = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 3000\r\n\r\n".to_vec();
+        let head = input.len();
+        input.extend_from_slice(&body);
+
+        let mut buf = HeaderBuf::<16>::new();
+        let mut request = Request::new();
+        request.try_read_request(&input, &mut buf)?;
+        let mut request = request.proceed();
+
+        let mut read = vec![];
+        request.read_body_to_vec(&input[head..head + 10], &mut read)?;
+        assert_eq!(read.len(), 10);
+        request.read_body_to_vec(&input[head + 10..], &mut read)?;
+        assert_eq!(read, body);
+
+        Ok(())
+    }
+}
@@ -1,26 +1,778 @@
-use crate::header::transmute_headers;
-use crate::util::cast_buf_for_headers;
-use crate::{Header, Result};
+use core::{fmt, str};
 
-pub(crate) fn parse_headers<'a, 'b>(src: &'a [u8], dst: &'b mut [u8]) -> Result<&'b [Header<'a>]> {
-    let hbuf = cast_buf_for_headers(dst);
+use crate::header::is_tchar;
+use crate::scan::{find_byte, target_len, value_len};
+use crate::util::{limit_header_buf, MAX_HEADERS};
+use crate::{Header, HootError, HttpVersion, Limit, Limits, Result, Violation};
 
-    // This parses into hbuf even if it fails due to an unfinished header line.
-    httparse::parse_headers(src, hbuf)?;
+/// How forgiving parsing of incoming requests and responses is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParseMode {
+    Normal,
+    /// Reject ambiguous framing, see [`crate::Violation`].
+    Strict,
+    /// Tolerate quirks of broken peers.
+    Lenient,
+}
+
+impl Default for ParseMode {
+    fn default() -> Self {
+        ParseMode::Normal
+    }
+}
+
+impl ParseMode {
+    pub fn is_strict(&self) -> bool {
+        *self == ParseMode::Strict
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        *self == ParseMode::Lenient
+    }
+}
+
+/// The request line of a parsed request head.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RequestLine<'a> {
+    pub method: &'a str,
+    pub target: &'a str,
+    pub version: HttpVersion,
+}
+
+/// The status line of a parsed response head.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StatusLine<'a> {
+    pub version: HttpVersion,
+    pub code: u16,
+    pub reason: &'a str,
+}
+
+/// A complete head.
+#[derive(Debug)]
+pub(crate) struct Head<'a, 'b, L> {
+    pub line: L,
+    pub headers: &'b [Header<'a>],
+    /// Length of the input used, including the empty line ending the head.
+    pub len: usize,
+}
+
+/// Validates a head line by line, continuing across reads of partial input.
+///
+/// Each attempt to read a head gets the input from the start of the head, with
+/// more bytes appended. Rather than parsing the entire prefix again, the scan
+/// continues from the start of the first incomplete line. Errors are found as
+/// soon as a line is complete, and the offsets of the request/status line and
+/// each header are kept, so that the complete head is turned into headers with
+/// [`HeadScan::request`] or [`HeadScan::response`] without parsing it again.
+///
+/// Leading empty lines before the request line are skipped, and lines may end
+/// with LF only, except in strict mode. A missing reason phrase is accepted.
+#[derive(Clone, Copy)]
+pub(crate) struct HeadScan {
+    /// Start of the first line not yet validated.
+    pub pos: usize,
+    /// Whether the request/status line is validated.
+    pub in_headers: bool,
+    /// Number of headers validated.
+    pub count: usize,
+    line: LineSpans,
+    headers: [HeaderSpans; MAX_HEADERS],
+}
+
+/// Offsets of the parts of a request/status line in the input.
+#[derive(Clone, Copy)]
+struct LineSpans {
+    version: HttpVersion,
+    /// Status code of a response.
+    code: u16,
+    /// Method of a request.
+    method: Span,
+    /// Target of a request, or reason phrase of a response.
+    text: Span,
+}
+
+/// Offsets of a header name and its value, without trailing whitespace.
+#[derive(Clone, Copy, Default)]
+struct HeaderSpans {
+    name: Span,
+    value: Span,
+}
+
+/// A range of the input. Offsets are `u32` to keep [`HeadScan`] small.
+#[derive(Clone, Copy, Default)]
+struct Span {
+    start: u32,
+    end: u32,
+}
+
+impl Span {
+    fn new(start: usize, end: usize) -> Step<Span> {
+        match (u32::try_from(start), u32::try_from(end)) {
+            (Ok(start), Ok(end)) => Ok(Span { start, end }),
+            // A head of 4GB is over any sensible limit.
+            _ => Err(HootError::LimitExceeded {
+                which: Limit::HeaderBytes,
+            }
+            .into()),
+        }
+    }
+
+    fn get(self, input: &[u8]) -> &[u8] {
+        input
+            .get(self.start as usize..self.end as usize)
+            .unwrap_or_default()
+    }
+}
+
+impl Default for HeadScan {
+    fn default() -> Self {
+        HeadScan {
+            pos: 0,
+            in_headers: false,
+            count: 0,
+            line: LineSpans {
+                version: HttpVersion::Http11,
+                code: 0,
+                method: Span::default(),
+                text: Span::default(),
+            },
+            headers: [HeaderSpans::default(); MAX_HEADERS],
+        }
+    }
+}
+
+impl fmt::Debug for HeadScan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeadScan")
+            .field("pos", &self.pos)
+            .field("in_headers", &self.in_headers)
+            .field("count", &self.count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HeadScan {
+    /// Validate the complete lines of `input` not seen before.
+    ///
+    /// `input` must be the input of the previous call with more bytes
+    /// appended, or a new head. The scan starts over if `input` is shorter
+    /// than what was scanned, or does not have a line ending where the scan
+    /// stopped, but other changes to the scanned bytes are not noticed.
+    ///
+    /// Returns whether the head is complete. Fails with
+    /// `HootError::TooManyHeaders` for more than `max_headers` headers.
+    pub fn scan(
+        &mut self,
+        input: &[u8],
+        mode: ParseMode,
+        response: bool,
+        max_headers: usize,
+    ) -> Result<bool> {
+        let extends = input
+            .get(..self.pos)
+            .map_or(false, |prev| prev.is_empty() || prev.ends_with(b"\n"));
+        if !extends {
+            // Not the input of the previous attempt.
+            *self = HeadScan::default();
+        }
+
+        let mut c = Cursor::new(input, mode);
+        c.pos = self.pos;
+
+        Ok(finish(self.resume(&mut c, mode, response, max_headers))?.is_some())
+    }
+
+    /// How much of the input has been validated.
+    pub fn scanned(&self) -> usize {
+        self.pos
+    }
+
+    /// The request head, once `scan()` found it complete in `input`.
+    ///
+    /// Fails with `HootError::TooManyHeaders` if `dst` is too short.
+    pub fn request<'a, 'b>(
+        &self,
+        input: &'a [u8],
+        dst: &'b mut [Header<'a>],
+    ) -> Result<Head<'a, 'b, RequestLine<'a>>> {
+        let line = RequestLine {
+            // Tokens are ASCII, and the target was checked by the scan.
+            method: str::from_utf8(self.line.method.get(input)).unwrap_or_default(),
+            target: str::from_utf8(self.line.text.get(input)).unwrap_or_default(),
+            version: self.line.version,
+        };
+        self.head(input, dst, line)
+    }
+
+    /// The response head, once `scan()` found it complete in `input`.
+    ///
+    /// Like [`HeadScan::request`].
+    pub fn response<'a, 'b>(
+        &self,
+        input: &'a [u8],
+        dst: &'b mut [Header<'a>],
+    ) -> Result<Head<'a, 'b, StatusLine<'a>>> {
+        let line = StatusLine {
+            version: self.line.version,
+            code: self.line.code,
+            // The reason is informational, obs-text in it is dropped.
+            reason: str::from_utf8(self.line.text.get(input)).unwrap_or(""),
+        };
+        self.head(input, dst, line)
+    }
+
+    fn head<'a, 'b, L>(
+        &self,
+        input: &'a [u8],
+        dst: &'b mut [Header<'a>],
+        line: L,
+    ) -> Result<Head<'a, 'b, L>> {
+        let spans = self.headers.get(..self.count).unwrap_or_default();
+        let dst = dst
+            .get_mut(..spans.len())
+            .ok_or(HootError::TooManyHeaders)?;
+
+        for (h, s) in dst.iter_mut().zip(spans) {
+            // Tokens are ASCII.
+            let name = str::from_utf8(s.name.get(input)).unwrap_or_default();
+            *h = Header::new(name, s.value.get(input));
+        }
+
+        Ok(Head {
+            line,
+            headers: dst,
+            len: self.pos,
+        })
+    }
+
+    fn resume(
+        &mut self,
+        c: &mut Cursor<'_>,
+        mode: ParseMode,
+        response: bool,
+        max_headers: usize,
+    ) -> Step<()> {
+        if !self.in_headers {
+            self.line = if response {
+                status_line(c, mode)?
+            } else {
+                request_line(c, mode)?
+            };
+            self.in_headers = true;
+            self.pos = c.pos;
+        }
+
+        loop {
+            let start = c.pos;
+            let line = header_line(c, mode, response, self.count > 0)?;
+            self.pos = c.pos;
+
+            match line {
+                Line::End => return Ok(()),
+                Line::Header { .. } if self.count == max_headers => {
+                    return Err(HootError::TooManyHeaders.into());
+                }
+                Line::Header {
+                    name,
+                    value_start,
+                    value,
+                } => {
+                    let spans = self
+                        .headers
+                        .get_mut(self.count)
+                        .ok_or(HootError::TooManyHeaders)?;
+                    *spans = HeaderSpans {
+                        name: Span::new(start, start + name.len())?,
+                        value: Span::new(value_start, value_start + trim_end(value).len())?,
+                    };
+                    self.count += 1;
+                }
+                Line::Fold { end } => {
+                    // The continuation is part of the previous value.
+                    let last = self.count.checked_sub(1);
+                    if let Some(spans) = last.and_then(|i| self.headers.get_mut(i)) {
+                        let start = spans.value.start as usize;
+                        let value = c.input.get(start..end).unwrap_or_default();
+                        spans.value = Span::new(start, start + trim_end(value).len())?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parse a header section, such as trailers, into `dst`.
+///
+/// Returns `None` if the input ends before the section does.
+pub(crate) fn parse_header_section<'a, 'b>(
+    input: &'a [u8],
+    dst: &'b mut [Header<'a>],
+) -> Result<Option<(usize, &'b [Header<'a>])>> {
+    let dst = limit_header_buf(dst);
+
+    let mut c = Cursor::new(input, ParseMode::Normal);
+    let mut count = 0;
+
+    if finish(headers(&mut c, dst, ParseMode::Normal, false, &mut count))?.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some((c.pos, &dst[..count])))
+}
+
+/// Parse the headers of all complete lines in `src`.
+///
+/// Unlike [`parse_header_section`], the end of the section is not required.
+pub(crate) fn parse_headers<'a, 'b>(
+    src: &'a [u8],
+    dst: &'b mut [Header<'a>],
+) -> Result<&'b [Header<'a>]> {
+    let hbuf = limit_header_buf(dst);
+
+    let mut c = Cursor::new(src, ParseMode::Normal);
+    let mut count = 0;
+
+    // Headers before an incomplete line are still written to hbuf.
+    finish(headers(&mut c, hbuf, ParseMode::Normal, false, &mut count))?;
+
+    Ok(&hbuf[..count])
+}
 
-    // cast_buf_for_headers fills the array with EMPTY_HEADER where name=""
-    // httparse::parse_headers still writes all found headers.
-    // This behavior is asserted in a test below.
-    let count = hbuf.iter().take_while(|h| !h.name.is_empty()).count();
+/// Check a header section without keeping the headers, calling `f` with the
+/// name of each.
+///
+/// Returns the length of the section, or `None` if the input ends before the
+/// section does.
+pub(crate) fn scan_header_section(
+    input: &[u8],
+    mut f: impl FnMut(&str) -> Result<()>,
+) -> Result<Option<usize>> {
+    let mut c = Cursor::new(input, ParseMode::Normal);
 
-    // Transmute to our own header type.
-    let output = transmute_headers(&hbuf[..count]);
+    loop {
+        match finish(header_line(&mut c, ParseMode::Normal, false, false))? {
+            Some(Line::End) => return Ok(Some(c.pos)),
+            Some(Line::Header { name, .. }) => f(name)?,
+            Some(Line::Fold { .. }) => {}
+            None => return Ok(None),
+        }
+    }
+}
 
-    Ok(output)
+/// Why parsing stopped before the end of the head.
+enum Stop {
+    /// More input is needed.
+    Partial,
+    Error(HootError),
+}
+
+impl From<HootError> for Stop {
+    fn from(value: HootError) -> Self {
+        Stop::Error(value)
+    }
+}
+
+type Step<T> = core::result::Result<T, Stop>;
+
+fn finish<T>(step: Step<T>) -> Result<Option<T>> {
+    match step {
+        Ok(v) => Ok(Some(v)),
+        Err(Stop::Partial) => Ok(None),
+        Err(Stop::Error(e)) => Err(e),
+    }
+}
+
+/// Kinds of syntax errors, which are placed at an offset in the input.
+#[derive(Clone, Copy)]
+enum Syntax {
+    NewLine,
+    Token,
+    Version,
+    Status,
+    HeaderName,
+    HeaderValue,
+    Target,
+}
+
+impl Syntax {
+    fn at(self, offset: usize) -> HootError {
+        match self {
+            Syntax::NewLine => HootError::NewLine { offset },
+            Syntax::Token => HootError::Token { offset },
+            Syntax::Version => HootError::Version { offset },
+            Syntax::Status => HootError::Status { offset },
+            Syntax::HeaderName => HootError::HeaderName { offset },
+            Syntax::HeaderValue => HootError::HeaderValue { offset },
+            Syntax::Target => HootError::InvalidTarget { offset },
+        }
+    }
+}
+
+struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+    /// Lines must end with CRLF, in strict mode.
+    crlf: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a [u8], mode: ParseMode) -> Self {
+        Cursor {
+            input,
+            pos: 0,
+            crlf: mode.is_strict(),
+        }
+    }
+
+    fn peek(&self) -> Step<u8> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> Step<u8> {
+        self.input.get(self.pos + n).copied().ok_or(Stop::Partial)
+    }
+
+    /// Consume bytes while `f` holds. The bytes must be followed by another.
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> Step<&'a [u8]> {
+        let start = self.pos;
+        while f(self.peek()?) {
+            self.pos += 1;
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    /// Error of `kind` at the current position.
+    fn error(&self, kind: Syntax) -> Stop {
+        Stop::Error(kind.at(self.pos))
+    }
+
+    /// Consume a single byte that must be `b`.
+    fn expect(&mut self, b: u8, err: Syntax) -> Step<()> {
+        if self.peek()? != b {
+            return Err(self.error(err));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Consume a line ending, CRLF or LF. Only CRLF in strict mode.
+    fn newline(&mut self, err: Syntax) -> Step<()> {
+        match self.peek()? {
+            b'\r' => self.pos += 1,
+            b'\n' if self.crlf => {
+                let which = Violation::BareLf;
+                return Err(Stop::Error(HootError::StrictViolation { which }));
+            }
+            _ => {}
+        }
+        self.expect(b'\n', err)
+    }
+
+    /// Consume bytes while `len` is less than the remaining input.
+    fn take_len(&mut self, len: impl Fn(&[u8]) -> usize) -> Step<&'a [u8]> {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let n = len(rest);
+        if n == rest.len() {
+            return Err(Stop::Partial);
+        }
+        self.pos += n;
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn at_newline(&self) -> Step<bool> {
+        Ok(matches!(self.peek()?, b'\r' | b'\n'))
+    }
+
+    /// Consume one space, or several if `many`.
+    fn spaces(&mut self, many: bool, err: Syntax) -> Step<()> {
+        self.expect(b' ', err)?;
+        if many {
+            self.take_while(|b| b == b' ')?;
+        }
+        Ok(())
+    }
+}
+
+fn request_line(c: &mut Cursor<'_>, mode: ParseMode) -> Step<LineSpans> {
+    // Leading empty lines are allowed before the request line.
+    while c.at_newline()? {
+        c.newline(Syntax::NewLine)?;
+    }
+
+    let method_start = c.pos;
+    let method = c.take_while(is_tchar)?;
+    if method.is_empty() {
+        return Err(c.error(Syntax::Token));
+    }
+    let method = Span::new(method_start, c.pos)?;
+    c.spaces(mode.is_lenient(), Syntax::Token)?;
+
+    let target_start = c.pos;
+    let target = c.take_len(target_len)?;
+    let target_end = c.pos;
+    match c.peek()? {
+        b' ' if !target.is_empty() => {}
+        b'\n' => return Err(c.error(Syntax::Version)),
+        b'\r' if c.peek_at(1)? == b'\n' => return Err(c.error(Syntax::Version)),
+        // Control bytes in the target.
+        _ => return Err(c.error(Syntax::Target)),
+    }
+    c.spaces(mode.is_lenient(), Syntax::Version)?;
+
+    let version = version(c)?;
+    c.newline(Syntax::Version)?;
+
+    // Decoded once the head is complete, but checked here.
+    str::from_utf8(target).map_err(|e| Syntax::Target.at(target_start + e.valid_up_to()))?;
+
+    Ok(LineSpans {
+        version,
+        code: 0,
+        method,
+        text: Span::new(target_start, target_end)?,
+    })
+}
+
+fn status_line(c: &mut Cursor<'_>, mode: ParseMode) -> Step<LineSpans> {
+    let version = version(c)?;
+    c.spaces(mode.is_lenient(), Syntax::Status)?;
+
+    let mut code = 0;
+    for _ in 0..3 {
+        let b = c.peek()?;
+        if !b.is_ascii_digit() {
+            return Err(c.error(Syntax::Status));
+        }
+        code = code * 10 + (b - b'0') as u16;
+        c.pos += 1;
+    }
+
+    if !c.at_newline()? {
+        c.spaces(mode.is_lenient(), Syntax::Status)?;
+    }
+    let reason_start = c.pos;
+    c.take_len(value_len)?;
+    let reason = Span::new(reason_start, c.pos)?;
+    c.newline(Syntax::Status)?;
+
+    Ok(LineSpans {
+        version,
+        code,
+        method: Span::default(),
+        text: reason,
+    })
+}
+
+fn version(c: &mut Cursor<'_>) -> Step<HttpVersion> {
+    for b in b"HTTP/" {
+        c.expect(*b, Syntax::Version)?;
+    }
+
+    match c.peek()? {
+        b'1' => {}
+        b @ b'0'..=b'9' => return Err(HootError::UnsupportedVersion { major: b - b'0' }.into()),
+        _ => return Err(c.error(Syntax::Version)),
+    }
+    c.pos += 1;
+    c.expect(b'.', Syntax::Version)?;
+
+    // Later 1.x minors are compatible with 1.1, RFC 9110 section 2.5.
+    let version = match c.peek()? {
+        b'0' => HttpVersion::Http10,
+        b'1'..=b'9' => HttpVersion::Http11,
+        _ => return Err(c.error(Syntax::Version)),
+    };
+    c.pos += 1;
+
+    Ok(version)
+}
+
+/// Parse header lines up to and including the empty line ending them.
+///
+/// `count` is the number of headers written to `dst`, also when stopping early.
+fn headers<'a>(
+    c: &mut Cursor<'a>,
+    dst: &mut [Header<'a>],
+    mode: ParseMode,
+    response: bool,
+    count: &mut usize,
+) -> Step<()> {
+    // The last header, for obs-fold continuations.
+    let mut last_name = "";
+    let mut value_start = 0;
+
+    loop {
+        match header_line(c, mode, response, *count > 0)? {
+            Line::End => return Ok(()),
+            Line::Header {
+                name,
+                value_start: start,
+                value,
+            } => {
+                if *count == dst.len() {
+                    return Err(HootError::TooManyHeaders.into());
+                }
+                last_name = name;
+                value_start = start;
+                dst[*count] = Header::new(name, trim_end(value));
+                *count += 1;
+            }
+            Line::Fold { end } => {
+                // The continuation is part of the previous value.
+                let value = trim_end(&c.input[value_start..end]);
+                dst[*count - 1] = Header::new(last_name, value);
+            }
+        }
+    }
+}
+
+/// A line of a header section.
+enum Line<'a> {
+    /// The empty line ending the section.
+    End,
+    Header {
+        name: &'a str,
+        value_start: usize,
+        value: &'a [u8],
+    },
+    /// obs-fold continuation of the previous value, which now ends at `end`.
+    Fold { end: usize },
+}
+
+/// Parse one header line. `has_prev` is whether a line can be continued.
+fn header_line<'a>(
+    c: &mut Cursor<'a>,
+    mode: ParseMode,
+    response: bool,
+    has_prev: bool,
+) -> Step<Line<'a>> {
+    let lenient = mode.is_lenient() && response;
+    let violated = |which| Stop::Error(HootError::StrictViolation { which });
+
+    if c.at_newline()? {
+        c.newline(Syntax::NewLine)?;
+        return Ok(Line::End);
+    }
+
+    if matches!(c.peek()?, b' ' | b'\t') {
+        if mode.is_strict() {
+            return Err(violated(Violation::ObsFold));
+        }
+        if !lenient || !has_prev {
+            return Err(c.error(Syntax::HeaderName));
+        }
+
+        c.take_len(value_len)?;
+        let end = c.pos;
+        c.newline(Syntax::HeaderValue)?;
+
+        return Ok(Line::Fold { end });
+    }
+
+    let name = c.take_while(is_tchar)?;
+    if name.is_empty() {
+        return Err(c.error(Syntax::HeaderName));
+    }
+
+    if matches!(c.peek()?, b' ' | b'\t') {
+        c.take_while(|b| b == b' ' || b == b'\t')?;
+        let colon = c.peek()? == b':';
+
+        if colon && mode.is_strict() {
+            return Err(violated(Violation::SpaceBeforeColon));
+        }
+        if !colon || !lenient {
+            return Err(c.error(Syntax::HeaderName));
+        }
+    }
+    c.expect(b':', Syntax::HeaderName)?;
+
+    c.take_while(|b| b == b' ' || b == b'\t')?;
+    let value_start = c.pos;
+    let value = c.take_len(value_len)?;
+    c.newline(Syntax::HeaderValue)?;
+
+    Ok(Line::Header {
+        // Tokens are ASCII.
+        name: str::from_utf8(name).unwrap_or_default(),
+        value_start,
+        value,
+    })
+}
+
+fn trim_end(v: &[u8]) -> &[u8] {
+    let end = v
+        .iter()
+        .rposition(|b| *b != b' ' && *b != b'\t')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    &v[..end]
+}
+
+/// Check the status/request line and header section against the limits.
+///
+/// `head_len` is the length of the entire head, if it is complete.
+pub(crate) fn check_head_limits(
+    input: &[u8],
+    head_len: Option<usize>,
+    limits: &Limits,
+) -> Result<()> {
+    let head = &input[..head_len.unwrap_or(input.len())];
+
+    let exceeded = |which| Err(HootError::LimitExceeded { which });
+
+    let line_end = match head.iter().position(|b| *b == b'\n') {
+        Some(v) => v,
+        None if head.len() > limits.max_line => return exceeded(Limit::Line),
+        None => return Ok(()),
+    };
+
+    if line_end > limits.max_line {
+        return exceeded(Limit::Line);
+    }
+
+    if head.len() - line_end - 1 > limits.max_header_bytes {
+        return exceeded(Limit::HeaderBytes);
+    }
+
+    Ok(())
+}
+
+/// Check the length of the request target, also before the request line is
+/// complete, so a long target is rejected early.
+pub(crate) fn check_target_limit(input: &[u8], limits: &Limits) -> Result<()> {
+    let line = input.split(|b| *b == b'\n').next().unwrap_or(&[]);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    // Method, target and version, separated by spaces.
+    let target = line.splitn(3, |b| *b == b' ').nth(1).unwrap_or(&[]);
+
+    if target.len() > limits.max_target {
+        return Err(HootError::LimitExceeded {
+            which: Limit::Target,
+        });
+    }
+
+    Ok(())
+}
+
+/// Limit the header buffer to the max header count.
+///
+/// Returns whether the count is limited by `limits` rather than the buffer size.
+pub(crate) fn limit_header_count<'a, 'b>(
+    headers: &'a mut [Header<'b>],
+    limits: &Limits,
+) -> (&'a mut [Header<'b>], bool) {
+    if headers.len() >= limits.max_headers {
+        (&mut headers[..limits.max_headers], true)
+    } else {
+        (headers, false)
+    }
 }
 
 pub(crate) fn find_crlf(b: &[u8]) -> Option<usize> {
-    let cr = b.iter().position(|c| *c == b'\r')?;
+    let cr = find_byte(b, b'\r')?;
     let maybe_lf = b.get(cr + 1)?;
     if *maybe_lf == b'\n' {
         Some(cr)
@@ -31,9 +783,8 @@ pub(crate) fn find_crlf(b: &[u8]) -> Option<usize> {
 
 #[cfg(test)]
 mod test {
-    use core::mem;
-
     use super::*;
+    use crate::util::HeaderBuf;
 
     #[test]
     fn test_find_crlf() {
@@ -43,37 +794,268 @@ mod test {
         assert_eq!(find_crlf(b" \r\n"), Some(1));
     }
 
+    fn request(input: &[u8], mode: ParseMode) -> Result<Option<(RequestLine<'_>, usize)>> {
+        let mut buf = HeaderBuf::<32>::new();
+        let mut scan = HeadScan::default();
+        if !scan.scan(input, mode, false, buf.len())? {
+            return Ok(None);
+        }
+        let head = scan.request(input, &mut buf)?;
+        Ok(Some((head.line, head.headers.len())))
+    }
+
+    fn response(input: &[u8], mode: ParseMode) -> Result<Option<(StatusLine<'_>, usize)>> {
+        let mut buf = HeaderBuf::<32>::new();
+        let mut scan = HeadScan::default();
+        if !scan.scan(input, mode, true, buf.len())? {
+            return Ok(None);
+        }
+        let head = scan.response(input, &mut buf)?;
+        Ok(Some((head.line, head.headers.len())))
+    }
+
+    #[test]
+    fn parse_request_line() {
+        let normal = ParseMode::Normal;
+
+        let (line, count) = request(b"\r\nGET /path HTTP/1.1\r\nX: 1\n\n", normal)
+            .unwrap()
+            .unwrap();
+        assert_eq!(line.method, "GET");
+        assert_eq!(line.target, "/path");
+        assert_eq!(line.version, HttpVersion::Http11);
+        assert_eq!(count, 1);
+
+        // Every prefix is partial.
+        const INPUT: &[u8] = b"GET / HTTP/1.0\r\nX: 1\r\n\r\n";
+        for i in 0..INPUT.len() {
+            assert!(request(&INPUT[..i], normal).unwrap().is_none());
+        }
+
+        let err = |input| request(input, normal).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err(b"GET /p\x01th HTTP/1.1\r\n"),
+            HootError::InvalidTarget { offset: 6 }
+        );
+        assert_eq!(
+            err(b"GET /p\rth HTTP/1.1\r\n"),
+            HootError::InvalidTarget { offset: 6 }
+        );
+        assert_eq!(err(b"GET /\r\n"), HootError::Version { offset: 5 });
+        assert_eq!(
+            err(b"PRI * HTTP/2.0\r\n"),
+            HootError::UnsupportedVersion { major: 2 }
+        );
+        assert_eq!(
+            err(b"GET / HTTP/1.x\r\n"),
+            HootError::Version { offset: 13 }
+        );
+        let (line, _) = request(b"GET / HTTP/1.2\r\n\r\n", normal).unwrap().unwrap();
+        assert_eq!(line.version, HttpVersion::Http11);
+        assert_eq!(err(b"G(T / HTTP/1.1\r\n"), HootError::Token { offset: 1 });
+        assert_eq!(
+            err(b"GET  / HTTP/1.1\r\n"),
+            HootError::InvalidTarget { offset: 4 }
+        );
+
+        assert!(request(b"GET  /  HTTP/1.1\r\n\r\n", ParseMode::Lenient).is_ok());
+    }
+
+    #[test]
+    fn parse_status_line() {
+        let normal = ParseMode::Normal;
+
+        let (line, _) = response(b"HTTP/1.1 404 Not Found\r\n\r\n", normal)
+            .unwrap()
+            .unwrap();
+        assert_eq!(line.code, 404);
+        assert_eq!(line.reason, "Not Found");
+
+        let (line, _) = response(b"HTTP/1.0 204\r\n\r\n", normal).unwrap().unwrap();
+        assert_eq!(line.version, HttpVersion::Http10);
+        assert_eq!(line.reason, "");
+
+        let err = |input| response(input, normal).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err(b"HTTP/1.1 20x OK\r\n"),
+            HootError::Status { offset: 11 }
+        );
+        assert_eq!(
+            err(b"HTTP/1.1  200 OK\r\n"),
+            HootError::Status { offset: 9 }
+        );
+        assert_eq!(
+            err(b"HTTP/1.1 200 O\x01K\r\n"),
+            HootError::Status { offset: 14 }
+        );
+    }
+
     #[test]
-    fn check_partial_httparse_parse_headers() {
-        const BUF_SIZE: usize = 2048;
+    fn parse_header_lines() {
+        let normal = ParseMode::Normal;
+        let lenient = ParseMode::Lenient;
 
-        // Depending on 32- or 64-bit architecture this might differ.
-        const HEADER_SIZE: usize = mem::size_of::<httparse::Header>();
-        const HEADER_COUNT: usize = BUF_SIZE / HEADER_SIZE;
+        let err = |input, mode| response(input, mode).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err(b"HTTP/1.1 200 OK\r\nX : 1\r\n", normal),
+            HootError::HeaderName { offset: 19 }
+        );
+        assert_eq!(
+            err(b"HTTP/1.1 200 OK\r\nX: \x01\r\n", normal),
+            HootError::HeaderValue { offset: 20 }
+        );
+        assert_eq!(
+            err(b"HTTP/1.1 200 OK\r\n: 1\r\n", normal),
+            HootError::HeaderName { offset: 17 }
+        );
+        assert_eq!(
+            err(b"HTTP/1.1 200 OK\r\n foo\r\n", lenient),
+            HootError::HeaderName { offset: 17 }
+        );
 
-        let mut buf = [0; BUF_SIZE];
-        let mut headers = cast_buf_for_headers(&mut buf);
-        assert_eq!(headers.len(), HEADER_COUNT);
+        let mut buf = HeaderBuf::<32>::new();
+        let input = b"HTTP/1.1 200 OK\r\nX : a  \r\n  b\r\nY:c\r\n\r\n";
+        let mut scan = HeadScan::default();
+        assert_eq!(scan.scan(input, lenient, true, 10), Ok(true));
+        let head = scan.response(input, &mut buf).unwrap();
+        assert_eq!(head.headers[0].name(), "X");
+        assert_eq!(head.headers[0].value_raw(), b"a  \r\n  b");
+        assert_eq!(head.headers[1].value_raw(), b"c");
 
-        // All values should be "" before we try parsing.
-        assert!(headers.iter().all(|h| h.name == "" && h.value == b""));
+        // Folding is only tolerated in responses.
+        let input = b"GET / HTTP/1.1\r\nX: a\r\n b\r\n\r\n";
+        assert_eq!(
+            request(input, lenient).map(|_| ()).unwrap_err(),
+            HootError::HeaderName { offset: 22 }
+        );
+
+        let mut buf = HeaderBuf::<32>::new();
+        let input = b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n";
+        let mut scan = HeadScan::default();
+        assert_eq!(scan.scan(input, normal, false, 10), Ok(true));
+        let r = scan.request(input, &mut buf[..1]);
+        assert_eq!(r.map(|_| ()).unwrap_err(), HootError::TooManyHeaders);
+    }
+
+    #[test]
+    fn strict_lines() {
+        let strict = ParseMode::Strict;
+        let err = |which| Err(HootError::StrictViolation { which });
+
+        assert!(request(b"GET / HTTP/1.1\r\nX: 1\r\n\r\n", strict).is_ok());
+        assert_eq!(
+            request(b"GET / HTTP/1.1\r\nX: 1\r\n foo\r\n\r\n", strict).map(|_| ()),
+            err(Violation::ObsFold)
+        );
+        assert_eq!(
+            response(b"HTTP/1.1 200 OK\r\nX : 1\r\n", strict).map(|_| ()),
+            err(Violation::SpaceBeforeColon)
+        );
+        assert_eq!(
+            request(b"GET / HTTP/1.1\nX: 1\r\n\r\n", strict).map(|_| ()),
+            err(Violation::BareLf)
+        );
+        assert_eq!(
+            response(b"HTTP/1.1 200 OK\r\nX: 1\r\n\n", strict).map(|_| ()),
+            err(Violation::BareLf)
+        );
+        // Incomplete line is not checked yet.
+        assert_eq!(
+            request(b"GET / HTTP/1.1\r\nX ", strict).map(|v| v.is_none()),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn head_scan() {
+        const INPUT: &[u8] = b"\r\nGET / HTTP/1.1\r\nX: 1\r\n\r\n";
+        let normal = ParseMode::Normal;
+
+        let mut scan = HeadScan::default();
+        for i in 0..INPUT.len() {
+            assert_eq!(scan.scan(&INPUT[..i], normal, false, 10), Ok(false));
+        }
+        // Continued after the complete lines.
+        assert_eq!(scan.pos, 24);
+        assert_eq!(scan.count, 1);
+        assert_eq!(scan.scan(INPUT, normal, false, 10), Ok(true));
+        let mut buf = HeaderBuf::<4>::new();
+        let head = scan.request(INPUT, &mut buf).unwrap();
+        assert_eq!(head.line.target, "/");
+        assert_eq!(head.headers[0].value_raw(), b"1");
+        assert_eq!(head.len, INPUT.len());
+
+        // A folded value is continued from where the scan stopped.
+        const FOLDED: &[u8] = b"HTTP/1.1 200 OK\nX: a \n b \nY: 2\n\n";
+        let lenient = ParseMode::Lenient;
+        let mut scan = HeadScan::default();
+        for i in 0..FOLDED.len() {
+            assert_eq!(scan.scan(&FOLDED[..i], lenient, true, 10), Ok(false));
+        }
+        assert_eq!(scan.scan(FOLDED, lenient, true, 10), Ok(true));
+        let head = scan.response(FOLDED, &mut buf).unwrap();
+        assert_eq!(head.line.reason, "OK");
+        assert_eq!(head.headers.len(), 2);
+        assert_eq!(head.headers[0].value_raw(), b"a \n b");
+        assert_eq!(head.headers[1].value_raw(), b"2");
+
+        // Errors are found before the head is complete.
+        let mut scan = HeadScan::default();
+        let input = b"HTTP/1.1 200 OK\nA: 1\n\x01";
+        assert_eq!(
+            scan.scan(input, normal, true, 10),
+            Err(HootError::HeaderName { offset: 21 })
+        );
+
+        let mut scan = HeadScan::default();
+        let input = b"HTTP/1.1 200 OK\nA: 1\nB: 2\n";
+        assert_eq!(
+            scan.scan(input, normal, true, 1),
+            Err(HootError::TooManyHeaders)
+        );
+
+        // A shorter input starts over.
+        let mut scan = HeadScan::default();
+        assert_eq!(
+            scan.scan(b"HTTP/1.1 200 OK\nA: 1\n", normal, true, 10),
+            Ok(false)
+        );
+        assert_eq!(scan.scan(b"HTTP/1.1 200", normal, true, 10), Ok(false));
+        assert_eq!(scan.pos, 0);
+
+        // So does one without a line ending where the scan stopped.
+        assert_eq!(
+            scan.scan(b"HTTP/1.1 200 OK\nA: 1\n", normal, true, 10),
+            Ok(false)
+        );
+        assert_eq!(
+            scan.scan(b"HTTP/1.1 200 OK!!A: 1\r\n", normal, true, 10),
+            Ok(false)
+        );
+        assert_eq!(scan.pos, 23);
+        assert_eq!(scan.count, 0);
+    }
+
+    #[test]
+    fn parse_partial_headers() {
+        let mut buf = HeaderBuf::<32>::new();
 
         // (missing last \n)
         const PARTIAL_INPUT: &[u8] = b"My-Header: 42\r\nSome-Partial: foo\r";
 
-        let r = httparse::parse_headers(PARTIAL_INPUT, &mut headers);
-
-        // the parse doesn't fail.
-        assert!(r.is_ok());
+        // Despite the partial line, we get the complete headers.
+        let headers = parse_headers(PARTIAL_INPUT, &mut buf).unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].name(), "My-Header");
+        assert_eq!(headers[0].value_raw(), b"42");
 
-        // but it also isn't complete.
-        assert!(matches!(r.unwrap(), httparse::Status::Partial));
+        let r = parse_header_section(PARTIAL_INPUT, &mut buf).unwrap();
+        assert!(r.is_none());
 
-        // Despite that, we can still detect the headers we did find by
-        // checking for the first ""/""
-        assert_eq!(headers[0].name, "My-Header");
-        assert_eq!(headers[0].value, b"42");
-        assert_eq!(headers[1].name, "");
-        assert_eq!(headers[1].value, b"");
+        let (len, headers) = parse_header_section(b"A: 1\r\n\r\nrest", &mut buf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(len, 8);
+        assert_eq!(headers.len(), 1);
     }
 }
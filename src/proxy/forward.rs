@@ -0,0 +1,368 @@
+use crate::client;
+use crate::header::{check_and_output_header, HeaderStyle};
+use crate::out::Out;
+use crate::server::{self, RequestAttempt};
+use crate::types::state::{RECV_BODY, RECV_RESPONSE};
+use crate::util::compare_lowercase_ascii;
+use crate::{ConnectionOptions, Header, HootError, HttpVersion, Method};
+use crate::{RecvBodyMode, RequestTarget, Result, Url};
+
+use super::{write_list, Via};
+
+/// Forwards a request read by a server to an upstream server.
+///
+/// The request is sent upstream as HTTP/1.1 with the same body framing.
+/// Hop-by-hop headers are removed, see [`ConnectionOptions`]. An absolute
+/// target, as sent to a forward proxy, is rewritten to a path.
+///
+/// ```
+/// use hoot::proxy::{Forward, Via};
+/// use hoot::server::Request;
+/// use hoot::util::HeaderBuf;
+///
+/// let mut buf = HeaderBuf::<16>::new();
+/// let mut out = [0; 1024];
+///
+/// const INPUT: &[u8] = b"POST /api HTTP/1.1\r\nHost: example.test\r\n\
+///     Connection: close\r\nContent-Length: 2\r\n\r\nhi";
+///
+/// let mut request = Request::new();
+/// let attempt = request.try_read_request(INPUT, &mut buf)?;
+/// let used = attempt.input_used();
+///
+/// let (head, mut relay) = Forward::new(&attempt)?
+///     .host("upstream.test:8080")
+///     .via(Via { protocol: "1.1", received_by: "proxy", comment: None })
+///     .send_head(&mut out)?;
+///
+/// assert_eq!(
+///     head,
+///     b"POST /api HTTP/1.1\r\nHost: upstream.test:8080\r\n\
+///     Via: 1.1 proxy\r\nContent-Length: 2\r\n\r\n"
+/// );
+///
+/// // Relay the body from the client to upstream.
+/// let mut request = request.proceed();
+/// let part = relay.relay(&mut request, &INPUT[used..], &mut out)?;
+/// assert_eq!(part.output(), b"hi");
+///
+/// // Read the upstream response.
+/// let response = relay.into_response()?;
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Forward<'a, 'b> {
+    method: Method,
+    target: RequestTarget<'a>,
+    headers: &'b [Header<'a>],
+    body_mode: RecvBodyMode,
+    host: Option<&'a str>,
+    path: Option<&'a str>,
+    via: Option<Via<'a>>,
+}
+
+impl<'a, 'b> Forward<'a, 'b> {
+    /// Forward a request read with `try_read_request()`.
+    ///
+    /// Fails with `HootError::StatusIsNotComplete` if the request was not read.
+    pub fn new(attempt: &RequestAttempt<'a, 'b>) -> Result<Self> {
+        let (line, headers, body_mode) =
+            match (attempt.line(), attempt.headers(), attempt.body_mode()) {
+                (Some(l), Some(h), Some(b)) => (l, h, b),
+                _ => return Err(HootError::StatusIsNotComplete),
+            };
+
+        Ok(Forward {
+            method: line.method(),
+            target: line.target(),
+            headers,
+            body_mode,
+            host: None,
+            path: None,
+            via: None,
+        })
+    }
+
+    /// Set the `Host` header for upstream.
+    ///
+    /// Defaults to the host of an absolute target, or the received `Host` header.
+    pub fn host(mut self, host: &'a str) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    /// Set the request target for upstream, like `/path?query`.
+    pub fn target(mut self, target: &'a str) -> Self {
+        self.path = Some(target);
+        self
+    }
+
+    /// Add the proxy to the received `Via` header.
+    pub fn via(mut self, via: Via<'a>) -> Self {
+        self.via = Some(via);
+        self
+    }
+
+    /// Write the request head for upstream into `buf`.
+    ///
+    /// Returns the bytes to send, and the [`Relay`] to continue with the body.
+    pub fn send_head<'c>(&self, buf: &'c mut [u8]) -> Result<(&'c [u8], Relay)> {
+        let absolute = match self.target {
+            RequestTarget::Absolute(v) => Some(Url::parse_str(v).map_err(HootError::UrlError)?),
+            _ => None,
+        };
+
+        let path = match (self.path, &absolute) {
+            (Some(v), _) => v,
+            (None, Some(url)) => url.path_and_query(),
+            (None, None) => self.target.as_str(),
+        };
+
+        let received_host = self
+            .headers
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "host"))
+            .and_then(|h| h.try_value());
+
+        let host = self
+            .host
+            .or_else(|| absolute.as_ref().map(|u| u.host_header()))
+            .or(received_host)
+            .ok_or(HootError::MissingHost)?;
+
+        if let Some(offset) = path
+            .bytes()
+            .position(|b| b <= b' ' || b == b'#' || b == 0x7f)
+        {
+            return Err(HootError::InvalidTarget { offset });
+        }
+        RequestTarget::parse(self.method, path)?;
+
+        if let Some(offset) = host.bytes().position(|b| b <= b' ' || b == 0x7f) {
+            return Err(HootError::HeaderValue { offset });
+        }
+
+        trace!("Forward {:?} {} to {}", self.method, path, host);
+
+        let mut out = Out::wrap(buf);
+
+        let mut w = out.writer();
+        write!(
+            w,
+            "{:?} {} HTTP/1.1\r\nHost: {}\r\n",
+            self.method, path, host
+        )?;
+        w.commit();
+
+        let mut sent = 0;
+        for h in ConnectionOptions::new(self.headers).end_to_end() {
+            let name = h.name();
+            let rewritten = compare_lowercase_ascii(name, "host")
+                || compare_lowercase_ascii(name, "content-length")
+                || self.via.is_some() && compare_lowercase_ascii(name, "via");
+
+            if rewritten {
+                continue;
+            }
+
+            let w = out.writer();
+            check_and_output_header(
+                w,
+                HeaderStyle {
+                    version: HttpVersion::Http11,
+                    canonical: false,
+                },
+                name,
+                h.value_raw(),
+                false,
+                false,
+                &mut sent,
+            )?;
+        }
+
+        let mut w = out.writer();
+
+        if let Some(via) = &self.via {
+            write!(w, "Via: ")?;
+            write_list(&mut w, self.headers, "via", via)?;
+            write!(w, "\r\n")?;
+        }
+
+        match self.body_mode {
+            RecvBodyMode::LengthDelimited(0) => {}
+            RecvBodyMode::LengthDelimited(len) => {
+                write!(w, "Content-Length: {}\r\n", len)?;
+            }
+            RecvBodyMode::Chunked => {
+                write!(w, "Transfer-Encoding: chunked\r\n")?;
+            }
+            // Not possible for server::Request.
+            RecvBodyMode::CloseDelimited => return Err(HootError::Internal),
+        }
+
+        write!(w, "\r\n")?;
+        w.commit();
+
+        let relay = Relay {
+            method: self.method,
+            body_mode: self.body_mode,
+            finished: self.body_mode == RecvBodyMode::LengthDelimited(0),
+        };
+
+        Ok((out.into_inner(), relay))
+    }
+}
+
+/// Relays the body of a [`Forward`] request from the client to upstream.
+#[derive(Debug)]
+pub struct Relay {
+    method: Method,
+    body_mode: RecvBodyMode,
+    finished: bool,
+}
+
+/// The result of [`Relay::relay()`].
+#[derive(Debug)]
+pub struct RelayPart<'c> {
+    input_used: usize,
+    output: &'c [u8],
+    finished: bool,
+}
+
+impl<'c> RelayPart<'c> {
+    /// Amount of the input from the client that was used.
+    pub fn input_used(&self) -> usize {
+        self.input_used
+    }
+
+    /// Bytes to send upstream.
+    pub fn output(&self) -> &'c [u8] {
+        self.output
+    }
+
+    /// Whether the entire body has been relayed.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+// Room for the chunk ending a chunked body, "0\r\n\r\n".
+const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+
+impl Relay {
+    /// Read body from `src` and write it, framed for upstream, into `buf`.
+    ///
+    /// `buf` bounds how much is relayed per call. Trailers of a chunked body
+    /// are not relayed, and can be read with `read_trailers()` afterwards.
+    pub fn relay<'c>(
+        &mut self,
+        request: &mut server::Request<RECV_BODY>,
+        src: &[u8],
+        buf: &'c mut [u8],
+    ) -> Result<RelayPart<'c>> {
+        if self.finished {
+            return Ok(RelayPart {
+                input_used: 0,
+                output: &buf[..0],
+                finished: true,
+            });
+        }
+
+        let (input_used, len, finished) = if self.body_mode == RecvBodyMode::Chunked {
+            if buf.len() < LAST_CHUNK.len() {
+                return Err(HootError::OutputOverflow {
+                    needed: LAST_CHUNK.len() - buf.len(),
+                });
+            }
+            let end = buf.len() - LAST_CHUNK.len();
+
+            let mut input_used = 0;
+            let mut finished = false;
+
+            let mut out = Out::wrap(&mut buf[..end]);
+            let mut w = out.writer();
+            w.write_chunk_with(|b| {
+                let part = request.read_body(src, b)?;
+                input_used = part.input_used();
+                finished = part.is_finished();
+                Ok(part.data().len())
+            })?;
+            w.commit();
+            let mut len = out.into_inner().len();
+
+            if finished {
+                buf[len..(len + LAST_CHUNK.len())].copy_from_slice(LAST_CHUNK);
+                len += LAST_CHUNK.len();
+            }
+
+            (input_used, len, finished)
+        } else {
+            let part = request.read_body(src, buf)?;
+            (part.input_used(), part.data().len(), part.is_finished())
+        };
+
+        trace!("Relayed {} from {} input", len, input_used);
+
+        self.finished = finished;
+
+        Ok(RelayPart {
+            input_used,
+            output: &buf[..len],
+            finished,
+        })
+    }
+
+    /// Whether the entire body has been relayed.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Continue with the response from upstream.
+    ///
+    /// Fails with `HootError::BodyNotFinished` if the body is not relayed.
+    pub fn into_response(self) -> Result<client::Response<RECV_RESPONSE>> {
+        if !self.finished {
+            return Err(HootError::BodyNotFinished);
+        }
+
+        Ok(client::Response::forwarded(self.method))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn forward_chunked() -> Result<()> {
+        const INPUT: &[u8] = b"PUT http://example.test/up HTTP/1.1\r\nHost: example.test\r\n\
+            Connection: x-hop\r\nX-Hop: 1\r\nAccept: */*\r\n\
+            Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+
+        let mut buf = HeaderBuf::<16>::new();
+        let mut out = [0; 1024];
+
+        let mut request = server::Request::new();
+        let attempt = request.try_read_request(INPUT, &mut buf)?;
+        let used = attempt.input_used();
+
+        let (head, mut relay) = Forward::new(&attempt)?.send_head(&mut out)?;
+        assert_eq!(
+            head,
+            b"PUT /up HTTP/1.1\r\nHost: example.test\r\nAccept: */*\r\n\
+            Transfer-Encoding: chunked\r\n\r\n"
+        );
+
+        let mut request = request.proceed();
+        let part = relay.relay(&mut request, &INPUT[used..], &mut out[..30])?;
+        assert_eq!(part.output(), b"5\r\nhello\r\n0\r\n\r\n");
+        assert!(part.is_finished());
+        // The (empty) trailer section is left for read_trailers().
+        assert_eq!(used + part.input_used(), INPUT.len() - 2);
+
+        relay.into_response()?;
+
+        Ok(())
+    }
+}
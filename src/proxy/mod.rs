@@ -0,0 +1,385 @@
+//! `Via` and `Forwarded` headers for intermediaries.
+//!
+//! A proxy adds itself to `Via` (RFC 7230 §5.7.1), and usually tells the
+//! origin about the client in `Forwarded` (RFC 7239). Both headers are lists
+//! where every hop appends an element. [`append_via`] and [`append_forwarded`]
+//! combine the received values with the new hop into a borrowed buffer.
+//!
+//! ```
+//! use hoot::proxy::{append_forwarded, Forwarded};
+//! # use hoot::Header;
+//! # fn forward(headers: &[Header]) -> Result<(), hoot::HootError> {
+//! let mut buf = [0; 256];
+//!
+//! let hop = Forwarded {
+//!     for_: Some("192.0.2.60"),
+//!     proto: Some("http"),
+//!     ..Default::default()
+//! };
+//!
+//! // Send as the Forwarded header to the next hop.
+//! let value = append_forwarded(headers, &hop, &mut buf)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Parsing is lenient, elements that can't be understood are skipped.
+//!
+//! A reverse proxy sends requests it reads as a server on to an upstream
+//! server using [`Forward`], which also relays the request body.
+
+use core::fmt;
+use core::str;
+
+use crate::out::{Out, Writer};
+use crate::util::compare_lowercase_ascii;
+use crate::{Header, Result};
+
+mod forward;
+pub use forward::{Forward, Relay, RelayPart};
+
+/// An element of the `Via` header, like `1.1 proxy.example (squid)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Via<'a> {
+    /// The protocol, such as `1.1` or `HTTP/1.1`. The name is optional for HTTP.
+    pub protocol: &'a str,
+    /// Host (and port), or a pseudonym, of the recipient.
+    pub received_by: &'a str,
+    /// Comment without the surrounding parenthesis.
+    pub comment: Option<&'a str>,
+}
+
+impl<'a> Via<'a> {
+    /// Parse all elements of all `Via` headers, in order.
+    pub fn parse_all<'b>(headers: &'b [Header<'a>]) -> impl Iterator<Item = Via<'b>> {
+        values(headers, "via")
+            .flat_map(split_list)
+            .filter_map(Via::parse)
+    }
+
+    /// Parse a single element.
+    pub fn parse(element: &'a str) -> Option<Self> {
+        let element = element.trim();
+        let (protocol, rest) = split_once(element, ' ')?;
+        let rest = rest.trim_start();
+
+        let (received_by, comment) = match split_once(rest, ' ') {
+            Some((by, comment)) => {
+                let comment = comment.trim();
+                let comment = comment.strip_prefix('(')?.strip_suffix(')')?;
+                (by, Some(comment))
+            }
+            None => (rest, None),
+        };
+
+        if protocol.is_empty() || received_by.is_empty() {
+            return None;
+        }
+
+        Some(Via {
+            protocol,
+            received_by,
+            comment,
+        })
+    }
+}
+
+impl fmt::Display for Via<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.protocol, self.received_by)?;
+        if let Some(comment) = self.comment {
+            write!(f, " ({})", comment)?;
+        }
+        Ok(())
+    }
+}
+
+/// An element of the `Forwarded` header, RFC 7239.
+///
+/// Like `for=192.0.2.60;proto=http;by=203.0.113.43`. Values are as sent,
+/// quoted values are without quotes, but escapes are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Forwarded<'a> {
+    /// The interface receiving the request at the proxy.
+    pub by: Option<&'a str>,
+    /// The client making the request to the proxy.
+    pub for_: Option<&'a str>,
+    /// The `Host` header as received by the proxy.
+    pub host: Option<&'a str>,
+    /// The protocol used for the request to the proxy, `http` or `https`.
+    pub proto: Option<&'a str>,
+}
+
+impl<'a> Forwarded<'a> {
+    /// Parse all elements of all `Forwarded` headers, in order.
+    pub fn parse_all<'b>(headers: &'b [Header<'a>]) -> impl Iterator<Item = Forwarded<'b>> {
+        values(headers, "forwarded")
+            .flat_map(split_list)
+            .map(Forwarded::parse)
+    }
+
+    /// Parse a single element. Unknown parameters are ignored.
+    pub fn parse(element: &'a str) -> Self {
+        let mut ret = Forwarded::default();
+
+        for pair in split_quoted(element, ';') {
+            let (name, value) = match split_once(pair.trim(), '=') {
+                Some(v) => v,
+                None => continue,
+            };
+            let name = name.trim();
+            let value = unquote(value.trim());
+
+            if compare_lowercase_ascii(name, "by") {
+                ret.by = Some(value);
+            } else if compare_lowercase_ascii(name, "for") {
+                ret.for_ = Some(value);
+            } else if compare_lowercase_ascii(name, "host") {
+                ret.host = Some(value);
+            } else if compare_lowercase_ascii(name, "proto") {
+                ret.proto = Some(value);
+            }
+        }
+
+        ret
+    }
+}
+
+impl fmt::Display for Forwarded<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = [
+            ("by", self.by),
+            ("for", self.for_),
+            ("host", self.host),
+            ("proto", self.proto),
+        ];
+
+        let mut first = true;
+        for (name, value) in params {
+            let value = match value {
+                Some(v) => v,
+                None => continue,
+            };
+            if !first {
+                write!(f, ";")?;
+            }
+            first = false;
+
+            if is_token(value) {
+                write!(f, "{}={}", name, value)?;
+            } else {
+                // IPv6 addresses and ports must be quoted.
+                write!(f, "{}=\"{}\"", name, value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The clients of the request, from the first hop.
+///
+/// Uses the `for` parameters of `Forwarded`, and falls back on the
+/// `X-Forwarded-For` header if there is no `Forwarded` header. These headers
+/// are sent by the client and any proxy on the way, only values added by
+/// trusted proxies should be relied on.
+pub fn forwarded_for<'a, 'b>(headers: &'b [Header<'a>]) -> impl Iterator<Item = &'b str> {
+    let has_forwarded = values(headers, "forwarded").next().is_some();
+
+    let forwarded = Forwarded::parse_all(headers)
+        .filter_map(|f| f.for_)
+        .filter(move |_| has_forwarded);
+
+    let x_forwarded = values(headers, "x-forwarded-for")
+        .flat_map(split_list)
+        .filter(move |_| !has_forwarded);
+
+    forwarded.chain(x_forwarded)
+}
+
+/// Join the received `Via` values with `hop` into `buf`.
+///
+/// The returned value is the `Via` header to send to the next hop. Fails with
+/// `HootError::OutputOverflow` if `buf` is too small.
+pub fn append_via<'c>(headers: &[Header<'_>], hop: &Via<'_>, buf: &'c mut [u8]) -> Result<&'c str> {
+    append(headers, "via", hop, buf)
+}
+
+/// Join the received `Forwarded` values with `hop` into `buf`.
+///
+/// The returned value is the `Forwarded` header to send to the next hop. Fails
+/// with `HootError::OutputOverflow` if `buf` is too small.
+pub fn append_forwarded<'c>(
+    headers: &[Header<'_>],
+    hop: &Forwarded<'_>,
+    buf: &'c mut [u8],
+) -> Result<&'c str> {
+    append(headers, "forwarded", hop, buf)
+}
+
+fn append<'c>(
+    headers: &[Header<'_>],
+    name: &str,
+    hop: &dyn fmt::Display,
+    buf: &'c mut [u8],
+) -> Result<&'c str> {
+    let mut out = Out::wrap(buf);
+    let mut w = out.writer();
+    write_list(&mut w, headers, name, hop)?;
+    w.commit();
+
+    // Can't fail since we only wrote &str.
+    Ok(str::from_utf8(out.into_inner())?)
+}
+
+/// Write the received values of `name` followed by `hop` as one list.
+fn write_list(
+    w: &mut Writer<'_, '_>,
+    headers: &[Header<'_>],
+    name: &str,
+    hop: &dyn fmt::Display,
+) -> Result<()> {
+    for value in values(headers, name) {
+        write!(w, "{}, ", value.trim())?;
+    }
+    write!(w, "{}", hop)
+}
+
+fn values<'b>(headers: &'b [Header<'_>], name: &'b str) -> impl Iterator<Item = &'b str> {
+    headers
+        .iter()
+        .filter(move |h| compare_lowercase_ascii(h.name(), name))
+        .filter_map(|h| h.try_value())
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    split_quoted(value, ',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// Split on `sep`, but not inside quoted strings.
+fn split_quoted(value: &str, sep: char) -> impl Iterator<Item = &str> {
+    let mut rest = Some(value);
+
+    core::iter::from_fn(move || {
+        let s = rest?;
+        let mut quoted = false;
+        let mut escaped = false;
+
+        for (i, c) in s.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if quoted && c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                quoted = !quoted;
+            } else if !quoted && c == sep {
+                rest = Some(&s[i + 1..]);
+                return Some(&s[..i]);
+            }
+        }
+
+        rest = None;
+        Some(s)
+    })
+}
+
+fn split_once(s: &str, c: char) -> Option<(&str, &str)> {
+    let i = s.find(c)?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_headers;
+    use crate::util::HeaderBuf;
+    use crate::HootError;
+
+    #[test]
+    fn parse_via() {
+        let mut buf = HeaderBuf::<16>::new();
+        let headers = parse_headers(
+            b"Via: 1.0 fred, 1.1 p.example.net (Apache/1.1)\r\nVia: HTTP/1.1 x\r\n\r\n",
+            &mut buf,
+        )
+        .unwrap();
+
+        let via: Vec<_> = Via::parse_all(headers).collect();
+        assert_eq!(via.len(), 3);
+        assert_eq!(via[0].received_by, "fred");
+        assert_eq!(via[1].comment, Some("Apache/1.1"));
+        assert_eq!(via[2].protocol, "HTTP/1.1");
+    }
+
+    #[test]
+    fn parse_forwarded() {
+        let f = Forwarded::parse("For=\"[2001:db8:cafe::17]:4711\";proto=https;by=_hidden");
+        assert_eq!(f.for_, Some("[2001:db8:cafe::17]:4711"));
+        assert_eq!(f.proto, Some("https"));
+        assert_eq!(f.by, Some("_hidden"));
+        assert_eq!(f.host, None);
+    }
+
+    #[test]
+    fn forwarded_for_fallback() {
+        let mut buf = HeaderBuf::<16>::new();
+        let headers = parse_headers(
+            b"X-Forwarded-For: 203.0.113.195, 70.41.3.18\r\n\r\n",
+            &mut buf,
+        )
+        .unwrap();
+        let ips: Vec<_> = forwarded_for(headers).collect();
+        assert_eq!(ips, ["203.0.113.195", "70.41.3.18"]);
+
+        let headers = parse_headers(
+            b"X-Forwarded-For: 1.1.1.1\r\nForwarded: for=192.0.2.43, for=\"[::1]\"\r\n\r\n",
+            &mut buf,
+        )
+        .unwrap();
+        let ips: Vec<_> = forwarded_for(headers).collect();
+        assert_eq!(ips, ["192.0.2.43", "[::1]"]);
+    }
+
+    #[test]
+    fn append_hops() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        let headers = parse_headers(b"Forwarded: for=192.0.2.43\r\n\r\n", &mut buf).unwrap();
+
+        let mut out = [0; 64];
+        let hop = Forwarded {
+            for_: Some("[2001:db8::1]:80"),
+            proto: Some("http"),
+            ..Default::default()
+        };
+        let v = append_forwarded(headers, &hop, &mut out)?;
+        assert_eq!(v, "for=192.0.2.43, for=\"[2001:db8::1]:80\";proto=http");
+
+        let hop = Via {
+            protocol: "1.1",
+            received_by: "proxy.test",
+            comment: None,
+        };
+        assert_eq!(append_via(&[], &hop, &mut out)?, "1.1 proxy.test");
+
+        let err = append_via(&[], &hop, &mut out[..5]).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 9 });
+
+        Ok(())
+    }
+}
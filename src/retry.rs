@@ -0,0 +1,221 @@
+//! Waiting before retrying a request.
+//!
+//! Servers under pressure answer `429 Too Many Requests` or `503 Service
+//! Unavailable`, often with a `Retry-After` header saying when to come back.
+//! [`RetryAfter`] parses that header, and [`Backoff`] spaces out retries with
+//! jitter, so that many clients failing at once don't retry in lockstep.
+//!
+//! hoot has no source of randomness or sleep. The caller passes a random
+//! number in and does the waiting.
+
+use core::time::Duration;
+
+use crate::date::parse_http_date;
+use crate::util::compare_lowercase_ascii;
+use crate::{Clock, Header};
+
+/// Parsed `Retry-After` header, RFC 7231 §7.1.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAfter {
+    /// Delay in seconds, such as `Retry-After: 120`.
+    Seconds(u64),
+    /// Point in time as seconds since the Unix epoch, such as
+    /// `Retry-After: Fri, 31 Dec 1999 23:59:59 GMT`.
+    Date(u64),
+}
+
+impl RetryAfter {
+    /// Parse a `Retry-After` value, `None` if it is neither delay nor date.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            // Too many digits to fit is still a very long wait.
+            return Some(RetryAfter::Seconds(value.parse().unwrap_or(u64::MAX)));
+        }
+
+        parse_http_date(value).map(RetryAfter::Date)
+    }
+
+    /// Find and parse the `Retry-After` header.
+    pub fn from_headers(headers: &[Header<'_>]) -> Option<Self> {
+        headers
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "retry-after"))
+            .and_then(|h| h.try_value())
+            .and_then(Self::parse)
+    }
+
+    /// How long to wait from now. A date in the past is no wait.
+    pub fn delay(&self, clock: &dyn Clock) -> Duration {
+        let secs = match *self {
+            RetryAfter::Seconds(v) => v,
+            RetryAfter::Date(v) => v.saturating_sub(clock.unix_time()),
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+/// The wait asked for by a `429 Too Many Requests` or `503 Service
+/// Unavailable` response with a valid `Retry-After` header.
+///
+/// `None` for other statuses, where `Retry-After` doesn't ask to retry the
+/// same request.
+pub fn retry_delay(status: u16, headers: &[Header<'_>], clock: &dyn Clock) -> Option<Duration> {
+    if status != 429 && status != 503 {
+        return None;
+    }
+
+    RetryAfter::from_headers(headers).map(|r| r.delay(clock))
+}
+
+/// Exponential backoff with full jitter.
+///
+/// The delay before retry `n` is random between zero and `base * 2^n`, capped
+/// at `max`.
+///
+/// ```
+/// use core::time::Duration;
+/// use hoot::retry::Backoff;
+///
+/// let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(10));
+///
+/// // `random` is from the caller's RNG, u32::MAX gives the longest delay.
+/// assert_eq!(backoff.delay(0, u32::MAX), Duration::from_millis(100));
+/// assert_eq!(backoff.delay(3, u32::MAX), Duration::from_millis(800));
+/// assert_eq!(backoff.delay(3, 0), Duration::ZERO);
+/// assert_eq!(backoff.delay(20, u32::MAX), Duration::from_secs(10));
+///
+/// // A server asking for longer wins.
+/// let asked = Some(Duration::from_secs(30));
+/// assert_eq!(backoff.delay_at_least(0, 0, asked), Duration::from_secs(30));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff { base, max }
+    }
+
+    /// Delay before retry number `attempt`, counting from `0`.
+    ///
+    /// `random` is a uniformly random number, which scales the delay from
+    /// zero at `0` to the full delay at `u32::MAX`.
+    pub fn delay(&self, attempt: u32, random: u32) -> Duration {
+        let factor = 1_u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        let ceiling = self
+            .base
+            .checked_mul(factor)
+            .unwrap_or(self.max)
+            .min(self.max);
+
+        let nanos = ceiling.as_nanos() * random as u128 / u32::MAX as u128;
+        // Can't overflow, it's at most the ceiling.
+        Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+
+    /// Like `delay()`, but at least `retry_after`, such as from
+    /// [`retry_delay()`].
+    ///
+    /// The server's wait is not capped by `max`. Give up instead if it is
+    /// too long to wait.
+    pub fn delay_at_least(
+        &self,
+        attempt: u32,
+        random: u32,
+        retry_after: Option<Duration>,
+    ) -> Duration {
+        let delay = self.delay(attempt, random);
+        match retry_after {
+            Some(v) => delay.max(v),
+            None => delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::client::Response;
+    use crate::util::HeaderBuf;
+
+    struct Fixed;
+    impl Clock for Fixed {
+        fn unix_time(&self) -> u64 {
+            784_111_777
+        }
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        assert_eq!(RetryAfter::parse(" 120 "), Some(RetryAfter::Seconds(120)));
+        assert_eq!(
+            RetryAfter::parse("99999999999999999999999"),
+            Some(RetryAfter::Seconds(u64::MAX))
+        );
+        assert_eq!(
+            RetryAfter::parse("Sun, 06 Nov 1994 08:51:37 GMT"),
+            Some(RetryAfter::Date(784_111_897))
+        );
+        assert_eq!(RetryAfter::parse("-1"), None);
+        assert_eq!(RetryAfter::parse(""), None);
+        assert_eq!(RetryAfter::parse("soon"), None);
+
+        let date = RetryAfter::parse("Sun, 06 Nov 1994 08:51:37 GMT").unwrap();
+        assert_eq!(date.delay(&Fixed), Duration::from_secs(120));
+        let past = RetryAfter::Date(0);
+        assert_eq!(past.delay(&Fixed), Duration::ZERO);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn retry_delay_from_response() -> crate::Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r = Response::new_test();
+        let input = b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: \
+            Sunday, 06-Nov-94 08:50:37 GMT\r\n\r\n";
+        let a = r.try_read_response(input, &mut buf)?;
+        assert_eq!(a.retry_after(&Fixed), Some(Duration::from_secs(60)));
+
+        // Retry-After on a redirect is not about retrying.
+        let mut r = Response::new_test();
+        let input = b"HTTP/1.1 301 Moved Permanently\r\nRetry-After: 5\r\n\r\n";
+        let a = r.try_read_response(input, &mut buf)?;
+        assert_eq!(a.retry_after(&Fixed), None);
+        let headers = a.headers().unwrap();
+        assert_eq!(
+            retry_delay(429, headers, &Fixed),
+            Some(Duration::from_secs(5))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn backoff() {
+        let b = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(b.delay(0, 0), Duration::ZERO);
+        assert_eq!(b.delay(1, u32::MAX), Duration::from_millis(200));
+        assert_eq!(b.delay(4, u32::MAX), Duration::from_secs(1));
+        assert_eq!(b.delay(31, u32::MAX), Duration::from_secs(1));
+        assert_eq!(b.delay(u32::MAX, u32::MAX), Duration::from_secs(1));
+        assert!(b.delay(2, 12345) < Duration::from_millis(400));
+
+        let huge = Backoff::new(Duration::MAX, Duration::MAX);
+        assert_eq!(huge.delay(3, u32::MAX), Duration::MAX);
+
+        assert_eq!(
+            b.delay_at_least(1, u32::MAX, Some(Duration::from_millis(50))),
+            Duration::from_millis(200)
+        );
+    }
+}
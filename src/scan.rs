@@ -0,0 +1,124 @@
+//! Scanning for delimiters in headers and chunk sizes.
+//!
+//! With the `swar` feature, input is scanned a word at a time ("SIMD within a
+//! register"), skipping words where no byte can end the scan. Words that might
+//! are scanned byte by byte, which is also what is done without the feature.
+
+/// Position of the first `needle` in `haystack`.
+pub(crate) fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    let start = skip_words(haystack, |w| !has_zero(w ^ repeat(needle)));
+
+    haystack[start..]
+        .iter()
+        .position(|b| *b == needle)
+        .map(|i| start + i)
+}
+
+/// Length of the prefix of `haystack` that is a header value: tab, space,
+/// visible characters and obs-text.
+pub(crate) fn value_len(haystack: &[u8]) -> usize {
+    span(haystack, b' ', |b| b == b'\t' || b >= b' ' && b != 0x7f)
+}
+
+/// Length of the prefix of `haystack` that is a request target, which is
+/// anything but space and control bytes.
+pub(crate) fn target_len(haystack: &[u8]) -> usize {
+    span(haystack, b' ' + 1, |b| b > b' ' && b != 0x7f)
+}
+
+/// Length of the prefix where `f` holds.
+///
+/// `f` must hold for all bytes from `min`, except DEL (0x7f), since words of
+/// those bytes are skipped without calling it.
+fn span(haystack: &[u8], min: u8, f: impl Fn(u8) -> bool) -> usize {
+    let start = skip_words(haystack, |w| {
+        !has_less(w, min) && !has_zero(w ^ repeat(0x7f))
+    });
+
+    haystack[start..]
+        .iter()
+        .position(|b| !f(*b))
+        .map(|i| start + i)
+        .unwrap_or(haystack.len())
+}
+
+#[cfg(feature = "swar")]
+const WORD: usize = core::mem::size_of::<usize>();
+
+/// Number of bytes in whole words from the start of `haystack` where `skip`
+/// holds for every word.
+#[cfg(feature = "swar")]
+fn skip_words(haystack: &[u8], skip: impl Fn(usize) -> bool) -> usize {
+    let mut start = 0;
+
+    for chunk in haystack.chunks_exact(WORD) {
+        let mut bytes = [0; WORD];
+        bytes.copy_from_slice(chunk);
+
+        if !skip(usize::from_ne_bytes(bytes)) {
+            break;
+        }
+        start += WORD;
+    }
+
+    start
+}
+
+#[cfg(not(feature = "swar"))]
+fn skip_words(_haystack: &[u8], _skip: impl Fn(usize) -> bool) -> usize {
+    0
+}
+
+/// `b` in every byte of a word.
+const fn repeat(b: u8) -> usize {
+    usize::MAX / 255 * b as usize
+}
+
+/// Whether any byte of `w` is zero.
+fn has_zero(w: usize) -> bool {
+    w.wrapping_sub(repeat(1)) & !w & repeat(0x80) != 0
+}
+
+/// Whether any byte of `w` is less than `n`, which must be at most 128.
+fn has_less(w: usize, n: u8) -> bool {
+    w.wrapping_sub(repeat(n)) & !w & repeat(0x80) != 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scan_like_bytewise() {
+        let mut input = [b'a'; 64];
+
+        // Pseudo random bytes at all positions, mostly letters.
+        let mut x: u32 = 7;
+        for _ in 0..5000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let b = (x >> 16) as u8;
+            input[(x >> 8) as usize % input.len()] = match b % 4 {
+                0 => (x >> 24) as u8,
+                _ => b'a' + b % 26,
+            };
+
+            let len = (x >> 4) as usize % input.len();
+            let h = &input[..len];
+
+            let value = h
+                .iter()
+                .position(|b| *b != b'\t' && (*b < b' ' || *b == 0x7f));
+            assert_eq!(value_len(h), value.unwrap_or(len));
+
+            let target = h.iter().position(|b| *b <= b' ' || *b == 0x7f);
+            assert_eq!(target_len(h), target.unwrap_or(len));
+
+            assert_eq!(find_byte(h, b'\r'), h.iter().position(|b| *b == b'\r'));
+        }
+
+        assert!(has_zero(repeat(b'\r') ^ repeat(b'\r')));
+        assert!(!has_zero(repeat(b'\n') ^ repeat(b'\r')));
+        assert!(has_less(repeat(0x1f), 0x20));
+        assert!(!has_less(repeat(0x80), 0x20));
+    }
+}
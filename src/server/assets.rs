@@ -0,0 +1,494 @@
+use core::mem;
+
+use crate::out::Out;
+use crate::types::body::*;
+use crate::types::method::*;
+use crate::types::state::*;
+use crate::types::*;
+use crate::{HeaderMap, HootError, Result};
+
+use super::res::reason_phrase;
+use super::{RequestAttempt, Response, ResponseVariant, ResumeToken};
+
+/// A file compiled into the program, such as a page of an embedded web UI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Asset {
+    /// Path the asset is served at, such as `/index.html`.
+    pub path: &'static str,
+    /// Value of the `Content-Type` header.
+    pub content_type: &'static str,
+    /// The entire content.
+    pub body: &'static [u8],
+    /// Entity tag with quotes, such as `"v1"`, for conditional requests.
+    pub etag: &'static str,
+}
+
+/// Length of an [`Etag`], a 64 bit hash in hex between quotes.
+pub const ETAG_LEN: usize = 18;
+
+/// Entity tag computed from the content of an asset.
+///
+/// This is a const fn, so the tag is computed at compile time, which is what
+/// [`asset!`](crate::asset) does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Etag([u8; ETAG_LEN]);
+
+impl Etag {
+    /// Hash `body` into a strong entity tag, such as `"af63bd4c8601b7be"`.
+    pub const fn of(body: &[u8]) -> Etag {
+        // FNV-1a, which is simple enough for const evaluation.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut i = 0;
+        while i < body.len() {
+            hash ^= body[i] as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let mut tag = [b'"'; ETAG_LEN];
+        let mut i = 0;
+        while i < 16 {
+            tag[i + 1] = HEX[((hash >> (60 - i * 4)) & 0xf) as usize];
+            i += 1;
+        }
+
+        Etag(tag)
+    }
+
+    pub const fn as_str(&self) -> &str {
+        // SAFETY: The tag is quotes and hex digits, which is ASCII.
+        unsafe { core::str::from_utf8_unchecked(&self.0) }
+    }
+}
+
+/// Build an [`Asset`](crate::server::Asset) at compile time, with the ETag
+/// computed from the body.
+///
+/// ```
+/// use hoot::asset;
+/// use hoot::server::Assets;
+///
+/// static ASSETS: Assets = Assets::new(&[
+///     asset!("/index.html", "text/html", b"<h1>hi</h1>"),
+///     // Usually: asset!("/app.js", "text/javascript", include_bytes!("app.js")),
+///     asset!("/app.js", "text/javascript", b"console.log(1)"),
+/// ]);
+///
+/// let asset = ASSETS.find("/index.html").unwrap();
+/// assert_eq!(asset.body.len(), 11);
+/// assert_eq!(asset.etag, "\"57bf99fcfdfe715b\"");
+/// ```
+#[macro_export]
+macro_rules! asset {
+    ($path:expr, $content_type:expr, $body:expr $(,)?) => {{
+        const BODY: &[u8] = $body;
+        const ETAG: $crate::server::Etag = $crate::server::Etag::of(BODY);
+        $crate::server::Asset {
+            path: $path,
+            content_type: $content_type,
+            body: BODY,
+            etag: (&ETAG).as_str(),
+        }
+    }};
+}
+
+/// Table of assets to serve.
+///
+/// `serve()` answers `GET` and `HEAD` requests from the table, with `304` for
+/// a matching `If-None-Match` and `206` for a single byte `Range`. Other
+/// methods get `405` and unknown paths `404`.
+///
+/// ```
+/// use hoot::server::{Asset, Assets, Request};
+/// use hoot::util::HeaderBuf;
+///
+/// static ASSETS: Assets = Assets::new(&[Asset {
+///     path: "/index.html",
+///     content_type: "text/html",
+///     body: b"<h1>hi</h1>",
+///     etag: "\"1\"",
+/// }]);
+///
+/// let mut buf = HeaderBuf::<16>::new();
+/// let mut request = Request::new();
+/// let attempt = request.try_read_request(
+///     b"GET /index.html HTTP/1.1\r\nHost: x\r\nRange: bytes=4-5\r\n\r\n",
+///     &mut buf,
+/// )?;
+/// let mut response = ASSETS.serve(&attempt, request.proceed().into_response()?);
+///
+/// let mut out = [0; 1024];
+/// let output = response.write(&mut out)?;
+/// assert!(output.starts_with(b"HTTP/1.1 206 Partial Content\r\n"));
+/// assert!(output.ends_with(b"Content-Range: bytes 4-5/11\r\nContent-Length: 2\r\n\r\nhi"));
+/// assert!(response.is_finished());
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Assets<'t> {
+    table: &'t [Asset],
+}
+
+impl<'t> Assets<'t> {
+    pub const fn new(table: &'t [Asset]) -> Self {
+        Assets { table }
+    }
+
+    /// The asset at `path`, ignoring any query.
+    pub fn find(&self, path: &str) -> Option<&'t Asset> {
+        let path = path.split('?').next().unwrap_or("");
+        self.table.iter().find(|a| a.path == path)
+    }
+
+    /// Decide the response to a request, which is then written by
+    /// [`AssetResponse::write()`].
+    ///
+    /// `attempt` is the request, which `variant` is the response to.
+    pub fn serve(
+        &self,
+        attempt: &RequestAttempt<'_, '_>,
+        variant: ResponseVariant,
+    ) -> AssetResponse {
+        let path = attempt
+            .line()
+            .and_then(|l| l.path_and_query())
+            .unwrap_or("");
+        let headers = HeaderMap::new(attempt.headers().unwrap_or(&[]));
+        let value = |name| headers.get(name).and_then(|h| h.try_value());
+
+        let is_get = matches!(variant, ResponseVariant::Get(_));
+        let is_head = matches!(variant, ResponseVariant::Head(_));
+
+        let asset = self.find(path).copied();
+
+        let (status, range) = match &asset {
+            _ if !is_get && !is_head => (405, None),
+            None => (404, None),
+            Some(a) if value("if-none-match").map_or(false, |v| etag_matches(v, a.etag)) => {
+                (304, None)
+            }
+            Some(a) if is_get && value("if-range").map_or(true, |v| v.trim() == a.etag) => {
+                match value("range").map(|v| parse_range(v, a.body.len())) {
+                    Some(Range::Bytes(start, end)) => (206, Some((start, end))),
+                    Some(Range::Unsatisfiable) => (416, None),
+                    Some(Range::Ignore) | None => (200, None),
+                }
+            }
+            Some(_) => (200, None),
+        };
+
+        trace!("Serve asset {}: {}", path, status);
+
+        AssetResponse {
+            step: Step::Start(variant),
+            status,
+            asset,
+            range,
+        }
+    }
+}
+
+/// Response to an asset request, see [`Assets::serve()`].
+pub struct AssetResponse {
+    step: Step,
+    status: u16,
+    asset: Option<Asset>,
+    range: Option<(usize, usize)>,
+}
+
+enum Step {
+    Start(ResponseVariant),
+    Body(ResumeToken<SEND_BODY, GET, BODY_LENGTH>, &'static [u8]),
+    Ended,
+}
+
+impl AssetResponse {
+    /// The status of the response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Write the next part of the response into `buf`.
+    ///
+    /// The first call writes the head and as much of the body as fits. Write
+    /// the output to the transport and call again until [`is_finished()`].
+    ///
+    /// [`is_finished()`]: AssetResponse::is_finished
+    pub fn write<'b>(&mut self, buf: &'b mut [u8]) -> Result<&'b [u8]> {
+        let len = match mem::replace(&mut self.step, Step::Ended) {
+            Step::Start(variant) => self.write_start(variant, buf)?,
+            Step::Body(token, rest) => self.write_body(Response::resume(token, buf), rest)?,
+            Step::Ended => 0,
+        };
+
+        Ok(&buf[..len])
+    }
+
+    /// Whether the entire response is written.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.step, Step::Ended)
+    }
+
+    fn write_start(&mut self, variant: ResponseVariant, buf: &mut [u8]) -> Result<usize> {
+        let body = match (&self.asset, self.status, self.range) {
+            (Some(a), 200, _) => a.body,
+            // 206 is only for a range.
+            (Some(a), 206, Some((start, end))) => a.body.get(start..end).unwrap_or(&[]),
+            _ => &[],
+        };
+
+        let res = match variant {
+            ResponseVariant::Get(t) => {
+                let res = self.write_head(t, buf)?;
+                if self.status == 304 {
+                    res.without_body()?
+                } else {
+                    return self.write_body(res.with_body(body.len())?, body);
+                }
+            }
+            ResponseVariant::Head(t) => self.write_head(t, buf)?.send()?,
+            ResponseVariant::Post(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Put(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Delete(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Connect(t) => self.write_head(t, buf)?.send()?,
+            ResponseVariant::Options(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Trace(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Patch(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Query(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Propfind(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Proppatch(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Mkcol(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Copy(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Move(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Lock(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Unlock(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+            ResponseVariant::Extension(t) => self.write_head(t, buf)?.with_body(0)?.finish()?,
+        };
+
+        Ok(res.flush().len())
+    }
+
+    fn write_head<'b, M: Method>(
+        &self,
+        token: ResumeToken<SEND_STATUS, M, ()>,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b, SEND_HEADERS, M, ()>> {
+        let status = self.status;
+        let mut res = Response::resume(token, buf).send_status(status, reason_phrase(status))?;
+
+        if status == 405 {
+            return res.header("Allow", "GET, HEAD");
+        }
+
+        let a = match &self.asset {
+            Some(a) => a,
+            None => return Ok(res),
+        };
+
+        if matches!(status, 200 | 206 | 304) {
+            res = res.header("ETag", a.etag)?;
+        }
+        if matches!(status, 200 | 206) {
+            res = res
+                .header("Content-Type", a.content_type)?
+                .header("Accept-Ranges", "bytes")?;
+        }
+
+        let mut range = [0; 64];
+        let mut out = Out::wrap(&mut range);
+        let mut w = out.writer();
+        match (status, self.range) {
+            (206, Some((start, end))) => write!(w, "bytes {}-{}/{}", start, end - 1, a.body.len())?,
+            (416, _) => write!(w, "bytes */{}", a.body.len())?,
+            _ => return Ok(res),
+        }
+        w.commit();
+
+        res.header_bytes("Content-Range", out.into_inner())
+    }
+
+    fn write_body(
+        &mut self,
+        mut res: Response<'_, SEND_BODY, GET, BODY_LENGTH>,
+        body: &'static [u8],
+    ) -> Result<usize> {
+        let res = match res.write_bytes(body) {
+            Ok(()) => res.finish()?,
+            Err(HootError::OutputFull(n)) => {
+                let output = res.flush();
+                let len = output.len();
+                self.step = Step::Body(output.ready(), &body[n..]);
+                return Ok(len);
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(res.flush().len())
+    }
+}
+
+/// Whether an `If-None-Match` list matches `etag`, using weak comparison.
+fn etag_matches(list: &str, etag: &str) -> bool {
+    fn weak(t: &str) -> &str {
+        t.trim().trim_start_matches("W/")
+    }
+    list.trim() == "*" || list.split(',').any(|t| weak(t) == weak(etag))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Range {
+    /// Start and (exclusive) end in the body.
+    Bytes(usize, usize),
+    Unsatisfiable,
+    /// Not a single byte range, served as the entire body.
+    Ignore,
+}
+
+/// Parse a `Range` header for a body of `len`. Only single ranges are used.
+fn parse_range(value: &str, len: usize) -> Range {
+    let spec = match value.trim().split_once('=') {
+        Some((unit, spec)) if unit.eq_ignore_ascii_case("bytes") && !spec.contains(',') => spec,
+        _ => return Range::Ignore,
+    };
+
+    let (first, last) = match spec.trim().split_once('-') {
+        Some(v) => v,
+        None => return Range::Ignore,
+    };
+
+    let num = |s: &str| -> Option<usize> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        // Numbers too large for usize are beyond any body.
+        Some(s.parse().unwrap_or(usize::MAX))
+    };
+
+    match (num(first), num(last), first.is_empty()) {
+        // A suffix, such as -500 for the last 500 bytes.
+        (None, Some(n), true) if n > 0 && len > 0 => Range::Bytes(len - n.min(len), len),
+        (None, Some(_), true) => Range::Unsatisfiable,
+        (Some(start), _, _) if start >= len => Range::Unsatisfiable,
+        (Some(start), None, _) if last.is_empty() => Range::Bytes(start, len),
+        (Some(start), Some(end), _) if start <= end => {
+            Range::Bytes(start, end.saturating_add(1).min(len))
+        }
+        _ => Range::Ignore,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::Request;
+    use crate::util::HeaderBuf;
+
+    static ASSETS: Assets = Assets::new(&[
+        Asset {
+            path: "/app.js",
+            content_type: "text/javascript",
+            body: b"console.log(1)",
+            etag: "\"abc\"",
+        },
+        crate::asset!("/big", "text/plain", &[b'a'; 1000]),
+    ]);
+
+    fn serve(input: &[u8], out: &mut [u8]) -> Result<usize> {
+        let mut buf = HeaderBuf::<16>::new();
+        let mut request = Request::new();
+        let attempt = request.try_read_request(input, &mut buf)?;
+        let mut response = ASSETS.serve(&attempt, request.proceed().into_response()?);
+
+        // Smaller than the big asset, to write it in several parts.
+        let mut chunk = [0; 512];
+        let mut len = 0;
+        while !response.is_finished() {
+            let output = response.write(&mut chunk)?;
+            out[len..(len + output.len())].copy_from_slice(output);
+            len += output.len();
+        }
+        Ok(len)
+    }
+
+    #[test]
+    fn etag_of_body() {
+        assert_eq!(Etag::of(b"").as_str(), "\"cbf29ce484222325\"");
+        assert_ne!(Etag::of(b"a"), Etag::of(b"b"));
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(parse_range("bytes=0-3", 10), Range::Bytes(0, 4));
+        assert_eq!(parse_range("bytes=5-", 10), Range::Bytes(5, 10));
+        assert_eq!(parse_range("bytes=-3", 10), Range::Bytes(7, 10));
+        assert_eq!(parse_range("bytes=-30", 10), Range::Bytes(0, 10));
+        assert_eq!(
+            parse_range("bytes=8-99999999999999999999999", 10),
+            Range::Bytes(8, 10)
+        );
+        assert_eq!(parse_range("bytes=10-", 10), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=3-1", 10), Range::Ignore);
+        assert_eq!(parse_range("bytes=0-1,3-4", 10), Range::Ignore);
+        assert_eq!(parse_range("items=0-1", 10), Range::Ignore);
+        assert_eq!(parse_range("bytes=a-1", 10), Range::Ignore);
+    }
+
+    #[test]
+    fn serve_assets() -> Result<()> {
+        let mut out = [0; 2048];
+
+        let n = serve(b"GET /big HTTP/1.1\r\nHost: x\r\n\r\n", &mut out)?;
+        let head = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert!(out[..head].ends_with(b"Content-Length: 1000\r\n\r\n"));
+        assert_eq!(&out[head..n], &[b'a'; 1000]);
+
+        let n = serve(b"GET /app.js?v=2 HTTP/1.1\r\nHost: x\r\n\r\n", &mut out)?;
+        assert_eq!(
+            &out[..n],
+            b"HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Type: text/javascript\r\n\
+            Accept-Ranges: bytes\r\nContent-Length: 14\r\n\r\nconsole.log(1)"
+        );
+
+        let n = serve(
+            b"GET /app.js HTTP/1.1\r\nHost: x\r\nIf-None-Match: \"x\", W/\"abc\"\r\n\r\n",
+            &mut out,
+        )?;
+        assert_eq!(
+            &out[..n],
+            b"HTTP/1.1 304 Not Modified\r\nETag: \"abc\"\r\n\r\n"
+        );
+
+        let n = serve(
+            b"GET /app.js HTTP/1.1\r\nHost: x\r\nRange: bytes=99-\r\n\r\n",
+            &mut out,
+        )?;
+        assert!(out[..n].starts_with(b"HTTP/1.1 416 Range Not Satisfiable\r\n"));
+        assert!(out[..n].ends_with(b"Content-Range: bytes */14\r\nContent-Length: 0\r\n\r\n"));
+
+        // A stale If-Range gets the entire body.
+        let n = serve(
+            b"GET /app.js HTTP/1.1\r\nHost: x\r\nRange: bytes=0-1\r\nIf-Range: \"old\"\r\n\r\n",
+            &mut out,
+        )?;
+        assert!(out[..n].starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        let n = serve(b"HEAD /app.js HTTP/1.1\r\nHost: x\r\n\r\n", &mut out)?;
+        assert!(out[..n].ends_with(b"Accept-Ranges: bytes\r\n\r\n"));
+
+        let n = serve(b"GET /nope HTTP/1.1\r\nHost: x\r\n\r\n", &mut out)?;
+        assert_eq!(
+            &out[..n],
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        );
+
+        let n = serve(b"DELETE /app.js HTTP/1.1\r\nHost: x\r\n\r\n", &mut out)?;
+        assert_eq!(
+            &out[..n],
+            b"HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, HEAD\r\nContent-Length: 0\r\n\r\n"
+        );
+
+        Ok(())
+    }
+}
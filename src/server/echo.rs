@@ -0,0 +1,214 @@
+//! Diagnostic echo of the raw request.
+//!
+//! [`Echo`] captures the request head and body exactly as received, up to the
+//! size of a caller provided buffer, and renders it as a response body. This
+//! is like httpbin's `/anything`, but without any dependencies beyond hoot,
+//! which makes it possible to check what a device actually sends using only
+//! the device itself.
+
+use crate::out::{Out, Writer};
+use crate::Result;
+
+/// Output format of [`Echo::render`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchoFormat {
+    /// The raw bytes separated by section markers.
+    Text,
+    /// A JSON object. Non-ASCII bytes are escaped as `\u00XX`.
+    Json,
+}
+
+impl EchoFormat {
+    /// Value for the `Content-Type` header of the rendered response.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            EchoFormat::Text => "text/plain",
+            EchoFormat::Json => "application/json",
+        }
+    }
+}
+
+/// Captures a raw request to reflect it back.
+pub struct Echo<'a> {
+    buf: &'a mut [u8],
+    head_len: usize,
+    body_len: usize,
+    truncated: bool,
+}
+
+impl<'a> Echo<'a> {
+    /// Create a new capture. The size of `buf` is the cap for head and body.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Echo {
+            buf,
+            head_len: 0,
+            body_len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Capture (part of) the request head.
+    ///
+    /// This is typically `&input[..attempt.input_used()]` after a successful
+    /// `try_read_request()`. The head must be captured before any body, once
+    /// there is a body this is ignored.
+    pub fn capture_head(&mut self, bytes: &[u8]) {
+        if self.body_len > 0 {
+            return;
+        }
+        self.head_len += self.append(bytes);
+    }
+
+    /// Capture (part of) the request body.
+    ///
+    /// To see the body byte-exact, including any chunk framing, this should be
+    /// `&input[..part.input_used()]` rather than the decoded [`BodyPart`].
+    ///
+    /// [`BodyPart`]: crate::BodyPart
+    pub fn capture_body(&mut self, bytes: &[u8]) {
+        self.body_len += self.append(bytes);
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> usize {
+        let pos = self.head_len + self.body_len;
+        let n = bytes.len().min(self.buf.len() - pos);
+
+        if n < bytes.len() {
+            self.truncated = true;
+        }
+
+        self.buf[pos..(pos + n)].copy_from_slice(&bytes[..n]);
+        n
+    }
+
+    /// The captured head.
+    pub fn head(&self) -> &[u8] {
+        &self.buf[..self.head_len]
+    }
+
+    /// The captured body.
+    pub fn body(&self) -> &[u8] {
+        &self.buf[self.head_len..(self.head_len + self.body_len)]
+    }
+
+    /// Whether input was dropped because the capture buffer is full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Render the captured request into `dst`, returns the length.
+    ///
+    /// The result is typically sent using `with_body()` of a server
+    /// [`Response`](super::Response).
+    pub fn render(&self, format: EchoFormat, dst: &mut [u8]) -> Result<usize> {
+        let mut out = Out::wrap(dst);
+        let mut w = out.writer();
+
+        match format {
+            EchoFormat::Text => {
+                write!(w, "--- head ({} bytes) ---\r\n", self.head_len)?;
+                w.write_bytes(self.head())?;
+                write!(w, "--- body ({} bytes) ---\r\n", self.body_len)?;
+                w.write_bytes(self.body())?;
+                if self.truncated {
+                    write!(w, "\r\n--- truncated ---\r\n")?;
+                }
+            }
+            EchoFormat::Json => {
+                write!(w, "{{\"head\":\"")?;
+                write_json_escaped(&mut w, self.head())?;
+                write!(w, "\",\"body\":\"")?;
+                write_json_escaped(&mut w, self.body())?;
+                write!(
+                    w,
+                    "\",\"head_len\":{},\"body_len\":{},\"truncated\":{}}}",
+                    self.head_len, self.body_len, self.truncated
+                )?;
+            }
+        }
+
+        w.commit();
+        Ok(out.into_inner().len())
+    }
+}
+
+fn write_json_escaped(w: &mut Writer<'_, '_>, bytes: &[u8]) -> Result<()> {
+    for b in bytes {
+        match b {
+            b'"' => write!(w, "\\\"")?,
+            b'\\' => write!(w, "\\\\")?,
+            b'\r' => write!(w, "\\r")?,
+            b'\n' => write!(w, "\\n")?,
+            b'\t' => write!(w, "\\t")?,
+            0x20..=0x7e => write!(w, "{}", *b as char)?,
+            _ => write!(w, "\\u{:04x}", b)?,
+        }
+    }
+    Ok(())
+}
+
+impl<'a> core::fmt::Debug for Echo<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Echo")
+            .field("head_len", &self.head_len)
+            .field("body_len", &self.body_len)
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::HootError;
+
+    #[test]
+    fn echo_text() {
+        let mut buf = [0; 64];
+        let mut echo = Echo::new(&mut buf);
+        echo.capture_head(b"POST / HTTP/1.1\r\n\r\n");
+        echo.capture_body(b"hello");
+
+        let mut dst = [0; 256];
+        let n = echo.render(EchoFormat::Text, &mut dst).unwrap();
+        assert_eq!(
+            &dst[..n],
+            &b"--- head (19 bytes) ---\r\nPOST / HTTP/1.1\r\n\r\n\
+            --- body (5 bytes) ---\r\nhello"[..]
+        );
+    }
+
+    #[test]
+    fn echo_json() {
+        let mut buf = [0; 64];
+        let mut echo = Echo::new(&mut buf);
+        echo.capture_head(b"GET / HTTP/1.0\r\nx: \"\xff\"\r\n\r\n");
+
+        let mut dst = [0; 256];
+        let n = echo.render(EchoFormat::Json, &mut dst).unwrap();
+        assert_eq!(
+            &dst[..n],
+            &br#"{"head":"GET / HTTP/1.0\r\nx: \"\u00ff\"\r\n\r\n","body":"","head_len":26,"body_len":0,"truncated":false}"#[..]
+        );
+    }
+
+    #[test]
+    fn echo_truncated() {
+        let mut buf = [0; 8];
+        let mut echo = Echo::new(&mut buf);
+        echo.capture_head(b"GET / ");
+        echo.capture_body(b"abcdef");
+
+        assert_eq!(echo.head(), b"GET / ");
+        assert_eq!(echo.body(), b"ab");
+        assert!(echo.is_truncated());
+
+        // Head after body is ignored.
+        echo.capture_head(b"x");
+        assert_eq!(echo.head(), b"GET / ");
+
+        let mut dst = [0; 8];
+        let err = echo.render(EchoFormat::Text, &mut dst).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 16 });
+    }
+}
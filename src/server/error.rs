@@ -0,0 +1,91 @@
+use crate::out::Out;
+use crate::{HootError, Result};
+
+use super::res::reason_phrase;
+
+/// Write a minimal response for a request that failed with `error`.
+///
+/// The status is [`HootError::suggested_status()`], or 500 for errors not
+/// caused by the request. The response has no body and closes the connection,
+/// since the rest of the input can not be trusted to be framed correctly.
+///
+/// ```
+/// use hoot::server::{error_response, Request};
+/// use hoot::util::HeaderBuf;
+///
+/// let mut buf = HeaderBuf::<16>::new();
+/// let mut request = Request::new();
+/// let err = request
+///     .try_read_request(b"GET / HTTP/2.0\r\n\r\n", &mut buf)
+///     .map(|_| ())
+///     .unwrap_err();
+///
+/// let mut out = [0; 128];
+/// let output = error_response(&err, &mut out)?;
+///
+/// assert_eq!(
+///     output,
+///     b"HTTP/1.1 505 HTTP Version Not Supported\r\n\
+///     Content-Length: 0\r\nConnection: close\r\n\r\n"
+/// );
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub fn error_response<'b>(error: &HootError, buf: &'b mut [u8]) -> Result<&'b [u8]> {
+    let status = error.suggested_status().unwrap_or(500);
+
+    trace!("Error response {} for: {}", status, error);
+
+    let mut out = Out::wrap(buf);
+    let mut w = out.writer();
+    write!(
+        w,
+        "HTTP/1.1 {} {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status,
+        reason_phrase(status)
+    )?;
+    w.commit();
+
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::Request;
+    use crate::util::HeaderBuf;
+    use crate::Limits;
+
+    #[test]
+    fn error_responses() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        let mut out = [0; 128];
+
+        let mut request = Request::new();
+        request.set_limits(Limits {
+            max_headers: 1,
+            ..Default::default()
+        });
+        let err = request
+            .try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\nA: 1\r\n\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.suggested_status(), Some(431));
+        assert!(error_response(&err, &mut out)?
+            .starts_with(b"HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        let mut request = Request::new();
+        let err = request
+            .try_read_request(b"BREW / HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.suggested_status(), Some(501));
+
+        let output = error_response(&HootError::OutputOverflow { needed: 1 }, &mut out)?;
+        assert!(output.starts_with(b"HTTP/1.1 500 Internal Server Error\r\n"));
+
+        let err = error_response(&err, &mut out[..10]).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 60 });
+
+        Ok(())
+    }
+}
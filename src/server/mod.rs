@@ -4,6 +4,7 @@
 //!
 //! ```
 //! use hoot::server::{Request, Response, ResponseVariant};
+//! use hoot::util::HeaderBuf;
 //! use hoot::{Method, HttpVersion};
 //!
 //! let mut buf = [0; 1024];
@@ -13,16 +14,17 @@
 //! let mut request = Request::new();
 //!
 //! // Try read incomplete input. The provided buffer is required
-//! // to parse request headers.
+//! // to parse request headers, it holds up to 16 of them.
+//! let mut headers = HeaderBuf::<16>::new();
 //! let attempt =
-//!     request.try_read_request(b"GET /path HTTP/1.", &mut buf)?;
+//!     request.try_read_request(b"GET /path HTTP/1.", &mut headers)?;
 //! assert!(!attempt.is_success());
 //!
 //! const COMPLETE: &[u8] =
 //!     b"GET /path HTTP/1.1\r\nHost: foo\r\nContent-Length: 10\r\n\r\n";
 //!
 //! // Try read complete input (and succeed). Borrow the buffer again.
-//! let attempt = request.try_read_request(COMPLETE, &mut buf)?;
+//! let attempt = request.try_read_request(COMPLETE, &mut headers)?;
 //! assert!(attempt.is_success());
 //!
 //! // Read request line information.
@@ -34,9 +36,9 @@
 //! // Read headers.
 //! let headers = attempt.headers().unwrap();
 //! assert_eq!(headers[0].name(), "Host");
-//! assert_eq!(headers[0].value(), "foo");
+//! assert_eq!(headers[0].value_str()?, "foo");
 //! assert_eq!(headers[1].name(), "Content-Length");
-//! assert_eq!(headers[1].value(), "10");
+//! assert_eq!(headers[1].value_str()?, "10");
 //!
 //! // Proceed to reading request body.
 //! let request = request.proceed();
@@ -62,6 +64,15 @@
 //!     ResponseVariant::Options(_) => todo!(),
 //!     ResponseVariant::Trace(_) => todo!(),
 //!     ResponseVariant::Patch(_) => todo!(),
+//!     ResponseVariant::Query(_) => todo!(),
+//!     ResponseVariant::Propfind(_) => todo!(),
+//!     ResponseVariant::Proppatch(_) => todo!(),
+//!     ResponseVariant::Mkcol(_) => todo!(),
+//!     ResponseVariant::Copy(_) => todo!(),
+//!     ResponseVariant::Move(_) => todo!(),
+//!     ResponseVariant::Lock(_) => todo!(),
+//!     ResponseVariant::Unlock(_) => todo!(),
+//!     ResponseVariant::Extension(_) => todo!(),
 //! };
 //!
 //! let response = Response::resume(token, &mut buf);
@@ -98,7 +109,28 @@
 //! ```
 
 mod req;
-pub use req::{Line, Request};
+pub use req::{Line, Request, RequestAttempt};
 
 mod res;
-pub use res::{Response, ResponseVariant, ResumeToken};
+pub use res::{Output, Response, ResponseVariant, ResumeToken};
+
+mod error;
+pub use error::error_response;
+
+mod sniff;
+pub use sniff::{sniff, Sniff};
+
+#[cfg(feature = "reply")]
+mod reply;
+#[cfg(feature = "reply")]
+pub use reply::{reply, Reply, MAX_REPLY_HEADERS};
+
+#[cfg(feature = "echo")]
+mod echo;
+#[cfg(feature = "echo")]
+pub use echo::{Echo, EchoFormat};
+
+#[cfg(feature = "assets")]
+mod assets;
+#[cfg(feature = "assets")]
+pub use assets::{Asset, AssetResponse, Assets, Etag, ETAG_LEN};
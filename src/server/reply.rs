@@ -0,0 +1,282 @@
+use crate::types::state::*;
+use crate::types::*;
+use crate::{HootError, Result};
+
+use super::res::reason_phrase;
+use super::{Output, Response, ResponseVariant, ResumeToken};
+
+/// Max number of headers a [`Reply`] can hold.
+pub const MAX_REPLY_HEADERS: usize = 16;
+
+/// Start a high level reply.
+///
+/// This collapses the type state steps of [`Response`] for the common case
+/// of sending a status, some headers and a body in one go.
+///
+/// ```
+/// use hoot::server::{reply, Request};
+/// use hoot::util::HeaderBuf;
+///
+/// let mut buf = [0; 1024];
+/// let mut headers = HeaderBuf::<16>::new();
+///
+/// let mut request = Request::new();
+/// request.try_read_request(b"GET / HTTP/1.1\r\nHost: foo\r\n\r\n", &mut headers)?;
+/// let variant = request.proceed().into_response()?;
+///
+/// let output = reply(200)
+///     .header("content-type", "text/plain")
+///     .body(b"hello")
+///     .send(variant, &mut buf)?;
+///
+/// assert_eq!(
+///     &*output,
+///     b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\n\
+///     Content-Length: 5\r\n\r\nhello"
+/// );
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub fn reply<'a>(status: u16) -> Reply<'a> {
+    Reply {
+        status,
+        text: reason_phrase(status),
+        headers: [("", ""); MAX_REPLY_HEADERS],
+        header_count: 0,
+        body: ReplyBody::Empty,
+    }
+}
+
+/// Builder for a response. See [`reply()`].
+pub struct Reply<'a> {
+    status: u16,
+    text: &'a str,
+    headers: [(&'a str, &'a str); MAX_REPLY_HEADERS],
+    header_count: usize,
+    body: ReplyBody<'a>,
+}
+
+enum ReplyBody<'a> {
+    Empty,
+    Bytes(&'a [u8]),
+    Chunked(&'a mut dyn FnMut(&mut [u8]) -> Result<usize>),
+}
+
+impl<'a> Reply<'a> {
+    /// Override the reason phrase of the status line.
+    pub fn text(mut self, text: &'a str) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Add a header.
+    ///
+    /// The header is validated when sending. Adding more than
+    /// [`MAX_REPLY_HEADERS`] results in `HootError::TooManyHeaders` on send.
+    pub fn header(mut self, name: &'a str, value: &'a str) -> Self {
+        if self.header_count < MAX_REPLY_HEADERS {
+            self.headers[self.header_count] = (name, value);
+        }
+        // Count also overflowing headers to be able to error on send.
+        self.header_count += 1;
+        self
+    }
+
+    /// Send a fixed body with a `Content-Length`.
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = ReplyBody::Bytes(body);
+        self
+    }
+
+    /// Send a chunked body produced by `f`.
+    ///
+    /// `f` is called repeatedly to write the next chunk into the provided
+    /// buffer and returns how much it wrote. Returning 0 ends the body. The
+    /// entire body must fit in the buffer given to [`Reply::send`].
+    pub fn chunked(mut self, f: &'a mut dyn FnMut(&mut [u8]) -> Result<usize>) -> Self {
+        self.body = ReplyBody::Chunked(f);
+        self
+    }
+
+    /// Write the response into `buf`.
+    ///
+    /// For `HEAD` and `CONNECT` requests, any body is ignored.
+    pub fn send(
+        mut self,
+        variant: ResponseVariant,
+        buf: &mut [u8],
+    ) -> Result<Output<'_, ENDED, (), ()>> {
+        if self.header_count > MAX_REPLY_HEADERS {
+            return Err(HootError::TooManyHeaders);
+        }
+
+        trace!("Reply {} {}", self.status, self.text);
+
+        let res = match variant {
+            ResponseVariant::Get(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Head(t) => self.send_without_body(t, buf)?,
+            ResponseVariant::Post(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Put(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Delete(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Connect(t) => self.send_without_body(t, buf)?,
+            ResponseVariant::Options(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Trace(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Patch(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Query(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Propfind(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Proppatch(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Mkcol(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Copy(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Move(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Lock(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Unlock(t) => self.send_with_body(t, buf)?,
+            ResponseVariant::Extension(t) => self.send_with_body(t, buf)?,
+        };
+
+        Ok(res.flush())
+    }
+
+    fn send_head<'b, M: Method>(
+        &self,
+        token: ResumeToken<SEND_STATUS, M, ()>,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b, SEND_HEADERS, M, ()>> {
+        let mut res = Response::resume(token, buf).send_status(self.status, self.text)?;
+
+        for (name, value) in &self.headers[..self.header_count] {
+            res = res.header(name, value)?;
+        }
+
+        Ok(res)
+    }
+
+    fn send_with_body<'b, M: MethodWithResponseBody>(
+        &mut self,
+        token: ResumeToken<SEND_STATUS, M, ()>,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b, ENDED, (), ()>> {
+        let res = self.send_head(token, buf)?;
+
+        match &mut self.body {
+            ReplyBody::Empty => res.with_body(0)?.finish(),
+            ReplyBody::Bytes(b) => {
+                let mut res = res.with_body(b.len())?;
+                res.write_bytes(b)?;
+                res.finish()
+            }
+            ReplyBody::Chunked(f) => {
+                let mut res = res.with_chunked()?;
+
+                loop {
+                    let mut ended = false;
+
+                    let n = res.write_chunk_from(|b| {
+                        let n = f(b)?;
+                        ended = n == 0;
+                        Ok(n)
+                    })?;
+
+                    if ended {
+                        break;
+                    }
+
+                    if n == 0 {
+                        // Not enough space left for another chunk.
+                        return Err(HootError::OutputOverflow { needed: 1 });
+                    }
+                }
+
+                res.finish()
+            }
+        }
+    }
+
+    fn send_without_body<'b, M: MethodWithoutResponseBody>(
+        &mut self,
+        token: ResumeToken<SEND_STATUS, M, ()>,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b, ENDED, (), ()>> {
+        self.send_head(token, buf)?.send()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::Request;
+    use crate::util::HeaderBuf;
+
+    fn variant(input: &[u8]) -> ResponseVariant {
+        let mut buf = HeaderBuf::<16>::new();
+        let mut request = Request::new();
+        let attempt = request.try_read_request(input, &mut buf).unwrap();
+        assert!(attempt.is_success());
+        request.proceed().into_response().unwrap()
+    }
+
+    #[test]
+    fn reply_empty() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let v = variant(b"GET / HTTP/1.0\r\n\r\n");
+        let output = reply(404).send(v, &mut buf)?;
+        assert_eq!(
+            output.as_bytes(),
+            b"HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reply_head() -> Result<()> {
+        let mut buf = [0; 1024];
+
+        let v = variant(b"HEAD / HTTP/1.1\r\nHost: foo\r\n\r\n");
+        let output = reply(299).body(b"ignored").send(v, &mut buf)?;
+        assert_eq!(output.as_bytes(), b"HTTP/1.1 299 \r\n\r\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn reply_chunked() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut parts: &[&[u8]] = &[b"hello", b" world"];
+
+        let mut f = |b: &mut [u8]| {
+            let n = match parts.split_first() {
+                Some((p, rest)) => {
+                    b[..p.len()].copy_from_slice(p);
+                    parts = rest;
+                    p.len()
+                }
+                None => 0,
+            };
+            Ok(n)
+        };
+
+        let v = variant(b"GET / HTTP/1.1\r\nHost: foo\r\n\r\n");
+        let output = reply(200).chunked(&mut f).send(v, &mut buf)?;
+        assert_eq!(
+            output.as_bytes(),
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reply_chunked_overflow() {
+        let mut buf = [0; 128];
+        let mut f = |b: &mut [u8]| Ok(b.len());
+
+        let v = variant(b"GET / HTTP/1.1\r\nHost: foo\r\n\r\n");
+        let err = reply(200)
+            .chunked(&mut f)
+            .send(v, &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 1 });
+    }
+}
@@ -1,14 +1,31 @@
+use core::fmt;
 use core::marker::PhantomData;
 use core::mem;
 
-use crate::body::{do_read_body, RecvBodyMode};
+use crate::body::{
+    bytes_remaining, chunk_remaining, do_read_body, do_read_body_transformed, do_read_trailers,
+    read_into_sink, RecvBodyMode,
+};
+use crate::client::Protocols;
 use crate::error::Result;
-use crate::header::transmute_headers;
+use crate::out::Out;
+use crate::parser::ParseMode;
+use crate::parser::{check_head_limits, check_target_limit, limit_header_count};
+use crate::strict::check_framing;
 use crate::types::state::*;
 use crate::types::*;
-use crate::util::{cast_buf_for_headers, LengthChecker};
-use crate::{BodyPart, CallState};
-use crate::{Header, HootError, HttpVersion, Method};
+use crate::util::{
+    compare_lowercase_ascii, limit_header_buf, LengthChecker, EMPTY_HEADER, MAX_HEADERS,
+};
+use crate::{h2c, H2cHandoff};
+use crate::{
+    BodyHasher, BodySink, ConnectionOptions, Deadline, ExtMethod, Header, HootError, HttpVersion,
+    Limit, Limits, Method, RequestTarget,
+};
+use crate::{
+    BodyPart, BodyTransform, CallState, ContentEncoding, ContentLengthPolicy, ExcessBodyPolicy,
+    RequestBodyPolicy, Trailers, TransferCodings,
+};
 
 use super::res::ResponseVariant;
 
@@ -28,6 +45,8 @@ impl Request<()> {
 
 impl<S: State> Request<S> {
     fn transition<S2: State>(self) -> Request<S2> {
+        trace!("Transition: {} -> {}", S::state_name(), S2::state_name());
+
         // SAFETY: this only changes the type state of the PhantomData
         unsafe { mem::transmute(self) }
     }
@@ -35,9 +54,9 @@ impl<S: State> Request<S> {
     fn do_try_read_request<'a, 'b>(
         &mut self,
         input: &'a [u8],
-        buf: &'b mut [u8],
+        buf: &'b mut [Header<'a>],
     ) -> Result<RequestAttempt<'a, 'b>> {
-        trace!("Try read request");
+        trace!("Try read request: {} bytes", input.len());
 
         let already_read_request = self.state.recv_body_mode.is_some();
 
@@ -46,74 +65,204 @@ impl<S: State> Request<S> {
             return Ok(RequestAttempt::empty());
         }
 
-        let headers = cast_buf_for_headers(buf);
-        let mut r = httparse::Request::new(headers);
+        let limits = self.state.limits;
+        let (headers, count_limited) = limit_header_count(limit_header_buf(buf), &limits);
+
+        let mode = self.state.parse_mode;
+
+        // Headers are taken from the scan once the head is complete, see HeadScan.
+        let head = self
+            .state
+            .head_scan
+            .scan(input, mode, false, headers.len())
+            .and_then(|complete| {
+                if complete {
+                    self.state.head_scan.request(input, headers).map(Some)
+                } else {
+                    Ok(None)
+                }
+            });
 
-        let input_used = match r.parse(input)? {
-            httparse::Status::Complete(v) => v,
-            httparse::Status::Partial => {
-                trace!("Read partial request");
+        let head = match head {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                trace!(
+                    "Read partial request, scanned: {}",
+                    self.state.head_scan.scanned()
+                );
+                check_target_limit(input, &limits)?;
+                check_head_limits(input, None, &limits)?;
+                self.state.deadline.check_head(false)?;
+                #[cfg(feature = "metrics")]
+                crate::metrics::record(self.state.metrics_id, crate::metrics::Event::ParseRetry);
                 return Ok(RequestAttempt::empty());
             }
+            Err(HootError::TooManyHeaders) if count_limited => {
+                return Err(HootError::LimitExceeded {
+                    which: Limit::HeaderCount,
+                })
+            }
+            Err(e) => {
+                debug!("Read request failed: {}", e);
+                return Err(e);
+            }
         };
 
-        let method: Method = r.method.unwrap().try_into()?;
-        self.state.method = Some(method);
+        let input_used = head.len;
 
-        let path = r.path.unwrap();
+        check_target_limit(input, &limits)?;
+        check_head_limits(input, Some(input_used), &limits)?;
+        self.state.deadline.check_head(true)?;
 
-        let ver = match r.version.unwrap() {
-            0 => HttpVersion::Http10,
-            1 => HttpVersion::Http11,
-            _ => return Err(HootError::Version),
+        let method = match Method::try_from(head.line.method) {
+            Err(HootError::UnknownMethod) if self.state.extension_methods.is_some() => {
+                Method::Extension(ExtMethod::new(head.line.method)?)
+            }
+            r => r?,
         };
+        self.state.method = Some(method);
+
+        let path = head.line.target;
+
+        let ver = head.line.version;
         self.state.version = Some(ver);
 
         trace!("Read complete request: {:?} {} {:?}", method, path, ver);
 
-        let line = Line(method, path, ver);
+        // Offsets in the target are made relative to the input.
+        let target_start = path.as_ptr() as usize - input.as_ptr() as usize;
+        let target = RequestTarget::parse(method, path).map_err(|e| match e {
+            HootError::InvalidTarget { offset } => HootError::InvalidTarget {
+                offset: target_start + offset,
+            },
+            e => e,
+        })?;
+
+        let line = Line(method, target, ver);
 
         // Derive body mode from knowledge this far.
         let http10 = ver == HttpVersion::Http10;
-        let headers = transmute_headers(r.headers);
+        let headers = head.headers;
         trace!("Headers: {:?}", headers);
 
-        let mode = RecvBodyMode::for_request(http10, method, headers)?;
+        if self.state.parse_mode.is_strict() {
+            check_framing(headers)?;
+        }
+
+        check_host(ver, headers)?;
+
+        let expect_continue = !http10 && has_expect_continue(headers);
+        self.state.expect_continue = expect_continue;
+
+        let has_body = match (self.state.has_request_body, method) {
+            (Some(f), _) => f(method),
+            (None, Method::Extension(_)) => self.state.extension_methods == Some(true),
+            (None, _) => method.has_request_body(),
+        };
+
+        let mode = RecvBodyMode::for_request(
+            http10,
+            has_body,
+            headers,
+            self.state.content_length_policy,
+            self.state.request_body_policy,
+        )?;
         self.state.recv_body_mode = Some(mode);
         trace!("Body mode: {:?}", mode);
 
+        let encoding = ContentEncoding::from_headers(headers);
+        self.state.content_encoding = encoding;
+
+        let transfer_codings = TransferCodings::from_headers(headers)?;
+        self.state.transfer_codings = transfer_codings;
+
+        if let Some(max) = self.state.max_recv_body {
+            if mode.exceeds(max, false) {
+                debug!("Request body too large: {:?} max {}", mode, max);
+                self.state.recv_too_large = true;
+            }
+        }
+
         // If we are awaiting a length, put a length checker in place
         if let RecvBodyMode::LengthDelimited(len) = mode {
             trace!("Set body length checker: {}", len);
             self.state.recv_checker = Some(LengthChecker::new(len));
         }
 
+        #[cfg(feature = "metrics")]
+        crate::metrics::head_recv(&self.state, input_used);
+
         Ok(RequestAttempt {
             input_used,
             line: Some(line),
             headers: Some(headers),
+            content_encoding: encoding,
+            transfer_codings,
+            body_mode: Some(mode),
+            expect_continue,
+            too_large: self.state.recv_too_large,
         })
     }
 }
 
+/// HTTP/1.1 requests must have exactly one `Host` header, RFC 7230 §5.4.
+fn check_host(ver: HttpVersion, headers: &[Header<'_>]) -> Result<()> {
+    let count = headers
+        .iter()
+        .filter(|h| compare_lowercase_ascii(h.name(), "host"))
+        .count();
+
+    match count {
+        0 if ver == HttpVersion::Http11 => Err(HootError::MissingHost),
+        0 | 1 => Ok(()),
+        _ => Err(HootError::DuplicateHost),
+    }
+}
+
+/// `Expect: 100-continue` is only defined for HTTP/1.1, RFC 7231 §5.1.1.
+fn has_expect_continue(headers: &[Header<'_>]) -> bool {
+    headers.iter().any(|h| {
+        compare_lowercase_ascii(h.name(), "expect")
+            && h.value_raw().eq_ignore_ascii_case(b"100-continue")
+    })
+}
+
 pub struct RequestAttempt<'a, 'b> {
     input_used: usize,
     line: Option<Line<'a>>,
     headers: Option<&'b [Header<'a>]>,
+    content_encoding: Option<ContentEncoding>,
+    transfer_codings: TransferCodings,
+    body_mode: Option<RecvBodyMode>,
+    expect_continue: bool,
+    too_large: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Line<'a>(Method, &'a str, HttpVersion);
+pub struct Line<'a>(Method, RequestTarget<'a>, HttpVersion);
 
-impl Line<'_> {
+impl<'a> Line<'a> {
     pub fn method(&self) -> Method {
         self.0
     }
 
     pub fn path(&self) -> &str {
+        self.1.as_str()
+    }
+
+    /// The request target and its form.
+    pub fn target(&self) -> RequestTarget<'a> {
         self.1
     }
 
+    /// The path and query, also for an absolute-form target such as
+    /// `http://host/path`, which some misconfigured clients send.
+    ///
+    /// `None` for the authority and asterisk forms.
+    pub fn path_and_query(&self) -> Option<&'a str> {
+        self.1.path_and_query()
+    }
+
     pub fn version(&self) -> HttpVersion {
         self.2
     }
@@ -125,6 +274,11 @@ impl<'a, 'b> RequestAttempt<'a, 'b> {
             input_used: 0,
             line: None,
             headers: None,
+            content_encoding: None,
+            transfer_codings: TransferCodings::empty(),
+            body_mode: None,
+            expect_continue: false,
+            too_large: false,
         }
     }
 
@@ -143,34 +297,329 @@ impl<'a, 'b> RequestAttempt<'a, 'b> {
     pub fn headers(&self) -> Option<&'b [Header<'a>]> {
         self.headers
     }
+
+    /// The host the request is for, RFC 7230 §5.4.
+    ///
+    /// This is the host of an absolute-form target, which takes precedence
+    /// over the `Host` header, otherwise the `Host` header.
+    pub fn host(&self) -> Option<&'a str> {
+        if let Some(url) = self.line?.target().url() {
+            return Some(url.host());
+        }
+
+        self.headers?
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "host"))
+            .and_then(|h| h.try_value())
+    }
+
+    /// The `HTTP2-Settings` of a request to upgrade to h2c.
+    ///
+    /// This is `None` unless the request has `Upgrade: h2c` and exactly one
+    /// `HTTP2-Settings`, both listed in `Connection`. See [`H2cHandoff`].
+    pub fn h2c_settings(&self) -> Option<&'a str> {
+        h2c::settings(self.headers?)
+    }
+
+    /// The `Content-Encoding` of the request body, if any.
+    pub fn content_encoding(&self) -> Option<ContentEncoding> {
+        self.content_encoding
+    }
+
+    /// The `Transfer-Encoding` codings of the request body.
+    pub fn transfer_codings(&self) -> TransferCodings {
+        self.transfer_codings
+    }
+
+    /// How the request body is framed.
+    pub fn body_mode(&self) -> Option<RecvBodyMode> {
+        self.body_mode
+    }
+
+    /// The options of the `Connection` header, such as hop-by-hop headers.
+    pub fn connection(&self) -> Option<ConnectionOptions<'a, 'b>> {
+        self.headers.map(ConnectionOptions::new)
+    }
+
+    /// Whether the client waits for `100 Continue` before sending the body.
+    ///
+    /// See `Request::send_continue()` and `Request::reject_body()`.
+    pub fn expects_continue(&self) -> bool {
+        self.expect_continue
+    }
+
+    /// Whether the connection persists after this request.
+    ///
+    /// HTTP/1.1 persists unless `Connection: close`, HTTP/1.0 only with
+    /// `Connection: keep-alive`. See [`ConnectionOptions::is_persistent()`].
+    pub fn is_keep_alive(&self) -> bool {
+        match (self.line, self.connection()) {
+            (Some(line), Some(c)) => c.is_persistent(line.version()),
+            _ => false,
+        }
+    }
+
+    /// Protocols the client offers to upgrade to, in order of preference.
+    ///
+    /// This is `None` unless the request has an `Upgrade` header, listed in
+    /// `Connection`. HTTP/1.0 requests can't upgrade.
+    pub fn upgrade(&self) -> Option<Protocols<'a>> {
+        if self.line?.version() != HttpVersion::Http11 || !self.connection()?.is_upgrade() {
+            return None;
+        }
+
+        self.headers?
+            .iter()
+            .find(|h| compare_lowercase_ascii(h.name(), "upgrade"))
+            .and_then(|h| h.try_value())
+            .map(Protocols::new)
+    }
+
+    /// Whether the client offers to upgrade to `protocol`, such as `websocket`.
+    ///
+    /// Compares the protocol name ignoring case, and any version.
+    pub fn wants_upgrade(&self, protocol: &str) -> bool {
+        self.upgrade()
+            .map(|mut p| p.any(|p| p.name().eq_ignore_ascii_case(protocol)))
+            .unwrap_or(false)
+    }
+
+    /// Whether the declared body is above the max set by `set_max_body_size()`.
+    ///
+    /// If this is true, respond with `413 Payload Too Large` rather than
+    /// reading the body, which fails with `HootError::BodyTooLarge`.
+    pub fn is_too_large(&self) -> bool {
+        self.too_large
+    }
 }
 
 impl Request<RECV_REQUEST> {
+    /// Try to read the request head from `input`, using `buf` for the headers.
+    ///
+    /// If the head is not complete, the attempt is empty. Call again with the
+    /// same input, from the start of the request, and more bytes appended.
+    /// Complete lines are not scanned again by later attempts.
     pub fn try_read_request<'a, 'b>(
         &mut self,
         input: &'a [u8],
-        buf: &'b mut [u8],
+        buf: &'b mut [Header<'a>],
     ) -> Result<RequestAttempt<'a, 'b>> {
         self.do_try_read_request(input, buf)
     }
 
+    /// Set a max size for the request body.
+    ///
+    /// A declared `Content-Length` above `max` is checked as soon as the
+    /// request head is read, see `RequestAttempt::is_too_large()`. Chunked
+    /// bodies fail with `HootError::BodyTooLarge` once more than `max` is read.
+    /// The error suggests `413 Payload Too Large`.
+    pub fn set_max_body_size(&mut self, max: u64) {
+        self.state.max_recv_body = Some(max);
+    }
+
+    /// Set limits for reading the request.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.state.limits = limits;
+    }
+
+    /// Set time budgets for reading the request.
+    pub fn set_deadline(&mut self, deadline: Deadline) {
+        self.state.deadline.budgets = deadline;
+    }
+
+    /// Supply the current time for [`Deadline`](crate::Deadline) accounting.
+    ///
+    /// Milliseconds from any monotonic source. Call before each read.
+    pub fn set_now(&mut self, now_ms: u64) {
+        self.state.deadline.set_now(now_ms);
+    }
+
+    /// Set the id of this exchange in [metrics](crate::metrics) events.
+    ///
+    /// The id carries over to the response.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics_id(&mut self, id: u32) {
+        self.state.metrics_id = id;
+    }
+
+    /// Write the response header names in canonical case, such as
+    /// `Content-Type` for `content-type`.
+    ///
+    /// For peers and signature schemes sensitive to case. See the
+    /// [crate docs](crate#header-order) for the order headers are written in.
+    pub fn set_canonical_header_names(&mut self) {
+        self.state.canonical_header_names = true;
+    }
+
+    /// Reject requests that could be framed differently by other parsers.
+    ///
+    /// See [`Violation`](crate::Violation) for what is checked. Intended for proxies, where a
+    /// disagreement on where a message ends enables request smuggling. Replaces
+    /// `set_lenient()`.
+    pub fn set_strict(&mut self) {
+        self.state.parse_mode = ParseMode::Strict;
+    }
+
+    /// Tolerate requests from broken peers.
+    ///
+    /// Accepts extra spaces in the request line, and duplicate `Content-Length`
+    /// headers with identical values. Replaces `set_strict()`.
+    pub fn set_lenient(&mut self) {
+        self.state.parse_mode = ParseMode::Lenient;
+        self.state.content_length_policy = ContentLengthPolicy::AcceptIfIdentical;
+    }
+
+    /// Set how repeated `Content-Length` headers are handled.
+    ///
+    /// Defaults to [`ContentLengthPolicy::Reject`].
+    pub fn set_content_length_policy(&mut self, policy: ContentLengthPolicy) {
+        self.state.content_length_policy = policy;
+    }
+
+    /// Set how input beyond the `Content-Length` of the body is handled.
+    ///
+    /// Defaults to [`ExcessBodyPolicy::Reject`].
+    pub fn set_excess_body_policy(&mut self, policy: ExcessBodyPolicy) {
+        self.state.excess_body_policy = policy;
+    }
+
+    /// Set how bodies are handled for methods without one, such as `GET`.
+    ///
+    /// Defaults to [`RequestBodyPolicy::Ignore`].
+    pub fn set_request_body_policy(&mut self, policy: RequestBodyPolicy) {
+        self.state.request_body_policy = policy;
+    }
+
+    /// Accept methods outside the standard set as `Method::Extension`.
+    ///
+    /// By default they fail with `HootError::UnknownMethod`. `request_body` is
+    /// whether they have a request body like `POST`, or none like `GET`, in
+    /// which case a declared body is handled by the [`RequestBodyPolicy`].
+    pub fn accept_extension_methods(&mut self, request_body: bool) {
+        self.state.extension_methods = Some(request_body);
+    }
+
+    /// Decide which methods have a request body, instead of
+    /// [`Method::has_request_body()`] and `accept_extension_methods()`.
+    ///
+    /// For APIs that send bodies with `DELETE` or `GET`. A body declared in a
+    /// request with a method without one is handled by the [`RequestBodyPolicy`].
+    ///
+    /// ```
+    /// use hoot::server::Request;
+    /// use hoot::Method;
+    ///
+    /// let mut request = Request::new();
+    /// request.set_has_request_body(|m| m.has_request_body() || m == Method::DELETE);
+    /// ```
+    pub fn set_has_request_body(&mut self, f: fn(Method) -> bool) {
+        self.state.has_request_body = Some(f);
+    }
+
+    /// Whether the read request has `Expect: 100-continue`.
+    pub fn expects_continue(&self) -> bool {
+        self.state.expect_continue
+    }
+
+    /// Accept the body of a request with `Expect: 100-continue`.
+    ///
+    /// Writes `100 Continue` into `buf`. The returned bytes must be sent before
+    /// the client sends the body. Fails with `HootError::NotExpectingContinue`
+    /// if the request did not ask for it.
+    pub fn send_continue(self, buf: &mut [u8]) -> Result<(Request<RECV_BODY>, &[u8])> {
+        if !self.state.expect_continue {
+            return Err(HootError::NotExpectingContinue);
+        }
+
+        trace!("Send 100 Continue");
+
+        let mut out = Out::wrap(buf);
+        let mut w = out.writer();
+        w.write_bytes(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        w.commit();
+
+        Ok((self.transition(), out.into_inner()))
+    }
+
+    /// Reject the body of a request with `Expect: 100-continue`.
+    ///
+    /// Continues straight to a final response, such as `417` or `413`, without
+    /// reading the body. The client might send the body anyway, so the
+    /// connection should be closed after the response.
+    pub fn reject_body(self) -> Result<ResponseVariant> {
+        if !self.state.expect_continue {
+            return Err(HootError::NotExpectingContinue);
+        }
+
+        trace!("Reject body");
+
+        ResponseVariant::from_state(self.state)
+    }
+
     pub fn proceed(self) -> Request<RECV_BODY> {
         self.transition()
     }
+
+    /// Accept an upgrade to h2c, ending HTTP/1.1 on the connection.
+    ///
+    /// `attempt` is the successful attempt of this request, and `input` the
+    /// input it read. Writes `101 Switching Protocols` into `buf`, which must be
+    /// sent before any HTTP/2. Fails with `HootError::NotH2cUpgrade` if the
+    /// request is not an upgrade, see [`RequestAttempt::h2c_settings()`], and
+    /// with `HootError::UnexpectedBody` if it has a body.
+    pub fn switch_to_h2c<'a, 'o>(
+        self,
+        attempt: RequestAttempt<'a, '_>,
+        input: &'a [u8],
+        buf: &'o mut [u8],
+    ) -> Result<(H2cHandoff<'a>, &'o [u8])> {
+        let settings = attempt.h2c_settings().ok_or(HootError::NotH2cUpgrade)?;
+
+        if attempt.body_mode != Some(RecvBodyMode::LengthDelimited(0)) {
+            return Err(HootError::UnexpectedBody);
+        }
+
+        trace!("Switch to h2c");
+
+        let mut out = Out::wrap(buf);
+        let mut w = out.writer();
+        w.write_bytes(
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n",
+        )?;
+        w.commit();
+
+        let rest = &input[attempt.input_used..];
+
+        Ok((H2cHandoff::new(Some(settings), rest), out.into_inner()))
+    }
 }
 
 impl Request<RECV_BODY> {
-    pub fn read_body<'a, 'b>(&mut self, src: &'a [u8], dst: &'b mut [u8]) -> Result<BodyPart<'b>> {
+    /// Supply the current time for [`Deadline`](crate::Deadline) accounting.
+    ///
+    /// Milliseconds from any monotonic source. Call before each read.
+    pub fn set_now(&mut self, now_ms: u64) {
+        self.state.deadline.set_now(now_ms);
+    }
+
+    /// Read the head when going straight to the body. The headers are parsed
+    /// into a buffer on the stack, since they are not kept.
+    ///
+    /// Returns whether the head is complete.
+    fn read_head_only(&mut self, src: &[u8]) -> Result<bool> {
+        let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+        Ok(self.do_try_read_request(src, &mut headers)?.is_success())
+    }
+
+    pub fn read_body<'b>(&mut self, src: &[u8], dst: &'b mut [u8]) -> Result<BodyPart<'b>> {
         let already_read_response = self.state.recv_body_mode.is_some();
 
         // It's valid to skip try_read_response() and progress straight to reading
         // the body. This ensures we skip the corresponding input.
         if !already_read_response {
-            let r = self.do_try_read_request(src, dst)?;
-
             // Still not enough input for the entire status and headers. Need
             // to try again later.
-            if !r.is_success() {
+            if !self.read_head_only(src)? {
                 return Ok(BodyPart::empty());
             }
         }
@@ -178,6 +627,114 @@ impl Request<RECV_BODY> {
         do_read_body(&mut self.state, src, dst)
     }
 
+    /// Read body like `read_body()`, feeding the read data to `hasher`.
+    pub fn read_body_hashed<'b>(
+        &mut self,
+        src: &[u8],
+        dst: &'b mut [u8],
+        hasher: &mut dyn BodyHasher,
+    ) -> Result<BodyPart<'b>> {
+        let part = self.read_body(src, dst)?;
+        hasher.update(part.data());
+        Ok(part)
+    }
+
+    /// Read body through a [`BodyTransform`], such as a decompressor.
+    ///
+    /// See `client::Response::read_body_with()`.
+    pub fn read_body_with<'b>(
+        &mut self,
+        src: &[u8],
+        dst: &'b mut [u8],
+        transform: &mut dyn BodyTransform,
+    ) -> Result<BodyPart<'b>> {
+        let already_read_request = self.state.recv_body_mode.is_some();
+
+        if !already_read_request && !self.read_head_only(src)? {
+            return Ok(BodyPart::empty());
+        }
+
+        let encoding = self.state.content_encoding;
+        do_read_body_transformed(&mut self.state, src, dst, transform, encoding)
+    }
+
+    /// Read body through a [`BodyTransform`] for the transfer coding.
+    ///
+    /// Like `read_body_with()`, but the transform selects on the outermost
+    /// transfer coding besides `chunked`, see [`TransferCodings::outermost()`].
+    /// Bodies with several such codings need further decoding of the output.
+    pub fn read_body_transfer_with<'b>(
+        &mut self,
+        src: &[u8],
+        dst: &'b mut [u8],
+        transform: &mut dyn BodyTransform,
+    ) -> Result<BodyPart<'b>> {
+        let already_read = self.state.recv_body_mode.is_some();
+
+        if !already_read && !self.read_head_only(src)? {
+            return Ok(BodyPart::empty());
+        }
+
+        let encoding = self.state.transfer_codings.outermost();
+        do_read_body_transformed(&mut self.state, src, dst, transform, encoding)
+    }
+
+    /// Read the body straight into a [`BodySink`].
+    ///
+    /// Returns how much of `src` was used. Reading stops when `src` is used, the
+    /// sink is full or the body is finished.
+    pub fn read_body_into(&mut self, src: &[u8], sink: &mut dyn BodySink) -> Result<usize> {
+        read_into_sink(src, sink, |src, dst| self.read_body(src, dst))
+    }
+
+    /// Read the body into a `Vec`, growing it as needed.
+    ///
+    /// Returns how much of `src` was used. The `Vec` grows with whatever the
+    /// client sends, check the declared size with `bytes_remaining()` first.
+    #[cfg(feature = "alloc")]
+    pub fn read_body_to_vec(
+        &mut self,
+        src: &[u8],
+        body: &mut alloc::vec::Vec<u8>,
+    ) -> Result<usize> {
+        self.read_body_into(src, &mut crate::owned::VecSink::new(body))
+    }
+
+    /// Read the body into `json`, to deserialize it once finished.
+    ///
+    /// Returns how much of `src` was used. See [`JsonBody`](crate::json::JsonBody).
+    #[cfg(feature = "serde")]
+    pub fn read_json(&mut self, src: &[u8], json: &mut crate::json::JsonBody<'_>) -> Result<usize> {
+        json.read(src, |src, dst| self.read_body(src, dst))
+    }
+
+    /// Read the trailer section after the body is finished.
+    ///
+    /// Returns `None` if more input is needed. For bodies that are not chunked
+    /// the trailers are empty. The raw bytes can be passed on as is by a proxy
+    /// using `finish_raw()`.
+    pub fn read_trailers<'a, 'b>(
+        &mut self,
+        src: &'a [u8],
+        buf: &'b mut [Header<'a>],
+    ) -> Result<Option<Trailers<'a, 'b>>> {
+        do_read_trailers(&mut self.state, src, buf)
+    }
+
+    /// Bytes left to read of a length delimited body.
+    ///
+    /// This is `None` for chunked and close delimited bodies.
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        bytes_remaining(&self.state)
+    }
+
+    /// Bytes left to read of the current chunk of a chunked body.
+    ///
+    /// This is `Some(0)` between chunks, and `None` if the body is not chunked.
+    pub fn chunk_remaining(&self) -> Option<u64> {
+        chunk_remaining(&self.state)
+    }
+
     pub fn is_finished(&self) -> bool {
         use RecvBodyMode::*;
 
@@ -188,21 +745,874 @@ impl Request<RECV_BODY> {
 
         match mode {
             LengthDelimited(n) => n == 0 || self.state.did_read_to_end,
-            Chunked => self.state.did_read_to_end,
-            CloseDelimited => unreachable!("CloseDelimited is not possible for server::Request"),
+            // CloseDelimited is not possible for server::Request.
+            Chunked | CloseDelimited => self.state.did_read_to_end,
         }
     }
 
     pub fn into_response(self) -> Result<ResponseVariant> {
         if let Some(checker) = &self.state.recv_checker {
-            checker.assert_expected(HootError::RecvLessThanContentLength)?;
+            checker.assert_expected(|expected, actual| HootError::RecvLessThanContentLength {
+                expected,
+                actual,
+            })?;
         }
 
         if !self.is_finished() {
             return Err(HootError::BodyNotFinished);
         }
 
-        // Unwrap is OK, because the request method was read earlier.
-        Ok(self.state.into())
+        // The method was read earlier, so this only fails on a bug.
+        ResponseVariant::from_state(self.state)
+    }
+}
+
+impl<S: State> fmt::Debug for Request<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("typ", &S::state_name())
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// Forks the request, see the `snapshot` feature.
+#[cfg(feature = "snapshot")]
+impl<S: State> Clone for Request<S> {
+    fn clone(&self) -> Self {
+        Request {
+            typ: PhantomData,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn test_request_body_policy() -> Result<()> {
+        const INPUT: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nhi";
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        r.try_read_request(INPUT, &mut headers)?;
+        assert_eq!(
+            r.state.recv_body_mode,
+            Some(RecvBodyMode::LengthDelimited(0))
+        );
+
+        let mut r = Request::new();
+        r.set_request_body_policy(RequestBodyPolicy::Read);
+        let attempt = r.try_read_request(INPUT, &mut headers)?;
+        let used = attempt.input_used();
+        let mut r = r.proceed();
+        let part = r.read_body(&INPUT[used..], &mut buf)?;
+        assert_eq!(part.data(), b"hi");
+        assert!(part.is_finished());
+
+        let mut r = Request::new();
+        r.set_request_body_policy(RequestBodyPolicy::Reject);
+        let err = r
+            .try_read_request(INPUT, &mut headers)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UnexpectedBody);
+
+        // No declared body is fine.
+        let mut r = Request::new();
+        r.set_request_body_policy(RequestBodyPolicy::Reject);
+        assert!(r
+            .try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?
+            .is_success());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_methods() -> Result<()> {
+        const INPUT: &[u8] = b"SEARCH / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nhi";
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        let err = r
+            .try_read_request(INPUT, &mut headers)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UnknownMethod);
+
+        let mut r = Request::new();
+        r.accept_extension_methods(true);
+        let attempt = r.try_read_request(INPUT, &mut headers)?;
+        let used = attempt.input_used();
+        let method = attempt.line().unwrap().method();
+        assert_eq!(method, Method::Extension(ExtMethod::new("SEARCH")?));
+        let mut r = r.proceed();
+        let part = r.read_body(&INPUT[used..], &mut buf)?;
+        assert_eq!(part.data(), b"hi");
+        assert!(matches!(r.into_response()?, ResponseVariant::Extension(_)));
+
+        // Without a request body, the declared body is handled by the policy.
+        let mut r = Request::new();
+        r.accept_extension_methods(false);
+        r.try_read_request(INPUT, &mut headers)?;
+        assert_eq!(
+            r.state.recv_body_mode,
+            Some(RecvBodyMode::LengthDelimited(0))
+        );
+
+        let mut r = Request::new();
+        r.accept_extension_methods(false);
+        let err = r
+            .try_read_request(b"SEA:RCH / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, HootError::Token { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_webdav_methods() -> Result<()> {
+        const INPUT: &[u8] = b"PROPFIND /d/ HTTP/1.1\r\nHost: x\r\nDepth: 1\r\n\
+            Content-Length: 4\r\n\r\n<x/>";
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        let attempt = r.try_read_request(INPUT, &mut headers)?;
+        let used = attempt.input_used();
+        assert_eq!(attempt.line().unwrap().method(), Method::PROPFIND);
+        let mut r = r.proceed();
+        let part = r.read_body(&INPUT[used..], &mut buf)?;
+        assert_eq!(part.data(), b"<x/>");
+        assert!(matches!(r.into_response()?, ResponseVariant::Propfind(_)));
+
+        // MOVE has no body, so a declared one is handled by the policy.
+        let mut r = Request::new();
+        r.set_request_body_policy(RequestBodyPolicy::Reject);
+        let err = r
+            .try_read_request(
+                b"MOVE /a HTTP/1.1\r\nHost: x\r\nDestination: /b\r\n\
+                Content-Length: 1\r\n\r\nx",
+                &mut headers,
+            )
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UnexpectedBody);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_request_body() -> Result<()> {
+        const INPUT: &[u8] = b"DELETE /a HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nhi";
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        r.set_has_request_body(|m| m.has_request_body() || m == Method::DELETE);
+        let attempt = r.try_read_request(INPUT, &mut headers)?;
+        let used = attempt.input_used();
+        let mut r = r.proceed();
+        let part = r.read_body(&INPUT[used..], &mut buf)?;
+        assert_eq!(part.data(), b"hi");
+
+        // Overrides the default for QUERY, which has a body.
+        let mut r = Request::new();
+        r.set_has_request_body(|_| false);
+        r.set_request_body_policy(RequestBodyPolicy::Reject);
+        let err = r
+            .try_read_request(
+                b"QUERY / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nhi",
+                &mut headers,
+            )
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::UnexpectedBody);
+
+        let mut r = Request::new();
+        let attempt = r.try_read_request(
+            b"QUERY / HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\n\r\nhi",
+            &mut headers,
+        )?;
+        assert_eq!(attempt.line().unwrap().method(), Method::QUERY);
+        assert_eq!(
+            r.state.recv_body_mode,
+            Some(RecvBodyMode::LengthDelimited(2))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_target() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        let a = r.try_read_request(b"OPTIONS * HTTP/1.1\r\nHost: x.test\r\n\r\n", &mut buf)?;
+        assert_eq!(a.line().unwrap().target(), RequestTarget::Asterisk);
+
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"CONNECT x.test:443 HTTP/1.1\r\nHost: x.test:443\r\n\r\n",
+            &mut buf,
+        )?;
+        let line = a.line().unwrap();
+        assert_eq!(line.target(), RequestTarget::Authority("x.test:443"));
+        assert_eq!(line.path(), "x.test:443");
+
+        let mut r = Request::new();
+        let err = r
+            .try_read_request(b"GET * HTTP/1.1\r\n\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::InvalidTarget { offset: 4 });
+
+        // Absolute-form, where the host in the target wins over Host.
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"GET http://a.test/p?q HTTP/1.1\r\nHost: b.test\r\n\r\n",
+            &mut buf,
+        )?;
+        let line = a.line().unwrap();
+        assert_eq!(line.path(), "http://a.test/p?q");
+        assert_eq!(line.path_and_query(), Some("/p?q"));
+        assert_eq!(a.host(), Some("a.test"));
+
+        let mut r = Request::new();
+        let a = r.try_read_request(b"GET /p HTTP/1.1\r\nHost: b.test\r\n\r\n", &mut buf)?;
+        assert_eq!(a.host(), Some("b.test"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_header() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        let err = r
+            .try_read_request(b"GET / HTTP/1.1\r\n\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::MissingHost);
+        assert_eq!(err.suggested_status(), Some(400));
+
+        let mut r = Request::new();
+        let err = r
+            .try_read_request(b"GET / HTTP/1.1\r\nHost: a\r\nhost: b\r\n\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::DuplicateHost);
+
+        // Not required for HTTP/1.0
+        let mut r = Request::new();
+        assert!(r
+            .try_read_request(b"GET / HTTP/1.0\r\n\r\n", &mut buf)?
+            .is_success());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_latin1_header_value() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"GET / HTTP/1.1\r\nHost: x\r\nX-Name: caf\xe9\r\n\r\n",
+            &mut buf,
+        )?;
+        let h = a.headers().unwrap()[1];
+        assert_eq!(h.value_raw(), b"caf\xe9");
+        assert_eq!(h.value_str(), Err(HootError::ConvertBytesToStr));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expect_continue() -> Result<()> {
+        const HEAD: &[u8] = b"PUT / HTTP/1.1\r\nHost: x\r\nExpect: 100-Continue\r\n\
+            Content-Length: 2\r\n\r\n";
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut out = [0; 64];
+
+        let mut r = Request::new();
+        assert!(r.try_read_request(HEAD, &mut headers)?.expects_continue());
+        let (mut r, output) = r.send_continue(&mut out)?;
+        assert_eq!(output, b"HTTP/1.1 100 Continue\r\n\r\n");
+        let part = r.read_body(b"hi", &mut buf)?;
+        assert_eq!(part.data(), b"hi");
+
+        let mut r = Request::new();
+        r.try_read_request(HEAD, &mut headers)?;
+        assert!(matches!(r.reject_body()?, ResponseVariant::Put(_)));
+
+        // Not for HTTP/1.0
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"PUT / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\n",
+            &mut headers,
+        )?;
+        assert!(!a.expects_continue());
+        let err = r.send_continue(&mut out).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::NotExpectingContinue);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_header() -> Result<()> {
+        use crate::server::Response;
+        use crate::Clock;
+
+        struct Fixed;
+        impl Clock for Fixed {
+            fn unix_time(&self) -> u64 {
+                784_111_777
+            }
+        }
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        r.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+
+        let token = match r.proceed().into_response()? {
+            ResponseVariant::Get(v) => v,
+            _ => unreachable!(),
+        };
+
+        let res = Response::resume(token, &mut buf)
+            .with_clock(&Fixed)
+            .send_status(200, "OK")?;
+        let output = res.flush();
+        assert_eq!(
+            &*output,
+            b"HTTP/1.1 200 OK\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+        );
+
+        let err = Response::resume(output.ready(), &mut buf)
+            .header("Date", "now")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::DuplicateHeader);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deprecation_headers() -> Result<()> {
+        use crate::server::{Response, ResumeToken};
+        use crate::types::method::GET;
+
+        fn headers() -> ResumeToken<SEND_HEADERS, GET, ()> {
+            let mut buf = [0; 1024];
+            let mut headers = HeaderBuf::<16>::new();
+            let mut r = Request::new();
+            r.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)
+                .unwrap();
+            let token = match r.proceed().into_response().unwrap() {
+                ResponseVariant::Get(v) => v,
+                _ => unreachable!(),
+            };
+            let res = Response::resume(token, &mut buf).send_status(200, "OK");
+            res.unwrap().flush().ready()
+        }
+
+        let mut buf = [0; 1024];
+        let output = Response::resume(headers(), &mut buf)
+            .warning(214, "cache.test:8080", "a\\b", None)?
+            .deprecation(0)?
+            .flush();
+        assert_eq!(
+            &*output,
+            b"Warning: 214 cache.test:8080 \"a\\\\b\"\r\nDeprecation: @0\r\n"
+        );
+
+        let err = Response::resume(output.ready(), &mut buf)
+            .deprecation(1)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::DuplicateHeader);
+
+        let mut warn = |code, agent, text| {
+            Response::resume(headers(), &mut buf)
+                .warning(code, agent, text, None)
+                .map(|_| ())
+                .unwrap_err()
+        };
+        assert_eq!(warn(99, "-", "x"), HootError::HeaderValue { offset: 0 });
+        assert_eq!(warn(299, "", "x"), HootError::HeaderValue { offset: 4 });
+        assert_eq!(warn(299, "a b", "x"), HootError::HeaderValue { offset: 5 });
+        assert_eq!(
+            warn(299, "-", "x\r\n"),
+            HootError::HeaderValue { offset: 8 }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_header_names() -> Result<()> {
+        use crate::server::Response;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        r.set_canonical_header_names();
+        r.try_read_request(b"GET / HTTP/1.1\r\nhost: x\r\n\r\n", &mut headers)?;
+        let token = match r.proceed().into_response()? {
+            ResponseVariant::Get(v) => v,
+            _ => unreachable!(),
+        };
+
+        let output = Response::resume(token, &mut buf)
+            .send_status(200, "OK")?
+            .header("cache-CONTROL", "no-store")?
+            .link(&crate::Link::new("/a").with_rel("next"))?
+            .send_body_full(b"")?
+            .flush();
+
+        assert_eq!(
+            &*output,
+            b"HTTP/1.1 200 OK\r\nCache-Control: no-store\r\nLink: </a>; rel=\"next\"\r\n\
+            Content-Length: 0\r\n\r\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_early_hints() -> Result<()> {
+        use crate::server::Response;
+        use crate::Link;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        r.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+
+        let token = match r.proceed().into_response()? {
+            ResponseVariant::Get(v) => v,
+            _ => unreachable!(),
+        };
+
+        let output = Response::resume(token, &mut buf)
+            .early_hints()?
+            .link(&Link::new("/a.js").with_rel("preload"))?
+            .end_hints()?
+            .early_hints()?
+            .header("X-Hint", "1")?
+            .end_hints()?
+            .send_status(200, "OK")?
+            .header("X-Hint", "2")?
+            .send_empty()?
+            .flush();
+
+        assert_eq!(
+            &*output,
+            b"HTTP/1.1 103 Early Hints\r\nLink: </a.js>; rel=\"preload\"\r\n\r\n\
+            HTTP/1.1 103 Early Hints\r\nX-Hint: 1\r\n\r\n\
+            HTTP/1.1 200 OK\r\nX-Hint: 2\r\nContent-Length: 0\r\n\r\n"
+        );
+
+        let mut r = Request::new();
+        r.try_read_request(b"GET / HTTP/1.0\r\n\r\n", &mut headers)?;
+
+        let token = match r.proceed().into_response()? {
+            ResponseVariant::Get(v) => v,
+            _ => unreachable!(),
+        };
+
+        let err = Response::resume(token, &mut buf)
+            .early_hints()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::InterimHttp10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_required() -> Result<()> {
+        use crate::server::Response;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        let a = r.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+        assert!(!a.wants_upgrade("TLS"));
+
+        let token = match r.proceed().into_response()? {
+            ResponseVariant::Get(v) => v,
+            _ => unreachable!(),
+        };
+
+        let output = Response::resume(token, &mut buf)
+            .upgrade_required("TLS/1.2")?
+            .send_body_full(b"use tls")?
+            .flush();
+
+        assert_eq!(
+            &*output,
+            b"HTTP/1.1 426 Upgrade Required\r\nUpgrade: TLS/1.2\r\nConnection: Upgrade\r\n\
+            Content-Length: 7\r\n\r\nuse tls"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_codings() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        // A request body without final chunked has no reliable length.
+        let mut r = Request::new();
+        let err = r
+            .try_read_request(
+                b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip\r\n\r\n",
+                &mut buf,
+            )
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::ChunkedNotLast);
+        assert_eq!(err.suggested_status(), Some(400));
+
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: deflate, chunked\r\n\r\n",
+            &mut buf,
+        )?;
+        assert_eq!(a.transfer_codings().codings(), &[ContentEncoding::Deflate]);
+        assert!(a.transfer_codings().is_chunked());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "snapshot")]
+    fn test_snapshot() -> Result<()> {
+        const INPUT: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 6\r\n\r\nabc";
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        let a = r.try_read_request(INPUT, &mut headers)?;
+        let used = a.input_used();
+        let mut r: Request<RECV_BODY> = r.proceed();
+        r.read_body(&INPUT[used..], &mut buf)?;
+
+        let debug = format!("{:?}", r);
+        assert!(debug.starts_with("Request { typ: \"RECV_BODY\""));
+        assert!(debug.contains("handled: 3, expected: 6"));
+
+        // Fork mid-body, one path ends correctly and the other sends too much.
+        let mut fork = r.clone();
+        r.read_body(b"def", &mut buf)?;
+        assert!(r.is_finished());
+        let err = fork.read_body(b"defg", &mut buf).map(|_| ()).unwrap_err();
+        assert!(matches!(err, HootError::RecvMoreThanContentLength { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_control_flags() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"GET /chat HTTP/1.1\r\nHost: x\r\nConnection: keep-alive, Upgrade\r\n\
+            Upgrade: h2c, WebSocket/13\r\nExpect: 100-continue\r\n\r\n",
+            &mut buf,
+        )?;
+        assert!(a.is_keep_alive());
+        assert!(a.expects_continue());
+        assert!(a.wants_upgrade("websocket"));
+        assert!(a.wants_upgrade("H2C"));
+        assert!(!a.wants_upgrade("tls"));
+        let protocols: Vec<_> = a.upgrade().unwrap().map(|p| p.name()).collect();
+        assert_eq!(protocols, ["h2c", "WebSocket"]);
+
+        // Upgrade must be listed in Connection.
+        let mut r = Request::new();
+        let a = r.try_read_request(
+            b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\nUpgrade: websocket\r\n\r\n",
+            &mut buf,
+        )?;
+        assert!(!a.is_keep_alive());
+        assert!(a.upgrade().is_none());
+        assert!(!a.wants_upgrade("websocket"));
+
+        let mut r = Request::new();
+        let a = r.try_read_request(b"GET / HTTP/1.0\r\n\r\n", &mut buf)?;
+        assert!(!a.is_keep_alive());
+        assert!(!a.expects_continue());
+
+        // Incomplete.
+        let mut r = Request::new();
+        let a = r.try_read_request(b"GET / HTTP/1.1\r\n", &mut buf)?;
+        assert!(!a.is_keep_alive());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_body_size() -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        const LENGTH: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 11\r\n\r\n";
+        let mut r = Request::new();
+        r.set_max_body_size(10);
+        let a = r.try_read_request(LENGTH, &mut headers)?;
+        assert!(a.is_too_large());
+        let mut r = r.proceed();
+        let err = r.read_body(b"hello", &mut buf).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::BodyTooLarge);
+        assert_eq!(err.suggested_status(), Some(413));
+
+        const CHUNKED: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let mut r = Request::new();
+        r.set_max_body_size(10);
+        let a = r.try_read_request(CHUNKED, &mut headers)?;
+        assert!(!a.is_too_large());
+        let mut r = r.proceed();
+        let part = r.read_body(b"5\r\nhello\r\n", &mut buf)?;
+        assert_eq!(&*part, b"hello");
+        let part = r.read_body(b"5\r\nworld\r\n", &mut buf)?;
+        assert_eq!(&*part, b"world");
+        let err = r
+            .read_body(b"1\r\n!\r\n", &mut buf)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, HootError::BodyTooLarge);
+
+        // Stays failed.
+        let err = r.read_body(b"0\r\n\r\n", &mut buf).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::BodyTooLarge);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excess_body_policy() -> Result<()> {
+        const INPUT: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\n\
+            abcGET / HTTP/1.1\r\n\r\n";
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        r.set_excess_body_policy(ExcessBodyPolicy::Preserve);
+        let used = r.try_read_request(INPUT, &mut headers)?.input_used();
+        let mut r = r.proceed();
+
+        let part = r.read_body(&INPUT[used..], &mut buf)?;
+        assert_eq!(&*part, b"abc");
+        assert!(part.is_finished());
+        let used = used + part.input_used();
+        assert_eq!(&INPUT[used..], b"GET / HTTP/1.1\r\n\r\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_body_into() -> Result<()> {
+        const INPUT: &[u8] = b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+
+        // Sums the body, four bytes at a time.
+        struct Sum {
+            buf: [u8; 4],
+            sum: u32,
+        }
+
+        impl BodySink for Sum {
+            fn unfilled(&mut self) -> &mut [u8] {
+                &mut self.buf
+            }
+
+            fn filled(&mut self, amount: usize) -> Result<()> {
+                self.sum += self.buf[..amount].iter().map(|b| *b as u32).sum::<u32>();
+                Ok(())
+            }
+        }
+
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        let a = r.try_read_request(INPUT, &mut headers)?;
+        let used = a.input_used();
+        let mut r: Request<RECV_BODY> = r.proceed();
+
+        let mut sink = Sum {
+            buf: [0; 4],
+            sum: 0,
+        };
+        let n = r.read_body_into(&INPUT[used..(used + 12)], &mut sink)?;
+        assert!(!r.is_finished());
+        r.read_body_into(&INPUT[(used + n)..], &mut sink)?;
+        assert!(r.is_finished());
+
+        let expected: u32 = b"hello world".iter().map(|b| *b as u32).sum();
+        assert_eq!(sink.sum, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_response() -> Result<()> {
+        use crate::server::Response;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        for (input, expected) in [
+            (
+                &b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"[..],
+                &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                3\r\nabc\r\n2\r\nde\r\n0\r\nX-Sum: 5\r\n\r\n"[..],
+            ),
+            // HTTP/1.0 has no chunks, the body ends by closing the connection.
+            (
+                &b"GET / HTTP/1.0\r\n\r\n"[..],
+                &b"HTTP/1.0 200 OK\r\n\r\nabcde"[..],
+            ),
+        ] {
+            let mut r = Request::new();
+            r.try_read_request(input, &mut headers)?;
+            let token = match r.proceed().into_response()? {
+                ResponseVariant::Get(v) => v,
+                _ => unreachable!(),
+            };
+
+            let output = Response::resume(token, &mut buf)
+                .send_status(200, "OK")?
+                .with_chunked()?
+                .write_chunk(b"abc")?
+                .write_chunk(b"de")?
+                .with_trailer()?
+                .trailer("X-Sum", "5")?
+                .finish()?
+                .flush();
+
+            assert_eq!(&*output, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_body_full() -> Result<()> {
+        use crate::server::Response;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+        let mut r = Request::new();
+        r.try_read_request(b"POST / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+        let token = match r.proceed().into_response()? {
+            ResponseVariant::Post(v) => v,
+            _ => unreachable!(),
+        };
+
+        let output = Response::resume(token, &mut buf)
+            .send_status(201, "Created")?
+            .send_body_full(b"done")?
+            .flush();
+        assert_eq!(
+            &*output,
+            b"HTTP/1.1 201 Created\r\nContent-Length: 4\r\n\r\ndone"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_empty() -> Result<()> {
+        use crate::server::Response;
+
+        let mut buf = [0; 1024];
+        let mut headers = HeaderBuf::<16>::new();
+
+        const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+        const HEAD: &[u8] = b"HEAD / HTTP/1.1\r\nHost: x\r\n\r\n";
+        const DELETE: &[u8] = b"DELETE / HTTP/1.1\r\nHost: x\r\n\r\n";
+
+        // Content-Length: 0 only where a body could be.
+        for (input, status, expected) in [
+            (
+                GET,
+                200,
+                &b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"[..],
+            ),
+            (GET, 304, &b"HTTP/1.1 304 OK\r\n\r\n"[..]),
+            (HEAD, 200, &b"HTTP/1.1 200 OK\r\n\r\n"[..]),
+            (DELETE, 204, &b"HTTP/1.1 204 OK\r\n\r\n"[..]),
+        ] {
+            let mut r = Request::new();
+            r.try_read_request(input, &mut headers)?;
+
+            let output = match r.proceed().into_response()? {
+                ResponseVariant::Get(t) => Response::resume(t, &mut buf)
+                    .send_status(status, "OK")?
+                    .send_empty()?
+                    .flush(),
+                ResponseVariant::Head(t) => Response::resume(t, &mut buf)
+                    .send_status(status, "OK")?
+                    .send_empty()?
+                    .flush(),
+                ResponseVariant::Delete(t) => Response::resume(t, &mut buf)
+                    .send_status(status, "OK")?
+                    .send_empty()?
+                    .flush(),
+                _ => unreachable!(),
+            };
+            assert_eq!(&*output, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_limits() -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        let limits = Limits {
+            max_target: 10,
+            max_header_bytes: 30,
+            ..Default::default()
+        };
+
+        let exceeded = |which| Err(HootError::LimitExceeded { which });
+
+        // Rejected before the request line is complete.
+        let mut r = Request::new();
+        r.set_limits(limits);
+        let a = r.try_read_request(b"GET /0123456789", &mut buf);
+        assert_eq!(a.map(|_| ()), exceeded(Limit::Target));
+        let err = exceeded(Limit::Target).unwrap_err();
+        assert_eq!(err.suggested_status(), Some(414));
+
+        let mut r = Request::new();
+        r.set_limits(limits);
+        let a = r.try_read_request(b"GET /012345678 HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf);
+        assert!(a?.is_success());
+
+        let mut r = Request::new();
+        r.set_limits(limits);
+        let a = r.try_read_request(
+            b"GET / HTTP/1.1\r\nHost: x\r\nCookie: 0123456789abcdef\r\n",
+            &mut buf,
+        );
+        assert_eq!(a.map(|_| ()), exceeded(Limit::HeaderBytes));
+        let err = exceeded(Limit::HeaderBytes).unwrap_err();
+        assert_eq!(err.suggested_status(), Some(431));
+
+        Ok(())
     }
 }
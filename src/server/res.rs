@@ -1,17 +1,28 @@
-use core::fmt::Write;
+use core::fmt;
+use core::fmt::Write as _;
 use core::marker::PhantomData;
 use core::mem;
 use core::ops::Deref;
-
-use crate::error::{Result, OVERFLOW};
-use crate::header::check_and_output_header;
+use core::str;
+
+use crate::body::{hash_written, write_chunk_from_source, write_length_from_source};
+use crate::date::format_http_date;
+use crate::error::Result;
+#[cfg(feature = "serde")]
+use crate::header::is_sent;
+use crate::header::{check_and_output_header, check_raw_trailers, is_tchar};
+use crate::header::{output_header_fmt, HeaderStyle};
+#[cfg(feature = "serde")]
+use crate::json::{to_slice, write_json_body};
+#[cfg(feature = "multipart")]
+use crate::multipart::MixedReplace;
 use crate::out::Out;
 use crate::types::body::*;
 use crate::types::method::*;
 use crate::types::state::*;
 use crate::types::*;
 use crate::util::LengthChecker;
-use crate::{CallState, HootError, HttpVersion};
+use crate::{BodyHasher, BodySource, CallState, Clock, HootError, HttpVersion, Link};
 
 pub enum ResponseVariant {
     Get(ResumeToken<SEND_STATUS, GET, ()>),
@@ -23,6 +34,16 @@ pub enum ResponseVariant {
     Options(ResumeToken<SEND_STATUS, OPTIONS, ()>),
     Trace(ResumeToken<SEND_STATUS, TRACE, ()>),
     Patch(ResumeToken<SEND_STATUS, PATCH, ()>),
+    Query(ResumeToken<SEND_STATUS, QUERY, ()>),
+    Propfind(ResumeToken<SEND_STATUS, PROPFIND, ()>),
+    Proppatch(ResumeToken<SEND_STATUS, PROPPATCH, ()>),
+    Mkcol(ResumeToken<SEND_STATUS, MKCOL, ()>),
+    Copy(ResumeToken<SEND_STATUS, COPY, ()>),
+    Move(ResumeToken<SEND_STATUS, MOVE, ()>),
+    Lock(ResumeToken<SEND_STATUS, LOCK, ()>),
+    Unlock(ResumeToken<SEND_STATUS, UNLOCK, ()>),
+    /// See `Request::accept_extension_methods()`.
+    Extension(ResumeToken<SEND_STATUS, EXTENSION, ()>),
 }
 
 pub struct Response<'a, S: State, M: Method, B: BodyType> {
@@ -45,6 +66,31 @@ pub struct ResumeToken<S: State, M: Method, B: BodyType> {
     state: CallState,
 }
 
+#[cfg(feature = "suspend")]
+impl<S: State, M: Method, B: BodyType> ResumeToken<S, M, B> {
+    fn typestate_id() -> u32 {
+        crate::suspend::typestate_id(&["server", S::state_name(), M::state_name(), B::state_name()])
+    }
+
+    /// Write the token into `dst`, see [`suspend`](crate::suspend).
+    ///
+    /// Returns the length, which is always `RESUME_TOKEN_LEN`.
+    pub fn write_to(&self, dst: &mut [u8]) -> Result<usize> {
+        crate::suspend::write_state(&self.state, Self::typestate_id(), dst)
+    }
+
+    /// Restore a token written by `write_to()`.
+    ///
+    /// Fails with `HootError::ResumeToken` if the bytes are not a token of this
+    /// type state.
+    pub fn read_from(src: &[u8]) -> Result<Self> {
+        Ok(ResumeToken {
+            typ: Typ(PhantomData, PhantomData, PhantomData),
+            state: crate::suspend::read_state(src, Self::typestate_id())?,
+        })
+    }
+}
+
 impl ResumeToken<(), (), ()> {
     pub(crate) fn new<M: Method>(state: CallState) -> ResumeToken<SEND_STATUS, M, ()> {
         let typ: Typ<(), (), ()> = Typ::default();
@@ -60,6 +106,19 @@ pub struct Output<'a, S: State, M: Method, B: BodyType> {
 
 impl<'a, S: State, M: Method, B: BodyType> Response<'a, S, M, B> {
     fn transition<S2: State, M2: Method, B2: BodyType>(self) -> Response<'a, S2, M2, B2> {
+        self.transition_with_body(0)
+    }
+
+    /// Transition where the output ends with `body` bytes written together
+    /// with the end of the head.
+    #[allow(unused_mut, unused_variables)]
+    fn transition_with_body<S2: State, M2: Method, B2: BodyType>(
+        mut self,
+        body: usize,
+    ) -> Response<'a, S2, M2, B2> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::transition::<S, S2>(&mut self.state, self.out.as_bytes().len(), body);
+
         trace!(
             "Transition: {}/{}/{} -> {}/{}/{}",
             S::state_name(),
@@ -74,16 +133,79 @@ impl<'a, S: State, M: Method, B: BodyType> Response<'a, S, M, B> {
         unsafe { mem::transmute(self) }
     }
 
-    fn header_raw(mut self, name: &str, bytes: &[u8], trailer: bool) -> Result<Self> {
-        let ver = self.state.version.unwrap();
+    /// The version of the request, which is always set when reading it.
+    fn version(&self) -> Result<HttpVersion> {
+        self.state.version.ok_or(HootError::Internal)
+    }
+
+    /// Whether a body without length is chunked. HTTP/1.0 clients don't know
+    /// chunks, so their body is sent as is, and ends by closing the connection.
+    fn is_chunked(&self) -> bool {
+        self.state.version != Some(HttpVersion::Http10)
+    }
+
+    fn link_write(mut self, link: &Link<'_>) -> Result<Self> {
+        trace!("Set link: {}", link);
+
+        link.check()?;
+
+        let style = HeaderStyle::of(self.version()?, &self.state);
+        let w = self.out.writer();
+        let sent = &mut self.state.sent_singular;
+        output_header_fmt(w, style, "Link", format_args!("{}", link), sent)?;
+
+        Ok(self)
+    }
+
+    fn header_raw(self, name: &str, bytes: &[u8], trailer: bool) -> Result<Self> {
+        self.header_write(name, bytes, trailer, true)
+    }
+
+    fn header_write(
+        mut self,
+        name: &str,
+        bytes: &[u8],
+        trailer: bool,
+        validate: bool,
+    ) -> Result<Self> {
+        let style = HeaderStyle::of(self.version()?, &self.state);
         // Attempt writing the header
         let w = self.out.writer();
-        check_and_output_header(w, ver, name, bytes, trailer)?;
+        let sent = &mut self.state.sent_singular;
+        check_and_output_header(w, style, name, bytes, trailer, validate, sent)?;
         Ok(self)
     }
 
-    pub fn flush(self) -> Output<'a, S, M, B> {
+    /// Output in the buffer that is not yet flushed.
+    pub fn pending(&self) -> &[u8] {
+        self.out.as_bytes()
+    }
+
+    /// Tell that the first `n` bytes of `pending()` are written to the transport.
+    ///
+    /// This frees up the buffer without `flush()` and `resume()`, which keeps
+    /// the type state. Output after the `n` bytes is moved to the start of the
+    /// buffer.
+    ///
+    /// More than `pending()` is `HootError::BufferOverrun`, and nothing is
+    /// flushed.
+    pub fn flushed(&mut self, n: usize) -> Result<()> {
+        trace!("Flushed: {}", n);
+        self.out.consume(n)?;
+        #[cfg(feature = "metrics")]
+        {
+            self.state.sent_len += n as u64;
+        }
+        Ok(())
+    }
+
+    #[allow(unused_mut)]
+    pub fn flush(mut self) -> Output<'a, S, M, B> {
         trace!("Flush");
+        #[cfg(feature = "metrics")]
+        {
+            self.state.sent_len += self.out.as_bytes().len() as u64;
+        }
         Output {
             token: ResumeToken {
                 typ: self.typ,
@@ -109,13 +231,72 @@ impl<'a, S: State, M: Method, B: BodyType> Response<'a, S, M, B> {
 }
 
 impl<'a, M: Method> Response<'a, SEND_STATUS, M, ()> {
+    /// Send a `Date` header with the time from `clock`.
+    ///
+    /// The header is written by `send_status()`, and setting another `Date`
+    /// header fails with `HootError::DuplicateHeader`.
+    pub fn with_clock(mut self, clock: &dyn Clock) -> Self {
+        self.state.date = Some(clock.unix_time());
+        self
+    }
+
+    /// Send a `103 Early Hints` interim response, RFC 8297.
+    ///
+    /// Hints, typically `Link` headers for preloading, are set on the returned
+    /// response, and `end_hints()` returns to sending the status. This can be
+    /// repeated before the final status.
+    ///
+    /// Fails with `HootError::InterimHttp10` for an HTTP/1.0 request, since
+    /// HTTP/1.0 clients don't know interim responses.
+    ///
+    /// ```
+    /// # use hoot::server::{Request, Response, ResponseVariant};
+    /// use hoot::Link;
+    /// # let mut buf = [0; 1024];
+    /// # let mut headers = hoot::util::HeaderBuf::<16>::new();
+    /// # let mut request = Request::new();
+    /// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+    /// # let token = match request.proceed().into_response()? {
+    /// #     ResponseVariant::Get(v) => v,
+    /// #     _ => unreachable!(),
+    /// # };
+    /// let style = Link::new("/style.css").with_rel("preload");
+    ///
+    /// let output = Response::resume(token, &mut buf)
+    ///     .early_hints()?
+    ///     .link(&style)?
+    ///     .end_hints()?
+    ///     .send_status(200, "OK")?
+    ///     .link(&style)?
+    ///     .flush();
+    ///
+    /// assert_eq!(
+    ///     &*output,
+    ///     b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=\"preload\"\r\n\r\n\
+    ///     HTTP/1.1 200 OK\r\nLink: </style.css>; rel=\"preload\"\r\n"
+    /// );
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn early_hints(mut self) -> Result<Response<'a, SEND_HINTS, M, ()>> {
+        if self.state.version != Some(HttpVersion::Http11) {
+            return Err(HootError::InterimHttp10);
+        }
+
+        trace!("Send early hints");
+
+        let mut w = self.out.writer();
+        w.write_bytes(b"HTTP/1.1 103 Early Hints\r\n")?;
+        w.commit();
+
+        Ok(self.transition())
+    }
+
     pub fn send_status(
         mut self,
         code: u16,
         text: &str,
     ) -> Result<Response<'a, SEND_HEADERS, M, ()>> {
-        // Unwrap is OK, because the request must have set the version.
-        let ver = match self.state.version.unwrap() {
+        let ver = match self.version()? {
             HttpVersion::Http10 => "1.0",
             HttpVersion::Http11 => "1.1",
         };
@@ -123,9 +304,81 @@ impl<'a, M: Method> Response<'a, SEND_STATUS, M, ()> {
         trace!("Send status: {} {} HTTP/{}", code, text, ver);
 
         let mut w = self.out.writer();
-        write!(w, "HTTP/{} {} {}\r\n", ver, code, text).or(OVERFLOW)?;
+        write!(w, "HTTP/{} {} {}\r\n", ver, code, text)?;
+        w.commit();
+
+        self.state.sent_status = Some(code);
+
+        let next: Response<'a, SEND_HEADERS, M, ()> = self.transition();
+
+        match next.state.date {
+            Some(secs) => next.header_write("Date", &format_http_date(secs), false, false),
+            None => Ok(next),
+        }
+    }
+}
+
+impl<'a, M: Method> Response<'a, SEND_STATUS, M, ()> {
+    /// Send `426 Upgrade Required`, refusing the request unless it upgrades.
+    ///
+    /// `protocols` is the `Upgrade` header, such as `TLS/1.2, HTTP/1.1`, in
+    /// order of preference. `Connection: Upgrade` is sent along. The response
+    /// continues with more headers and a body, such as a text explaining the
+    /// refusal. See [`RequestAttempt::wants_upgrade()`](crate::server::RequestAttempt::wants_upgrade)
+    /// for whether a request offers a protocol.
+    ///
+    /// ```
+    /// # use hoot::server::{Request, Response, ResponseVariant};
+    /// # let mut buf = [0; 1024];
+    /// # let mut headers = hoot::util::HeaderBuf::<16>::new();
+    /// # let mut request = Request::new();
+    /// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+    /// # let token = match request.proceed().into_response()? {
+    /// #     ResponseVariant::Get(v) => v,
+    /// #     _ => unreachable!(),
+    /// # };
+    /// let output = Response::resume(token, &mut buf)
+    ///     .upgrade_required("TLS/1.2, HTTP/1.1")?
+    ///     .send_empty()?
+    ///     .flush();
+    ///
+    /// assert_eq!(
+    ///     &*output,
+    ///     b"HTTP/1.1 426 Upgrade Required\r\nUpgrade: TLS/1.2, HTTP/1.1\r\n\
+    ///     Connection: Upgrade\r\nContent-Length: 0\r\n\r\n"
+    /// );
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn upgrade_required(self, protocols: &str) -> Result<Response<'a, SEND_HEADERS, M, ()>> {
+        trace!("Upgrade required: {}", protocols);
+        self.send_status(426, "Upgrade Required")?
+            .header("Upgrade", protocols)?
+            .header("Connection", "Upgrade")
+    }
+}
+
+impl<'a, M: Method> Response<'a, SEND_HINTS, M, ()> {
+    pub fn header(self, name: &str, value: &str) -> Result<Self> {
+        trace!("Set hint header {}: {}", name, value);
+        self.header_raw(name, value.as_bytes(), false)
+    }
+
+    /// Send a `Link` header in the hints, see `link()` on the final response.
+    pub fn link(self, link: &Link<'_>) -> Result<Self> {
+        self.link_write(link)
+    }
+
+    /// End the `103 Early Hints` to send another, or the final status.
+    pub fn end_hints(mut self) -> Result<Response<'a, SEND_STATUS, M, ()>> {
+        trace!("End early hints");
+
+        let mut w = self.out.writer();
+        w.write_bytes(b"\r\n")?;
         w.commit();
 
+        // Headers of the final response are a separate section.
+        self.state.sent_singular = 0;
+
         Ok(self.transition())
     }
 }
@@ -140,6 +393,237 @@ impl<'a, M: Method> Response<'a, SEND_HEADERS, M, ()> {
         trace!("Set header bytes {}: {:?}", name, bytes);
         self.header_raw(name, bytes, false)
     }
+
+    /// Set a header without validating the name and value.
+    ///
+    /// For trusted constants only. Values from user input must use `header()`,
+    /// which rejects control characters that could inject headers. Forbidden
+    /// and repeated headers are still rejected.
+    pub fn header_unchecked(self, name: &str, value: &str) -> Result<Self> {
+        trace!("Set header unchecked {}: {}", name, value);
+        self.header_write(name, value.as_bytes(), false, false)
+    }
+}
+
+impl<'a, M: Method> Response<'a, SEND_HEADERS, M, ()> {
+    /// Send a `Warning` header, RFC 7234 §5.5.
+    ///
+    /// `code` is the three digit warn-code, such as `299` for a persistent
+    /// miscellaneous warning. `agent` is the host adding the warning, or `-`
+    /// when unknown. `text` is sent as a quoted string, escaping `"` and `\`.
+    /// `date` is seconds since the Unix epoch, sent as an HTTP-date.
+    ///
+    /// Errors with `HootError::HeaderValue` if the code is not three digits,
+    /// the agent is not a host or token, or the text has control characters.
+    ///
+    /// ```
+    /// # use hoot::server::{Request, Response, ResponseVariant};
+    /// # let mut buf = [0; 1024];
+    /// # let mut headers = hoot::util::HeaderBuf::<16>::new();
+    /// # let mut request = Request::new();
+    /// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+    /// # let token = match request.proceed().into_response()? {
+    /// #     ResponseVariant::Get(v) => v,
+    /// #     _ => unreachable!(),
+    /// # };
+    /// let output = Response::resume(token, &mut buf)
+    ///     .send_status(200, "OK")?
+    ///     .warning(299, "-", "v1 is \"deprecated\"", Some(784111777))?
+    ///     .flush();
+    ///
+    /// assert_eq!(
+    ///     &*output,
+    ///     b"HTTP/1.1 200 OK\r\nWarning: 299 - \"v1 is \\\"deprecated\\\"\" \
+    ///     \"Sun, 06 Nov 1994 08:49:37 GMT\"\r\n"
+    /// );
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn warning(
+        mut self,
+        code: u16,
+        agent: &str,
+        text: &str,
+        date: Option<u64>,
+    ) -> Result<Self> {
+        trace!("Set warning: {} {} {}", code, agent, text);
+
+        if !(100..1000).contains(&code) {
+            return Err(HootError::HeaderValue { offset: 0 });
+        }
+
+        // warn-agent = ( uri-host [ ":" port ] ) / pseudonym
+        let is_agent = |b: u8| is_tchar(b) || b":[]".contains(&b);
+        if agent.is_empty() {
+            return Err(HootError::HeaderValue { offset: 4 });
+        }
+        if let Some(i) = agent.bytes().position(|b| !is_agent(b)) {
+            return Err(HootError::HeaderValue { offset: 4 + i });
+        }
+
+        if let Some(i) = text
+            .bytes()
+            .position(|b| (b < 0x20 && b != b'\t') || b == 0x7f)
+        {
+            return Err(HootError::HeaderValue {
+                offset: 4 + agent.len() + 2 + i,
+            });
+        }
+
+        let date = date.map(format_http_date);
+        let date = match &date {
+            Some(d) => Some(str::from_utf8(d)?),
+            None => None,
+        };
+
+        let style = HeaderStyle::of(self.version()?, &self.state);
+        let w = self.out.writer();
+        let sent = &mut self.state.sent_singular;
+        let value = format_args!("{} {} {}{}", code, agent, Quoted(text), WarnDate(date));
+        output_header_fmt(w, style, "Warning", value, sent)?;
+
+        Ok(self)
+    }
+
+    /// Send a `Deprecation` header, RFC 9745.
+    ///
+    /// `secs` is when the resource was or will be deprecated, as seconds since
+    /// the Unix epoch. Pair with `sunset()` for when it stops working.
+    ///
+    /// ```
+    /// # use hoot::server::{Request, Response, ResponseVariant};
+    /// # let mut buf = [0; 1024];
+    /// # let mut headers = hoot::util::HeaderBuf::<16>::new();
+    /// # let mut request = Request::new();
+    /// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+    /// # let token = match request.proceed().into_response()? {
+    /// #     ResponseVariant::Get(v) => v,
+    /// #     _ => unreachable!(),
+    /// # };
+    /// let output = Response::resume(token, &mut buf)
+    ///     .send_status(200, "OK")?
+    ///     .deprecation(1688169599)?
+    ///     .sunset(1719791999)?
+    ///     .flush();
+    ///
+    /// assert_eq!(
+    ///     &*output,
+    ///     b"HTTP/1.1 200 OK\r\nDeprecation: @1688169599\r\n\
+    ///     Sunset: Sun, 30 Jun 2024 23:59:59 GMT\r\n"
+    /// );
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn deprecation(mut self, secs: u64) -> Result<Self> {
+        trace!("Set deprecation: {}", secs);
+
+        let style = HeaderStyle::of(self.version()?, &self.state);
+        let w = self.out.writer();
+        let sent = &mut self.state.sent_singular;
+        output_header_fmt(w, style, "Deprecation", format_args!("@{}", secs), sent)?;
+
+        Ok(self)
+    }
+
+    /// Send a `Sunset` header, RFC 8594.
+    ///
+    /// `secs` is when the resource is expected to stop responding, as seconds
+    /// since the Unix epoch.
+    pub fn sunset(self, secs: u64) -> Result<Self> {
+        trace!("Set sunset: {}", secs);
+        self.header_write("Sunset", &format_http_date(secs), false, false)
+    }
+}
+
+impl<'a, M: Method> Response<'a, SEND_HEADERS, M, ()> {
+    /// Send a `Link` header, RFC 8288, such as for pagination.
+    ///
+    /// Each call sends one `Link` header.
+    ///
+    /// Errors with `HootError::HeaderValue` if the target has whitespace,
+    /// control characters or `>`, or the rel or media type has control
+    /// characters, `"` or `\`.
+    ///
+    /// ```
+    /// # use hoot::server::{Request, Response, ResponseVariant};
+    /// use hoot::Link;
+    /// # let mut buf = [0; 1024];
+    /// # let mut headers = hoot::util::HeaderBuf::<16>::new();
+    /// # let mut request = Request::new();
+    /// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+    /// # let token = match request.proceed().into_response()? {
+    /// #     ResponseVariant::Get(v) => v,
+    /// #     _ => unreachable!(),
+    /// # };
+    /// let output = Response::resume(token, &mut buf)
+    ///     .send_status(200, "OK")?
+    ///     .link(&Link::new("/items?page=3").with_rel("next"))?
+    ///     .link(&Link::new("/items?page=9").with_rel("last"))?
+    ///     .flush();
+    ///
+    /// assert_eq!(
+    ///     &*output,
+    ///     b"HTTP/1.1 200 OK\r\nLink: </items?page=3>; rel=\"next\"\r\n\
+    ///     Link: </items?page=9>; rel=\"last\"\r\n"
+    /// );
+    /// # Ok::<(), hoot::HootError>(())
+    /// ```
+    pub fn link(self, link: &Link<'_>) -> Result<Self> {
+        self.link_write(link)
+    }
+}
+
+/// A quoted-string, RFC 7230 §3.2.6.
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            if c == '"' || c == '\\' {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        f.write_char('"')
+    }
+}
+
+/// The optional trailing warn-date.
+struct WarnDate<'a>(Option<&'a str>);
+
+impl fmt::Display for WarnDate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(date) => write!(f, " {}", Quoted(date)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, M: Method> Response<'a, SEND_HEADERS, M, ()> {
+    /// End the response without a body.
+    ///
+    /// `Content-Length: 0` is sent, unless the method or status has no body
+    /// at all, as for `HEAD`, `204 No Content` or `304 Not Modified`.
+    pub fn send_empty(mut self) -> Result<Response<'a, ENDED, (), ()>> {
+        let status = self.state.sent_status.unwrap_or(200);
+        let no_body = M::is_head()
+            || M::is_connect() && (200..300).contains(&status)
+            || status < 200
+            || status == 204
+            || status == 304;
+
+        trace!("Send empty, no body: {}", no_body);
+
+        let mut w = self.out.writer();
+        if no_body {
+            write!(w, "\r\n")?;
+        } else {
+            write!(w, "Content-Length: 0\r\n\r\n")?;
+        }
+        w.commit();
+
+        Ok(self.transition())
+    }
 }
 
 impl<'a, M: MethodWithResponseBody> Response<'a, SEND_HEADERS, M, ()> {
@@ -152,7 +636,7 @@ impl<'a, M: MethodWithResponseBody> Response<'a, SEND_HEADERS, M, ()> {
         trace!("Length delimited body: {}", length);
 
         let mut w = self.out.writer();
-        write!(w, "Content-Length: {}\r\n\r\n", length).or(OVERFLOW)?;
+        write!(w, "Content-Length: {}\r\n\r\n", length)?;
         w.commit();
 
         self.state.send_checker = Some(LengthChecker::new(length));
@@ -160,21 +644,65 @@ impl<'a, M: MethodWithResponseBody> Response<'a, SEND_HEADERS, M, ()> {
         Ok(self.transition())
     }
 
+    /// Send an entire body, with `Content-Length` set to its length.
+    ///
+    /// Same as `with_body()`, `write_bytes()` and `finish()`, except the entire
+    /// body must fit in the buffer.
+    pub fn send_body_full(mut self, body: &[u8]) -> Result<Response<'a, ENDED, (), ()>> {
+        trace!("Send full body: {}", body.len());
+
+        let mut w = self.out.writer();
+        write!(w, "Content-Length: {}\r\n\r\n", body.len())?;
+        w.write_bytes(body)?;
+        w.commit();
+
+        Ok(self.transition_with_body(body.len()))
+    }
+
+    /// Send a body of unknown length in chunks.
+    ///
+    /// For an HTTP/1.0 request, the chunks are written without framing, and
+    /// the body ends when the connection is closed after `finish()`.
     pub fn with_chunked(mut self) -> Result<Response<'a, SEND_BODY, M, BODY_CHUNKED>> {
         trace!("Chunked body");
 
+        let chunked = self.is_chunked();
         let mut w = self.out.writer();
-        write!(w, "Transfer-Encoding: chunked\r\n\r\n").or(OVERFLOW)?;
+        if chunked {
+            write!(w, "Transfer-Encoding: chunked\r\n\r\n")?;
+        } else {
+            write!(w, "\r\n")?;
+        }
         w.commit();
 
         Ok(self.transition())
     }
 
-    pub fn without_body(mut self) -> Result<Response<'a, RECV_RESPONSE, M, ()>> {
+    /// Send `value` serialized as JSON, with `Content-Length`.
+    ///
+    /// `Content-Type: application/json` is added unless set as a header. The
+    /// entire value must fit in the buffer, use `with_chunked()` and
+    /// `write_json()` to send larger bodies in parts.
+    #[cfg(feature = "serde")]
+    pub fn send_json<T: serde::Serialize + ?Sized>(
+        mut self,
+        value: &T,
+    ) -> Result<Response<'a, ENDED, (), ()>> {
+        let content_type = !is_sent(self.state.sent_singular, "content-type");
+
+        let mut w = self.out.writer();
+        let len = write_json_body(&mut w, value, content_type)?;
+        w.commit();
+
+        Ok(self.transition_with_body(len))
+    }
+
+    /// End the response without a body, such as for `304 Not Modified`.
+    pub fn without_body(mut self) -> Result<Response<'a, ENDED, (), ()>> {
         trace!("Without body");
 
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
         w.commit();
 
         Ok(self.transition())
@@ -187,7 +715,7 @@ impl<'a, M: MethodWithoutResponseBody> Response<'a, SEND_HEADERS, M, ()> {
         trace!("Without body");
 
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        write!(w, "\r\n")?;
         w.commit();
 
         Ok(self.transition())
@@ -196,34 +724,77 @@ impl<'a, M: MethodWithoutResponseBody> Response<'a, SEND_HEADERS, M, ()> {
 
 impl<'a, M: MethodWithResponseBody> Response<'a, SEND_BODY, M, BODY_LENGTH> {
     #[inline(always)]
-    fn checker(&mut self) -> &mut LengthChecker {
-        self.state
-            .send_checker
-            .as_mut()
-            // If we don't have the checker when in type state SEND_BODY, we got a bug.
-            .expect("SendByteCheck when SEND_BODY")
+    fn checker(&mut self) -> Result<&mut LengthChecker> {
+        // If we don't have the checker when in type state SEND_BODY, we got a bug.
+        self.state.send_checker.as_mut().ok_or(HootError::Internal)
     }
 
+    /// Write body bytes.
+    ///
+    /// If the output buffer can't hold all bytes, as much as fits is written and
+    /// this returns `HootError::OutputFull` with the amount. Call `.flush()` and
+    /// continue with the rest of the bytes after resuming.
     pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
         trace!("Write bytes len: {}", bytes.len());
 
         // This returns Err if we try to write more bytes than content-length.
-        self.checker()
-            .append(bytes.len(), HootError::SentMoreThanContentLength)?;
+        self.checker()?.check(bytes.len(), |expected, actual| {
+            HootError::SentMoreThanContentLength { expected, actual }
+        })?;
+
+        let n = bytes.len().min(self.out.available());
 
         let mut w = self.out.writer();
-        w.write_bytes(bytes)?;
+        w.write_bytes(&bytes[..n])?;
         w.commit();
 
+        self.checker()?
+            .append(n, |expected, actual| HootError::SentMoreThanContentLength {
+                expected,
+                actual,
+            })?;
+
+        if n < bytes.len() {
+            return Err(HootError::OutputFull(n));
+        }
+
         Ok(())
     }
 
+    /// Write body bytes like `write_bytes()`, feeding what is written to `hasher`.
+    pub fn write_bytes_hashed(&mut self, bytes: &[u8], hasher: &mut dyn BodyHasher) -> Result<()> {
+        let result = self.write_bytes(bytes);
+        hash_written(result, bytes, hasher)
+    }
+
+    /// Write body bytes read straight into the buffer from a [`BodySource`].
+    ///
+    /// Returns the amount written, which is 0 when the buffer is full or the
+    /// source has nothing right now. Call `finish()` once `Content-Length`
+    /// bytes are written.
+    pub fn write_body_from(&mut self, source: &mut dyn BodySource) -> Result<usize> {
+        let checker = self
+            .state
+            .send_checker
+            .as_mut()
+            .ok_or(HootError::Internal)?;
+
+        let mut w = self.out.writer();
+        let n = write_length_from_source(&mut w, checker, source)?;
+        w.commit();
+
+        trace!("Write body from source: {}", n);
+
+        Ok(n)
+    }
+
     pub fn finish(mut self) -> Result<Response<'a, ENDED, (), ()>> {
         trace!("Body finished");
 
         // This returns Err if we have written less than content-length.
-        self.checker()
-            .assert_expected(HootError::SentLessThanContentLength)?;
+        self.checker()?.assert_expected(|expected, actual| {
+            HootError::SentLessThanContentLength { expected, actual }
+        })?;
 
         Ok(self.transition())
     }
@@ -238,38 +809,170 @@ impl<'a, M: MethodWithResponseBody> Response<'a, SEND_BODY, M, BODY_CHUNKED> {
             return Ok(self);
         }
 
+        let chunked = self.is_chunked();
         let mut w = self.out.writer();
 
         // chunk length
-        write!(w, "{:0x?}\r\n", bytes.len()).or(OVERFLOW)?;
+        if chunked {
+            write!(w, "{:0x?}\r\n", bytes.len())?;
+        }
 
         // chunk
         w.write_bytes(bytes)?;
 
         // chunk end
-        write!(w, "\r\n").or(OVERFLOW)?;
+        if chunked {
+            write!(w, "\r\n")?;
+        }
 
         w.commit();
 
         Ok(self)
     }
 
-    pub fn with_trailer(mut self) -> Result<Response<'a, SEND_TRAILER, M, BODY_CHUNKED>> {
-        trace!("With trailer");
+    /// Write a chunk where `f` produces the data directly into the buffer.
+    ///
+    /// Returns 0 if `f` wrote nothing or there is no room for another chunk.
+    #[cfg(any(feature = "multipart", feature = "reply"))]
+    pub(crate) fn write_chunk_from(
+        &mut self,
+        f: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> Result<usize> {
+        let chunked = self.is_chunked();
+        let mut w = self.out.writer();
+        let n = w.write_body_with(chunked, f)?;
+        w.commit();
+
+        trace!("Write chunk from len: {}", n);
+
+        Ok(n)
+    }
+
+    /// Write a `multipart/x-mixed-replace` part as one chunk.
+    ///
+    /// Call `.flush()` after each part to send it to the client.
+    #[cfg(feature = "multipart")]
+    pub fn write_part(
+        mut self,
+        multipart: &MixedReplace<'_>,
+        headers: &[(&str, &str)],
+        data: &[u8],
+    ) -> Result<Self> {
+        trace!("Write part len: {}", data.len());
+
+        let n = self.write_chunk_from(|buf| multipart.write_part(headers, data, buf))?;
+
+        if n == 0 {
+            return Err(HootError::OutputOverflow { needed: 1 });
+        }
+
+        Ok(self)
+    }
+
+    /// Write the `multipart/x-mixed-replace` closing delimiter and finish the body.
+    #[cfg(feature = "multipart")]
+    pub fn finish_parts(
+        mut self,
+        multipart: &MixedReplace<'_>,
+    ) -> Result<Response<'a, ENDED, (), ()>> {
+        let n = self.write_chunk_from(|buf| multipart.write_close(buf))?;
+
+        if n == 0 {
+            return Err(HootError::OutputOverflow { needed: 1 });
+        }
+
+        self.finish()
+    }
+
+    /// Write bytes as a chunk, without consuming `self`.
+    ///
+    /// If the output buffer can't hold all bytes, as much as fits is written as
+    /// a chunk and this returns `HootError::OutputFull` with the amount. Call
+    /// `.flush()` and continue with the rest of the bytes after resuming.
+    pub fn write_chunk_partial(&mut self, bytes: &[u8]) -> Result<()> {
+        trace!("Write chunk partial len: {}", bytes.len());
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut n = 0;
 
+        let chunked = self.is_chunked();
         let mut w = self.out.writer();
-        write!(w, "0\r\n").or(OVERFLOW)?;
+        w.write_body_with(chunked, |buf| {
+            n = bytes.len().min(buf.len());
+            buf[..n].copy_from_slice(&bytes[..n]);
+            Ok(n)
+        })?;
         w.commit();
 
+        if n < bytes.len() {
+            return Err(HootError::OutputFull(n));
+        }
+
+        Ok(())
+    }
+
+    /// Write a chunk like `write_chunk_partial()`, feeding what is written to `hasher`.
+    pub fn write_chunk_hashed(&mut self, bytes: &[u8], hasher: &mut dyn BodyHasher) -> Result<()> {
+        let result = self.write_chunk_partial(bytes);
+        hash_written(result, bytes, hasher)
+    }
+
+    /// Write a chunk read straight into the buffer from a [`BodySource`].
+    ///
+    /// Returns the amount of body written, which is 0 when the buffer is full
+    /// or the source has nothing right now. Call `finish()` once the source
+    /// has ended.
+    pub fn write_body_from(&mut self, source: &mut dyn BodySource) -> Result<usize> {
+        let chunked = self.is_chunked();
+        let mut w = self.out.writer();
+        let n = write_chunk_from_source(&mut w, source, chunked)?;
+        w.commit();
+
+        trace!("Write chunk from source: {}", n);
+
+        Ok(n)
+    }
+
+    /// Write `value` serialized as JSON as one chunk.
+    ///
+    /// Fails with `HootError::OutputOverflow` if the value does not fit in the
+    /// buffer, in which case nothing is written.
+    #[cfg(feature = "serde")]
+    pub fn write_json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Result<Self> {
+        let chunked = self.is_chunked();
+        let mut w = self.out.writer();
+        let n = w.write_body_with(chunked, |buf| to_slice(value, buf))?;
+        w.commit();
+
+        trace!("Write JSON chunk len: {}", n);
+
+        Ok(self)
+    }
+
+    /// Send trailers after the body. They are dropped for HTTP/1.0 requests.
+    pub fn with_trailer(mut self) -> Result<Response<'a, SEND_TRAILER, M, BODY_CHUNKED>> {
+        trace!("With trailer");
+
+        if self.is_chunked() {
+            let mut w = self.out.writer();
+            write!(w, "0\r\n")?;
+            w.commit();
+        }
+
         Ok(self.transition())
     }
 
     pub fn finish(mut self) -> Result<Response<'a, ENDED, (), ()>> {
         trace!("Body chunks finished");
 
-        let mut w = self.out.writer();
-        write!(w, "0\r\n\r\n").or(OVERFLOW)?;
-        w.commit();
+        if self.is_chunked() {
+            let mut w = self.out.writer();
+            write!(w, "0\r\n\r\n")?;
+            w.commit();
+        }
 
         Ok(self.transition())
     }
@@ -280,20 +983,50 @@ impl<'a, M: MethodWithResponseBody> Response<'a, SEND_TRAILER, M, BODY_CHUNKED>
     pub fn trailer(self, name: &str, value: &str) -> Result<Self> {
         trace!("Set trailer {}: {}", name, value);
 
-        self.header_raw(name, value.as_bytes(), true)
+        self.trailer_bytes(name, value.as_bytes())
     }
 
     pub fn trailer_bytes(self, name: &str, bytes: &[u8]) -> Result<Self> {
         trace!("Set trailer bytes {}: {:?}", name, bytes);
 
+        if !self.is_chunked() {
+            debug!("Drop trailer for HTTP/1.0: {}", name);
+            return Ok(self);
+        }
+
         self.header_raw(name, bytes, true)
     }
 
     pub fn finish(mut self) -> Result<Response<'a, ENDED, (), ()>> {
         trace!("Trailer finish");
 
+        if self.is_chunked() {
+            let mut w = self.out.writer();
+            write!(w, "\r\n")?;
+            w.commit();
+        }
+
+        Ok(self.transition())
+    }
+
+    /// Write a raw trailer section, such as `Trailers::raw()` from a received body.
+    ///
+    /// The bytes are passed on exactly, after checking they are a complete trailer
+    /// section, including the ending empty line, without forbidden trailers. The
+    /// check needs some free space in the buffer to parse into.
+    pub fn finish_raw(mut self, raw: &[u8]) -> Result<Response<'a, ENDED, (), ()>> {
+        trace!("Trailer finish raw: {}", raw.len());
+
+        if !self.is_chunked() {
+            return Ok(self.transition());
+        }
+
         let mut w = self.out.writer();
-        write!(w, "\r\n").or(OVERFLOW)?;
+        w.write_bytes(raw)?;
+
+        let (written, _) = w.split_and_borrow();
+        check_raw_trailers(written)?;
+
         w.commit();
 
         Ok(self.transition())
@@ -306,7 +1039,7 @@ impl<'a, S: State, M: Method, B: BodyType> Output<'a, S, M, B> {
     }
 
     pub fn as_bytes(&self) -> &[u8] {
-        &self.output
+        self.output
     }
 }
 
@@ -314,15 +1047,15 @@ impl<'a, S: State, M: Method, B: BodyType> Deref for Output<'a, S, M, B> {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &self.output
+        self.output
     }
 }
 
-impl From<CallState> for ResponseVariant {
-    fn from(value: CallState) -> Self {
-        // If we get an error on this unwrap, we have a bug.
-        let method = value.method.unwrap();
-        match method {
+impl ResponseVariant {
+    pub(crate) fn from_state(value: CallState) -> Result<Self> {
+        // The request line sets the method.
+        let method = value.method.ok_or(HootError::Internal)?;
+        Ok(match method {
             crate::Method::OPTIONS => ResponseVariant::Options(ResumeToken::new(value)),
             crate::Method::GET => ResponseVariant::Get(ResumeToken::new(value)),
             crate::Method::POST => ResponseVariant::Post(ResumeToken::new(value)),
@@ -332,6 +1065,99 @@ impl From<CallState> for ResponseVariant {
             crate::Method::TRACE => ResponseVariant::Trace(ResumeToken::new(value)),
             crate::Method::CONNECT => ResponseVariant::Connect(ResumeToken::new(value)),
             crate::Method::PATCH => ResponseVariant::Patch(ResumeToken::new(value)),
+            crate::Method::QUERY => ResponseVariant::Query(ResumeToken::new(value)),
+            crate::Method::PROPFIND => ResponseVariant::Propfind(ResumeToken::new(value)),
+            crate::Method::PROPPATCH => ResponseVariant::Proppatch(ResumeToken::new(value)),
+            crate::Method::MKCOL => ResponseVariant::Mkcol(ResumeToken::new(value)),
+            crate::Method::COPY => ResponseVariant::Copy(ResumeToken::new(value)),
+            crate::Method::MOVE => ResponseVariant::Move(ResumeToken::new(value)),
+            crate::Method::LOCK => ResponseVariant::Lock(ResumeToken::new(value)),
+            crate::Method::UNLOCK => ResponseVariant::Unlock(ResumeToken::new(value)),
+            crate::Method::Extension(_) => ResponseVariant::Extension(ResumeToken::new(value)),
+        })
+    }
+}
+
+/// Reason phrase for common status codes.
+///
+/// The reason phrase is optional in HTTP/1.1, unknown codes get an empty one.
+pub(crate) fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+impl<'a, S: State, M: Method, B: BodyType> fmt::Debug for Response<'a, S, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("typ", &self.typ)
+            .field("state", &self.state)
+            .field("available", &self.out.available())
+            .finish()
+    }
+}
+
+impl<S: State, M: Method, B: BodyType> fmt::Debug for ResumeToken<S, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResumeToken")
+            .field("typ", &self.typ)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<S: State, M: Method, B: BodyType> fmt::Debug for Typ<S, M, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            S::state_name(),
+            M::state_name(),
+            B::state_name()
+        )
+    }
+}
+
+/// Forks the response, see the `snapshot` feature.
+#[cfg(feature = "snapshot")]
+impl<S: State, M: Method, B: BodyType> Clone for ResumeToken<S, M, B> {
+    fn clone(&self) -> Self {
+        ResumeToken {
+            typ: Typ(PhantomData, PhantomData, PhantomData),
+            state: self.state.clone(),
         }
     }
 }
@@ -0,0 +1,88 @@
+use crate::header::is_tchar;
+use crate::MAX_EXT_METHOD_LEN;
+
+/// What the first bytes of a connection are, see [`sniff()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Sniff {
+    /// An HTTP/1.x request line, for [`Request`](super::Request).
+    Http1,
+    /// The HTTP/2 connection preface, `PRI * HTTP/2.0`.
+    Http2,
+    /// A TLS record with a ClientHello.
+    Tls,
+    /// None of the above.
+    Unknown,
+}
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Classify the first bytes of a connection, to route it to a handler.
+///
+/// Returns `None` if more bytes are needed to tell. Nothing is consumed, the
+/// same bytes are then given to the handler. At most 24 bytes are needed.
+///
+/// ```
+/// use hoot::server::{sniff, Sniff};
+///
+/// assert_eq!(sniff(b"GET / HTTP/1.1\r\n"), Some(Sniff::Http1));
+/// assert_eq!(sniff(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"), Some(Sniff::Http2));
+/// assert_eq!(sniff(b"\x16\x03\x01\x02\x00\x01"), Some(Sniff::Tls));
+/// assert_eq!(sniff(b"PRI * HT"), None);
+/// ```
+pub fn sniff(buf: &[u8]) -> Option<Sniff> {
+    if *buf.first()? == 0x16 {
+        return sniff_tls(buf);
+    }
+
+    let n = buf.len().min(PREFACE.len());
+    if buf[..n] == PREFACE[..n] {
+        return (n == PREFACE.len()).then(|| Sniff::Http2);
+    }
+
+    // A request line starts with a method token and a space.
+    match buf.iter().position(|b| !is_tchar(*b)) {
+        Some(i) if i > 0 && buf[i] == b' ' => Some(Sniff::Http1),
+        Some(_) => Some(Sniff::Unknown),
+        None if buf.len() <= MAX_EXT_METHOD_LEN => None,
+        None => Some(Sniff::Unknown),
+    }
+}
+
+/// A TLS handshake record, protocol version 3.x, with a ClientHello.
+fn sniff_tls(buf: &[u8]) -> Option<Sniff> {
+    let expected = [(1, 0x03..=0x03), (2, 0x00..=0x04), (5, 0x01..=0x01)];
+
+    for (i, range) in expected.iter() {
+        match buf.get(*i) {
+            Some(b) if !range.contains(b) => return Some(Sniff::Unknown),
+            Some(_) => {}
+            None => return None,
+        }
+    }
+
+    Some(Sniff::Tls)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sniff_prefixes() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"GE"), None);
+        assert_eq!(sniff(b"PRI "), None);
+        assert_eq!(sniff(b"PRI / HTTP/1.1\r\n"), Some(Sniff::Http1));
+        assert_eq!(sniff(b"PROPFIND "), Some(Sniff::Http1));
+        assert_eq!(sniff(b" GET"), Some(Sniff::Unknown));
+        assert_eq!(sniff(b"GET\r\n"), Some(Sniff::Unknown));
+        assert_eq!(sniff(&[b'A'; 30]), Some(Sniff::Unknown));
+
+        assert_eq!(sniff(b"\x16\x03"), None);
+        assert_eq!(sniff(b"\x16\x03\x03\x00\x10"), None);
+        assert_eq!(sniff(b"\x16\x02"), Some(Sniff::Unknown));
+        assert_eq!(sniff(b"\x16\x03\x01\x00\x10\x02"), Some(Sniff::Unknown));
+        assert_eq!(sniff(b"\x00\x00"), Some(Sniff::Unknown));
+    }
+}
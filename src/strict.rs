@@ -0,0 +1,115 @@
+use core::fmt;
+use core::str;
+
+use crate::util::compare_lowercase_ascii;
+use crate::{Header, HootError, Result};
+
+/// Which rule of strict parsing was violated.
+///
+/// Strict parsing is enabled with `client::Response::set_strict()` or
+/// `server::Request::set_strict()`. It rejects messages that peers might frame
+/// differently, as described in RFC 7230 §3.3.3, which is the basis of request
+/// smuggling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Violation {
+    /// Both `Transfer-Encoding` and `Content-Length` are present.
+    TransferEncodingAndContentLength,
+    /// A header line continued with leading whitespace (obs-fold).
+    ObsFold,
+    /// Whitespace between the header name and the colon.
+    SpaceBeforeColon,
+    /// `Transfer-Encoding` where `chunked` is missing or not the final coding.
+    NonFinalChunked,
+    /// A line ending with LF only, without CR.
+    BareLf,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Violation::TransferEncodingAndContentLength => {
+                "both transfer-encoding and content-length"
+            }
+            Violation::ObsFold => "obsolete line folding",
+            Violation::SpaceBeforeColon => "whitespace before colon",
+            Violation::NonFinalChunked => "chunked is not the final transfer-coding",
+            Violation::BareLf => "line ending without carriage return",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn violated(which: Violation) -> Result<()> {
+    Err(HootError::StrictViolation { which })
+}
+
+/// Check the parsed headers for ambiguous body framing.
+pub(crate) fn check_framing(headers: &[Header<'_>]) -> Result<()> {
+    let mut content_length = false;
+    let mut transfer_encoding = false;
+    let mut last_coding_chunked = false;
+
+    for head in headers {
+        if compare_lowercase_ascii(head.name(), "content-length") {
+            content_length = true;
+        } else if compare_lowercase_ascii(head.name(), "transfer-encoding") {
+            transfer_encoding = true;
+
+            let s = str::from_utf8(head.value_raw())?;
+            for coding in s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+                // chunked must not be applied more than once.
+                if last_coding_chunked {
+                    return violated(Violation::NonFinalChunked);
+                }
+                last_coding_chunked = compare_lowercase_ascii(coding, "chunked");
+            }
+        }
+    }
+
+    if transfer_encoding && content_length {
+        return violated(Violation::TransferEncodingAndContentLength);
+    }
+
+    if transfer_encoding && !last_coding_chunked {
+        return violated(Violation::NonFinalChunked);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse_headers;
+    use crate::util::HeaderBuf;
+
+    fn check(head: &[u8]) -> Result<()> {
+        let mut buf = HeaderBuf::<16>::new();
+        let headers = parse_headers(head, &mut buf)?;
+        check_framing(headers)
+    }
+
+    #[test]
+    fn strict_framing() {
+        let err = |which| Err(HootError::StrictViolation { which });
+
+        assert_eq!(check(b"Transfer-Encoding: gzip, chunked\r\n\r\n"), Ok(()));
+        assert_eq!(
+            check(b"Transfer-Encoding: chunked\r\nContent-Length: 2\r\n\r\n"),
+            err(Violation::TransferEncodingAndContentLength)
+        );
+        assert_eq!(
+            check(b"Transfer-Encoding: chunked, gzip\r\n\r\n"),
+            err(Violation::NonFinalChunked)
+        );
+        assert_eq!(
+            check(b"Transfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            err(Violation::NonFinalChunked)
+        );
+        assert_eq!(
+            check(b"Transfer-Encoding: gzip\r\n\r\n"),
+            err(Violation::NonFinalChunked)
+        );
+    }
+}
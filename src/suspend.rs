@@ -0,0 +1,562 @@
+//! Suspending an exchange as bytes.
+//!
+//! The `ResumeToken` of `server::Response` and `client::Request` can be
+//! written to [`RESUME_TOKEN_LEN`] bytes with `write_to()`, and restored with
+//! `read_from()`. Deeply embedded targets can park an exchange in retained
+//! memory across a sleep cycle, or move it between cores, without keeping the
+//! token alive.
+//!
+//! The bytes are only valid for the same version of hoot, and the type state
+//! of the restored token must be that of the written one. A function set with
+//! `server::Request::set_has_request_body()` is not kept, since it is only
+//! used before there is a token. Neither is the scan of a partly read head,
+//! which is complete by the time there is a token.
+//!
+//! ```
+//! use hoot::server::{Request, Response, ResponseVariant, ResumeToken};
+//! use hoot::suspend::RESUME_TOKEN_LEN;
+//! use hoot::types::body::BODY_LENGTH;
+//! use hoot::types::method::GET;
+//! use hoot::types::state::SEND_BODY;
+//! use hoot::util::HeaderBuf;
+//!
+//! let mut buf = [0; 1024];
+//! let mut headers = HeaderBuf::<16>::new();
+//! let mut request = Request::new();
+//! request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut headers)?;
+//! let token = match request.proceed().into_response()? {
+//!     ResponseVariant::Get(v) => v,
+//!     _ => unreachable!(),
+//! };
+//!
+//! let token = Response::resume(token, &mut buf)
+//!     .send_status(200, "OK")?
+//!     .with_body(5)?
+//!     .flush()
+//!     .ready();
+//!
+//! let mut parked = [0; RESUME_TOKEN_LEN];
+//! token.write_to(&mut parked)?;
+//!
+//! // Later, possibly after a sleep.
+//! let token: ResumeToken<SEND_BODY, GET, BODY_LENGTH> = ResumeToken::read_from(&parked)?;
+//! let mut response = Response::resume(token, &mut buf);
+//! response.write_bytes(b"hello")?;
+//! let output = response.finish()?.flush();
+//! assert_eq!(&output[..], b"hello");
+//! # Ok::<(), hoot::HootError>(())
+//! ```
+
+use crate::body::RecvBodyMode;
+use crate::chunk::Dechunker;
+use crate::parser::ParseMode;
+use crate::util::LengthChecker;
+use crate::HttpVersion;
+use crate::{CallState, ContentEncoding, ContentLengthPolicy, ExcessBodyPolicy, ExtMethod};
+use crate::{HootError, Method, RequestBodyPolicy, Result, MAX_EXT_METHOD_LEN};
+
+/// Length of a written `ResumeToken`.
+pub const RESUME_TOKEN_LEN: usize = 268;
+
+const TOKEN_VERSION: u8 = 2;
+
+const METHODS: [Method; 17] = [
+    Method::OPTIONS,
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::HEAD,
+    Method::TRACE,
+    Method::CONNECT,
+    Method::PATCH,
+    Method::QUERY,
+    Method::PROPFIND,
+    Method::PROPPATCH,
+    Method::MKCOL,
+    Method::COPY,
+    Method::MOVE,
+    Method::LOCK,
+    Method::UNLOCK,
+];
+
+const ENCODINGS: [ContentEncoding; 5] = [
+    ContentEncoding::Gzip,
+    ContentEncoding::Deflate,
+    ContentEncoding::Brotli,
+    ContentEncoding::Compress,
+    ContentEncoding::Other,
+];
+
+/// Identifies the type state of a token, from the state names (FNV-1a).
+pub(crate) fn typestate_id(names: &[&str]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in names.iter().flat_map(|n| n.bytes().chain(Some(b'/'))) {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// Write `state` for a token of type state `id` into `dst`.
+pub(crate) fn write_state(state: &CallState, id: u32, dst: &mut [u8]) -> Result<usize> {
+    if dst.len() < RESUME_TOKEN_LEN {
+        return Err(HootError::OutputOverflow {
+            needed: RESUME_TOKEN_LEN - dst.len(),
+        });
+    }
+    let dst = &mut dst[..RESUME_TOKEN_LEN];
+    let mut w = Enc { dst, pos: 0 };
+
+    w.u8(TOKEN_VERSION);
+    w.u32(id);
+
+    w.u8(match state.version {
+        None => 0,
+        Some(HttpVersion::Http10) => 1,
+        Some(HttpVersion::Http11) => 2,
+    });
+
+    let (tag, name) = match &state.method {
+        None => (0, ""),
+        Some(Method::Extension(m)) => (u8::MAX, m.as_str()),
+        Some(m) => (index_of(&METHODS, *m) + 1, ""),
+    };
+    w.u8(tag);
+    w.u8(name.len() as u8);
+    let mut padded = [0; MAX_EXT_METHOD_LEN];
+    padded[..name.len()].copy_from_slice(name.as_bytes());
+    w.bytes(&padded);
+
+    w.checker(state.send_checker.as_ref());
+    match state.recv_body_mode {
+        None => w.tagged(0, 0),
+        Some(RecvBodyMode::LengthDelimited(n)) => w.tagged(1, n),
+        Some(RecvBodyMode::Chunked) => w.tagged(2, 0),
+        Some(RecvBodyMode::CloseDelimited) => w.tagged(3, 0),
+    }
+    w.checker(state.recv_checker.as_ref());
+    match state.dechunker {
+        None => w.tagged(0, 0),
+        Some(Dechunker::Size) => w.tagged(1, 0),
+        Some(Dechunker::Chunk(n)) => w.tagged(2, n as u64),
+        Some(Dechunker::CrLf) => w.tagged(3, 0),
+        Some(Dechunker::Ended) => w.tagged(4, 0),
+    }
+
+    w.encoding(state.content_encoding);
+    let tc = &state.transfer_codings;
+    for (i, c) in tc.codings.iter().enumerate() {
+        w.encoding((i < tc.len).then(|| *c));
+    }
+    w.u8(tc.len as u8);
+
+    w.opt_u64(state.max_recv_body);
+
+    let limits = &state.limits;
+    w.u64(limits.max_headers as u64);
+    w.u64(limits.max_header_bytes as u64);
+    w.u64(limits.max_line as u64);
+    w.u64(limits.max_target as u64);
+    w.u64(limits.max_chunk_line as u64);
+
+    w.u8(match state.parse_mode {
+        ParseMode::Normal => 0,
+        ParseMode::Strict => 1,
+        ParseMode::Lenient => 2,
+    });
+    w.u8(match state.content_length_policy {
+        ContentLengthPolicy::Reject => 0,
+        ContentLengthPolicy::AcceptIfIdentical => 1,
+    });
+    w.u8(match state.excess_body_policy {
+        ExcessBodyPolicy::Reject => 0,
+        ExcessBodyPolicy::Ignore => 1,
+        ExcessBodyPolicy::Preserve => 2,
+    });
+    w.u8(match state.request_body_policy {
+        RequestBodyPolicy::Ignore => 0,
+        RequestBodyPolicy::Read => 1,
+        RequestBodyPolicy::Reject => 2,
+    });
+    w.u8(match state.extension_methods {
+        None => 0,
+        Some(false) => 1,
+        Some(true) => 2,
+    });
+    w.u32(state.sent_singular);
+
+    let d = &state.deadline;
+    w.opt_u64(d.budgets.headers);
+    w.opt_u64(d.budgets.body);
+    w.opt_u64(d.budgets.idle);
+    w.opt_u64(d.now);
+    w.opt_u64(d.head_start);
+    w.opt_u64(d.body_start);
+    w.opt_u64(d.last_progress);
+    w.opt_u64(state.date);
+    w.u16(state.sent_status.unwrap_or(0));
+    w.u64(state.recv_unused as u64);
+    w.u64(state.recv_body_len);
+    w.u64(state.body_align.unwrap_or(0) as u64);
+    // Same layout without the metrics feature.
+    #[cfg(feature = "metrics")]
+    let metrics = (state.metrics_id, state.sent_len, state.sent_head);
+    #[cfg(not(feature = "metrics"))]
+    let metrics = (0, 0, 0);
+    w.u32(metrics.0);
+    w.u64(metrics.1);
+    w.u64(metrics.2);
+
+    let flags = [
+        state.did_read_to_end,
+        state.reject_unknown_recv_length,
+        state.recv_too_large,
+        state.percent_encode_target,
+        state.accept_http09,
+        state.expect_continue,
+        tc.present,
+        tc.chunked,
+        state.canonical_header_names,
+    ];
+    let bits = flags
+        .iter()
+        .enumerate()
+        .fold(0_u16, |acc, (i, f)| acc | (*f as u16) << i);
+    w.u16(bits);
+
+    if w.pos != RESUME_TOKEN_LEN {
+        return Err(HootError::Internal);
+    }
+    Ok(w.pos)
+}
+
+/// Read a state written by `write_state()` for type state `id`.
+pub(crate) fn read_state(src: &[u8], id: u32) -> Result<CallState> {
+    let src = src.get(..RESUME_TOKEN_LEN).ok_or(HootError::ResumeToken)?;
+    let mut r = Dec { src, pos: 0 };
+
+    if r.u8() != TOKEN_VERSION || r.u32() != id {
+        return Err(HootError::ResumeToken);
+    }
+
+    let mut state = CallState {
+        version: match r.u8() {
+            0 => None,
+            1 => Some(HttpVersion::Http10),
+            2 => Some(HttpVersion::Http11),
+            _ => return Err(HootError::ResumeToken),
+        },
+        ..Default::default()
+    };
+
+    let tag = r.u8();
+    let len = r.u8() as usize;
+    let name = r.bytes(MAX_EXT_METHOD_LEN);
+    state.method = match tag {
+        0 => None,
+        u8::MAX => {
+            let name = name.get(..len).ok_or(HootError::ResumeToken)?;
+            let name = core::str::from_utf8(name).map_err(|_| HootError::ResumeToken)?;
+            let m = ExtMethod::new(name).map_err(|_| HootError::ResumeToken)?;
+            Some(Method::Extension(m))
+        }
+        n => Some(*METHODS.get(n as usize - 1).ok_or(HootError::ResumeToken)?),
+    };
+
+    state.send_checker = r.checker()?;
+    state.recv_body_mode = match r.tagged() {
+        (0, _) => None,
+        (1, n) => Some(RecvBodyMode::LengthDelimited(n)),
+        (2, _) => Some(RecvBodyMode::Chunked),
+        (3, _) => Some(RecvBodyMode::CloseDelimited),
+        _ => return Err(HootError::ResumeToken),
+    };
+    state.recv_checker = r.checker()?;
+    state.dechunker = match r.tagged() {
+        (0, _) => None,
+        (1, _) => Some(Dechunker::Size),
+        (2, n) => Some(Dechunker::Chunk(r.usize(n)?)),
+        (3, _) => Some(Dechunker::CrLf),
+        (4, _) => Some(Dechunker::Ended),
+        _ => return Err(HootError::ResumeToken),
+    };
+
+    state.content_encoding = r.encoding()?;
+    let tc = &mut state.transfer_codings;
+    for c in tc.codings.iter_mut() {
+        if let Some(v) = r.encoding()? {
+            *c = v;
+        }
+    }
+    tc.len = r.u8() as usize;
+    if tc.len > tc.codings.len() {
+        return Err(HootError::ResumeToken);
+    }
+
+    state.max_recv_body = r.opt_u64()?;
+
+    let n = r.u64();
+    state.limits.max_headers = r.usize(n)?;
+    let n = r.u64();
+    state.limits.max_header_bytes = r.usize(n)?;
+    let n = r.u64();
+    state.limits.max_line = r.usize(n)?;
+    let n = r.u64();
+    state.limits.max_target = r.usize(n)?;
+    let n = r.u64();
+    state.limits.max_chunk_line = r.usize(n)?;
+
+    state.parse_mode = match r.u8() {
+        0 => ParseMode::Normal,
+        1 => ParseMode::Strict,
+        2 => ParseMode::Lenient,
+        _ => return Err(HootError::ResumeToken),
+    };
+    state.content_length_policy = match r.u8() {
+        0 => ContentLengthPolicy::Reject,
+        1 => ContentLengthPolicy::AcceptIfIdentical,
+        _ => return Err(HootError::ResumeToken),
+    };
+    state.excess_body_policy = match r.u8() {
+        0 => ExcessBodyPolicy::Reject,
+        1 => ExcessBodyPolicy::Ignore,
+        2 => ExcessBodyPolicy::Preserve,
+        _ => return Err(HootError::ResumeToken),
+    };
+    state.request_body_policy = match r.u8() {
+        0 => RequestBodyPolicy::Ignore,
+        1 => RequestBodyPolicy::Read,
+        2 => RequestBodyPolicy::Reject,
+        _ => return Err(HootError::ResumeToken),
+    };
+    state.extension_methods = match r.u8() {
+        0 => None,
+        1 => Some(false),
+        2 => Some(true),
+        _ => return Err(HootError::ResumeToken),
+    };
+    state.sent_singular = r.u32();
+
+    let d = &mut state.deadline;
+    d.budgets.headers = r.opt_u64()?;
+    d.budgets.body = r.opt_u64()?;
+    d.budgets.idle = r.opt_u64()?;
+    d.now = r.opt_u64()?;
+    d.head_start = r.opt_u64()?;
+    d.body_start = r.opt_u64()?;
+    d.last_progress = r.opt_u64()?;
+    state.date = r.opt_u64()?;
+    state.sent_status = Some(r.u16()).filter(|s| *s > 0);
+    let unused = r.u64();
+    state.recv_unused = r.usize(unused)?;
+    state.recv_body_len = r.u64();
+    let align = r.u64();
+    state.body_align = Some(r.usize(align)?).filter(|a| *a > 0);
+    let metrics = (r.u32(), r.u64(), r.u64());
+    #[cfg(feature = "metrics")]
+    {
+        (state.metrics_id, state.sent_len, state.sent_head) = metrics;
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = metrics;
+
+    let bits = r.u16();
+    let flag = |i: u16| bits & (1 << i) > 0;
+    state.did_read_to_end = flag(0);
+    state.reject_unknown_recv_length = flag(1);
+    state.recv_too_large = flag(2);
+    state.percent_encode_target = flag(3);
+    state.accept_http09 = flag(4);
+    state.expect_continue = flag(5);
+    state.transfer_codings.present = flag(6);
+    state.transfer_codings.chunked = flag(7);
+    state.canonical_header_names = flag(8);
+
+    Ok(state)
+}
+
+fn index_of<T: PartialEq>(all: &[T], v: T) -> u8 {
+    // All values are in the list, which is short.
+    all.iter().position(|x| *x == v).unwrap_or(0) as u8
+}
+
+struct Enc<'a> {
+    dst: &'a mut [u8],
+    pos: usize,
+}
+
+impl Enc<'_> {
+    fn bytes(&mut self, b: &[u8]) {
+        self.dst[self.pos..(self.pos + b.len())].copy_from_slice(b);
+        self.pos += b.len();
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.bytes(&[v]);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn tagged(&mut self, tag: u8, v: u64) {
+        self.u8(tag);
+        self.u64(v);
+    }
+
+    fn opt_u64(&mut self, v: Option<u64>) {
+        match v {
+            Some(v) => self.tagged(1, v),
+            None => self.tagged(0, 0),
+        }
+    }
+
+    fn checker(&mut self, c: Option<&LengthChecker>) {
+        self.u8(c.is_some() as u8);
+        self.u64(c.map(|c| c.handled).unwrap_or(0));
+        self.u64(c.map(|c| c.expected).unwrap_or(0));
+    }
+
+    fn encoding(&mut self, e: Option<ContentEncoding>) {
+        self.u8(e.map(|e| index_of(&ENCODINGS, e) + 1).unwrap_or(0));
+    }
+}
+
+struct Dec<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Dec<'a> {
+    fn bytes(&mut self, n: usize) -> &'a [u8] {
+        let b = &self.src[self.pos..(self.pos + n)];
+        self.pos += n;
+        b
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let mut a = [0; N];
+        a.copy_from_slice(self.bytes(N));
+        a
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.array())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.array())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.array())
+    }
+
+    fn usize(&self, v: u64) -> Result<usize> {
+        usize::try_from(v).map_err(|_| HootError::ResumeToken)
+    }
+
+    fn tagged(&mut self) -> (u8, u64) {
+        (self.u8(), self.u64())
+    }
+
+    fn opt_u64(&mut self) -> Result<Option<u64>> {
+        match self.tagged() {
+            (0, _) => Ok(None),
+            (1, v) => Ok(Some(v)),
+            _ => Err(HootError::ResumeToken),
+        }
+    }
+
+    fn checker(&mut self) -> Result<Option<LengthChecker>> {
+        let (some, handled, expected) = (self.u8(), self.u64(), self.u64());
+        match some {
+            0 => Ok(None),
+            1 => Ok(Some(LengthChecker { handled, expected })),
+            _ => Err(HootError::ResumeToken),
+        }
+    }
+
+    fn encoding(&mut self) -> Result<Option<ContentEncoding>> {
+        match self.u8() {
+            0 => Ok(None),
+            n => Ok(Some(
+                *ENCODINGS
+                    .get(n as usize - 1)
+                    .ok_or(HootError::ResumeToken)?,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{Request, ResumeToken};
+    use crate::types::body::{BODY_CHUNKED, BODY_LENGTH};
+    use crate::types::method::EXTENSION;
+    use crate::types::state::SEND_BODY;
+    use crate::types::version::HTTP_11;
+
+    type Token = ResumeToken<SEND_BODY, HTTP_11, EXTENSION, BODY_CHUNKED>;
+
+    #[test]
+    fn resume_token_round_trip() -> Result<()> {
+        let mut buf = [0; 1024];
+        let token = Request::new(&mut buf)
+            .percent_encode_target()
+            .http_11()
+            .extension(ExtMethod::new("SEARCH")?, "x", "/a b")?
+            .with_chunked()?
+            .flush()
+            .ready();
+
+        let mut parked = [0; RESUME_TOKEN_LEN];
+        assert_eq!(token.write_to(&mut parked)?, RESUME_TOKEN_LEN);
+
+        let err = token.write_to(&mut [0; 10]).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::OutputOverflow { needed: 258 });
+
+        let token = Token::read_from(&parked)?;
+        let mut again = [0; RESUME_TOKEN_LEN];
+        token.write_to(&mut again)?;
+        assert_eq!(again, parked);
+        assert_eq!(
+            token.into_state().method,
+            Some(Method::Extension(ExtMethod::new("SEARCH")?))
+        );
+
+        let output = Request::resume(Token::read_from(&parked)?, &mut buf)
+            .write_chunk(b"hi")?
+            .finish()?
+            .flush();
+        assert_eq!(&output[..], b"2\r\nhi\r\n0\r\n\r\n");
+
+        // Other type state, truncated, or corrupt.
+        type Other = ResumeToken<SEND_BODY, HTTP_11, EXTENSION, BODY_LENGTH>;
+        let err = Other::read_from(&parked).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::ResumeToken);
+
+        let err = Token::read_from(&parked[..100]).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::ResumeToken);
+
+        parked[5] = 9;
+        let err = Token::read_from(&parked).map(|_| ()).unwrap_err();
+        assert_eq!(err, HootError::ResumeToken);
+
+        Ok(())
+    }
+}
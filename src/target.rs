@@ -0,0 +1,135 @@
+use core::fmt;
+
+use crate::{HootError, Method, Result, Url};
+
+/// The form of a request target, RFC 7230 §5.3.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RequestTarget<'a> {
+    /// Absolute path and query, such as `/where?q=now`.
+    Origin(&'a str),
+    /// Absolute URI, such as `http://www.example.org/pub`, used with proxies.
+    Absolute(&'a str),
+    /// Host and port, such as `www.example.com:80`, only used with `CONNECT`.
+    Authority(&'a str),
+    /// `*`, only used with server-wide `OPTIONS`.
+    Asterisk,
+}
+
+impl<'a> RequestTarget<'a> {
+    /// Classify the target of a request with `method`.
+    ///
+    /// Fails with `HootError::InvalidTarget` if the form is not allowed for
+    /// the method. The offset is in `target`.
+    pub fn parse(method: Method, target: &'a str) -> Result<Self> {
+        let ret = if method == Method::CONNECT {
+            if target.is_empty() {
+                return Err(HootError::InvalidTarget { offset: 0 });
+            }
+            if let Some(offset) = target.find('/') {
+                return Err(HootError::InvalidTarget { offset });
+            }
+            RequestTarget::Authority(target)
+        } else if target == "*" {
+            if method != Method::OPTIONS {
+                return Err(HootError::InvalidTarget { offset: 0 });
+            }
+            RequestTarget::Asterisk
+        } else if target.starts_with('/') {
+            RequestTarget::Origin(target)
+        } else if target.contains("://") {
+            if Url::parse_str(target).is_err() {
+                return Err(HootError::InvalidTarget { offset: 0 });
+            }
+            RequestTarget::Absolute(target)
+        } else {
+            return Err(HootError::InvalidTarget { offset: 0 });
+        };
+
+        Ok(ret)
+    }
+
+    /// The URL of an absolute-form target.
+    pub fn url(&self) -> Option<Url<'a>> {
+        match self {
+            RequestTarget::Absolute(v) => Url::parse_str(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// The path and query of an origin or absolute-form target.
+    ///
+    /// This is what a server handles, whichever form the client sent, such
+    /// as `/pub` for `http://www.example.org/pub`.
+    pub fn path_and_query(&self) -> Option<&'a str> {
+        match self {
+            RequestTarget::Origin(v) => Some(v),
+            RequestTarget::Absolute(_) => self.url().map(|u| u.path_and_query()),
+            _ => None,
+        }
+    }
+
+    /// The target as sent in the request line.
+    pub fn as_str(&self) -> &'a str {
+        match self {
+            RequestTarget::Origin(v) | RequestTarget::Absolute(v) | RequestTarget::Authority(v) => {
+                v
+            }
+            RequestTarget::Asterisk => "*",
+        }
+    }
+}
+
+impl<'a> fmt::Debug for RequestTarget<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Origin(v) => f.debug_tuple("Origin").field(v).finish(),
+            Self::Absolute(v) => f.debug_tuple("Absolute").field(v).finish(),
+            Self::Authority(v) => f.debug_tuple("Authority").field(v).finish(),
+            Self::Asterisk => write!(f, "Asterisk"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_forms() {
+        use RequestTarget::*;
+
+        assert_eq!(
+            RequestTarget::parse(Method::GET, "/a?b"),
+            Ok(Origin("/a?b"))
+        );
+        assert_eq!(
+            RequestTarget::parse(Method::GET, "http://x.test/"),
+            Ok(Absolute("http://x.test/"))
+        );
+        assert_eq!(
+            RequestTarget::parse(Method::CONNECT, "x.test:443"),
+            Ok(Authority("x.test:443"))
+        );
+        assert_eq!(RequestTarget::parse(Method::OPTIONS, "*"), Ok(Asterisk));
+
+        let invalid = Err(HootError::InvalidTarget { offset: 0 });
+        assert_eq!(RequestTarget::parse(Method::GET, "*"), invalid);
+        assert_eq!(RequestTarget::parse(Method::CONNECT, "/a"), invalid);
+        assert_eq!(RequestTarget::parse(Method::GET, "a"), invalid);
+        assert_eq!(RequestTarget::parse(Method::GET, "http://"), invalid);
+    }
+
+    #[test]
+    fn path_of_absolute_form() {
+        let target = RequestTarget::parse(Method::GET, "http://x.test:8080/a?q").unwrap();
+        assert_eq!(target.path_and_query(), Some("/a?q"));
+        assert_eq!(target.url().unwrap().host(), "x.test:8080");
+
+        let target = RequestTarget::parse(Method::GET, "http://x.test").unwrap();
+        assert_eq!(target.path_and_query(), Some("/"));
+
+        let target = RequestTarget::parse(Method::GET, "/a?b").unwrap();
+        assert_eq!(target.path_and_query(), Some("/a?b"));
+        assert!(target.url().is_none());
+    }
+}
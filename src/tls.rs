@@ -0,0 +1,317 @@
+//! Seam for running hoot over TLS.
+//!
+//! hoot does neither IO nor TLS. The bytes it writes to send buffers are the
+//! plaintext of the TLS record layer, and the plaintext read out of the
+//! record layer is the input for parsing. [`Transport`] is the glue between
+//! the two, and [`TlsTarget`] takes what the TLS handshake needs from a
+//! [`Url`], including the name for SNI.
+//!
+//! Adapters for TLS stacks:
+//!
+//! * [`IoTransport`] over any `std::io::Read + Write` (`std` feature), and
+//!   [`IoTransport::rustls()`] to open a rustls client session for a
+//!   [`TlsTarget`] (`tls-rustls` feature).
+//! * [`EmbeddedIoTransport`] over `embedded_io::{Read, Write}`, which is what
+//!   an embedded-tls `TlsConnection` implements (`embedded-tls` feature).
+//!
+//! rustls needs a newer Rust than the MSRV of hoot.
+
+use crate::{HostKind, Url};
+
+/// A connection moving bytes to and from the peer.
+///
+/// For TLS, this is the plaintext side of the record layer.
+pub trait Transport {
+    type Error;
+
+    /// Send all of `bytes`, which typically is the output of hoot.
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Receive into `buf`, returning the number of bytes received.
+    ///
+    /// `0` means the peer closed the connection.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// [`Transport`] over `std::io::Read + Write`, like rustls `StreamOwned`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct IoTransport<T>(pub T);
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write> Transport for IoTransport<T> {
+    type Error = std::io::Error;
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(bytes)?;
+        self.0.flush()
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(feature = "tls-rustls")]
+impl<T: std::io::Read + std::io::Write>
+    IoTransport<rustls::StreamOwned<rustls::ClientConnection, T>>
+{
+    /// rustls client session to `target` over `io`, typically a `TcpStream`.
+    ///
+    /// The server name from the target is sent as SNI. For IP addresses,
+    /// the certificate is verified against the address and no SNI is sent.
+    ///
+    /// ```no_run
+    /// use std::net::TcpStream;
+    /// use std::sync::Arc;
+    /// use hoot::Url;
+    /// use hoot::client::call;
+    /// use hoot::tls::{IoTransport, TlsTarget, Transport};
+    /// use rustls::{ClientConfig, RootCertStore};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let roots = RootCertStore::empty(); // Add your trust anchors.
+    /// let config = ClientConfig::builder()
+    ///     .with_root_certificates(roots)
+    ///     .with_no_client_auth();
+    ///
+    /// let url = Url::parse_str("https://myhost.test/path")?;
+    /// let target = TlsTarget::from_url(&url).unwrap();
+    /// let tcp = TcpStream::connect((target.host(), target.port()))?;
+    /// let mut transport = IoTransport::rustls(&target, Arc::new(config), tcp)?;
+    ///
+    /// let mut buf = [0; 1024];
+    /// let output = call().get("https://myhost.test/path").send(&mut buf)?;
+    /// transport.send(&output)?;
+    ///
+    /// let mut input = [0; 1024];
+    /// let n = transport.recv(&mut input)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rustls(
+        target: &TlsTarget<'_>,
+        config: std::sync::Arc<rustls::ClientConfig>,
+        io: T,
+    ) -> Result<Self, rustls::Error> {
+        let name = target.server_name().unwrap_or(target.host);
+        let name = rustls::pki_types::ServerName::try_from(name)
+            .map_err(|_| rustls::Error::General("invalid server name".into()))?
+            .to_owned();
+        let conn = rustls::ClientConnection::new(config, name)?;
+        Ok(IoTransport(rustls::StreamOwned::new(conn, io)))
+    }
+}
+
+/// [`Transport`] over `embedded_io::{Read, Write}`, like embedded-tls
+/// `TlsConnection`.
+///
+/// ```
+/// use embedded_io::{Read, Write};
+/// use hoot::client::call;
+/// use hoot::tls::{EmbeddedIoTransport, Transport};
+///
+/// // The stream is an embedded-tls TlsConnection, opened with a TlsConfig
+/// // that has the server name from TlsTarget.
+/// fn fetch<S: Read + Write>(stream: S, input: &mut [u8]) -> Result<usize, S::Error> {
+///     let mut transport = EmbeddedIoTransport(stream);
+///
+///     let mut buf = [0; 1024];
+///     let output = call().get("https://myhost.test/path").send(&mut buf).unwrap();
+///     transport.send(&output)?;
+///     transport.recv(input)
+/// }
+/// ```
+#[cfg(feature = "embedded-tls")]
+#[derive(Debug)]
+pub struct EmbeddedIoTransport<T>(pub T);
+
+#[cfg(feature = "embedded-tls")]
+impl<T: embedded_io::Read + embedded_io::Write> Transport for EmbeddedIoTransport<T> {
+    type Error = T::Error;
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(bytes)?;
+        self.0.flush()
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+
+/// Where to connect a TLS session for a [`Url`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsTarget<'a> {
+    host: &'a str,
+    port: u16,
+    server_name: Option<&'a str>,
+}
+
+impl<'a> TlsTarget<'a> {
+    /// Target for an `https` or `wss` url, `None` for other schemes.
+    ///
+    /// ```
+    /// use hoot::Url;
+    /// use hoot::tls::TlsTarget;
+    ///
+    /// let url = Url::parse_str("https://myhost.test/path")?;
+    /// let target = TlsTarget::from_url(&url).unwrap();
+    ///
+    /// assert_eq!(target.host(), "myhost.test");
+    /// assert_eq!(target.port(), 443);
+    /// assert_eq!(target.server_name(), Some("myhost.test"));
+    ///
+    /// let url = Url::parse_str("http://myhost.test/path")?;
+    /// assert!(TlsTarget::from_url(&url).is_none());
+    /// # Ok::<(), hoot::UrlError>(())
+    /// ```
+    pub fn from_url(url: &Url<'a>) -> Option<Self> {
+        let scheme = url.scheme();
+        if !scheme.eq_ignore_ascii_case("https") && !scheme.eq_ignore_ascii_case("wss") {
+            return None;
+        }
+
+        let host = match url.host_kind() {
+            // Without the brackets, for connecting.
+            HostKind::Ipv6 => url.hostname().trim_start_matches('[').trim_end_matches(']'),
+            _ => url.hostname(),
+        };
+
+        Some(TlsTarget {
+            host,
+            port: url.port_or_default()?,
+            server_name: url.server_name(),
+        })
+    }
+
+    /// The host to connect to. IPv6 addresses are without brackets.
+    pub fn host(&self) -> &'a str {
+        self.host
+    }
+
+    /// The port to connect to, `443` unless the url has another.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The name to send as SNI and to verify the certificate against.
+    ///
+    /// This is `None` for IP addresses, which must not be sent as SNI
+    /// (RFC 6066 §3).
+    pub fn server_name(&self) -> Option<&'a str> {
+        self.server_name
+    }
+}
+
+#[cfg(all(test, feature = "std", feature = "call"))]
+mod test {
+    use super::*;
+    use crate::client::call;
+    use crate::util::HeaderBuf;
+    use std::io;
+
+    #[test]
+    fn tls_target() {
+        let url = Url::parse_str("wss://myhost.test:8443/").unwrap();
+        let target = TlsTarget::from_url(&url).unwrap();
+        assert_eq!(target.host(), "myhost.test");
+        assert_eq!(target.port(), 8443);
+        assert_eq!(target.server_name(), Some("myhost.test"));
+
+        let url = Url::parse_str("https://[::1]/").unwrap();
+        let target = TlsTarget::from_url(&url).unwrap();
+        assert_eq!(target.host(), "::1");
+        assert_eq!(target.server_name(), None);
+
+        let url = Url::parse_str("https://127.0.0.1/").unwrap();
+        let target = TlsTarget::from_url(&url).unwrap();
+        assert_eq!(target.host(), "127.0.0.1");
+        assert_eq!(target.server_name(), None);
+
+        let url = Url::parse_str("ws://myhost.test/").unwrap();
+        assert!(TlsTarget::from_url(&url).is_none());
+    }
+
+    /// In place of a TLS stream.
+    struct Pipe {
+        sent: Vec<u8>,
+        to_recv: io::Cursor<&'static [u8]>,
+    }
+
+    impl io::Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.to_recv.read(buf)
+        }
+    }
+
+    impl io::Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn call_over_transport() -> Result<(), Box<dyn std::error::Error>> {
+        let mut transport = IoTransport(Pipe {
+            sent: Vec::new(),
+            to_recv: io::Cursor::new(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi"),
+        });
+
+        let mut buf = [0; 1024];
+        let output = call().get("https://myhost.test/p").send(&mut buf)?;
+        transport.send(&output)?;
+        assert_eq!(
+            transport.0.sent,
+            b"GET /p HTTP/1.1\r\nHost: myhost.test\r\n\r\n"
+        );
+
+        let mut input = [0; 1024];
+        let n = transport.recv(&mut input)?;
+
+        let mut response = output.into_response();
+        let mut headers = HeaderBuf::<16>::new();
+        let attempt = response.try_read_response(&input[..n], &mut headers)?;
+        assert!(attempt.is_success());
+        assert_eq!(attempt.status().unwrap().code(), 200);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tls-rustls")]
+    fn rustls_hello(url: &str) -> Vec<u8> {
+        use rustls::{ClientConfig, RootCertStore};
+        use std::sync::Arc;
+
+        let config = ClientConfig::builder()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+
+        let url = Url::parse_str(url).unwrap();
+        let target = TlsTarget::from_url(&url).unwrap();
+        let pipe = Pipe {
+            sent: Vec::new(),
+            to_recv: io::Cursor::new(b""),
+        };
+        let mut transport = IoTransport::rustls(&target, Arc::new(config), pipe).unwrap();
+
+        // The handshake fails on the closed pipe, after sending the hello.
+        transport.send(b"GET").unwrap_err();
+        transport.0.sock.sent
+    }
+
+    #[cfg(feature = "tls-rustls")]
+    #[test]
+    fn rustls_sni() {
+        let hello = rustls_hello("https://myhost.test/");
+        assert!(hello.windows(11).any(|w| w == b"myhost.test"));
+
+        let hello = rustls_hello("https://127.0.0.1/");
+        assert!(!hello.is_empty());
+        assert!(!hello.windows(9).any(|w| w == b"127.0.0.1"));
+    }
+}
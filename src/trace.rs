@@ -0,0 +1,143 @@
+//! Tracing of the state machines.
+//!
+//! With the `log` feature (on by default), trace points go to the `log` crate.
+//! With the `trace-hook` feature, they also go to a [`TraceHook`], which is a
+//! way to see them without `log`, for instance forwarded to defmt. Without
+//! either feature the trace points compile to nothing.
+//!
+//! ```
+//! # #[cfg(feature = "trace-hook")] {
+//! use core::fmt;
+//! use hoot::trace::{set_trace_hook, Level, TraceHook};
+//!
+//! struct Print;
+//!
+//! impl TraceHook for Print {
+//!     fn trace(&self, level: Level, target: &str, args: fmt::Arguments<'_>) {
+//!         println!("{:?} {}: {}", level, target, args);
+//!     }
+//! }
+//!
+//! static HOOK: &dyn TraceHook = &Print;
+//! set_trace_hook(&HOOK);
+//! # }
+//! ```
+
+use core::fmt;
+
+/// How detailed a trace point is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Level {
+    /// Unusual events, like aborted or rejected messages.
+    Debug,
+    /// Parse attempts, state transitions and such.
+    Trace,
+}
+
+/// Receiver of trace points, see [`set_trace_hook()`].
+pub trait TraceHook: Sync {
+    /// Handle one trace point. `target` is the module it is from.
+    fn trace(&self, level: Level, target: &str, args: fmt::Arguments<'_>);
+}
+
+#[cfg(feature = "trace-hook")]
+mod hook {
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    use super::TraceHook;
+
+    static HOOK: AtomicPtr<&'static dyn TraceHook> = AtomicPtr::new(ptr::null_mut());
+
+    /// Send all trace points to `hook`, replacing any previous hook.
+    ///
+    /// The double reference keeps the pointer thin, so it can be swapped
+    /// atomically without compare-and-swap, which not all targets have.
+    pub fn set_trace_hook(hook: &'static &'static dyn TraceHook) {
+        let hook = hook as *const &'static dyn TraceHook as *mut &'static dyn TraceHook;
+        HOOK.store(hook, Ordering::Release);
+    }
+
+    pub(crate) fn current() -> Option<&'static dyn TraceHook> {
+        let hook = HOOK.load(Ordering::Acquire);
+        // SAFETY: The pointer is either null or from a &'static in set_trace_hook().
+        unsafe { hook.as_ref() }.copied()
+    }
+}
+
+#[cfg(feature = "trace-hook")]
+pub use hook::set_trace_hook;
+
+/// Send a trace point to `log` and the hook, as enabled by features.
+#[inline(always)]
+#[allow(unused_variables)]
+pub(crate) fn emit(level: Level, target: &str, args: fmt::Arguments<'_>) {
+    #[cfg(feature = "log")]
+    {
+        let level = match level {
+            Level::Debug => log::Level::Debug,
+            Level::Trace => log::Level::Trace,
+        };
+        log::log!(target: target, level, "{}", args);
+    }
+
+    #[cfg(feature = "trace-hook")]
+    if let Some(hook) = hook::current() {
+        hook.trace(level, target, args);
+    }
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::trace::emit(
+            $crate::trace::Level::Trace,
+            module_path!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::trace::emit(
+            $crate::trace::Level::Debug,
+            module_path!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+#[cfg(all(test, feature = "trace-hook"))]
+mod test {
+    use crate::util::HeaderBuf;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::server::Request;
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct Count;
+
+    impl TraceHook for Count {
+        fn trace(&self, _: Level, target: &str, _: fmt::Arguments<'_>) {
+            assert!(target.starts_with("hoot"));
+            COUNT.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn trace_hook() {
+        static HOOK: &dyn TraceHook = &Count;
+        set_trace_hook(&HOOK);
+
+        let mut buf = HeaderBuf::<16>::new();
+        let mut request = Request::new();
+        request
+            .try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf)
+            .unwrap();
+
+        assert!(COUNT.load(Ordering::Relaxed) > 0);
+    }
+}
@@ -0,0 +1,370 @@
+//! Replay of recorded HTTP exchanges for conformance tests.
+//!
+//! A transcript is a text file (conventionally `.http`) with the bytes of one
+//! or more request/response exchanges. Lines starting with `> ` are sent by
+//! the client, and lines starting with `< ` by the server. Each line ends with
+//! `\r\n`, unless it ends with a `\`, which is removed. Empty lines and lines
+//! starting with `#` are ignored.
+//!
+//! ```text
+//! # Plain GET
+//! > GET /hello HTTP/1.1
+//! > Host: example.test
+//! >
+//! < HTTP/1.1 200 OK
+//! < Content-Length: 2
+//! <
+//! < hi\
+//! ```
+//!
+//! [`replay_client`] checks that a client writes the recorded requests, and
+//! reads the recorded responses. [`replay_server`] reads the recorded requests,
+//! and checks that a server writes the recorded responses. Mismatches panic
+//! with both sides escaped, like `assert_eq!`.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::client;
+use crate::server::{self, ResponseVariant};
+use crate::types::state::{RECV_BODY, RECV_RESPONSE};
+use crate::util::HeaderBuf;
+use crate::{HootError, HttpVersion, Method, Result};
+
+/// Parsed transcript of exchanges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    exchanges: Vec<Exchange>,
+}
+
+/// One request and its response, as raw bytes.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Exchange {
+    pub request: Vec<u8>,
+    pub response: Vec<u8>,
+}
+
+/// A request as read by the server state machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub target: String,
+    pub version: HttpVersion,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+/// A response as read by the client state machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedResponse {
+    pub code: u16,
+    pub text: String,
+    pub version: HttpVersion,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+// Size of the buffers used for parsing and bodies.
+const BUF_SIZE: usize = 16 * 1024;
+const MAX_HEADERS: usize = 100;
+
+impl Transcript {
+    /// Read a transcript file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse the transcript format, see the [module docs](self).
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` for lines that are not part of
+    /// the format.
+    pub fn parse(s: &str) -> io::Result<Self> {
+        let mut exchanges: Vec<Exchange> = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if !line.starts_with('>') && !line.starts_with('<') {
+                let msg = format!("line {}: expected '>' or '<'", i + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+
+            let (dir, rest) = line.split_at(1);
+            let rest = rest.strip_prefix(' ').unwrap_or(rest);
+
+            let (bytes, eol) = match rest.strip_suffix('\\') {
+                Some(v) => (v, ""),
+                None => (rest, "\r\n"),
+            };
+
+            // A request after a response starts a new exchange.
+            let new_exchange = match exchanges.last() {
+                None => true,
+                Some(e) => dir == ">" && !e.response.is_empty(),
+            };
+            if new_exchange {
+                exchanges.push(Exchange::default());
+            }
+            // Unwrap is OK since we just pushed if there are none.
+            let exchange = exchanges.last_mut().unwrap();
+
+            let target = if dir == ">" {
+                &mut exchange.request
+            } else {
+                &mut exchange.response
+            };
+
+            target.extend_from_slice(bytes.as_bytes());
+            target.extend_from_slice(eol.as_bytes());
+        }
+
+        Ok(Transcript { exchanges })
+    }
+
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+}
+
+/// Check a client against the transcript.
+///
+/// For each exchange, `send` writes the request with `client::Request` into
+/// the vec and returns the response state. The written bytes must equal the
+/// recorded request. The recorded response is then read by the response state.
+pub fn replay_client<F>(transcript: &Transcript, mut send: F) -> Result<Vec<RecordedResponse>>
+where
+    F: FnMut(usize, &mut Vec<u8>) -> Result<client::Response<RECV_RESPONSE>>,
+{
+    let mut ret = Vec::new();
+
+    for (i, exchange) in transcript.exchanges.iter().enumerate() {
+        let mut written = Vec::new();
+        let response = send(i, &mut written)?;
+        assert_bytes(i, "request", &written, &exchange.request);
+
+        ret.push(read_response(response, &exchange.response)?);
+    }
+
+    Ok(ret)
+}
+
+/// Check a server against the transcript.
+///
+/// For each exchange, the recorded request is read with `server::Request`,
+/// and `respond` writes the response with `server::Response` into the vec.
+/// The written bytes must equal the recorded response.
+pub fn replay_server<F>(transcript: &Transcript, mut respond: F) -> Result<()>
+where
+    F: FnMut(&RecordedRequest, ResponseVariant, &mut Vec<u8>) -> Result<()>,
+{
+    for (i, exchange) in transcript.exchanges.iter().enumerate() {
+        let (request, variant) = read_request(&exchange.request)?;
+
+        let mut written = Vec::new();
+        respond(&request, variant, &mut written)?;
+        assert_bytes(i, "response", &written, &exchange.response);
+    }
+
+    Ok(())
+}
+
+fn read_request(input: &[u8]) -> Result<(RecordedRequest, ResponseVariant)> {
+    let mut buf = vec![0; BUF_SIZE];
+    let mut headers = HeaderBuf::<MAX_HEADERS>::new();
+
+    let mut request = server::Request::new();
+    let attempt = request.try_read_request(input, &mut headers)?;
+
+    if !attempt.is_success() {
+        return Err(HootError::StatusIsNotComplete);
+    }
+
+    // Unwraps are OK since the attempt is a success.
+    let line = attempt.line().unwrap();
+    let mut recorded = RecordedRequest {
+        method: line.method(),
+        target: line.path().into(),
+        version: line.version(),
+        headers: owned_headers(attempt.headers().unwrap()),
+        body: Vec::new(),
+    };
+    let used = attempt.input_used();
+
+    let mut request = request.proceed();
+    let mut src = &input[used..];
+
+    while !request.is_finished() {
+        let part = request.read_body(src, &mut buf)?;
+        if part.input_used() == 0 && part.data().is_empty() {
+            return Err(HootError::BodyNotFinished);
+        }
+        recorded.body.extend_from_slice(part.data());
+        src = &src[part.input_used()..];
+    }
+
+    Ok((recorded, request.into_response()?))
+}
+
+fn read_response(
+    mut response: client::Response<RECV_RESPONSE>,
+    input: &[u8],
+) -> Result<RecordedResponse> {
+    let mut buf = vec![0; BUF_SIZE];
+    let mut headers = HeaderBuf::<MAX_HEADERS>::new();
+
+    let attempt = response.try_read_response(input, &mut headers)?;
+
+    if !attempt.is_success() {
+        return Err(HootError::StatusIsNotComplete);
+    }
+
+    // Unwrap is OK since the attempt is a success.
+    let status = attempt.status().unwrap();
+    let mut recorded = RecordedResponse {
+        code: status.code(),
+        text: status.text().into(),
+        version: status.version(),
+        headers: owned_headers(attempt.headers().unwrap()),
+        body: Vec::new(),
+    };
+    let used = attempt.input_used();
+
+    let response = response.proceed();
+    recorded.body = read_to_end(response, &input[used..], &mut buf)?;
+
+    Ok(recorded)
+}
+
+fn read_to_end(
+    mut response: client::Response<RECV_BODY>,
+    mut src: &[u8],
+    buf: &mut [u8],
+) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+
+    while !response.is_finished() {
+        let part = response.read_body(src, buf)?;
+        body.extend_from_slice(part.data());
+        src = &src[part.input_used()..];
+
+        if part.input_used() == 0 && part.data().is_empty() {
+            // Close delimited bodies end with the transcript.
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+fn owned_headers(headers: &[crate::Header<'_>]) -> Vec<(String, Vec<u8>)> {
+    headers
+        .iter()
+        .map(|h| (h.name().into(), h.value_raw().into()))
+        .collect()
+}
+
+fn assert_bytes(index: usize, what: &str, written: &[u8], recorded: &[u8]) {
+    assert!(
+        written == recorded,
+        "exchange {} {} mismatch\n  written: {}\n recorded: {}",
+        index,
+        what,
+        Escaped(written),
+        Escaped(recorded)
+    );
+}
+
+struct Escaped<'a>(&'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{}", std::ascii::escape_default(*b))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Exchange")
+            .field("request", &format_args!("{}", Escaped(&self.request)))
+            .field("response", &format_args!("{}", Escaped(&self.response)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRANSCRIPT: &str = "\
+# Plain GET
+> GET /hello HTTP/1.1
+> Host: example.test
+>
+< HTTP/1.1 200 OK
+< Content-Length: 2
+<
+< hi\\
+";
+
+    #[test]
+    fn parse_transcript() {
+        let t = Transcript::parse(TRANSCRIPT).unwrap();
+        assert_eq!(t.exchanges().len(), 1);
+        assert_eq!(
+            t.exchanges()[0].request,
+            b"GET /hello HTTP/1.1\r\nHost: example.test\r\n\r\n"
+        );
+        assert_eq!(
+            t.exchanges()[0].response,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi"
+        );
+
+        let err = Transcript::parse("GET / HTTP/1.1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn replay() -> Result<()> {
+        let t = Transcript::parse(TRANSCRIPT).unwrap();
+
+        let responses = replay_client(&t, |_, written| {
+            let mut buf = [0; 1024];
+            let output = client::Request::new(&mut buf)
+                .http_11()
+                .get("example.test", "/hello")?
+                .send()?
+                .flush();
+            written.extend_from_slice(&output);
+            Ok(output.into_response())
+        })?;
+        assert_eq!(responses[0].code, 200);
+        assert_eq!(responses[0].body, b"hi");
+
+        replay_server(&t, |request, variant, written| {
+            assert_eq!(request.target, "/hello");
+
+            let token = match variant {
+                ResponseVariant::Get(v) => v,
+                _ => unreachable!(),
+            };
+
+            let mut buf = [0; 1024];
+            let mut res = server::Response::resume(token, &mut buf)
+                .send_status(200, "OK")?
+                .with_body(2)?;
+            res.write_bytes(b"hi")?;
+            let output = res.finish()?.flush();
+            written.extend_from_slice(&output);
+            Ok(())
+        })
+    }
+}
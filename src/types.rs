@@ -45,7 +45,7 @@ pub trait BodyType: Private {}
 
 impl Private for () {
     fn state_name() -> &'static str {
-        return "*";
+        "*"
     }
 }
 
@@ -67,6 +67,7 @@ pub mod state {
     pub struct INIT;
     pub struct SEND_LINE;
     pub struct SEND_STATUS;
+    pub struct SEND_HINTS;
     pub struct SEND_HEADERS;
     pub struct SEND_BODY;
     pub struct SEND_TRAILER;
@@ -81,6 +82,7 @@ pub mod state {
     impl_private!(State, INIT);
     impl_private!(State, SEND_LINE);
     impl_private!(State, SEND_STATUS);
+    impl_private!(State, SEND_HINTS);
     impl_private!(State, SEND_HEADERS);
     impl_private!(State, SEND_BODY);
     impl_private!(State, SEND_TRAILER);
@@ -101,8 +103,8 @@ pub mod version {
 
     impl Version for () {
         fn version() -> HttpVersion {
-            // Calling .version() on a () is a bug.
-            unreachable!()
+            // Never called on a (), the version is set before it's needed.
+            HttpVersion::Http11
         }
     }
 
@@ -143,6 +145,19 @@ pub mod method {
     pub struct TRACE;
     pub struct CONNECT;
     pub struct PATCH;
+    pub struct QUERY;
+    pub struct PROPFIND;
+    pub struct PROPPATCH;
+    pub struct MKCOL;
+    pub struct COPY;
+    pub struct MOVE;
+    pub struct LOCK;
+    pub struct UNLOCK;
+    /// Any [`ExtMethod`](crate::ExtMethod). Requests can be sent with or
+    /// without a body.
+    pub struct EXTENSION;
+    /// A method without a request body, sent with one after `allow_body()`.
+    pub struct ALLOW_BODY;
 
     impl Method for () {}
     impl_private!(Method, OPTIONS);
@@ -152,6 +167,16 @@ pub mod method {
     impl_private!(Method, DELETE);
     impl_private!(Method, TRACE);
     impl_private!(Method, PATCH);
+    impl_private!(Method, QUERY);
+    impl_private!(Method, PROPFIND);
+    impl_private!(Method, PROPPATCH);
+    impl_private!(Method, MKCOL);
+    impl_private!(Method, COPY);
+    impl_private!(Method, MOVE);
+    impl_private!(Method, LOCK);
+    impl_private!(Method, UNLOCK);
+    impl_private!(Method, EXTENSION);
+    impl_private!(Method, ALLOW_BODY);
 
     impl super::Private for HEAD {
         fn state_name() -> &'static str {
@@ -178,6 +203,13 @@ pub mod method {
     impl MethodWithRequestBody for POST {}
     impl MethodWithRequestBody for PUT {}
     impl MethodWithRequestBody for PATCH {}
+    impl MethodWithRequestBody for QUERY {}
+    impl MethodWithRequestBody for PROPFIND {}
+    impl MethodWithRequestBody for PROPPATCH {}
+    impl MethodWithRequestBody for MKCOL {}
+    impl MethodWithRequestBody for LOCK {}
+    impl MethodWithRequestBody for EXTENSION {}
+    impl MethodWithRequestBody for ALLOW_BODY {}
 
     impl MethodWithoutRequestBody for OPTIONS {}
     impl MethodWithoutRequestBody for GET {}
@@ -185,6 +217,10 @@ pub mod method {
     impl MethodWithoutRequestBody for HEAD {}
     impl MethodWithoutRequestBody for CONNECT {}
     impl MethodWithoutRequestBody for TRACE {}
+    impl MethodWithoutRequestBody for COPY {}
+    impl MethodWithoutRequestBody for MOVE {}
+    impl MethodWithoutRequestBody for UNLOCK {}
+    impl MethodWithoutRequestBody for EXTENSION {}
 
     impl MethodWithResponseBody for OPTIONS {}
     impl MethodWithResponseBody for GET {}
@@ -193,16 +229,66 @@ pub mod method {
     impl MethodWithResponseBody for DELETE {}
     impl MethodWithResponseBody for TRACE {}
     impl MethodWithResponseBody for PATCH {}
+    impl MethodWithResponseBody for QUERY {}
+    impl MethodWithResponseBody for PROPFIND {}
+    impl MethodWithResponseBody for PROPPATCH {}
+    impl MethodWithResponseBody for MKCOL {}
+    impl MethodWithResponseBody for COPY {}
+    impl MethodWithResponseBody for MOVE {}
+    impl MethodWithResponseBody for LOCK {}
+    impl MethodWithResponseBody for UNLOCK {}
+    impl MethodWithResponseBody for EXTENSION {}
 
     impl MethodWithoutResponseBody for HEAD {}
     impl MethodWithoutResponseBody for CONNECT {}
 }
 
+/// How a sent body is delimited, which is fixed when leaving `SEND_HEADERS`.
+///
+/// `SEND_BODY` is paired with one of these, so operations for the other
+/// framing are compile errors. A `Content-Length` body has no chunks:
+///
+/// ```compile_fail
+/// # use hoot::server::{Request, Response, ResponseVariant};
+/// # let mut buf = [0; 1024];
+/// # let mut request = Request::new();
+/// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf)?;
+/// # let token = match request.proceed().into_response()? {
+/// #     ResponseVariant::Get(v) => v,
+/// #     _ => unreachable!(),
+/// # };
+/// Response::resume(token, &mut buf)
+///     .send_status(200, "OK")?
+///     .with_body(5)?
+///     .write_chunk(b"hello")?;
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+///
+/// And a chunked body has no total length to write up to:
+///
+/// ```compile_fail
+/// # use hoot::server::{Request, Response, ResponseVariant};
+/// # let mut buf = [0; 1024];
+/// # let mut request = Request::new();
+/// # request.try_read_request(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", &mut buf)?;
+/// # let token = match request.proceed().into_response()? {
+/// #     ResponseVariant::Get(v) => v,
+/// #     _ => unreachable!(),
+/// # };
+/// Response::resume(token, &mut buf)
+///     .send_status(200, "OK")?
+///     .with_chunked()?
+///     .write_bytes(b"hello")?;
+/// # Ok::<(), hoot::HootError>(())
+/// ```
 #[allow(non_camel_case_types)]
 pub mod body {
     use super::BodyType;
 
+    /// Body delimited by `Content-Length`, written with `write_bytes()`.
     pub struct BODY_LENGTH;
+    /// Body sent with `Transfer-Encoding: chunked`, written with
+    /// `write_chunk()` and optionally ended by trailers.
     pub struct BODY_CHUNKED;
 
     impl BodyType for () {}
@@ -1,41 +1,79 @@
+//! Helpers for integrating hoot with a transport.
+
+use crate::{Header, HootError, Result};
 use core::fmt;
-use core::mem;
-use httparse::{Header, EMPTY_HEADER};
+use core::ops::{Deref, DerefMut};
+
+/// Most headers parsed into a buffer, however large. `Limits::max_headers`
+/// lowers this per request or response, this is the ceiling for buffers that
+/// are parsed without limits, such as trailers.
+pub(crate) const MAX_HEADERS: usize = 100;
+
+pub(crate) const EMPTY_HEADER: Header<'static> = Header::new("", &[]);
+
+/// Limit a buffer for parsed headers to at most 100 headers.
+pub(crate) fn limit_header_buf<'a, 'b>(headers: &'a mut [Header<'b>]) -> &'a mut [Header<'b>] {
+    let max = headers.len().min(MAX_HEADERS);
+    &mut headers[..max]
+}
 
-use crate::{HootError, Result};
+/// Storage for parsing up to `N` headers.
+///
+/// Functions reading requests and responses take a `&mut [Header]` that is
+/// used to hold the parsed headers. `HeaderBuf` holds `N` of them, and derefs
+/// to the slice those functions take. At most 100 headers are used.
+///
+/// The parsed headers borrow the input. When the input changes between
+/// attempts, such as when more is appended, use a new `HeaderBuf` for each
+/// attempt.
+///
+/// ```
+/// use hoot::server::Request;
+/// use hoot::util::HeaderBuf;
+///
+/// let mut buf = HeaderBuf::<16>::new();
+///
+/// let mut request = Request::new();
+/// let attempt = request.try_read_request(b"GET / HTTP/1.1\r\nHost: foo\r\n\r\n", &mut buf)?;
+/// assert_eq!(attempt.headers().unwrap().len(), 1);
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub struct HeaderBuf<'a, const N: usize> {
+    headers: [Header<'a>; N],
+}
+
+impl<'a, const N: usize> HeaderBuf<'a, N> {
+    pub fn new() -> Self {
+        HeaderBuf {
+            headers: [EMPTY_HEADER; N],
+        }
+    }
+}
 
-// TODO: make this configurable.
-const MAX_HEADERS: usize = 100;
+impl<'a, const N: usize> Default for HeaderBuf<'a, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-/// Use a generic byte buffer to write httparse Header.
-pub(crate) fn cast_buf_for_headers<'a, 'b>(buf: &'a mut [u8]) -> &'a mut [Header<'b>] {
-    // SAFETY: align_to_mut docs say "This method is essentially a transmute with
-    // respect to the elements in the returned middle slice". Transmute further
-    // says that "..the result must be _valid_ at their given type". The "valid"
-    // word means that we can't have data, even temporarily, that is not correct.
-    //
-    // Header contains a &str and &[u8] and &str must never be made to exist while
-    // pointing to garbage data (which the incoming buf might have).
-    //
-    // This situation is similar to the example "Initializing an array element-by-element"
-    // https://doc.rust-lang.org/core/mem/union.MaybeUninit.html#initializing-an-array-element-by-element
-    //
-    // By transmuting to MaybeUninit<Header<'b>>, we can initialize the data safely
-    // before transmuting to our final result.
-    let (_, mut headers, _) = unsafe { buf.align_to_mut::<mem::MaybeUninit<Header<'b>>>() };
+impl<'a, const N: usize> Deref for HeaderBuf<'a, N> {
+    type Target = [Header<'a>];
 
-    if headers.len() > MAX_HEADERS {
-        let max = headers.len().min(MAX_HEADERS);
-        headers = &mut headers[..max];
+    fn deref(&self) -> &Self::Target {
+        &self.headers
     }
+}
 
-    // This is the point of using MaybeUninit.
-    for header in &mut *headers {
-        header.write(EMPTY_HEADER);
+impl<'a, const N: usize> DerefMut for HeaderBuf<'a, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.headers
     }
+}
 
-    // SAFETY: See above rust doc link.
-    unsafe { mem::transmute(headers) }
+impl<const N: usize> fmt::Debug for HeaderBuf<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderBuf").field("capacity", &N).finish()
+    }
 }
 
 pub(crate) fn compare_lowercase_ascii(a: &str, lowercased: &str) -> bool {
@@ -56,9 +94,10 @@ pub(crate) fn compare_lowercase_ascii(a: &str, lowercased: &str) -> bool {
     true
 }
 
+#[derive(Clone)]
 pub(crate) struct LengthChecker {
-    handled: u64,
-    expected: u64,
+    pub handled: u64,
+    pub expected: u64,
 }
 
 impl LengthChecker {
@@ -69,18 +108,25 @@ impl LengthChecker {
         }
     }
 
-    pub fn append(&mut self, amount: usize, err: HootError) -> Result<()> {
-        let new_total = self.handled + amount as u64;
-        if new_total > self.expected {
-            return Err(err);
+    /// Check that `amount` more fits. `err` makes the error from the expected
+    /// and the actual length.
+    pub fn check(&self, amount: usize, err: fn(u64, u64) -> HootError) -> Result<()> {
+        let actual = self.handled + amount as u64;
+        if actual > self.expected {
+            return Err(err(self.expected, actual));
         }
-        self.handled = new_total;
         Ok(())
     }
 
-    pub fn assert_expected(&self, err: HootError) -> Result<()> {
+    pub fn append(&mut self, amount: usize, err: fn(u64, u64) -> HootError) -> Result<()> {
+        self.check(amount, err)?;
+        self.handled += amount as u64;
+        Ok(())
+    }
+
+    pub fn assert_expected(&self, err: fn(u64, u64) -> HootError) -> Result<()> {
         if self.handled != self.expected {
-            return Err(err);
+            return Err(err(self.expected, self.handled));
         }
         Ok(())
     }
@@ -88,6 +134,10 @@ impl LengthChecker {
     pub fn complete(&self) -> bool {
         self.handled == self.expected
     }
+
+    pub fn remaining(&self) -> u64 {
+        self.expected - self.handled
+    }
 }
 
 impl fmt::Debug for LengthChecker {
@@ -98,3 +148,210 @@ impl fmt::Debug for LengthChecker {
             .finish()
     }
 }
+
+/// Accumulates input until it can be parsed.
+///
+/// Reading a request or response means appending bytes from the transport
+/// until `try_read_*` succeeds, then dropping the `input_used()` prefix. This
+/// buffer does that bookkeeping, compacting lazily when space is needed.
+///
+/// ```
+/// use hoot::client::Request;
+/// use hoot::util::{HeaderBuf, InputBuffer};
+///
+/// let mut buf = [0; 1024];
+/// let output = Request::new(&mut buf).http_11().get("foo.test", "/")?.send()?.flush();
+/// let mut response = output.into_response();
+///
+/// let mut input = InputBuffer::<1024>::new();
+///
+/// input.append(b"HTTP/1.1 200 OK\r\n");
+/// let mut headers = HeaderBuf::<16>::new();
+/// let attempt = response.try_read_response(&input, &mut headers)?;
+/// assert!(!attempt.is_success());
+///
+/// input.append(b"Content-Length: 2\r\n\r\nhi");
+/// let mut headers = HeaderBuf::<16>::new();
+/// let attempt = response.try_read_response(&input, &mut headers)?;
+/// assert!(attempt.is_success());
+///
+/// input.consume(attempt.input_used())?;
+/// assert_eq!(&*input, b"hi");
+/// # Ok::<(), hoot::HootError>(())
+/// ```
+pub struct InputBuffer<const N: usize> {
+    buf: [u8; N],
+    start: usize,
+    end: usize,
+}
+
+impl<const N: usize> InputBuffer<N> {
+    pub fn new() -> Self {
+        InputBuffer {
+            buf: [0; N],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Append as much of `bytes` as fits. Returns the amount appended.
+    pub fn append(&mut self, bytes: &[u8]) -> usize {
+        let free = self.unfilled();
+        let n = bytes.len().min(free.len());
+        free[..n].copy_from_slice(&bytes[..n]);
+        self.end += n;
+        n
+    }
+
+    /// Free space to read input into directly, followed by `filled()`.
+    pub fn unfilled(&mut self) -> &mut [u8] {
+        self.compact();
+        &mut self.buf[self.end..]
+    }
+
+    /// Mark `amount` bytes of `unfilled()` as input.
+    ///
+    /// More than `unfilled()` is `HootError::BufferOverrun`.
+    pub fn filled(&mut self, amount: usize) -> Result<()> {
+        advance_end(&mut self.end, N, amount)
+    }
+
+    /// Drop `amount` bytes of used input, typically `input_used()`.
+    ///
+    /// More than the buffered input is `HootError::BufferOverrun`.
+    pub fn consume(&mut self, amount: usize) -> Result<()> {
+        advance_start(&mut self.start, &mut self.end, amount)
+    }
+
+    /// Whether there is no space for more input.
+    ///
+    /// When this happens before a successful parse, the input is too large
+    /// for the buffer.
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.end = 0;
+    }
+
+    fn compact(&mut self) {
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+    }
+}
+
+impl<const N: usize> Default for InputBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for InputBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf[self.start..self.end]
+    }
+}
+
+impl<const N: usize> fmt::Debug for InputBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InputBuffer")
+            .field("len", &self.len())
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+// Not generic, so the no-panic check links these optimized in this crate
+// rather than in each user of InputBuffer<N>, such as doctests.
+#[inline(never)]
+fn advance_end(end: &mut usize, cap: usize, amount: usize) -> Result<()> {
+    no_panic!({
+        let available = cap.saturating_sub(*end);
+        if amount > available {
+            return Err(HootError::BufferOverrun { amount, available });
+        }
+        *end += amount;
+        Ok(())
+    })
+}
+
+#[inline(never)]
+fn advance_start(start: &mut usize, end: &mut usize, amount: usize) -> Result<()> {
+    no_panic!({
+        let available = end.saturating_sub(*start);
+        if amount > available {
+            return Err(HootError::BufferOverrun { amount, available });
+        }
+        *start += amount;
+
+        if *start == *end {
+            *start = 0;
+            *end = 0;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::HeaderBuf;
+
+    #[test]
+    fn input_buffer() {
+        let mut b = InputBuffer::<8>::new();
+        assert_eq!(b.append(b"hello"), 5);
+        b.consume(3).unwrap();
+        assert_eq!(&*b, b"lo");
+
+        // Compacts to make room.
+        assert_eq!(b.append(b"world!!"), 6);
+        assert_eq!(&*b, b"loworld!");
+        assert!(b.is_full());
+
+        b.consume(8).unwrap();
+        assert!(b.is_empty());
+        assert_eq!(b.unfilled().len(), 8);
+
+        assert_eq!(
+            b.consume(1).unwrap_err(),
+            HootError::BufferOverrun {
+                amount: 1,
+                available: 0
+            }
+        );
+        b.filled(8).unwrap();
+        assert!(b.is_full());
+        assert_eq!(
+            b.filled(1).unwrap_err(),
+            HootError::BufferOverrun {
+                amount: 1,
+                available: 0
+            }
+        );
+    }
+
+    #[test]
+    fn header_buf() {
+        let mut buf = HeaderBuf::<2>::new();
+        assert_eq!(limit_header_buf(&mut buf).len(), 2);
+
+        let mut buf = HeaderBuf::<200>::new();
+        assert_eq!(limit_header_buf(&mut buf).len(), MAX_HEADERS);
+    }
+}